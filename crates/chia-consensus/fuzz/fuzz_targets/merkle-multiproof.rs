@@ -0,0 +1,36 @@
+#![no_main]
+use chia_consensus::merkle_tree::{validate_merkle_multiproof, MerkleSet};
+use clvmr::sha2::{Digest, Sha256};
+use hex_literal::hex;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let dummy: [u8; 32] = hex!("cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc");
+    assert!(validate_merkle_multiproof(data, &[dummy], &dummy).is_err());
+
+    // every other leaf is in the tree, the rest are covered by
+    // proofs-of-exclusion
+    let mut leafs: Vec<[u8; 32]> = data
+        .chunks_exact(32)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect();
+    let mut tree_leafs: Vec<[u8; 32]> = leafs.iter().step_by(2).copied().collect();
+    let tree = MerkleSet::from_leafs(&mut tree_leafs);
+    let root = tree.get_root();
+
+    // this is a leaf that's *not* in the tree
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    leafs.push(hasher.finalize().into());
+
+    let (included, proof) = tree
+        .generate_multiproof(&leafs)
+        .expect("failed to generate proof");
+    for (item, included) in leafs.iter().zip(&included) {
+        assert_eq!(*included, tree_leafs.contains(item));
+    }
+    assert_eq!(
+        validate_merkle_multiproof(&proof, &leafs, &root).expect("proof failed"),
+        included
+    );
+});
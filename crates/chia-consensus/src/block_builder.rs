@@ -0,0 +1,352 @@
+use crate::allocator::make_allocator;
+use crate::consensus_constants::ConsensusConstants;
+use crate::fork_heights::get_flags_for_height;
+use crate::gen::conditions::EmptyVisitor;
+use crate::gen::flags::ALLOW_BACKREFS;
+use crate::gen::owned_conditions::OwnedSpendBundleConditions;
+use crate::gen::run_block_generator::run_block_generator2;
+use crate::gen::solution_generator::{solution_generator, solution_generator_backrefs};
+use crate::gen::validation_error::ErrorCode;
+use crate::mempool::FeePerCost;
+use chia_bls::Signature;
+use chia_protocol::{Bytes32, SpendBundle};
+use clvmr::chia_dialect::LIMIT_HEAP;
+use std::collections::HashSet;
+
+// once this many spend bundles have been found not to fit in the block, we
+// stop trying to add more
+pub const MAX_SKIPPED_ITEMS: usize = 10;
+
+struct Candidate {
+    spend_bundle: SpendBundle,
+    cost: u64,
+    fee: u64,
+}
+
+// the result of building a block. The generator is the serialized program to
+// put in the block, and conditions are the result of running it. The
+// signature is the aggregate signature of all included spend bundles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltBlock {
+    pub generator: Vec<u8>,
+    pub signature: Signature,
+    pub conditions: OwnedSpendBundleConditions,
+    // the IDs of the spend bundles included in the block
+    pub included: Vec<Bytes32>,
+}
+
+// Selects spend bundles to include in a block and builds the block generator
+// from them. Spend bundles are picked greedily by fee-per-cost, until the
+// block cost limit (max_block_cost_clvm) is reached. The cost of each spend
+// bundle is expected to be the cost reported by validate_spend_bundle(), which
+// includes the byte cost of the (uncompressed) spend bundle. This makes the
+// sum of the costs an upper bound of the cost of the block. When the upper
+// bound no longer fits, the generator is serialized (with back references
+// when allowed at the height) and run, to get the exact cost of the block,
+// before deciding whether more spend bundles fit.
+#[derive(Default)]
+pub struct BlockBuilder {
+    candidates: Vec<Candidate>,
+}
+
+impl BlockBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    // the spend bundle is expected to already have been validated
+    pub fn add_spend_bundle(&mut self, spend_bundle: SpendBundle, cost: u64, fee: u64) {
+        self.candidates.push(Candidate {
+            spend_bundle,
+            cost,
+            fee,
+        });
+    }
+
+    pub fn build(
+        mut self,
+        height: u32,
+        constants: &ConsensusConstants,
+    ) -> Result<BuiltBlock, ErrorCode> {
+        let flags = get_flags_for_height(height, constants, false);
+        let max_cost = constants.max_block_cost_clvm;
+
+        // highest fee-per-cost first. The sort is stable, so items with the
+        // same fee-per-cost are kept in the order they were added
+        self.candidates.sort_by(|lhs, rhs| {
+            FeePerCost::new(rhs.fee, rhs.cost).cmp(&FeePerCost::new(lhs.fee, lhs.cost))
+        });
+
+        let mut selected = Vec::<&Candidate>::new();
+        let mut spent_coins = HashSet::<Bytes32>::new();
+
+        // the exact cost of the block, as of the last time we ran it, and the
+        // generator and conditions from that run
+        let mut exact_cost: u64 = 0;
+        let mut last_run: Option<(Vec<u8>, OwnedSpendBundleConditions)> = None;
+        // the sum of the costs of the spend bundles added since the last run
+        let mut pending_cost: u64 = 0;
+        let mut skipped: usize = 0;
+
+        for c in &self.candidates {
+            if c.cost > max_cost {
+                continue;
+            }
+            if c.spend_bundle
+                .coin_spends
+                .iter()
+                .any(|cs| spent_coins.contains(&cs.coin.coin_id()))
+            {
+                continue;
+            }
+
+            let mut fits = exact_cost + pending_cost + c.cost <= max_cost;
+            if !fits && pending_cost > 0 {
+                // the upper bound doesn't fit, compute the exact cost of what
+                // we have so far
+                let ret = run_generator(&selected, flags, max_cost, constants)?;
+                exact_cost = ret.1.cost;
+                pending_cost = 0;
+                last_run = Some(ret);
+                fits = exact_cost + c.cost <= max_cost;
+            }
+
+            if fits {
+                pending_cost += c.cost;
+                last_run = None;
+                selected.push(c);
+            } else {
+                // the upper bound doesn't fit, but the exact cost might
+                selected.push(c);
+                match run_generator(&selected, flags, max_cost, constants) {
+                    Ok(ret) => {
+                        exact_cost = ret.1.cost;
+                        last_run = Some(ret);
+                    }
+                    Err(ErrorCode::CostExceeded) => {
+                        selected.pop();
+                        skipped += 1;
+                        if skipped >= MAX_SKIPPED_ITEMS {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+            spent_coins.extend(
+                c.spend_bundle
+                    .coin_spends
+                    .iter()
+                    .map(|cs| cs.coin.coin_id()),
+            );
+        }
+
+        let (generator, conditions) = match last_run {
+            Some(ret) => ret,
+            None => run_generator(&selected, flags, max_cost, constants)?,
+        };
+
+        let mut signature = Signature::default();
+        for c in &selected {
+            signature.aggregate(&c.spend_bundle.aggregated_signature);
+        }
+
+        Ok(BuiltBlock {
+            generator,
+            signature,
+            conditions,
+            included: selected.iter().map(|c| c.spend_bundle.name()).collect(),
+        })
+    }
+}
+
+// serializes the block generator and runs it, to compute the exact cost and
+// the conditions
+fn run_generator(
+    selected: &[&Candidate],
+    flags: u32,
+    max_cost: u64,
+    constants: &ConsensusConstants,
+) -> Result<(Vec<u8>, OwnedSpendBundleConditions), ErrorCode> {
+    let spends = selected.iter().flat_map(|c| {
+        c.spend_bundle
+            .coin_spends
+            .iter()
+            .map(|cs| (cs.coin, cs.puzzle_reveal.as_slice(), cs.solution.as_slice()))
+    });
+    let generator = if (flags & ALLOW_BACKREFS) != 0 {
+        solution_generator_backrefs(spends)
+    } else {
+        solution_generator(spends)
+    }
+    .map_err(|_| ErrorCode::InvalidSpendBundle)?;
+
+    let mut a = make_allocator(LIMIT_HEAP);
+    let conds = run_block_generator2::<&[u8], EmptyVisitor>(
+        &mut a,
+        &generator,
+        &[],
+        max_cost,
+        flags,
+        constants,
+    )
+    .map_err(|e| e.1)?;
+    Ok((generator, OwnedSpendBundleConditions::from(&a, conds)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::opcodes::CREATE_COIN;
+    use crate::gen::validate_spend_bundle::validate_spend_bundle;
+    use chia_protocol::{Coin, CoinSpend, Program};
+    use clvm_traits::ToClvm;
+    use clvm_utils::tree_hash_from_bytes;
+    use clvmr::serde::node_to_bytes;
+    use clvmr::Allocator;
+    use rstest::rstest;
+
+    // a spend bundle with a single spend, whose puzzle creates num_coins
+    // output coins. All bundles share the same puzzle, which makes them
+    // compress well with back references
+    fn make_bundle(parent: u8, num_coins: u64) -> (SpendBundle, u64, u64) {
+        let mut a = Allocator::new();
+        let conds: Vec<_> = (0..num_coins)
+            .map(|i| {
+                (CREATE_COIN, (Bytes32::from([0x22; 32]), (i + 1, ())))
+                    .to_clvm(&mut a)
+                    .unwrap()
+            })
+            .collect();
+        let quoted = (1, conds).to_clvm(&mut a).unwrap();
+        let puzzle = Program::new(node_to_bytes(&a, quoted).unwrap().into());
+        let puzzle_hash = tree_hash_from_bytes(puzzle.as_ref()).unwrap();
+        let coin = Coin::new([parent; 32].into(), puzzle_hash.into(), 1_000_000);
+        let bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle, Program::from(vec![0x80]))],
+            Signature::default(),
+        );
+        let (conds, cost) =
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).expect("validate_spend_bundle");
+        let total = cost.byte_cost + cost.execution_cost + cost.condition_cost;
+        assert_eq!(total, conds.cost);
+        let fee = u64::try_from(conds.removal_amount - conds.addition_amount).unwrap();
+        (bundle, conds.cost, fee)
+    }
+
+    #[test]
+    fn test_empty() {
+        let block = BlockBuilder::new()
+            .build(0, &TEST_CONSTANTS)
+            .expect("build");
+        assert!(block.included.is_empty());
+        assert!(block.conditions.spends.is_empty());
+        assert_eq!(block.signature, Signature::default());
+    }
+
+    #[rstest]
+    fn test_build(#[values(0, 5_496_000)] height: u32) {
+        let mut builder = BlockBuilder::new();
+        let mut expected = Vec::new();
+        // the more output coins, the lower the fee
+        for i in 0..10_u8 {
+            let (bundle, cost, fee) = make_bundle(i, u64::from(i) + 1);
+            expected.push(bundle.name());
+            builder.add_spend_bundle(bundle, cost, fee);
+        }
+        assert_eq!(builder.len(), 10);
+        let block = builder.build(height, &TEST_CONSTANTS).expect("build");
+        assert_eq!(block.included, expected);
+        assert_eq!(block.conditions.spends.len(), 10);
+
+        // make sure the generator is valid, and produces the same conditions
+        let flags = get_flags_for_height(height, &TEST_CONSTANTS, false);
+        let mut a = make_allocator(LIMIT_HEAP);
+        let conds = run_block_generator2::<&[u8], EmptyVisitor>(
+            &mut a,
+            &block.generator,
+            &[],
+            TEST_CONSTANTS.max_block_cost_clvm,
+            flags,
+            &TEST_CONSTANTS,
+        )
+        .expect("run_block_generator2");
+        // the order of CREATE_COIN conditions is not deterministic, so we
+        // don't compare the whole conditions object
+        let conds = OwnedSpendBundleConditions::from(&a, conds);
+        assert_eq!(conds.cost, block.conditions.cost);
+        assert_eq!(conds.addition_amount, block.conditions.addition_amount);
+        assert_eq!(conds.removal_amount, block.conditions.removal_amount);
+        let coin_ids = |c: &OwnedSpendBundleConditions| -> Vec<Bytes32> {
+            c.spends.iter().map(|s| s.coin_id).collect()
+        };
+        assert_eq!(coin_ids(&conds), coin_ids(&block.conditions));
+    }
+
+    #[test]
+    fn test_cost_limit() {
+        let bundles: Vec<_> = (0..20_u8).map(|i| make_bundle(i, 10)).collect();
+        let single_cost = bundles[0].1;
+
+        // with back references, the spends compress well (they all share the
+        // same puzzle). So more bundles fit in the block than the sum of their
+        // costs suggests
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.max_block_cost_clvm = single_cost * 5 + single_cost / 2;
+
+        let mut builder = BlockBuilder::new();
+        for (bundle, cost, fee) in &bundles {
+            builder.add_spend_bundle(bundle.clone(), *cost, *fee);
+        }
+        let block = builder.build(5_496_000, &constants).expect("build");
+        assert!(block.included.len() > 5);
+        assert!(block.conditions.cost <= constants.max_block_cost_clvm);
+
+        // without back references, the upper bound is close to the exact
+        // cost
+        let mut builder = BlockBuilder::new();
+        for (bundle, cost, fee) in &bundles {
+            builder.add_spend_bundle(bundle.clone(), *cost, *fee);
+        }
+        let block = builder.build(0, &constants).expect("build");
+        assert_eq!(block.included.len(), 5);
+        assert!(block.conditions.cost <= constants.max_block_cost_clvm);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        // both bundles spend the same coin, only the one with the higher
+        // fee-per-cost is included
+        let (bundle1, cost, fee) = make_bundle(1, 1);
+        let mut bundle2 = bundle1.clone();
+        bundle2.coin_spends[0].solution = Program::from(vec![1_u8]);
+        let mut builder = BlockBuilder::new();
+        builder.add_spend_bundle(bundle1, cost, fee - 1);
+        builder.add_spend_bundle(bundle2.clone(), cost, fee);
+        let block = builder.build(0, &TEST_CONSTANTS).expect("build");
+        assert_eq!(block.included, vec![bundle2.name()]);
+    }
+
+    #[test]
+    fn test_too_expensive() {
+        let (bundle, cost, fee) = make_bundle(1, 1);
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.max_block_cost_clvm = cost - 1;
+        let mut builder = BlockBuilder::new();
+        builder.add_spend_bundle(bundle, cost, fee);
+        let block = builder.build(0, &constants).expect("build");
+        assert!(block.included.is_empty());
+    }
+}
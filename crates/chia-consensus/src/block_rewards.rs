@@ -0,0 +1,358 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::validation_error::ErrorCode;
+use chia_protocol::{Bytes32, Coin, FullBlock};
+use std::collections::HashSet;
+
+const MOJO_PER_CHIA: u64 = 1_000_000_000_000;
+const BLOCKS_PER_YEAR: u32 = 1_681_920; // 32 * 6 * 24 * 365
+
+// 1/8 of the total reward for farming a block at the specified height. The
+// pool gets 7/8 and the farmer gets 1/8 of the total. The genesis block has
+// the pre-farm of 21 million XCH. The reward halves every 3 years, for 12
+// years
+fn reward_eighth(height: u32) -> u64 {
+    if height == 0 {
+        21_000_000 * (MOJO_PER_CHIA / 8)
+    } else if height < 3 * BLOCKS_PER_YEAR {
+        2 * MOJO_PER_CHIA / 8
+    } else if height < 6 * BLOCKS_PER_YEAR {
+        MOJO_PER_CHIA / 8
+    } else if height < 9 * BLOCKS_PER_YEAR {
+        MOJO_PER_CHIA / 16
+    } else if height < 12 * BLOCKS_PER_YEAR {
+        MOJO_PER_CHIA / 32
+    } else {
+        MOJO_PER_CHIA / 64
+    }
+}
+
+// the reward going to the pool (or the farmer, when solo farming) for a
+// block at the specified height
+pub fn calculate_pool_reward(height: u32) -> u64 {
+    reward_eighth(height) * 7
+}
+
+// the reward going to the farmer for a block at the specified height, not
+// including the transaction fees
+pub fn calculate_base_farmer_reward(height: u32) -> u64 {
+    reward_eighth(height)
+}
+
+fn reward_parent_id(prefix: &[u8], height: u32) -> Bytes32 {
+    let mut ret = [0_u8; 32];
+    ret[..16].copy_from_slice(prefix);
+    ret[16..].copy_from_slice(&u128::from(height).to_be_bytes());
+    ret.into()
+}
+
+// the parent coin ID of the pool reward coin for the block at the specified
+// height. It's the first half of the genesis challenge followed by the height
+pub fn pool_parent_id(height: u32, genesis_challenge: &Bytes32) -> Bytes32 {
+    reward_parent_id(&genesis_challenge[..16], height)
+}
+
+// the parent coin ID of the farmer reward coin for the block at the specified
+// height. It's the second half of the genesis challenge followed by the height
+pub fn farmer_parent_id(height: u32, genesis_challenge: &Bytes32) -> Bytes32 {
+    reward_parent_id(&genesis_challenge[16..], height)
+}
+
+pub fn create_pool_coin(
+    height: u32,
+    puzzle_hash: Bytes32,
+    reward: u64,
+    genesis_challenge: &Bytes32,
+) -> Coin {
+    Coin::new(
+        pool_parent_id(height, genesis_challenge),
+        puzzle_hash,
+        reward,
+    )
+}
+
+pub fn create_farmer_coin(
+    height: u32,
+    puzzle_hash: Bytes32,
+    reward: u64,
+    genesis_challenge: &Bytes32,
+) -> Coin {
+    Coin::new(
+        farmer_parent_id(height, genesis_challenge),
+        puzzle_hash,
+        reward,
+    )
+}
+
+// The pool and farmer reward coins created for farming the specified block.
+// For transaction blocks, the farmer coin also includes the transaction fees.
+// These coins are claimed (i.e. added to the coin set) by a later transaction
+// block.
+pub fn block_reward_coins(block: &FullBlock, constants: &ConsensusConstants) -> [Coin; 2] {
+    let height = block.height();
+    let fees = block.transactions_info.as_ref().map_or(0, |ti| ti.fees);
+    let block_data = &block.foliage.foliage_block_data;
+    [
+        create_pool_coin(
+            height,
+            block_data.pool_target.puzzle_hash,
+            calculate_pool_reward(height),
+            &constants.genesis_challenge,
+        ),
+        create_farmer_coin(
+            height,
+            block_data.farmer_reward_puzzle_hash,
+            calculate_base_farmer_reward(height) + fees,
+            &constants.genesis_challenge,
+        ),
+    ]
+}
+
+// The reward coins the specified transaction block is expected to claim.
+// prev_blocks are the ancestors of the block, most recent first. A
+// transaction block claims the rewards of the previous transaction block and
+// of the non-transaction blocks preceding it, back to (but not including) the
+// transaction block before that. The iterator is only advanced as far as
+// needed. The genesis block doesn't claim any rewards.
+pub fn expected_reward_coins<'a, I>(
+    block: &FullBlock,
+    prev_blocks: I,
+    constants: &ConsensusConstants,
+) -> Result<Vec<Coin>, ErrorCode>
+where
+    I: IntoIterator<Item = &'a FullBlock>,
+{
+    let Some(ftb) = &block.foliage_transaction_block else {
+        return Err(ErrorCode::InvalidRewardCoins);
+    };
+    if block.height() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut prev_blocks = prev_blocks
+        .into_iter()
+        .skip_while(|b| !b.is_transaction_block());
+
+    let Some(prev_tx_block) = prev_blocks.next() else {
+        return Err(ErrorCode::InvalidPrevBlockHash);
+    };
+    if prev_tx_block.header_hash() != ftb.prev_transaction_block_hash {
+        return Err(ErrorCode::InvalidPrevBlockHash);
+    }
+
+    let mut ret = Vec::from(block_reward_coins(prev_tx_block, constants));
+    let mut height = prev_tx_block.height();
+    while height > 0 {
+        let Some(b) = prev_blocks.next() else {
+            return Err(ErrorCode::InvalidPrevBlockHash);
+        };
+        if b.height() + 1 != height {
+            return Err(ErrorCode::InvalidPrevBlockHash);
+        }
+        if b.is_transaction_block() {
+            break;
+        }
+        ret.extend(block_reward_coins(b, constants));
+        height = b.height();
+    }
+    Ok(ret)
+}
+
+// Validates that the reward coins claimed by the transaction block are
+// exactly the ones we expect (in any order). See expected_reward_coins()
+pub fn validate_reward_coins<'a, I>(
+    block: &FullBlock,
+    prev_blocks: I,
+    constants: &ConsensusConstants,
+) -> Result<(), ErrorCode>
+where
+    I: IntoIterator<Item = &'a FullBlock>,
+{
+    let expected = expected_reward_coins(block, prev_blocks, constants)?;
+    let claimed = block.get_included_reward_coins();
+    if claimed.len() != expected.len() {
+        return Err(ErrorCode::InvalidRewardCoins);
+    }
+    let expected: HashSet<Coin> = expected.into_iter().collect();
+    if claimed.iter().collect::<HashSet<_>>() != expected.iter().collect() {
+        return Err(ErrorCode::InvalidRewardCoins);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_roots::tests::make_block;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, 18_375_000_000_000_000_000, 2_625_000_000_000_000_000)]
+    #[case(1, 1_750_000_000_000, 250_000_000_000)]
+    #[case(5_045_759, 1_750_000_000_000, 250_000_000_000)]
+    #[case(5_045_760, 875_000_000_000, 125_000_000_000)]
+    #[case(10_091_519, 875_000_000_000, 125_000_000_000)]
+    #[case(10_091_520, 437_500_000_000, 62_500_000_000)]
+    #[case(15_137_280, 218_750_000_000, 31_250_000_000)]
+    #[case(20_183_039, 218_750_000_000, 31_250_000_000)]
+    #[case(20_183_040, 109_375_000_000, 15_625_000_000)]
+    #[case(u32::MAX, 109_375_000_000, 15_625_000_000)]
+    fn test_rewards(#[case] height: u32, #[case] pool: u64, #[case] farmer: u64) {
+        assert_eq!(calculate_pool_reward(height), pool);
+        assert_eq!(calculate_base_farmer_reward(height), farmer);
+    }
+
+    #[test]
+    fn test_reward_coin_parents() {
+        // mainnet genesis challenge
+        let genesis = Bytes32::new(hex!(
+            "ccd5bb71183532bff220ba46c268991a3ff07eb358e8255a65c30a2dce0e5fbb"
+        ));
+        let ph = Bytes32::from([0x11; 32]);
+        let coin = create_pool_coin(0, ph, 1, &genesis);
+        assert_eq!(
+            coin.parent_coin_info,
+            Bytes32::new(hex!(
+                "ccd5bb71183532bff220ba46c268991a00000000000000000000000000000000"
+            ))
+        );
+        assert_eq!(coin.puzzle_hash, ph);
+        assert_eq!(coin.amount, 1);
+        let coin = create_farmer_coin(0x0102_0304, ph, 2, &genesis);
+        assert_eq!(
+            coin.parent_coin_info,
+            Bytes32::new(hex!(
+                "3ff07eb358e8255a65c30a2dce0e5fbb00000000000000000000000001020304"
+            ))
+        );
+        assert_eq!(coin.amount, 2);
+    }
+
+    fn block(height: u32, tx: bool, fees: u64) -> FullBlock {
+        let mut b = make_block(height, Bytes32::default(), Bytes32::default(), Vec::new());
+        b.foliage.foliage_block_data.pool_target.puzzle_hash = [height as u8; 32].into();
+        b.foliage.foliage_block_data.farmer_reward_puzzle_hash = [0x80 | height as u8; 32].into();
+        if tx {
+            b.transactions_info.as_mut().unwrap().fees = fees;
+        } else {
+            b.foliage.foliage_transaction_block_hash = None;
+            b.foliage_transaction_block = None;
+            b.transactions_info = None;
+        }
+        b
+    }
+
+    fn set_prev_tx(b: &mut FullBlock, prev: &FullBlock) {
+        b.foliage_transaction_block
+            .as_mut()
+            .unwrap()
+            .prev_transaction_block_hash = prev.header_hash();
+    }
+
+    #[test]
+    fn test_block_reward_coins() {
+        let [pool, farmer] = block_reward_coins(&block(1, true, 100), &TEST_CONSTANTS);
+        assert_eq!(
+            pool,
+            create_pool_coin(
+                1,
+                [1; 32].into(),
+                1_750_000_000_000,
+                &TEST_CONSTANTS.genesis_challenge
+            )
+        );
+        assert_eq!(
+            farmer,
+            create_farmer_coin(
+                1,
+                [0x81; 32].into(),
+                250_000_000_100,
+                &TEST_CONSTANTS.genesis_challenge
+            )
+        );
+
+        // non-transaction blocks don't have fees
+        let [_, farmer] = block_reward_coins(&block(2, false, 0), &TEST_CONSTANTS);
+        assert_eq!(farmer.amount, 250_000_000_000);
+    }
+
+    #[test]
+    fn test_expected_reward_coins() {
+        // 0 (tx), 1 (tx), 2, 3, 4 (tx), 5, 6 (tx)
+        let b0 = block(0, true, 0);
+        let mut b1 = block(1, true, 10);
+        set_prev_tx(&mut b1, &b0);
+        let b2 = block(2, false, 0);
+        let b3 = block(3, false, 0);
+        let mut b4 = block(4, true, 20);
+        set_prev_tx(&mut b4, &b1);
+        let b5 = block(5, false, 0);
+        let mut b6 = block(6, true, 30);
+        set_prev_tx(&mut b6, &b4);
+
+        let coins = |b: &FullBlock| Vec::from(block_reward_coins(b, &TEST_CONSTANTS));
+
+        assert_eq!(
+            expected_reward_coins(&b0, [], &TEST_CONSTANTS),
+            Ok(Vec::new())
+        );
+        // the block after the genesis block only claims the genesis rewards
+        assert_eq!(
+            expected_reward_coins(&b1, [&b0], &TEST_CONSTANTS),
+            Ok(coins(&b0))
+        );
+        // claims the previous transaction block and the non-transaction blocks
+        // before it, back to the transaction block before that
+        assert_eq!(
+            expected_reward_coins(&b4, [&b3, &b2, &b1, &b0], &TEST_CONSTANTS),
+            Ok(coins(&b1))
+        );
+        assert_eq!(
+            expected_reward_coins(&b6, [&b5, &b4, &b3, &b2, &b1, &b0], &TEST_CONSTANTS),
+            Ok([coins(&b4), coins(&b3), coins(&b2)].concat())
+        );
+
+        // missing ancestors
+        assert_eq!(
+            expected_reward_coins(&b6, [&b5, &b4, &b3], &TEST_CONSTANTS),
+            Err(ErrorCode::InvalidPrevBlockHash)
+        );
+        // wrong previous transaction block
+        assert_eq!(
+            expected_reward_coins(&b6, [&b1, &b0], &TEST_CONSTANTS),
+            Err(ErrorCode::InvalidPrevBlockHash)
+        );
+        // not a transaction block
+        assert_eq!(
+            expected_reward_coins(&b5, [&b4, &b3, &b2, &b1, &b0], &TEST_CONSTANTS),
+            Err(ErrorCode::InvalidRewardCoins)
+        );
+
+        b6.transactions_info
+            .as_mut()
+            .unwrap()
+            .reward_claims_incorporated = [coins(&b2), coins(&b4), coins(&b3)].concat();
+        let prev = [&b5, &b4, &b3, &b2, &b1, &b0];
+        assert_eq!(validate_reward_coins(&b6, prev, &TEST_CONSTANTS), Ok(()));
+
+        b6.transactions_info
+            .as_mut()
+            .unwrap()
+            .reward_claims_incorporated
+            .pop();
+        assert_eq!(
+            validate_reward_coins(&b6, prev, &TEST_CONSTANTS),
+            Err(ErrorCode::InvalidRewardCoins)
+        );
+
+        b6.transactions_info
+            .as_mut()
+            .unwrap()
+            .reward_claims_incorporated = [coins(&b1), coins(&b4), coins(&b3)].concat();
+        assert_eq!(
+            validate_reward_coins(&b6, prev, &TEST_CONSTANTS),
+            Err(ErrorCode::InvalidRewardCoins)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::coin_store::additions_and_removals;
+use crate::gen::owned_conditions::OwnedSpendBundleConditions;
+use crate::gen::validation_error::ErrorCode;
+use crate::merkle_set::compute_merkle_set_root;
+use chia_protocol::{Bytes32, Coin, FullBlock};
+use clvmr::sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// the hash of a list of coin IDs, as used in the additions Merkle set. A
+// single coin ID is hashed on its own, multiple coin IDs are sorted in
+// descending order and hashed together.
+pub fn hash_coin_ids(coin_ids: &mut [Bytes32]) -> Bytes32 {
+    let mut hasher = Sha256::new();
+    if coin_ids.len() > 1 {
+        coin_ids.sort_unstable_by(|a, b| b.cmp(a));
+    }
+    for id in coin_ids.iter() {
+        hasher.update(id);
+    }
+    Bytes32::new(hasher.finalize().into())
+}
+
+// The additions Merkle set contains the puzzle hash of every added coin and,
+// for each puzzle hash, the hash of the coin IDs created with that puzzle
+// hash. additions is expected to include the reward coins.
+pub fn compute_additions_root<'a, I>(additions: I) -> Bytes32
+where
+    I: IntoIterator<Item = &'a Coin>,
+{
+    let mut by_puzzle_hash = HashMap::<Bytes32, Vec<Bytes32>>::new();
+    for coin in additions {
+        by_puzzle_hash
+            .entry(coin.puzzle_hash)
+            .or_default()
+            .push(coin.coin_id());
+    }
+
+    let mut leafs = Vec::<[u8; 32]>::with_capacity(by_puzzle_hash.len() * 2);
+    for (puzzle_hash, mut coin_ids) in by_puzzle_hash {
+        leafs.push(puzzle_hash.into());
+        leafs.push(hash_coin_ids(&mut coin_ids).into());
+    }
+    compute_merkle_set_root(&mut leafs).into()
+}
+
+// The removals Merkle set contains the IDs of all spent coins
+pub fn compute_removals_root(removals: &[Bytes32]) -> Bytes32 {
+    let mut leafs: Vec<[u8; 32]> = removals.iter().map(|id| (*id).into()).collect();
+    compute_merkle_set_root(&mut leafs).into()
+}
+
+// Validates the additions and removals roots of a transaction block, given the
+// conditions from running its generator. The additions include the reward
+// coins claimed by the block. This mirrors the checks in the full node's block
+// body validation. A block without a foliage transaction block has no roots
+// to validate against, and fails with BadAdditionRoot.
+pub fn validate_merkle_roots(
+    block: &FullBlock,
+    conds: &OwnedSpendBundleConditions,
+) -> Result<(), ErrorCode> {
+    let Some(ftb) = &block.foliage_transaction_block else {
+        return Err(ErrorCode::BadAdditionRoot);
+    };
+
+    let (additions, removals) = additions_and_removals(conds);
+    let reward_coins = block
+        .transactions_info
+        .as_ref()
+        .map_or(&[][..], |ti| &ti.reward_claims_incorporated[..]);
+
+    if compute_additions_root(additions.iter().chain(reward_coins)) != ftb.additions_root {
+        return Err(ErrorCode::BadAdditionRoot);
+    }
+    if compute_removals_root(&removals) != ftb.removals_root {
+        return Err(ErrorCode::BadRemovalRoot);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::gen::owned_conditions::OwnedSpend;
+    use chia_bls::{PublicKey, Signature};
+    use chia_protocol::{
+        Bytes, ClassgroupElement, Foliage, FoliageBlockData, FoliageTransactionBlock, PoolTarget,
+        ProofOfSpace, RewardChainBlock, TransactionsInfo, VDFInfo, VDFProof,
+    };
+    use chia_traits::Streamable;
+
+    fn sha256(buf: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(buf);
+        hasher.finalize().into()
+    }
+
+    // a block at the specified height, with the specified roots and reward
+    // claims. All other fields are placeholders
+    pub fn make_block(
+        height: u32,
+        additions_root: Bytes32,
+        removals_root: Bytes32,
+        reward_claims: Vec<Coin>,
+    ) -> FullBlock {
+        let vdf_info = VDFInfo::new(Bytes32::default(), 0, ClassgroupElement::default());
+        let vdf_proof = VDFProof::new(0, Bytes::default(), false);
+        let pos = ProofOfSpace::new(
+            Bytes32::default(),
+            None,
+            Some(Bytes32::default()),
+            PublicKey::default(),
+            32,
+            Bytes::default(),
+        );
+        let rcb = RewardChainBlock::new(
+            0,
+            height,
+            0,
+            0,
+            Bytes32::default(),
+            pos,
+            None,
+            Signature::default(),
+            vdf_info.clone(),
+            None,
+            Signature::default(),
+            vdf_info,
+            None,
+            true,
+        );
+        let ftb = FoliageTransactionBlock::new(
+            Bytes32::default(),
+            0,
+            Bytes32::default(),
+            additions_root,
+            removals_root,
+            Bytes32::default(),
+        );
+        let foliage = Foliage::new(
+            Bytes32::default(),
+            Bytes32::default(),
+            FoliageBlockData::new(
+                Bytes32::default(),
+                PoolTarget::new(Bytes32::default(), 0),
+                None,
+                Bytes32::default(),
+                Bytes32::default(),
+            ),
+            Signature::default(),
+            Some(ftb.hash().into()),
+            Some(Signature::default()),
+        );
+        let ti = TransactionsInfo::new(
+            Bytes32::default(),
+            Bytes32::default(),
+            Signature::default(),
+            0,
+            0,
+            reward_claims,
+        );
+        FullBlock::new(
+            Vec::new(),
+            rcb,
+            None,
+            vdf_proof.clone(),
+            None,
+            vdf_proof,
+            None,
+            foliage,
+            Some(ftb),
+            Some(ti),
+            None,
+            Vec::new(),
+        )
+    }
+
+    fn make_conds(spends: &[(Coin, Vec<Coin>)]) -> OwnedSpendBundleConditions {
+        OwnedSpendBundleConditions::for_spends(
+            spends
+                .iter()
+                .map(|(coin, create_coin)| OwnedSpend::for_coin(coin, create_coin))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_hash_coin_ids() {
+        let id1 = Bytes32::from([1; 32]);
+        let id2 = Bytes32::from([2; 32]);
+        assert_eq!(hash_coin_ids(&mut [id1]), sha256(&[1; 32]).into());
+        let expected: Bytes32 = sha256(&[[2_u8; 32], [1_u8; 32]].concat()).into();
+        assert_eq!(hash_coin_ids(&mut [id1, id2]), expected);
+        assert_eq!(hash_coin_ids(&mut [id2, id1]), expected);
+    }
+
+    #[test]
+    fn test_empty_roots() {
+        let blank: Bytes32 = compute_merkle_set_root(&mut []).into();
+        assert_eq!(compute_additions_root(&[]), blank);
+        assert_eq!(compute_removals_root(&[]), blank);
+
+        let block = make_block(1, blank, blank, Vec::new());
+        assert_eq!(validate_merkle_roots(&block, &make_conds(&[])), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_merkle_roots() {
+        let puzzle_hash1 = Bytes32::from([0x11; 32]);
+        let puzzle_hash2 = Bytes32::from([0x22; 32]);
+        let spent = Coin::new([0xff; 32].into(), puzzle_hash1, 10);
+        let addition1 = Coin::new(spent.coin_id(), puzzle_hash1, 3);
+        let addition2 = Coin::new(spent.coin_id(), puzzle_hash2, 7);
+        // the reward coin is grouped with the addition with the same puzzle
+        // hash
+        let reward = Coin::new([0xee; 32].into(), puzzle_hash2, 1_750_000_000_000);
+        let conds = make_conds(&[(spent, vec![addition1, addition2])]);
+
+        let mut leafs = [
+            puzzle_hash1.into(),
+            sha256(&addition1.coin_id()),
+            puzzle_hash2.into(),
+            hash_coin_ids(&mut [addition2.coin_id(), reward.coin_id()]).into(),
+        ];
+        let additions_root: Bytes32 = compute_merkle_set_root(&mut leafs).into();
+        let removals_root: Bytes32 = compute_merkle_set_root(&mut [spent.coin_id().into()]).into();
+
+        let block = make_block(1, additions_root, removals_root, vec![reward]);
+        assert_eq!(validate_merkle_roots(&block, &conds), Ok(()));
+
+        // without the reward coin
+        let block = make_block(1, additions_root, removals_root, Vec::new());
+        assert_eq!(
+            validate_merkle_roots(&block, &conds),
+            Err(ErrorCode::BadAdditionRoot)
+        );
+
+        let block = make_block(1, additions_root, additions_root, vec![reward]);
+        assert_eq!(
+            validate_merkle_roots(&block, &conds),
+            Err(ErrorCode::BadRemovalRoot)
+        );
+
+        // not a transaction block
+        let mut block = make_block(1, additions_root, removals_root, vec![reward]);
+        block.foliage_transaction_block = None;
+        assert_eq!(
+            validate_merkle_roots(&block, &conds),
+            Err(ErrorCode::BadAdditionRoot)
+        );
+    }
+}
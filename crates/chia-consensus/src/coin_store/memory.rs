@@ -0,0 +1,115 @@
+use super::{validation_error, CoinRecord, CoinStore};
+use crate::error::Result;
+use crate::gen::validation_error::ErrorCode;
+use chia_protocol::{Bytes32, Coin};
+use std::collections::{HashMap, HashSet};
+
+// A CoinStore keeping all coin records in memory
+#[derive(Debug, Default, Clone)]
+pub struct MemoryCoinStore {
+    coins: HashMap<Bytes32, CoinRecord>,
+}
+
+impl MemoryCoinStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+}
+
+impl CoinStore for MemoryCoinStore {
+    fn get_coin_record(&self, coin_id: &Bytes32) -> Result<Option<CoinRecord>> {
+        Ok(self.coins.get(coin_id).copied())
+    }
+
+    fn apply_block(
+        &mut self,
+        height: u32,
+        timestamp: u64,
+        additions: &[Coin],
+        reward_coins: &[Coin],
+        removals: &[Bytes32],
+    ) -> Result<()> {
+        // a coin that already exists must not be overwritten, since that could
+        // make a spent coin unspent again
+        let mut new_coins =
+            HashMap::<Bytes32, CoinRecord>::with_capacity(additions.len() + reward_coins.len());
+        for (coin, coinbase) in additions
+            .iter()
+            .map(|c| (c, false))
+            .chain(reward_coins.iter().map(|c| (c, true)))
+        {
+            let coin_id = coin.coin_id();
+            if self.coins.contains_key(&coin_id) || new_coins.contains_key(&coin_id) {
+                return Err(validation_error(ErrorCode::DuplicateOutput));
+            }
+            new_coins.insert(
+                coin_id,
+                CoinRecord {
+                    coin: *coin,
+                    confirmed_block_index: height,
+                    spent_block_index: 0,
+                    coinbase,
+                    timestamp,
+                },
+            );
+        }
+
+        // validate all removals before we make any changes
+        let mut spent = HashSet::<Bytes32>::with_capacity(removals.len());
+        for coin_id in removals {
+            if !new_coins.contains_key(coin_id) {
+                match self.coins.get(coin_id) {
+                    None => return Err(validation_error(ErrorCode::UnknownUnspent)),
+                    Some(rec) if rec.spent() => {
+                        return Err(validation_error(ErrorCode::DoubleSpend))
+                    }
+                    Some(_) => {}
+                }
+            }
+            if !spent.insert(*coin_id) {
+                return Err(validation_error(ErrorCode::DoubleSpend));
+            }
+        }
+
+        self.coins.extend(new_coins);
+        for coin_id in removals {
+            if let Some(rec) = self.coins.get_mut(coin_id) {
+                rec.spent_block_index = height;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_store::tests::{coin, test_coin_store};
+
+    #[test]
+    fn test_memory_coin_store() {
+        let mut store = MemoryCoinStore::new();
+        assert!(store.is_empty());
+        test_coin_store(&mut store);
+        assert_eq!(store.len(), 5);
+    }
+
+    #[test]
+    fn test_reward_coin() {
+        let mut store = MemoryCoinStore::new();
+        store.apply_block(1, 1000, &[coin(1)], &[], &[]).unwrap();
+        store.apply_block(2, 2000, &[], &[coin(2)], &[]).unwrap();
+        assert_eq!(store.len(), 2);
+        let rec = store.get_coin_record(&coin(2).coin_id()).unwrap().unwrap();
+        assert_eq!(rec.confirmed_block_index, 2);
+        assert!(rec.coinbase);
+    }
+}
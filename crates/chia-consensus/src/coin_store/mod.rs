@@ -0,0 +1,302 @@
+use crate::error::{Error, Result};
+use crate::gen::owned_conditions::OwnedSpendBundleConditions;
+use crate::gen::validation_error::{ErrorCode, ValidationErr};
+use crate::time_locks::CoinConfirmation;
+use chia_protocol::{Bytes32, Coin};
+use clvmr::NodePtr;
+use std::collections::HashMap;
+
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+// The state of a coin in the coin set. spent_block_index is 0 for unspent
+// coins. The timestamp is the timestamp of the block the coin was created in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoinRecord {
+    pub coin: Coin,
+    pub confirmed_block_index: u32,
+    pub spent_block_index: u32,
+    pub coinbase: bool,
+    pub timestamp: u64,
+}
+
+impl CoinRecord {
+    pub fn spent(&self) -> bool {
+        self.spent_block_index > 0
+    }
+}
+
+impl From<&CoinRecord> for CoinConfirmation {
+    fn from(rec: &CoinRecord) -> Self {
+        Self {
+            height: rec.confirmed_block_index,
+            timestamp: rec.timestamp,
+        }
+    }
+}
+
+pub(crate) fn validation_error(code: ErrorCode) -> Error {
+    Error::Validation(ValidationErr(NodePtr::NIL, code))
+}
+
+// The set of coins on the chain, both spent and unspent.
+pub trait CoinStore {
+    fn get_coin_record(&self, coin_id: &Bytes32) -> Result<Option<CoinRecord>>;
+
+    // adds the coins created by a transaction block (including reward coins)
+    // and marks the spent coins as spent at this height. Removals may include
+    // coins created by the same block (ephemeral coins). A removal that's
+    // unknown or already spent, or an addition that already exists, is an
+    // error, and leaves the store unchanged
+    fn apply_block(
+        &mut self,
+        height: u32,
+        timestamp: u64,
+        additions: &[Coin],
+        reward_coins: &[Coin],
+        removals: &[Bytes32],
+    ) -> Result<()>;
+
+    // applies a transaction block based on the conditions from running its
+    // generator
+    fn apply_block_conditions(
+        &mut self,
+        height: u32,
+        timestamp: u64,
+        conds: &OwnedSpendBundleConditions,
+        reward_coins: &[Coin],
+    ) -> Result<()> {
+        let (additions, removals) = additions_and_removals(conds);
+        self.apply_block(height, timestamp, &additions, reward_coins, &removals)
+    }
+}
+
+// returns the coins created and the coin IDs spent by the spends
+pub fn additions_and_removals(conds: &OwnedSpendBundleConditions) -> (Vec<Coin>, Vec<Bytes32>) {
+    let mut additions = Vec::<Coin>::new();
+    let mut removals = Vec::<Bytes32>::with_capacity(conds.spends.len());
+    for spend in &conds.spends {
+        removals.push(spend.coin_id);
+        for (puzzle_hash, amount, _hint) in &spend.create_coin {
+            additions.push(Coin::new(spend.coin_id, *puzzle_hash, *amount));
+        }
+    }
+    (additions, removals)
+}
+
+// Validates the removals of a transaction block (at the specified height and
+// timestamp) against the coin set, as of the previous block. Every spent coin
+// must either exist and be unspent, or be created by the same block
+// (ephemeral). Returns the coin records of all removals. Records of ephemeral
+// coins are confirmed at this block's height and timestamp.
+pub fn validate_removals<S: CoinStore + ?Sized>(
+    store: &S,
+    conds: &OwnedSpendBundleConditions,
+    height: u32,
+    timestamp: u64,
+) -> Result<HashMap<Bytes32, CoinRecord>> {
+    let (additions, _) = additions_and_removals(conds);
+    let additions: HashMap<Bytes32, Coin> =
+        additions.into_iter().map(|c| (c.coin_id(), c)).collect();
+
+    let mut ret = HashMap::<Bytes32, CoinRecord>::with_capacity(conds.spends.len());
+    for spend in &conds.spends {
+        let record = if let Some(coin) = additions.get(&spend.coin_id) {
+            CoinRecord {
+                coin: *coin,
+                confirmed_block_index: height,
+                spent_block_index: 0,
+                coinbase: false,
+                timestamp,
+            }
+        } else {
+            let Some(record) = store.get_coin_record(&spend.coin_id)? else {
+                return Err(validation_error(ErrorCode::UnknownUnspent));
+            };
+            if record.spent() {
+                return Err(validation_error(ErrorCode::DoubleSpend));
+            }
+            record
+        };
+        if ret.insert(spend.coin_id, record).is_some() {
+            return Err(validation_error(ErrorCode::DoubleSpend));
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::gen::owned_conditions::OwnedSpend;
+
+    pub fn coin(idx: u8) -> Coin {
+        Coin::new([idx; 32].into(), [0x22; 32].into(), u64::from(idx) + 1)
+    }
+
+    fn error_code(err: &Error) -> ErrorCode {
+        match err {
+            Error::Validation(ValidationErr(_, code)) => *code,
+            _ => panic!("unexpected error {err}"),
+        }
+    }
+
+    // exercises any CoinStore implementation
+    pub fn test_coin_store<S: CoinStore>(store: &mut S) {
+        let coin1 = coin(1);
+        let coin2 = coin(2);
+        let reward = coin(3);
+        assert_eq!(store.get_coin_record(&coin1.coin_id()).unwrap(), None);
+
+        store
+            .apply_block(1, 1000, &[coin1, coin2], &[reward], &[])
+            .expect("apply_block");
+        let rec = store.get_coin_record(&coin1.coin_id()).unwrap().unwrap();
+        assert_eq!(
+            rec,
+            CoinRecord {
+                coin: coin1,
+                confirmed_block_index: 1,
+                spent_block_index: 0,
+                coinbase: false,
+                timestamp: 1000,
+            }
+        );
+        assert!(!rec.spent());
+        let rec = store.get_coin_record(&reward.coin_id()).unwrap().unwrap();
+        assert!(rec.coinbase);
+
+        // spend coin1, creating coin4, which is also spent in the same block
+        // (ephemeral), creating coin5
+        let coin4 = Coin::new(coin1.coin_id(), [0x44; 32].into(), 1);
+        let coin5 = Coin::new(coin4.coin_id(), [0x55; 32].into(), 1);
+        let conds = OwnedSpendBundleConditions::for_spends(vec![
+            OwnedSpend::for_coin(&coin1, &[coin4]),
+            OwnedSpend::for_coin(&coin4, &[coin5]),
+        ]);
+        let removals = validate_removals(store, &conds, 2, 2000).expect("validate_removals");
+        assert_eq!(removals.len(), 2);
+        assert_eq!(removals[&coin1.coin_id()].confirmed_block_index, 1);
+        assert_eq!(removals[&coin4.coin_id()].confirmed_block_index, 2);
+        assert_eq!(
+            CoinConfirmation::from(&removals[&coin4.coin_id()]),
+            CoinConfirmation {
+                height: 2,
+                timestamp: 2000
+            }
+        );
+
+        store
+            .apply_block_conditions(2, 2000, &conds, &[])
+            .expect("apply_block_conditions");
+        let rec = store.get_coin_record(&coin1.coin_id()).unwrap().unwrap();
+        assert_eq!(rec.spent_block_index, 2);
+        let rec = store.get_coin_record(&coin4.coin_id()).unwrap().unwrap();
+        assert_eq!(rec.confirmed_block_index, 2);
+        assert_eq!(rec.spent_block_index, 2);
+        let rec = store.get_coin_record(&coin5.coin_id()).unwrap().unwrap();
+        assert!(!rec.spent());
+
+        // coin1 has already been spent
+        assert_eq!(
+            error_code(&validate_removals(store, &conds, 3, 3000).unwrap_err()),
+            ErrorCode::DoubleSpend
+        );
+        assert_eq!(
+            error_code(
+                &store
+                    .apply_block(3, 3000, &[], &[], &[coin2.coin_id(), coin1.coin_id()])
+                    .unwrap_err()
+            ),
+            ErrorCode::DoubleSpend
+        );
+        // the failed block did not spend coin2
+        let rec = store.get_coin_record(&coin2.coin_id()).unwrap().unwrap();
+        assert!(!rec.spent());
+
+        // adding a coin that already exists, spent or not, must not reset it
+        for existing in [coin1, coin2] {
+            assert_eq!(
+                error_code(
+                    &store
+                        .apply_block(3, 3000, &[existing], &[], &[])
+                        .unwrap_err()
+                ),
+                ErrorCode::DuplicateOutput
+            );
+            assert_eq!(
+                error_code(
+                    &store
+                        .apply_block(3, 3000, &[], &[existing], &[])
+                        .unwrap_err()
+                ),
+                ErrorCode::DuplicateOutput
+            );
+        }
+        let rec = store.get_coin_record(&coin1.coin_id()).unwrap().unwrap();
+        assert_eq!(rec.spent_block_index, 2);
+        assert_eq!(rec.confirmed_block_index, 1);
+
+        // the same coin added twice in the same block. The block is rejected
+        // as a whole
+        let coin6 = coin(6);
+        assert_eq!(
+            error_code(
+                &store
+                    .apply_block(3, 3000, &[coin6], &[coin6], &[])
+                    .unwrap_err()
+            ),
+            ErrorCode::DuplicateOutput
+        );
+        assert_eq!(store.get_coin_record(&coin6.coin_id()).unwrap(), None);
+
+        // unknown coin
+        let conds =
+            OwnedSpendBundleConditions::for_spends(vec![OwnedSpend::for_coin(&coin(9), &[])]);
+        assert_eq!(
+            error_code(&validate_removals(store, &conds, 3, 3000).unwrap_err()),
+            ErrorCode::UnknownUnspent
+        );
+        assert_eq!(
+            error_code(
+                &store
+                    .apply_block(3, 3000, &[], &[], &[coin(9).coin_id()])
+                    .unwrap_err()
+            ),
+            ErrorCode::UnknownUnspent
+        );
+
+        // the same coin spent twice in the same block
+        let conds = OwnedSpendBundleConditions::for_spends(vec![
+            OwnedSpend::for_coin(&coin2, &[]),
+            OwnedSpend::for_coin(&coin2, &[]),
+        ]);
+        assert_eq!(
+            error_code(&validate_removals(store, &conds, 3, 3000).unwrap_err()),
+            ErrorCode::DoubleSpend
+        );
+        assert_eq!(
+            error_code(
+                &store
+                    .apply_block(3, 3000, &[], &[], &[coin2.coin_id(), coin2.coin_id()])
+                    .unwrap_err()
+            ),
+            ErrorCode::DoubleSpend
+        );
+    }
+
+    #[test]
+    fn test_additions_and_removals() {
+        let coin1 = coin(1);
+        let coin2 = Coin::new(coin1.coin_id(), [0x44; 32].into(), 1);
+        let coin3 = Coin::new(coin1.coin_id(), [0x55; 32].into(), 2);
+        let conds = OwnedSpendBundleConditions::for_spends(vec![OwnedSpend::for_coin(
+            &coin1,
+            &[coin2, coin3],
+        )]);
+        let (additions, removals) = additions_and_removals(&conds);
+        assert_eq!(additions, vec![coin2, coin3]);
+        assert_eq!(removals, vec![coin1.coin_id()]);
+    }
+}
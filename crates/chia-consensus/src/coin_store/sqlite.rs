@@ -0,0 +1,218 @@
+use super::{validation_error, CoinRecord, CoinStore};
+use crate::error::{Error, Result};
+use crate::gen::validation_error::ErrorCode;
+use chia_protocol::{Bytes32, Coin};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::path::Path;
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Database(err.to_string())
+    }
+}
+
+// the schema matches the coin_record table in the full node's database (v2).
+// The amount is stored as an 8 byte big-endian blob
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS coin_record(
+    coin_name blob PRIMARY KEY,
+    confirmed_index bigint,
+    spent_index bigint,
+    coinbase int,
+    puzzle_hash blob,
+    coin_parent blob,
+    amount blob,
+    timestamp bigint)";
+
+// A CoinStore backed by an SQLite database
+pub struct SqliteCoinStore {
+    conn: Connection,
+}
+
+impl SqliteCoinStore {
+    pub fn new(conn: Connection) -> Result<Self> {
+        conn.execute(CREATE_TABLE, [])?;
+        Ok(Self { conn })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+fn to_bytes32(blob: &[u8]) -> Result<Bytes32> {
+    let bytes: [u8; 32] = blob
+        .try_into()
+        .map_err(|_| Error::Database("invalid hash in coin_record".to_string()))?;
+    Ok(bytes.into())
+}
+
+fn get_record(conn: &Connection, coin_id: &Bytes32) -> Result<Option<CoinRecord>> {
+    let row = conn
+        .query_row(
+            "SELECT confirmed_index, spent_index, coinbase, puzzle_hash, coin_parent, amount, timestamp \
+             FROM coin_record WHERE coin_name=?",
+            params![coin_id.as_ref()],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, bool>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                    row.get::<_, Vec<u8>>(5)?,
+                    row.get::<_, u64>(6)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((confirmed, spent, coinbase, puzzle_hash, parent, amount, timestamp)) = row else {
+        return Ok(None);
+    };
+    // the block indices are stored as bigint, but heights are 32 bits
+    let to_height = |index: i64| {
+        u32::try_from(index)
+            .map_err(|_| Error::Database("invalid block index in coin_record".to_string()))
+    };
+    let amount: [u8; 8] = amount
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::Database("invalid amount in coin_record".to_string()))?;
+    Ok(Some(CoinRecord {
+        coin: Coin::new(
+            to_bytes32(&parent)?,
+            to_bytes32(&puzzle_hash)?,
+            u64::from_be_bytes(amount),
+        ),
+        confirmed_block_index: to_height(confirmed)?,
+        spent_block_index: to_height(spent)?,
+        coinbase,
+        timestamp,
+    }))
+}
+
+fn insert_coins(
+    tx: &Transaction<'_>,
+    height: u32,
+    timestamp: u64,
+    coins: &[Coin],
+    coinbase: bool,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached("INSERT INTO coin_record VALUES(?, ?, 0, ?, ?, ?, ?, ?)")?;
+    for coin in coins {
+        // a coin that already exists must not be overwritten, since that
+        // could make a spent coin unspent again
+        let coin_id = coin.coin_id();
+        if get_record(tx, &coin_id)?.is_some() {
+            return Err(validation_error(ErrorCode::DuplicateOutput));
+        }
+        stmt.execute(params![
+            coin_id.as_ref(),
+            height,
+            coinbase,
+            coin.puzzle_hash.as_ref(),
+            coin.parent_coin_info.as_ref(),
+            coin.amount.to_be_bytes().as_slice(),
+            timestamp,
+        ])?;
+    }
+    Ok(())
+}
+
+impl CoinStore for SqliteCoinStore {
+    fn get_coin_record(&self, coin_id: &Bytes32) -> Result<Option<CoinRecord>> {
+        get_record(&self.conn, coin_id)
+    }
+
+    fn apply_block(
+        &mut self,
+        height: u32,
+        timestamp: u64,
+        additions: &[Coin],
+        reward_coins: &[Coin],
+        removals: &[Bytes32],
+    ) -> Result<()> {
+        // if we fail, the transaction is rolled back when it's dropped
+        let tx = self.conn.transaction()?;
+        insert_coins(&tx, height, timestamp, additions, false)?;
+        insert_coins(&tx, height, timestamp, reward_coins, true)?;
+
+        {
+            // a coin that's removed twice is caught by the spent check, since
+            // we update the record as we go
+            let mut stmt =
+                tx.prepare_cached("UPDATE coin_record SET spent_index=? WHERE coin_name=?")?;
+            for coin_id in removals {
+                match get_record(&tx, coin_id)? {
+                    None => return Err(validation_error(ErrorCode::UnknownUnspent)),
+                    Some(rec) if rec.spent() => {
+                        return Err(validation_error(ErrorCode::DoubleSpend))
+                    }
+                    Some(_) => {}
+                }
+                stmt.execute(params![height, coin_id.as_ref()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_store::tests::{coin, test_coin_store};
+
+    #[test]
+    fn test_sqlite_coin_store() {
+        let mut store = SqliteCoinStore::open_in_memory().expect("open_in_memory");
+        test_coin_store(&mut store);
+        let count: u32 = store
+            .connection()
+            .query_row("SELECT COUNT(*) FROM coin_record", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_invalid_block_index() {
+        let store = SqliteCoinStore::open_in_memory().expect("open_in_memory");
+        let coin = coin(1);
+        store
+            .connection()
+            .execute(
+                "INSERT INTO coin_record VALUES(?, 1, ?, 0, ?, ?, ?, 1000)",
+                params![
+                    coin.coin_id().as_ref(),
+                    i64::from(u32::MAX) + 1,
+                    coin.puzzle_hash.as_ref(),
+                    coin.parent_coin_info.as_ref(),
+                    coin.amount.to_be_bytes().as_slice(),
+                ],
+            )
+            .unwrap();
+        assert!(matches!(
+            store.get_coin_record(&coin.coin_id()),
+            Err(Error::Database(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_amount() {
+        let mut store = SqliteCoinStore::open_in_memory().expect("open_in_memory");
+        let coin = Coin::new(coin(1).parent_coin_info, coin(1).puzzle_hash, u64::MAX);
+        store
+            .apply_block(1, u64::from(u32::MAX) + 1, &[coin], &[], &[])
+            .unwrap();
+        let rec = store.get_coin_record(&coin.coin_id()).unwrap().unwrap();
+        assert_eq!(rec.coin, coin);
+        assert_eq!(rec.timestamp, u64::from(u32::MAX) + 1);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use chia_protocol::Bytes32;
 use chia_streamable_macro::streamable;
 
@@ -144,6 +145,119 @@ pub struct ConsensusConstants {
     plot_filter_32_height: u32,
 }
 
+// a value of a constant, parsed from its string form in config.yaml. Integers
+// are decimal and hashes are hex, with an optional 0x prefix
+trait ConstantValue: Sized {
+    fn parse(value: &str) -> Option<Self>;
+}
+
+macro_rules! impl_constant_value {
+    ($($t:ty),*) => {
+        $(impl ConstantValue for $t {
+            fn parse(value: &str) -> Option<Self> {
+                value.parse().ok()
+            }
+        })*
+    };
+}
+
+impl_constant_value!(u8, u16, u32, u64, u128);
+
+impl ConstantValue for Bytes32 {
+    fn parse(value: &str) -> Option<Self> {
+        let buf = hex::decode(value.strip_prefix("0x").unwrap_or(value)).ok()?;
+        Bytes32::try_from(buf).ok()
+    }
+}
+
+macro_rules! set_constant {
+    ($constants:ident, $name:ident, $value:ident, $($field:ident),* $(,)?) => {
+        match $name.to_lowercase().as_str() {
+            $(stringify!($field) => {
+                $constants.$field = ConstantValue::parse($value)
+                    .ok_or_else(|| Error::InvalidConstant($name.to_string()))?;
+            })*
+            // unknown keys are ignored, like chia does. In particular,
+            // NETWORK_TYPE used to be part of the default network_overrides
+            _ => {}
+        }
+    };
+}
+
+impl ConsensusConstants {
+    // returns a copy of these constants with the specified fields replaced,
+    // the same way network_overrides in chia's config.yaml are applied. Field
+    // names are case insensitive, so both GENESIS_CHALLENGE and
+    // genesis_challenge are accepted. Unknown field names are ignored, values
+    // that don't parse as the field's type are errors.
+    pub fn with_overrides<I, K, V>(&self, overrides: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut ret = self.clone();
+        for (name, value) in overrides {
+            let (name, value) = (name.as_ref(), value.as_ref());
+            set_constant!(
+                ret,
+                name,
+                value,
+                slot_blocks_target,
+                min_blocks_per_challenge_block,
+                max_sub_slot_blocks,
+                num_sps_sub_slot,
+                sub_slot_iters_starting,
+                difficulty_constant_factor,
+                difficulty_starting,
+                difficulty_change_max_factor,
+                sub_epoch_blocks,
+                epoch_blocks,
+                significant_bits,
+                discriminant_size_bits,
+                number_zero_bits_plot_filter,
+                min_plot_size,
+                max_plot_size,
+                sub_slot_time_target,
+                num_sp_intervals_extra,
+                max_future_time2,
+                number_of_timestamps,
+                genesis_challenge,
+                agg_sig_me_additional_data,
+                agg_sig_parent_additional_data,
+                agg_sig_puzzle_additional_data,
+                agg_sig_amount_additional_data,
+                agg_sig_puzzle_amount_additional_data,
+                agg_sig_parent_amount_additional_data,
+                agg_sig_parent_puzzle_additional_data,
+                genesis_pre_farm_pool_puzzle_hash,
+                genesis_pre_farm_farmer_puzzle_hash,
+                max_vdf_witness_size,
+                mempool_block_buffer,
+                max_coin_amount,
+                max_block_cost_clvm,
+                cost_per_byte,
+                weight_proof_threshold,
+                weight_proof_recent_blocks,
+                max_block_count_per_requests,
+                blocks_cache_size,
+                max_generator_size,
+                max_generator_ref_list_size,
+                pool_sub_slot_iters,
+                soft_fork2_height,
+                soft_fork4_height,
+                soft_fork5_height,
+                hard_fork_height,
+                hard_fork_fix_height,
+                plot_filter_128_height,
+                plot_filter_64_height,
+                plot_filter_32_height,
+            );
+        }
+        Ok(ret)
+    }
+}
+
 pub const TEST_CONSTANTS: ConsensusConstants = ConsensusConstants {
     slot_blocks_target: 32,
     min_blocks_per_challenge_block: 16,
@@ -215,3 +329,211 @@ pub const TEST_CONSTANTS: ConsensusConstants = ConsensusConstants {
     plot_filter_64_height: 15_592_000,
     plot_filter_32_height: 20_643_000,
 };
+
+/// The constants of the main network. These are the same as TEST_CONSTANTS,
+/// except for the genesis challenge.
+pub const MAINNET_CONSTANTS: ConsensusConstants = ConsensusConstants {
+    genesis_challenge: Bytes32::new(hex!(
+        "ccd5bb71183532bff220ba46c268991a3ff07eb358e8255a65c30a2dce0e5fbb"
+    )),
+    ..TEST_CONSTANTS
+};
+
+/// The constants of testnet11
+pub const TESTNET11_CONSTANTS: ConsensusConstants = ConsensusConstants {
+    sub_slot_iters_starting: u64::pow(2, 26),
+    difficulty_constant_factor: 10_052_721_566_054,
+    difficulty_starting: 30,
+    sub_epoch_blocks: 170,
+    epoch_blocks: 768,
+    min_plot_size: 18,
+    genesis_challenge: Bytes32::new(hex!(
+        "37a90eb5185a9c4439a91ddc98bbadce7b4feba060d50116a067de66bf236615"
+    )),
+    agg_sig_me_additional_data: Bytes32::new(hex!(
+        "37a90eb5185a9c4439a91ddc98bbadce7b4feba060d50116a067de66bf236615"
+    )),
+    agg_sig_parent_additional_data: Bytes32::new(hex!(
+        "c0754ae8602c47489b5394af8972c58238c4389d715f0585ca512d9428395e62"
+    )),
+    agg_sig_puzzle_additional_data: Bytes32::new(hex!(
+        "2e63e4ca0796d9ef8e8a748d740f4b8632c4d994ad6cce51bd61a6612d602697"
+    )),
+    agg_sig_amount_additional_data: Bytes32::new(hex!(
+        "cf15f86103bee6260b0e020a1ba02bcf61230fe209592543399dcf9267f8dfcc"
+    )),
+    agg_sig_puzzle_amount_additional_data: Bytes32::new(hex!(
+        "02c0ecb453e75bd77823dd0affd3f224d968012a8c6c6c423801cc30dd5eb347"
+    )),
+    agg_sig_parent_amount_additional_data: Bytes32::new(hex!(
+        "fc5eaa82087943fbee8683d42ae7a2a7aac0d4eecd4c98d71c228b9c62bf9497"
+    )),
+    agg_sig_parent_puzzle_additional_data: Bytes32::new(hex!(
+        "54c3ed8017f77354acca4000b40424396a369740e5a504467784f392b961ab37"
+    )),
+    genesis_pre_farm_pool_puzzle_hash: Bytes32::new(hex!(
+        "3ef7c233fc0785f3c0cae5992c1d35e7c955ca37a423571c1607ba392a9d12f7"
+    )),
+    genesis_pre_farm_farmer_puzzle_hash: Bytes32::new(hex!(
+        "08296fc227decd043aee855741444538e4cc9a31772c4d1a9e6242d1e777e42a"
+    )),
+    hard_fork_height: 0,
+    soft_fork4_height: 641_500,
+    soft_fork5_height: 1_340_000,
+    plot_filter_128_height: 6_029_568,
+    plot_filter_64_height: 11_075_328,
+    plot_filter_32_height: 16_121_856,
+    ..TEST_CONSTANTS
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::opcodes::{
+        AGG_SIG_AMOUNT, AGG_SIG_PARENT, AGG_SIG_PARENT_AMOUNT, AGG_SIG_PARENT_PUZZLE,
+        AGG_SIG_PUZZLE, AGG_SIG_PUZZLE_AMOUNT,
+    };
+    use clvmr::sha2::{Digest, Sha256};
+    use rstest::rstest;
+    use std::collections::HashMap;
+
+    fn derive(me: &Bytes32, opcode: u16) -> Bytes32 {
+        let mut hasher = Sha256::new();
+        hasher.update(me);
+        hasher.update(&opcode.to_be_bytes()[1..]);
+        Bytes32::new(hasher.finalize().into())
+    }
+
+    #[rstest]
+    #[case(&MAINNET_CONSTANTS)]
+    #[case(&TESTNET11_CONSTANTS)]
+    fn test_agg_sig_additional_data(#[case] c: &ConsensusConstants) {
+        // by convention, AGG_SIG_ME uses the genesis challenge and the other
+        // AGG_SIG_* conditions are derived from it
+        let me = &c.agg_sig_me_additional_data;
+        assert_eq!(*me, c.genesis_challenge);
+        assert_eq!(c.agg_sig_parent_additional_data, derive(me, AGG_SIG_PARENT));
+        assert_eq!(c.agg_sig_puzzle_additional_data, derive(me, AGG_SIG_PUZZLE));
+        assert_eq!(c.agg_sig_amount_additional_data, derive(me, AGG_SIG_AMOUNT));
+        assert_eq!(
+            c.agg_sig_puzzle_amount_additional_data,
+            derive(me, AGG_SIG_PUZZLE_AMOUNT)
+        );
+        assert_eq!(
+            c.agg_sig_parent_amount_additional_data,
+            derive(me, AGG_SIG_PARENT_AMOUNT)
+        );
+        assert_eq!(
+            c.agg_sig_parent_puzzle_additional_data,
+            derive(me, AGG_SIG_PARENT_PUZZLE)
+        );
+    }
+
+    #[test]
+    fn test_testnet11_overrides() {
+        // the network_overrides for testnet11, as they appear in config.yaml
+        let genesis = "37a90eb5185a9c4439a91ddc98bbadce7b4feba060d50116a067de66bf236615";
+        let mut overrides = HashMap::from([
+            ("MIN_PLOT_SIZE", "18".to_string()),
+            ("GENESIS_CHALLENGE", genesis.to_string()),
+            (
+                "GENESIS_PRE_FARM_POOL_PUZZLE_HASH",
+                "3ef7c233fc0785f3c0cae5992c1d35e7c955ca37a423571c1607ba392a9d12f7".to_string(),
+            ),
+            (
+                "GENESIS_PRE_FARM_FARMER_PUZZLE_HASH",
+                "08296fc227decd043aee855741444538e4cc9a31772c4d1a9e6242d1e777e42a".to_string(),
+            ),
+            ("MEMPOOL_BLOCK_BUFFER", "10".to_string()),
+            ("EPOCH_BLOCKS", "768".to_string()),
+            ("DIFFICULTY_STARTING", "30".to_string()),
+            ("DIFFICULTY_CONSTANT_FACTOR", "10052721566054".to_string()),
+            ("SUB_EPOCH_BLOCKS", "170".to_string()),
+            ("SUB_SLOT_ITERS_STARTING", "67108864".to_string()),
+            ("HARD_FORK_HEIGHT", "0".to_string()),
+            ("SOFT_FORK4_HEIGHT", "641500".to_string()),
+            ("SOFT_FORK5_HEIGHT", "1340000".to_string()),
+            ("PLOT_FILTER_128_HEIGHT", "6029568".to_string()),
+            ("PLOT_FILTER_64_HEIGHT", "11075328".to_string()),
+            ("PLOT_FILTER_32_HEIGHT", "16121856".to_string()),
+            ("AGG_SIG_ME_ADDITIONAL_DATA", format!("0x{genesis}")),
+        ]);
+        let me = Bytes32::try_from(hex::decode(genesis).unwrap()).unwrap();
+        for (name, opcode) in [
+            ("AGG_SIG_PARENT_ADDITIONAL_DATA", AGG_SIG_PARENT),
+            ("AGG_SIG_PUZZLE_ADDITIONAL_DATA", AGG_SIG_PUZZLE),
+            ("AGG_SIG_AMOUNT_ADDITIONAL_DATA", AGG_SIG_AMOUNT),
+            (
+                "AGG_SIG_PUZZLE_AMOUNT_ADDITIONAL_DATA",
+                AGG_SIG_PUZZLE_AMOUNT,
+            ),
+            (
+                "AGG_SIG_PARENT_AMOUNT_ADDITIONAL_DATA",
+                AGG_SIG_PARENT_AMOUNT,
+            ),
+            (
+                "AGG_SIG_PARENT_PUZZLE_ADDITIONAL_DATA",
+                AGG_SIG_PARENT_PUZZLE,
+            ),
+        ] {
+            overrides.insert(name, hex::encode(derive(&me, opcode)));
+        }
+
+        let testnet11 = MAINNET_CONSTANTS
+            .with_overrides(&overrides)
+            .expect("with_overrides");
+        assert_eq!(testnet11, TESTNET11_CONSTANTS);
+    }
+
+    #[test]
+    fn test_mainnet_overrides() {
+        let mainnet = TEST_CONSTANTS
+            .with_overrides([(
+                "genesis_challenge",
+                "ccd5bb71183532bff220ba46c268991a3ff07eb358e8255a65c30a2dce0e5fbb",
+            )])
+            .expect("with_overrides");
+        assert_eq!(mainnet, MAINNET_CONSTANTS);
+
+        // no overrides
+        assert_eq!(
+            MAINNET_CONSTANTS
+                .with_overrides(Vec::<(&str, &str)>::new())
+                .expect("with_overrides"),
+            MAINNET_CONSTANTS
+        );
+    }
+
+    #[rstest]
+    #[case("NETWORK_TYPE")]
+    #[case("network_type")]
+    #[case("FOOBAR")]
+    fn test_unknown_overrides(#[case] name: &str) {
+        let constants = MAINNET_CONSTANTS
+            .with_overrides([(name, "1"), ("MIN_PLOT_SIZE", "18")])
+            .expect("with_overrides");
+        assert_eq!(constants.min_plot_size, 18);
+        assert_eq!(
+            ConsensusConstants {
+                min_plot_size: MAINNET_CONSTANTS.min_plot_size,
+                ..constants
+            },
+            MAINNET_CONSTANTS
+        );
+    }
+
+    #[rstest]
+    #[case("MIN_PLOT_SIZE", "256", Error::InvalidConstant("MIN_PLOT_SIZE".to_string()))]
+    #[case("MIN_PLOT_SIZE", "-1", Error::InvalidConstant("MIN_PLOT_SIZE".to_string()))]
+    #[case("MIN_PLOT_SIZE", "0x12", Error::InvalidConstant("MIN_PLOT_SIZE".to_string()))]
+    #[case("GENESIS_CHALLENGE", "abcd", Error::InvalidConstant("GENESIS_CHALLENGE".to_string()))]
+    #[case("GENESIS_CHALLENGE", "1", Error::InvalidConstant("GENESIS_CHALLENGE".to_string()))]
+    fn test_invalid_overrides(#[case] name: &str, #[case] value: &str, #[case] expected: Error) {
+        assert_eq!(
+            MAINNET_CONSTANTS
+                .with_overrides([(name, value)])
+                .unwrap_err(),
+            expected
+        );
+    }
+}
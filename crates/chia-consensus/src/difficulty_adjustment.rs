@@ -0,0 +1,671 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::validation_error::ErrorCode;
+use crate::pot_iterations::sp_total_iters;
+use chia_protocol::{BlockRecord, Bytes32};
+
+// The block records we know about, both in the main chain and in forks.
+pub trait BlockRecords {
+    fn block_record(&self, header_hash: &Bytes32) -> Option<BlockRecord>;
+
+    // the header hash of the block at this height in the main chain. This
+    // lets us look up ancestors by height, rather than walking back one block
+    // at a time. Implementations without a height index can return None
+    fn height_to_hash(&self, height: u32) -> Option<Bytes32>;
+}
+
+pub(crate) fn get_block<B: BlockRecords + ?Sized>(
+    blocks: &B,
+    header_hash: &Bytes32,
+) -> Result<BlockRecord, ErrorCode> {
+    blocks
+        .block_record(header_hash)
+        .ok_or(ErrorCode::ExtendsUnknownBlock)
+}
+
+// the number of bits between the most and least significant set bits
+pub fn count_significant_bits(x: u64) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        64 - x.leading_zeros() - x.trailing_zeros()
+    }
+}
+
+// clears all but the num_significant_bits most significant bits
+pub fn truncate_to_significant_bits(x: u64, num_significant_bits: u8) -> u64 {
+    let bit_length = 64 - x.leading_zeros();
+    let num_significant_bits = u32::from(num_significant_bits);
+    if bit_length <= num_significant_bits {
+        return x;
+    }
+    x & !((1_u64 << (bit_length - num_significant_bits)) - 1)
+}
+
+// the ancestors of prev_b (including prev_b itself) at heights
+// [target_height, target_height + max_num_blocks)
+fn get_blocks_at_height<B: BlockRecords + ?Sized>(
+    blocks: &B,
+    prev_b: &BlockRecord,
+    target_height: u32,
+    max_num_blocks: u32,
+) -> Result<Vec<BlockRecord>, ErrorCode> {
+    let end = std::cmp::min(
+        target_height.saturating_add(max_num_blocks),
+        prev_b.height.saturating_add(1),
+    );
+    if blocks.height_to_hash(prev_b.height) == Some(prev_b.header_hash) {
+        // prev_b is in the main chain, so we can look up its ancestors by
+        // height
+        return (target_height..end)
+            .map(|height| {
+                let hash = blocks
+                    .height_to_hash(height)
+                    .ok_or(ErrorCode::ExtendsUnknownBlock)?;
+                get_block(blocks, &hash)
+            })
+            .collect();
+    }
+
+    let mut ret = Vec::new();
+    let mut curr = prev_b.clone();
+    while curr.height >= target_height {
+        if curr.height < end {
+            ret.push(curr.clone());
+        }
+        if curr.height == 0 {
+            break;
+        }
+        curr = get_block(blocks, &curr.prev_hash)?;
+    }
+    ret.reverse();
+    Ok(ret)
+}
+
+// The second to last transaction block in the epoch before the one last_b is
+// in (or about to finish). The iterations and time since this block are used
+// to adjust the difficulty and sub_slot_iters. For the first epoch, this is
+// the genesis block.
+fn get_second_to_last_transaction_block_in_previous_epoch<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    last_b: &BlockRecord,
+) -> Result<BlockRecord, ErrorCode> {
+    let max_sub_slot_blocks = constants.max_sub_slot_blocks;
+    let height_in_next_epoch = last_b.height
+        + 2 * max_sub_slot_blocks
+        + u32::from(constants.min_blocks_per_challenge_block)
+        + 5;
+    let height_epoch_surpass =
+        height_in_next_epoch - (height_in_next_epoch % constants.epoch_blocks);
+    let height_prev_epoch_surpass = height_epoch_surpass
+        .checked_sub(constants.epoch_blocks)
+        .ok_or(ErrorCode::InvalidHeight)?;
+
+    // sanity check, don't go too far past the epoch barrier
+    if height_in_next_epoch - height_epoch_surpass >= 5 * max_sub_slot_blocks {
+        return Err(ErrorCode::InvalidHeight);
+    }
+
+    if height_prev_epoch_surpass == 0 {
+        // the genesis block is an edge case, where we measure from the first
+        // block in the epoch, as opposed to a block in the previous epoch
+        return get_blocks_at_height(blocks, last_b, 0, 1)?
+            .into_iter()
+            .next()
+            .ok_or(ErrorCode::ExtendsUnknownBlock);
+    }
+
+    // The target block must be in this range. Either the surpass block must
+    // be a transaction block, or something in its sub-slot must be. If that's
+    // the only transaction block in the sub-slot, the last block in the
+    // previous sub-slot must also be a transaction block
+    let fetched_blocks = get_blocks_at_height(
+        blocks,
+        last_b,
+        height_prev_epoch_surpass - max_sub_slot_blocks - 1,
+        3 * max_sub_slot_blocks + u32::from(constants.min_blocks_per_challenge_block) + 3,
+    )?;
+
+    // we want the last block in the sub-slot in which we surpass the height.
+    // The last block in the epoch is before it
+    let mut fetched = fetched_blocks.iter().skip(max_sub_slot_blocks as usize);
+    let mut curr_b = fetched.next().ok_or(ErrorCode::ExtendsUnknownBlock)?;
+    let mut next_b = fetched.next().ok_or(ErrorCode::ExtendsUnknownBlock)?;
+    if curr_b.height != height_prev_epoch_surpass - 1 || next_b.height != height_prev_epoch_surpass
+    {
+        return Err(ErrorCode::InvalidHeight);
+    }
+
+    // wait until the sub-slot finishes with a challenge chain infusion at the
+    // start of a sub-slot. There are no overflow blocks at the start of new
+    // epochs
+    while next_b.sub_epoch_summary_included.is_none() {
+        curr_b = next_b;
+        next_b = fetched.next().ok_or(ErrorCode::InvalidSubEpochSummary)?;
+    }
+
+    // backtrack to find the second to last transaction block
+    let mut curr_b = curr_b.clone();
+    let mut found_tx_blocks = u32::from(curr_b.is_transaction_block());
+    while found_tx_blocks < 2 {
+        curr_b = get_block(blocks, &curr_b.prev_hash)?;
+        if curr_b.is_transaction_block() {
+            found_tx_blocks += 1;
+        }
+    }
+    Ok(curr_b)
+}
+
+pub fn height_can_be_first_in_epoch(constants: &ConsensusConstants, height: u32) -> bool {
+    (height - (height % constants.sub_epoch_blocks)) % constants.epoch_blocks == 0
+}
+
+// Returns whether the sub-slot after the block at height can start a new
+// sub-epoch (i.e. the block at height is the last one in the sub-epoch) and
+// whether it can start a new epoch. prev_header_hash is the header hash of
+// the block before the one at height.
+pub fn can_finish_sub_and_full_epoch<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    height: u32,
+    prev_header_hash: &Bytes32,
+    deficit: u8,
+    block_at_height_included_ses: bool,
+) -> Result<(bool, bool), ErrorCode> {
+    if height + 1 < constants.sub_epoch_blocks || deficit > 0 || block_at_height_included_ses {
+        return Ok((false, false));
+    }
+
+    // if (height + 1) % SUB_EPOCH_BLOCKS is 0, height + 1 is the first height
+    // that can include a sub-epoch summary. If it's 1, the block at height
+    // is the first, and we already checked whether it included one
+    if (height + 1) % constants.sub_epoch_blocks > 1 {
+        let mut curr = get_block(blocks, prev_header_hash)?;
+        while curr.height % constants.sub_epoch_blocks > 0 {
+            if curr.sub_epoch_summary_included.is_some() {
+                return Ok((false, false));
+            }
+            curr = get_block(blocks, &curr.prev_hash)?;
+        }
+        if curr.sub_epoch_summary_included.is_some() {
+            return Ok((false, false));
+        }
+    }
+
+    Ok((true, height_can_be_first_in_epoch(constants, height + 1)))
+}
+
+// limits new_value to within DIFFICULTY_CHANGE_MAX_FACTOR of old_value (and at
+// least min)
+fn clamp_change(constants: &ConsensusConstants, old_value: u64, new_value: u128, min: u64) -> u128 {
+    let factor = u128::from(constants.difficulty_change_max_factor);
+    let old_value = u128::from(old_value);
+    if new_value >= old_value {
+        std::cmp::min(new_value, factor * old_value)
+    } else {
+        [u128::from(min), new_value, old_value / factor]
+            .into_iter()
+            .max()
+            .expect("non-empty")
+    }
+}
+
+// The last transaction block before the signage point of the block after
+// prev_b. If the block at height ends up being a transaction block, this is
+// the second to last transaction block in the epoch. If it's not, there was
+// exactly one other transaction block between its signage point and infusion
+// point, and this is the second to last as well.
+fn last_transaction_block_before_sp<B: BlockRecords + ?Sized>(
+    blocks: &B,
+    prev_b: BlockRecord,
+    signage_point_total_iters: u128,
+) -> Result<BlockRecord, ErrorCode> {
+    let mut curr = prev_b;
+    while curr.total_iters > signage_point_total_iters || !curr.is_transaction_block() {
+        curr = get_block(blocks, &curr.prev_hash)?;
+    }
+    Ok(curr)
+}
+
+fn epoch_time(last_block_curr: &BlockRecord, last_block_prev: &BlockRecord) -> Option<u64> {
+    last_block_curr
+        .timestamp?
+        .checked_sub(last_block_prev.timestamp?)
+        .filter(|t| *t > 0)
+}
+
+// Returns the sub_slot_iters for the block after the one at height, where
+// new_slot is true if that block is in a new sub-slot. The sub_slot_iters
+// only change at the start of an epoch, where they're set based on the
+// iterations per second during the previous epoch.
+#[allow(clippy::too_many_arguments)]
+pub fn get_next_sub_slot_iters<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    prev_header_hash: &Bytes32,
+    height: u32,
+    curr_sub_slot_iters: u64,
+    deficit: u8,
+    block_at_height_included_ses: bool,
+    new_slot: bool,
+    signage_point_total_iters: u128,
+    skip_epoch_check: bool,
+) -> Result<u64, ErrorCode> {
+    let next_height = height + 1;
+    if next_height < constants.epoch_blocks {
+        return Ok(constants.sub_slot_iters_starting);
+    }
+
+    let prev_b = get_block(blocks, prev_header_hash)?;
+
+    if !skip_epoch_check {
+        let (_, can_finish_epoch) = can_finish_sub_and_full_epoch(
+            constants,
+            blocks,
+            height,
+            prev_header_hash,
+            deficit,
+            block_at_height_included_ses,
+        )?;
+        if !new_slot || !can_finish_epoch {
+            return Ok(curr_sub_slot_iters);
+        }
+    }
+
+    let last_block_prev =
+        get_second_to_last_transaction_block_in_previous_epoch(constants, blocks, &prev_b)?;
+    let last_block_curr =
+        last_transaction_block_before_sp(blocks, prev_b, signage_point_total_iters)?;
+    let time =
+        epoch_time(&last_block_curr, &last_block_prev).ok_or(ErrorCode::InvalidNewSubSlotIters)?;
+    let iters = last_block_curr
+        .total_iters
+        .checked_sub(last_block_prev.total_iters)
+        .ok_or(ErrorCode::InvalidNewSubSlotIters)?;
+
+    // the iterations per second during the last epoch, times the target
+    // number of seconds per sub-slot
+    let new_ssi_precise = u128::from(constants.sub_slot_time_target) * iters / u128::from(time);
+    let new_ssi_precise = clamp_change(
+        constants,
+        last_block_curr.sub_slot_iters,
+        new_ssi_precise,
+        u64::from(constants.num_sps_sub_slot),
+    );
+    let new_ssi_precise =
+        u64::try_from(new_ssi_precise).map_err(|_| ErrorCode::InvalidNewSubSlotIters)?;
+
+    // the sub-slot must be divisible into signage points
+    let new_ssi = truncate_to_significant_bits(new_ssi_precise, constants.significant_bits);
+    Ok(new_ssi - new_ssi % u64::from(constants.num_sps_sub_slot))
+}
+
+// Returns the difficulty for the block after the one at height, where
+// new_slot is true if that block is in a new sub-slot. The difficulty only
+// changes at the start of an epoch, where it's set based on the weight added
+// per second during the previous epoch.
+#[allow(clippy::too_many_arguments)]
+pub fn get_next_difficulty<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    prev_header_hash: &Bytes32,
+    height: u32,
+    current_difficulty: u64,
+    deficit: u8,
+    block_at_height_included_ses: bool,
+    new_slot: bool,
+    signage_point_total_iters: u128,
+    skip_epoch_check: bool,
+) -> Result<u64, ErrorCode> {
+    let next_height = height + 1;
+    if next_height
+        < constants
+            .epoch_blocks
+            .saturating_sub(3 * constants.max_sub_slot_blocks)
+    {
+        // we're in the first epoch
+        return Ok(constants.difficulty_starting);
+    }
+
+    let prev_b = get_block(blocks, prev_header_hash)?;
+
+    if !skip_epoch_check {
+        let (_, can_finish_epoch) = can_finish_sub_and_full_epoch(
+            constants,
+            blocks,
+            height,
+            prev_header_hash,
+            deficit,
+            block_at_height_included_ses,
+        )?;
+        if !new_slot || !can_finish_epoch {
+            return Ok(current_difficulty);
+        }
+    }
+
+    let last_block_prev =
+        get_second_to_last_transaction_block_in_previous_epoch(constants, blocks, &prev_b)?;
+    let old_difficulty = prev_b
+        .weight
+        .checked_sub(get_block(blocks, &prev_b.prev_hash)?.weight)
+        .and_then(|d| u64::try_from(d).ok())
+        .ok_or(ErrorCode::InvalidWeight)?;
+    let last_block_curr =
+        last_transaction_block_before_sp(blocks, prev_b, signage_point_total_iters)?;
+    let time =
+        epoch_time(&last_block_curr, &last_block_prev).ok_or(ErrorCode::InvalidNewDifficulty)?;
+    let weight = last_block_curr
+        .weight
+        .checked_sub(last_block_prev.weight)
+        .ok_or(ErrorCode::InvalidWeight)?;
+
+    // the terms are rearranged so there's only one division
+    let new_difficulty_precise = weight * u128::from(constants.sub_slot_time_target)
+        / (u128::from(constants.slot_blocks_target) * u128::from(time));
+    let new_difficulty_precise = clamp_change(constants, old_difficulty, new_difficulty_precise, 1);
+    let new_difficulty_precise =
+        u64::try_from(new_difficulty_precise).map_err(|_| ErrorCode::InvalidNewDifficulty)?;
+    Ok(truncate_to_significant_bits(
+        new_difficulty_precise,
+        constants.significant_bits,
+    ))
+}
+
+// Returns the sub_slot_iters and difficulty of the block after prev_b, which
+// is None for the genesis block.
+pub fn get_next_sub_slot_iters_and_difficulty<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    is_first_in_sub_slot: bool,
+    prev_b: Option<&BlockRecord>,
+    blocks: &B,
+) -> Result<(u64, u64), ErrorCode> {
+    let Some(prev_b) = prev_b else {
+        return Ok((
+            constants.sub_slot_iters_starting,
+            constants.difficulty_starting,
+        ));
+    };
+
+    let prev_difficulty = if prev_b.height == 0 {
+        prev_b.weight
+    } else {
+        prev_b
+            .weight
+            .checked_sub(get_block(blocks, &prev_b.prev_hash)?.weight)
+            .ok_or(ErrorCode::InvalidWeight)?
+    };
+    let prev_difficulty = u64::try_from(prev_difficulty).map_err(|_| ErrorCode::InvalidWeight)?;
+
+    if prev_b.sub_epoch_summary_included.is_some() {
+        return Ok((prev_b.sub_slot_iters, prev_difficulty));
+    }
+
+    let sp_total_iters = sp_total_iters(constants, prev_b)?;
+    let difficulty = get_next_difficulty(
+        constants,
+        blocks,
+        &prev_b.prev_hash,
+        prev_b.height,
+        prev_difficulty,
+        prev_b.deficit,
+        false,
+        is_first_in_sub_slot,
+        sp_total_iters,
+        false,
+    )?;
+    let sub_slot_iters = get_next_sub_slot_iters(
+        constants,
+        blocks,
+        &prev_b.prev_hash,
+        prev_b.height,
+        prev_b.sub_slot_iters,
+        prev_b.deficit,
+        false,
+        is_first_in_sub_slot,
+        sp_total_iters,
+        false,
+    )?;
+    Ok((sub_slot_iters, difficulty))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::pot_iterations::calculate_ip_iters;
+    use crate::pot_iterations::tests::make_block_record;
+    use chia_protocol::SubEpochSummary;
+    use rstest::rstest;
+    use std::collections::HashMap;
+
+    const SSI: u64 = 1 << 27;
+    const REQUIRED_ITERS: u64 = 1000;
+
+    pub struct TestBlocks {
+        by_hash: HashMap<Bytes32, BlockRecord>,
+        main_chain: Vec<Bytes32>,
+        // when false, ancestors are found by walking back one block at a time
+        height_index: bool,
+    }
+
+    impl TestBlocks {
+        // no blocks, and no height index
+        pub fn new() -> Self {
+            Self {
+                by_hash: HashMap::new(),
+                main_chain: Vec::new(),
+                height_index: false,
+            }
+        }
+
+        // adds the block to the main chain
+        pub fn add(&mut self, block: BlockRecord) {
+            self.main_chain.push(block.header_hash);
+            self.by_hash.insert(block.header_hash, block);
+        }
+    }
+
+    impl BlockRecords for TestBlocks {
+        fn block_record(&self, header_hash: &Bytes32) -> Option<BlockRecord> {
+            self.by_hash.get(header_hash).cloned()
+        }
+
+        fn height_to_hash(&self, height: u32) -> Option<Bytes32> {
+            if !self.height_index {
+                return None;
+            }
+            self.main_chain.get(height as usize).copied()
+        }
+    }
+
+    fn constants() -> ConsensusConstants {
+        TEST_CONSTANTS
+            .with_overrides([
+                ("SUB_EPOCH_BLOCKS", "20"),
+                ("EPOCH_BLOCKS", "80"),
+                ("MAX_SUB_SLOT_BLOCKS", "4"),
+                ("MIN_BLOCKS_PER_CHALLENGE_BLOCK", "2"),
+            ])
+            .expect("with_overrides")
+    }
+
+    fn header_hash(height: u32) -> Bytes32 {
+        let mut hash = [0xaa; 32];
+        hash[..4].copy_from_slice(&height.to_be_bytes());
+        hash.into()
+    }
+
+    // a chain of transaction blocks, one per sub-slot and each at signage
+    // point 0, at a constant difficulty and sub_slot_iters. The blocks are
+    // block_time seconds apart. Sub-epoch summaries are included at every
+    // multiple of SUB_EPOCH_BLOCKS
+    fn make_chain(
+        num_blocks: u32,
+        difficulty: u64,
+        block_time: u64,
+        height_index: bool,
+    ) -> TestBlocks {
+        let constants = constants();
+        let ip_iters = calculate_ip_iters(&constants, SSI, 0, REQUIRED_ITERS).unwrap();
+        let mut by_hash = HashMap::new();
+        let mut main_chain = Vec::new();
+        for height in 0..num_blocks {
+            let total_iters = u128::from(height) * u128::from(SSI) + u128::from(ip_iters);
+            let mut block = make_block_record(height, total_iters, SSI, 0, REQUIRED_ITERS);
+            block.header_hash = header_hash(height);
+            block.prev_hash = header_hash(height.wrapping_sub(1));
+            block.weight = u128::from(height + 1) * u128::from(difficulty);
+            block.timestamp = Some(1_700_000_000 + u64::from(height) * block_time);
+            if height > 0 && height % constants.sub_epoch_blocks == 0 {
+                block.sub_epoch_summary_included = Some(SubEpochSummary::new(
+                    Bytes32::default(),
+                    Bytes32::default(),
+                    0,
+                    None,
+                    None,
+                ));
+            }
+            main_chain.push(block.header_hash);
+            by_hash.insert(block.header_hash, block);
+        }
+        TestBlocks {
+            by_hash,
+            main_chain,
+            height_index,
+        }
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(1, 1)]
+    #[case(0b1011_0000, 4)]
+    #[case(0b1000_0001, 8)]
+    #[case(u64::MAX, 64)]
+    fn test_count_significant_bits(#[case] x: u64, #[case] expected: u32) {
+        assert_eq!(count_significant_bits(x), expected);
+    }
+
+    #[rstest]
+    #[case(0, 8, 0)]
+    #[case(255, 8, 255)]
+    #[case(257, 8, 256)]
+    #[case(333, 8, 332)]
+    #[case(0b11_1111_1111, 8, 0b11_1111_1100)]
+    #[case(u64::MAX, 8, 0xff00_0000_0000_0000)]
+    #[case(u64::MAX, 64, u64::MAX)]
+    fn test_truncate_to_significant_bits(#[case] x: u64, #[case] bits: u8, #[case] expected: u64) {
+        let truncated = truncate_to_significant_bits(x, bits);
+        assert_eq!(truncated, expected);
+        assert!(count_significant_bits(truncated) <= u32::from(bits));
+    }
+
+    #[rstest]
+    #[case(0, true)]
+    #[case(19, true)]
+    #[case(20, false)]
+    #[case(79, false)]
+    #[case(80, true)]
+    #[case(99, true)]
+    #[case(100, false)]
+    fn test_height_can_be_first_in_epoch(#[case] height: u32, #[case] expected: bool) {
+        assert_eq!(height_can_be_first_in_epoch(&constants(), height), expected);
+    }
+
+    #[rstest]
+    #[case(10, 0, false, (false, false))]
+    #[case(19, 0, false, (true, false))]
+    #[case(19, 1, false, (false, false))]
+    #[case(19, 0, true, (false, false))]
+    #[case(79, 0, false, (true, true))]
+    // the sub-epoch summary was already included at height 80
+    #[case(81, 0, false, (false, false))]
+    #[case(85, 0, false, (false, false))]
+    fn test_can_finish_sub_and_full_epoch(
+        #[case] height: u32,
+        #[case] deficit: u8,
+        #[case] included_ses: bool,
+        #[case] expected: (bool, bool),
+    ) {
+        let blocks = make_chain(100, 1000, 15, false);
+        assert_eq!(
+            can_finish_sub_and_full_epoch(
+                &constants(),
+                &blocks,
+                height,
+                &header_hash(height - 1),
+                deficit,
+                included_ses,
+            ),
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    // the difficulty increases by 1000 * 600 / (32 * 15), truncated to 8
+    // significant bits. The sub_slot_iters are limited to 3x
+    #[case(15, 1248, 3 * SSI)]
+    // the difficulty decreases, but by at most 3x, and is truncated. The
+    // sub_slot_iters increase by 600 / 400
+    #[case(400, 332, SSI / 2 * 3)]
+    // the sub_slot_iters decrease by at most 3x, and must be divisible by the
+    // number of signage points
+    #[case(5000, 332, (SSI / 3) & !0x3_ffff)]
+    fn test_next_sub_slot_iters_and_difficulty(
+        #[case] block_time: u64,
+        #[case] new_difficulty: u64,
+        #[case] new_ssi: u64,
+        #[values(true, false)] height_index: bool,
+    ) {
+        let constants = constants();
+        let blocks = make_chain(170, 1000, block_time, height_index);
+        let block = |height| blocks.block_record(&header_hash(height)).unwrap();
+        let next = |height, new_slot| {
+            get_next_sub_slot_iters_and_difficulty(
+                &constants,
+                new_slot,
+                Some(&block(height)),
+                &blocks,
+            )
+        };
+
+        assert_eq!(
+            get_next_sub_slot_iters_and_difficulty(&constants, true, None, &blocks),
+            Ok((
+                constants.sub_slot_iters_starting,
+                constants.difficulty_starting
+            ))
+        );
+
+        // the first epoch
+        assert_eq!(
+            next(50, true),
+            Ok((
+                constants.sub_slot_iters_starting,
+                constants.difficulty_starting
+            ))
+        );
+
+        // the end of an epoch, but not in a new sub-slot
+        assert_eq!(next(159, false), Ok((SSI, 1000)));
+        // in the middle of an epoch
+        assert_eq!(next(150, true), Ok((SSI, 1000)));
+        // the block included a sub-epoch summary
+        assert_eq!(next(160, true), Ok((SSI, 1000)));
+
+        // a new epoch. The first one measures from the genesis block
+        assert_eq!(next(159, true), Ok((new_ssi, new_difficulty)));
+        assert_eq!(next(79, true), Ok((new_ssi, new_difficulty)));
+    }
+
+    #[test]
+    fn test_missing_block() {
+        let constants = constants();
+        let mut blocks = make_chain(170, 1000, 15, false);
+        let block = blocks.block_record(&header_hash(159)).unwrap();
+        blocks.by_hash.remove(&header_hash(77));
+        assert_eq!(
+            get_next_sub_slot_iters_and_difficulty(&constants, true, Some(&block), &blocks),
+            Err(ErrorCode::ExtendsUnknownBlock)
+        );
+    }
+}
@@ -47,6 +47,21 @@ pub enum Error {
     #[error("expected lineage proof, found eve proof")]
     ExpectedLineageProof,
 
+    #[error("empty lineage")]
+    EmptyLineage,
+
+    #[error("lineage link {0}: {1}")]
+    Lineage(usize, Box<Error>),
+
+    #[error("invalid value for constant {0}")]
+    InvalidConstant(String),
+
+    #[error("database error {0}")]
+    Database(String),
+
+    #[error("invalid transactions filter")]
+    InvalidTransactionsFilter,
+
     #[error("{0}")]
     Custom(String),
 }
@@ -152,6 +152,64 @@ pub fn fast_forward_singleton(
     Ok(new_solution.to_clvm(a)?)
 }
 
+// one generation in the lineage of a singleton. The coin and the inner puzzle
+// hash of its parent, which is needed to compute the parent's puzzle hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineageLink {
+    pub coin: Coin,
+    pub parent_inner_puzzle_hash: Bytes32,
+}
+
+// like fast_forward_singleton() but rebases the spend across any number of
+// generations. lineage is the chain of singleton coins created since coin,
+// starting with coin's child and ending with the new coin to spend. Every link
+// in the chain is validated against its parent before the spend is rebased
+// onto the last coin. An error in a link is reported as Error::Lineage(),
+// with the index of the link and what failed.
+pub fn fast_forward_singleton_lineage(
+    a: &mut Allocator,
+    puzzle: NodePtr,
+    solution: NodePtr,
+    coin: &Coin,
+    lineage: &[LineageLink],
+) -> Result<NodePtr> {
+    let Some(new_coin) = lineage.last() else {
+        return Err(Error::EmptyLineage);
+    };
+
+    let singleton = CurriedProgram::<NodePtr, SingletonArgs<NodePtr>>::from_clvm(a, puzzle)?;
+    let singleton_struct = &singleton.args.singleton_struct;
+
+    let mut parent = coin;
+    for (index, link) in lineage.iter().enumerate() {
+        let link_err = |err: Error| Error::Lineage(index, Box::new(err));
+
+        if (link.coin.amount & 1) == 0 {
+            return Err(link_err(Error::CoinAmountEven));
+        }
+        // the puzzle hash can't change along the chain, otherwise we would
+        // not be able to fast-forward onto the last coin
+        if link.coin.puzzle_hash != coin.puzzle_hash {
+            return Err(link_err(Error::PuzzleHashMismatch));
+        }
+        if curry_and_treehash(&link.parent_inner_puzzle_hash, singleton_struct)
+            != parent.puzzle_hash
+        {
+            return Err(link_err(Error::InnerPuzzleHashMismatch));
+        }
+        if link.coin.parent_coin_info != parent.coin_id() {
+            return Err(link_err(Error::ParentCoinMismatch));
+        }
+        parent = &link.coin;
+    }
+
+    let new_parent = match lineage {
+        [.., new_parent, _] => &new_parent.coin,
+        _ => coin,
+    };
+    fast_forward_singleton(a, puzzle, solution, coin, &new_coin.coin, new_parent)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -500,4 +558,104 @@ mod tests {
             Error::NotSingletonModHash,
         );
     }
+
+    // builds a lineage of the specified number of generations on top of the
+    // coin in the e3c0 spend. Returns the puzzle, solution, coin and lineage
+    fn make_lineage(
+        a: &mut Allocator,
+        generations: usize,
+    ) -> (NodePtr, NodePtr, Coin, Vec<LineageLink>) {
+        let spend_bytes = fs::read("../../ff-tests/e3c0.spend").expect("read file");
+        let spend = CoinSpend::from_bytes(&spend_bytes).expect("parse CoinSpend");
+        let puzzle = spend.puzzle_reveal.to_node_ptr(a).expect("to_clvm");
+        let solution = spend.solution.to_node_ptr(a).expect("to_clvm");
+        let singleton = CurriedProgram::<NodePtr, SingletonArgs<NodePtr>>::from_clvm(a, puzzle)
+            .expect("uncurry");
+        let inner_puzzle_hash = Bytes32::from(tree_hash(a, singleton.args.inner_puzzle));
+
+        let mut lineage = Vec::<LineageLink>::new();
+        let mut parent = spend.coin;
+        for _ in 0..generations {
+            let coin = Coin::new(parent.coin_id(), spend.coin.puzzle_hash, spend.coin.amount);
+            lineage.push(LineageLink {
+                coin,
+                parent_inner_puzzle_hash: inner_puzzle_hash,
+            });
+            parent = coin;
+        }
+        (puzzle, solution, spend.coin, lineage)
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(2)]
+    #[case(5)]
+    fn test_fast_forward_lineage(#[case] generations: usize) {
+        let mut a = Allocator::new_limited(500_000_000);
+        let (puzzle, solution, coin, lineage) = make_lineage(&mut a, generations);
+
+        let new_solution =
+            fast_forward_singleton_lineage(&mut a, puzzle, solution, &coin, &lineage)
+                .expect("fast-forward");
+        let new_solution = node_to_bytes(&a, new_solution).expect("serialize");
+
+        // the result is the same as fast-forwarding one generation at a time
+        let mut expected = solution;
+        let mut prev = coin;
+        for link in &lineage {
+            expected = fast_forward_singleton(&mut a, puzzle, expected, &prev, &link.coin, &prev)
+                .expect("fast-forward");
+            prev = link.coin;
+        }
+        assert_eq!(
+            new_solution,
+            node_to_bytes(&a, expected).expect("serialize")
+        );
+
+        // and the new spend is valid
+        let new_coin = lineage.last().unwrap().coin;
+        let puzzle = node_to_bytes(&a, puzzle).expect("serialize");
+        run_puzzle::<MempoolVisitor>(
+            &mut a,
+            puzzle.as_slice(),
+            new_solution.as_slice(),
+            &new_coin.parent_coin_info,
+            new_coin.amount,
+            11_000_000_000,
+            0,
+            &TEST_CONSTANTS,
+        )
+        .expect("run_puzzle");
+    }
+
+    #[rstest]
+    #[case(0, |l: &mut LineageLink| l.coin.amount = 2, Error::CoinAmountEven)]
+    #[case(2, |l: &mut LineageLink| l.coin.amount = 2, Error::CoinAmountEven)]
+    #[case(1, |l: &mut LineageLink| l.coin.puzzle_hash = [0xfe; 32].into(), Error::PuzzleHashMismatch)]
+    #[case(1, |l: &mut LineageLink| l.parent_inner_puzzle_hash = [0xfe; 32].into(), Error::InnerPuzzleHashMismatch)]
+    #[case(0, |l: &mut LineageLink| l.parent_inner_puzzle_hash = [0xfe; 32].into(), Error::InnerPuzzleHashMismatch)]
+    #[case(1, |l: &mut LineageLink| l.coin.parent_coin_info = [0xfe; 32].into(), Error::ParentCoinMismatch)]
+    fn test_invalid_lineage(
+        #[case] index: usize,
+        #[case] mutate: fn(&mut LineageLink),
+        #[case] expected: Error,
+    ) {
+        let mut a = Allocator::new_limited(500_000_000);
+        let (puzzle, solution, coin, mut lineage) = make_lineage(&mut a, 3);
+        mutate(&mut lineage[index]);
+        assert_eq!(
+            fast_forward_singleton_lineage(&mut a, puzzle, solution, &coin, &lineage).unwrap_err(),
+            Error::Lineage(index, Box::new(expected))
+        );
+    }
+
+    #[test]
+    fn test_empty_lineage() {
+        let mut a = Allocator::new_limited(500_000_000);
+        let (puzzle, solution, coin, lineage) = make_lineage(&mut a, 0);
+        assert_eq!(
+            fast_forward_singleton_lineage(&mut a, puzzle, solution, &coin, &lineage).unwrap_err(),
+            Error::EmptyLineage
+        );
+    }
 }
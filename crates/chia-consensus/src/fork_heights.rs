@@ -0,0 +1,98 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::flags::{
+    AGG_SIG_ARGS, ALLOW_BACKREFS, DISALLOW_INFINITY_G1, ENABLE_MESSAGE_CONDITIONS,
+    ENABLE_SOFTFORK_CONDITION, MEMPOOL_MODE,
+};
+use clvmr::{ENABLE_BLS_OPS_OUTSIDE_GUARD, ENABLE_FIXED_DIV};
+
+// the flags to pass to run_block_generator() (and friends) for a block at the
+// specified height, based on the soft- and hard-fork activation heights in the
+// constants. In mempool mode, the stricter MEMPOOL_MODE rules are added on
+// top. soft_fork2_height doesn't have a flag, since its rules are enforced
+// unconditionally.
+pub fn get_flags_for_height(height: u32, constants: &ConsensusConstants, mempool: bool) -> u32 {
+    let mut flags: u32 = 0;
+    if height >= constants.soft_fork4_height {
+        flags |= ENABLE_MESSAGE_CONDITIONS;
+    }
+    if height >= constants.soft_fork5_height {
+        flags |= DISALLOW_INFINITY_G1;
+    }
+    if height >= constants.hard_fork_height {
+        flags |= ENABLE_SOFTFORK_CONDITION
+            | ENABLE_BLS_OPS_OUTSIDE_GUARD
+            | ENABLE_FIXED_DIV
+            | AGG_SIG_ARGS
+            | ALLOW_BACKREFS;
+    }
+    if mempool {
+        flags |= MEMPOOL_MODE;
+    }
+    flags
+}
+
+// the number of leading zero bits the plot filter requires at the specified
+// height. The filter is made one bit easier at the hard fork, and then once
+// more at each of the plot filter adjustment heights.
+pub fn get_plot_filter_bits(height: u32, constants: &ConsensusConstants) -> u8 {
+    let bits = constants.number_zero_bits_plot_filter;
+    if height >= constants.plot_filter_32_height {
+        bits.saturating_sub(4)
+    } else if height >= constants.plot_filter_64_height {
+        bits.saturating_sub(3)
+    } else if height >= constants.plot_filter_128_height {
+        bits.saturating_sub(2)
+    } else if height >= constants.hard_fork_height {
+        bits.saturating_sub(1)
+    } else {
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use rstest::rstest;
+
+    const HARD_FORK: u32 = ENABLE_SOFTFORK_CONDITION
+        | ENABLE_BLS_OPS_OUTSIDE_GUARD
+        | ENABLE_FIXED_DIV
+        | AGG_SIG_ARGS
+        | ALLOW_BACKREFS;
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(5_495_999, 0)]
+    #[case(5_496_000, HARD_FORK)]
+    #[case(5_715_999, HARD_FORK)]
+    #[case(5_716_000, HARD_FORK | ENABLE_MESSAGE_CONDITIONS)]
+    #[case(5_939_999, HARD_FORK | ENABLE_MESSAGE_CONDITIONS)]
+    #[case(5_940_000, HARD_FORK | ENABLE_MESSAGE_CONDITIONS | DISALLOW_INFINITY_G1)]
+    #[case(u32::MAX, HARD_FORK | ENABLE_MESSAGE_CONDITIONS | DISALLOW_INFINITY_G1)]
+    fn test_get_flags_for_height(#[case] height: u32, #[case] expected: u32) {
+        assert_eq!(
+            get_flags_for_height(height, &TEST_CONSTANTS, false),
+            expected
+        );
+        assert_eq!(
+            get_flags_for_height(height, &TEST_CONSTANTS, true),
+            expected | MEMPOOL_MODE
+        );
+    }
+
+    #[rstest]
+    #[case(0, 9)]
+    #[case(5_495_999, 9)]
+    #[case(5_496_000, 8)]
+    #[case(10_541_999, 8)]
+    #[case(10_542_000, 7)]
+    #[case(15_591_999, 7)]
+    #[case(15_592_000, 6)]
+    #[case(20_642_999, 6)]
+    #[case(20_643_000, 5)]
+    #[case(u32::MAX, 5)]
+    fn test_get_plot_filter_bits(#[case] height: u32, #[case] expected: u8) {
+        assert_eq!(get_plot_filter_bits(height, &TEST_CONSTANTS), expected);
+    }
+}
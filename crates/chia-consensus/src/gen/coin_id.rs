@@ -2,6 +2,9 @@ use chia_protocol::Bytes32;
 use clvmr::allocator::{Allocator, NodePtr};
 use clvmr::sha2::{Digest, Sha256};
 
+#[cfg(test)]
+use rstest::rstest;
+
 pub fn compute_coin_id(
     a: &Allocator,
     parent_id: NodePtr,
@@ -16,6 +19,31 @@ pub fn compute_coin_id(
     coin_id.into()
 }
 
+// returns the canonical CLVM integer encoding of a coin amount. This is the
+// same representation used when computing coin IDs
+pub fn u64_to_bytes(val: u64) -> Vec<u8> {
+    let amount_bytes = val.to_be_bytes();
+    if val >= 0x8000_0000_0000_0000_u64 {
+        let mut ret = Vec::<u8>::with_capacity(9);
+        ret.push(0_u8);
+        ret.extend_from_slice(&amount_bytes);
+        ret
+    } else {
+        let start = match val {
+            n if n >= 0x0080_0000_0000_0000_u64 => 0,
+            n if n >= 0x8000_0000_0000_u64 => 1,
+            n if n >= 0x0080_0000_0000_u64 => 2,
+            n if n >= 0x8000_0000_u64 => 3,
+            n if n >= 0x0080_0000_u64 => 4,
+            n if n >= 0x8000_u64 => 5,
+            n if n >= 0x80_u64 => 6,
+            n if n > 0 => 7,
+            _ => 8,
+        };
+        amount_bytes[start..].to_vec()
+    }
+}
+
 // from chia.types.blockchain_format.coin import Coin
 // Coin(b"abababababababababababababababab", b"11111111111111111111111111111111", 123).name()
 // <bytes32: d82ed74b945e6a140ffecda9a619c30c323cdf2053a58dae8922c0c15a87646e>
@@ -81,3 +109,16 @@ fn test_compute_coin_id() {
         coin_id
     );
 }
+
+#[cfg(test)]
+#[rstest]
+#[case(0, &[])]
+#[case(1, &[1])]
+#[case(0x7f, &[0x7f])]
+#[case(0x80, &[0, 0x80])]
+#[case(0xff, &[0, 0xff])]
+#[case(0x1234, &[0x12, 0x34])]
+#[case(u64::MAX, &[0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])]
+fn test_u64_to_bytes(#[case] val: u64, #[case] expected: &[u8]) {
+    assert_eq!(u64_to_bytes(val), expected);
+}
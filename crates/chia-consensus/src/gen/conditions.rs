@@ -689,6 +689,11 @@ pub struct Spend {
     pub agg_sig_parent_puzzle: Vec<(PublicKey, NodePtr)>,
     // Flags describing properties of this spend. See flags above
     pub flags: u32,
+    // the CLVM cost of running the puzzle of this spend. This is only known
+    // when the puzzle is run on its own, i.e. not by run_block_generator()
+    pub execution_cost: u64,
+    // the cost of the conditions output by this spend
+    pub condition_cost: u64,
 }
 
 impl Spend {
@@ -718,6 +723,8 @@ impl Spend {
             agg_sig_parent_amount: Vec::new(),
             agg_sig_parent_puzzle: Vec::new(),
             flags: 0,
+            execution_cost: 0,
+            condition_cost: 0,
         }
     }
 }
@@ -727,7 +734,7 @@ impl Spend {
 // spend bundle level, like reserve_fee and absolute time locks. Other
 // conditions are per spend, like relative time-locks and create coins (because
 // they have an implied parent coin ID).
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct SpendBundleConditions {
     pub spends: Vec<Spend>,
     // conditions
@@ -752,6 +759,14 @@ pub struct SpendBundleConditions {
     // the total cost)
     pub cost: u64,
 
+    // the breakdown of cost into its components. cost is the sum of these.
+    // The cost of the block generator's serialized size
+    pub byte_cost: u64,
+    // the CLVM cost of running the generator and all puzzles
+    pub execution_cost: u64,
+    // the sum of condition_cost of all spends
+    pub condition_cost: u64,
+
     // the sum of all values of all spent coins
     pub removal_amount: u128,
 
@@ -759,30 +774,30 @@ pub struct SpendBundleConditions {
     pub addition_amount: u128,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ParseState {
     // hashing of the announcements is deferred until parsing is complete. This
     // means less work up-front, in case parsing/validation fails
-    announce_coin: HashSet<(Arc<Bytes32>, NodePtr)>,
-    announce_puzzle: HashSet<(NodePtr, NodePtr)>,
+    pub(crate) announce_coin: HashSet<(Arc<Bytes32>, NodePtr)>,
+    pub(crate) announce_puzzle: HashSet<(NodePtr, NodePtr)>,
 
     // the assert announcements are checked once everything has been parsed and
     // validated.
-    assert_coin: HashSet<NodePtr>,
-    assert_puzzle: HashSet<NodePtr>,
+    pub(crate) assert_coin: HashSet<NodePtr>,
+    pub(crate) assert_puzzle: HashSet<NodePtr>,
 
     // These are just list of all the messages being sent or received. There's
     // no deduplication. We defer resolving and checking the messages until
     // after we're done parsing all conditions for all spends
-    messages: Vec<Message>,
+    pub(crate) messages: Vec<Message>,
 
     // the assert concurrent spend coin IDs are inserted into this set and
     // checked once everything has been parsed.
-    assert_concurrent_spend: HashSet<NodePtr>,
+    pub(crate) assert_concurrent_spend: HashSet<NodePtr>,
 
     // the assert concurrent puzzle hashes are inserted into this set and
     // checked once everything has been parsed.
-    assert_concurrent_puzzle: HashSet<NodePtr>,
+    pub(crate) assert_concurrent_puzzle: HashSet<NodePtr>,
 
     // all coin IDs that have been spent so far. When we parse a spend we also
     // compute the coin ID, and stick it in this map. It's reference counted
@@ -794,13 +809,13 @@ pub struct ParseState {
     // spent. Note that these are just the node pointers into the allocator, so
     // there may still be duplicates here. We defer creating a hash set of the
     // actual hashes until the end, and only if there are any puzzle assertions
-    spent_puzzles: HashSet<NodePtr>,
+    pub(crate) spent_puzzles: HashSet<NodePtr>,
 
     // we record all coins that assert that they are ephemeral in here. Once
     // we've processed all spends, we ensure that all of these coins were
     // created in this same block
     // each item is the index into the SpendBundleConditions::spends vector
-    assert_ephemeral: HashSet<usize>,
+    pub(crate) assert_ephemeral: HashSet<usize>,
 
     // spends that use relative height- or time conditions are disallowed on
     // ephemeral coins. They are recorded in this set to be be checked once all
@@ -812,7 +827,135 @@ pub struct ParseState {
     // ASSERT_MY_BIRTH_SECONDS
     // ASSERT_MY_BIRTH_HEIGHT
     // each item is the index into the SpendBundleConditions::spends vector
-    assert_not_ephemeral: HashSet<usize>,
+    pub(crate) assert_not_ephemeral: HashSet<usize>,
+
+    // only set when failures are explained (see gen::explain). Records which
+    // spend and condition every check came from
+    trace: Option<Box<ParseTrace>>,
+}
+
+// the nodes of a spend, as passed to process_single_spend()
+#[derive(Debug, Clone, Copy)]
+pub struct TracedSpend {
+    pub parent_id: NodePtr,
+    pub puzzle_hash: NodePtr,
+    pub amount: NodePtr,
+    pub conditions: NodePtr,
+}
+
+// Records where conditions were parsed from, as they are parsed. When parsing
+// fails, spend_index and condition_index identify the spend and condition
+// that failed. Failures that apply to the whole spend bundle have neither.
+// Checks that are deferred until all spends have been parsed remember the
+// spend and condition they came from, to be attributed to them if they fail.
+#[derive(Debug, Default, Clone)]
+pub struct ParseTrace {
+    // all spends passed to process_single_spend(), in order
+    pub spends: Vec<TracedSpend>,
+    pub spend_index: Option<usize>,
+    // the index into the spend's condition list
+    pub condition_index: Option<usize>,
+
+    // maps the asserted announcement, coin ID or puzzle hash to the (spend,
+    // condition) index of the first condition asserting it
+    assertions: HashMap<NodePtr, (usize, usize)>,
+
+    // maps spend index to the first ASSERT_EPHEMERAL condition and the first
+    // relative condition, respectively
+    ephemeral: HashMap<usize, usize>,
+    relative: HashMap<usize, usize>,
+}
+
+impl ParseState {
+    // returns a ParseState that traces where conditions came from
+    pub fn with_trace() -> Self {
+        Self {
+            trace: Some(Box::default()),
+            ..Default::default()
+        }
+    }
+
+    pub fn trace(&self) -> Option<&ParseTrace> {
+        self.trace.as_deref()
+    }
+
+    // records that the spend at index idx is being processed, before its
+    // conditions are known (i.e. while running its puzzle)
+    pub fn trace_spend_index(&mut self, idx: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.spend_index = Some(idx);
+            trace.condition_index = None;
+        }
+    }
+
+    fn trace_spend(&mut self, spend: TracedSpend) {
+        if let Some(trace) = &mut self.trace {
+            trace.spend_index = Some(trace.spends.len());
+            trace.condition_index = None;
+            trace.spends.push(spend);
+        }
+    }
+
+    fn trace_condition(&mut self, idx: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.condition_index = Some(idx);
+        }
+    }
+
+    fn trace_assertion(&mut self, n: NodePtr) {
+        if let Some(trace) = &mut self.trace {
+            if let (Some(spend), Some(cond)) = (trace.spend_index, trace.condition_index) {
+                trace.assertions.entry(n).or_insert((spend, cond));
+            }
+        }
+    }
+
+    fn trace_ephemeral(&mut self, relative: bool) {
+        if let Some(trace) = &mut self.trace {
+            if let (Some(spend), Some(cond)) = (trace.spend_index, trace.condition_index) {
+                let map = if relative {
+                    &mut trace.relative
+                } else {
+                    &mut trace.ephemeral
+                };
+                map.entry(spend).or_insert(cond);
+            }
+        }
+    }
+
+    // the failure is attributed to the whole spend bundle, unless one of the
+    // fail_* functions is called
+    fn trace_bundle(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            trace.spend_index = None;
+            trace.condition_index = None;
+        }
+    }
+}
+
+// these are called when a deferred check fails in validate_conditions(), to
+// attribute the failure to the condition that requested the check. They take
+// the trace rather than the ParseState, since the checks iterate over the
+// state
+fn fail_assertion(trace: &mut Option<Box<ParseTrace>>, n: NodePtr) {
+    if let Some(trace) = trace {
+        if let Some((spend, cond)) = trace.assertions.get(&n) {
+            trace.spend_index = Some(*spend);
+            trace.condition_index = Some(*cond);
+        }
+    }
+}
+
+fn fail_ephemeral(trace: &mut Option<Box<ParseTrace>>, spend: usize, relative: bool) {
+    if let Some(trace) = trace {
+        let map = if relative {
+            &trace.relative
+        } else {
+            &trace.ephemeral
+        };
+        trace.condition_index = map.get(&spend).copied();
+        trace.spend_index = Some(spend);
+    }
 }
 
 // returns (parent-id, puzzle-hash, amount, condition-list)
@@ -844,6 +987,12 @@ pub fn process_single_spend<V: SpendVisitor>(
     max_cost: &mut Cost,
     constants: &ConsensusConstants,
 ) -> Result<(), ValidationErr> {
+    state.trace_spend(TracedSpend {
+        parent_id,
+        puzzle_hash,
+        amount,
+        conditions,
+    });
     let parent_id = sanitize_hash(a, parent_id, 32, ErrorCode::InvalidParentId)?;
     let puzzle_hash = sanitize_hash(a, puzzle_hash, 32, ErrorCode::InvalidPuzzleHash)?;
     let my_amount = parse_amount(a, amount, ErrorCode::InvalidCoinAmount)?;
@@ -893,6 +1042,7 @@ fn assert_not_ephemeral(spend_flags: &mut u32, state: &mut ParseState, idx: usiz
     }
 
     state.assert_not_ephemeral.insert(idx);
+    state.trace_ephemeral(true);
     *spend_flags |= HAS_RELATIVE_CONDITION;
 }
 
@@ -932,9 +1082,13 @@ pub fn parse_conditions<V: SpendVisitor>(
     visitor: &mut V,
 ) -> Result<(), ValidationErr> {
     let mut announce_countdown: u32 = 1024;
+    let cost_before = *max_cost;
+    let mut cond_index = 0;
 
     while let Some((mut c, next)) = next(a, iter)? {
         iter = next;
+        state.trace_condition(cond_index);
+        cond_index += 1;
         let Some(op) = parse_opcode(a, first(a, c)?, flags) else {
             // in strict mode we don't allow unknown conditions
             if (flags & NO_UNKNOWN_CONDS) != 0 {
@@ -1126,6 +1280,7 @@ pub fn parse_conditions<V: SpendVisitor>(
             }
             Condition::AssertEphemeral => {
                 state.assert_ephemeral.insert(ret.spends.len());
+                state.trace_ephemeral(false);
             }
             Condition::AssertMyParentId(id) => {
                 if a.atom(id).as_ref() != a.atom(spend.parent_id).as_ref() {
@@ -1148,18 +1303,22 @@ pub fn parse_conditions<V: SpendVisitor>(
             Condition::AssertCoinAnnouncement(msg) => {
                 decrement(&mut announce_countdown, msg)?;
                 state.assert_coin.insert(msg);
+                state.trace_assertion(msg);
             }
             Condition::AssertPuzzleAnnouncement(msg) => {
                 decrement(&mut announce_countdown, msg)?;
                 state.assert_puzzle.insert(msg);
+                state.trace_assertion(msg);
             }
             Condition::AssertConcurrentSpend(id) => {
                 decrement(&mut announce_countdown, id)?;
                 state.assert_concurrent_spend.insert(id);
+                state.trace_assertion(id);
             }
             Condition::AssertConcurrentPuzzle(id) => {
                 decrement(&mut announce_countdown, id)?;
                 state.assert_concurrent_puzzle.insert(id);
+                state.trace_assertion(id);
             }
             Condition::AggSigMe(pk, msg) => {
                 if let Some(pk) = to_key(a, pk, flags)? {
@@ -1251,11 +1410,13 @@ pub fn parse_conditions<V: SpendVisitor>(
 
     visitor.post_spend(a, &mut spend);
 
+    spend.condition_cost = cost_before - *max_cost;
+    ret.condition_cost += spend.condition_cost;
     ret.spends.push(spend);
     Ok(())
 }
 
-fn is_ephemeral(
+pub(crate) fn is_ephemeral(
     a: &Allocator,
     spend_idx: usize,
     spent_ids: &HashMap<Arc<Bytes32>, usize>,
@@ -1288,9 +1449,28 @@ pub fn parse_spends<V: SpendVisitor>(
     max_cost: Cost,
     flags: u32,
     constants: &ConsensusConstants,
+) -> Result<SpendBundleConditions, ValidationErr> {
+    parse_spends_with_state::<V>(
+        a,
+        spends,
+        max_cost,
+        flags,
+        constants,
+        &mut ParseState::default(),
+    )
+}
+
+// Like parse_spends(), but with the ParseState passed in. This allows the
+// caller to trace where failures come from (see ParseState::with_trace())
+pub fn parse_spends_with_state<V: SpendVisitor>(
+    a: &Allocator,
+    spends: NodePtr,
+    max_cost: Cost,
+    flags: u32,
+    constants: &ConsensusConstants,
+    state: &mut ParseState,
 ) -> Result<SpendBundleConditions, ValidationErr> {
     let mut ret = SpendBundleConditions::default();
-    let mut state = ParseState::default();
 
     let mut cost_left = max_cost;
 
@@ -1307,7 +1487,7 @@ pub fn parse_spends<V: SpendVisitor>(
         process_single_spend::<V>(
             a,
             &mut ret,
-            &mut state,
+            state,
             parent_id,
             puzzle_hash,
             amount,
@@ -1327,10 +1507,12 @@ pub fn parse_spends<V: SpendVisitor>(
 pub fn validate_conditions(
     a: &Allocator,
     ret: &SpendBundleConditions,
-    state: ParseState,
+    state: &mut ParseState,
     spends: NodePtr,
     _flags: u32,
 ) -> Result<(), ValidationErr> {
+    state.trace_bundle();
+
     if ret.removal_amount < ret.addition_amount {
         // The sum of removal amounts must not be less than the sum of addition
         // amounts
@@ -1367,13 +1549,14 @@ pub fn validate_conditions(
     }
 
     // check concurrent spent assertions
-    for coin_id in state.assert_concurrent_spend {
+    for coin_id in &state.assert_concurrent_spend {
         if !state
             .spent_coins
-            .contains_key(&Bytes32::try_from(a.atom(coin_id).as_ref()).unwrap())
+            .contains_key(&Bytes32::try_from(a.atom(*coin_id).as_ref()).unwrap())
         {
+            fail_assertion(&mut state.trace, *coin_id);
             return Err(ValidationErr(
-                coin_id,
+                *coin_id,
                 ErrorCode::AssertConcurrentSpendFailed,
             ));
         }
@@ -1384,14 +1567,15 @@ pub fn validate_conditions(
 
         // expand all the spent puzzle hashes into a set, to allow
         // fast lookups of all assertions
-        for ph in state.spent_puzzles {
-            spent_phs.insert(a.atom(ph).as_ref().try_into().unwrap());
+        for ph in &state.spent_puzzles {
+            spent_phs.insert(a.atom(*ph).as_ref().try_into().unwrap());
         }
 
-        for puzzle_assert in state.assert_concurrent_puzzle {
-            if !spent_phs.contains(&a.atom(puzzle_assert).as_ref().try_into().unwrap()) {
+        for puzzle_assert in &state.assert_concurrent_puzzle {
+            if !spent_phs.contains(&a.atom(*puzzle_assert).as_ref().try_into().unwrap()) {
+                fail_assertion(&mut state.trace, *puzzle_assert);
                 return Err(ValidationErr(
-                    puzzle_assert,
+                    *puzzle_assert,
                     ErrorCode::AssertConcurrentPuzzleFailed,
                 ));
             }
@@ -1403,41 +1587,44 @@ pub fn validate_conditions(
     if !state.assert_coin.is_empty() {
         let mut announcements = HashSet::<Bytes32>::new();
 
-        for (coin_id, announce) in state.announce_coin {
+        for (coin_id, announce) in &state.announce_coin {
             let mut hasher = Sha256::new();
-            hasher.update(*coin_id);
-            hasher.update(a.atom(announce));
+            hasher.update(**coin_id);
+            hasher.update(a.atom(*announce));
             let announcement_id: [u8; 32] = hasher.finalize().into();
             announcements.insert(announcement_id.into());
         }
 
-        for coin_assert in state.assert_coin {
-            if !announcements.contains(&a.atom(coin_assert).as_ref().try_into().unwrap()) {
+        for coin_assert in &state.assert_coin {
+            if !announcements.contains(&a.atom(*coin_assert).as_ref().try_into().unwrap()) {
+                fail_assertion(&mut state.trace, *coin_assert);
                 return Err(ValidationErr(
-                    coin_assert,
+                    *coin_assert,
                     ErrorCode::AssertCoinAnnouncementFailed,
                 ));
             }
         }
     }
 
-    for spend_idx in state.assert_ephemeral {
+    for spend_idx in &state.assert_ephemeral {
         // make sure this coin was created in this block
-        if !is_ephemeral(a, spend_idx, &state.spent_coins, &ret.spends) {
+        if !is_ephemeral(a, *spend_idx, &state.spent_coins, &ret.spends) {
+            fail_ephemeral(&mut state.trace, *spend_idx, false);
             return Err(ValidationErr(
-                ret.spends[spend_idx].parent_id,
+                ret.spends[*spend_idx].parent_id,
                 ErrorCode::AssertEphemeralFailed,
             ));
         }
     }
 
-    for spend_idx in state.assert_not_ephemeral {
+    for spend_idx in &state.assert_not_ephemeral {
         // make sure this coin was NOT created in this block
         // because consensus rules do not allow relative conditions on
         // ephemeral spends
-        if is_ephemeral(a, spend_idx, &state.spent_coins, &ret.spends) {
+        if is_ephemeral(a, *spend_idx, &state.spent_coins, &ret.spends) {
+            fail_ephemeral(&mut state.trace, *spend_idx, true);
             return Err(ValidationErr(
-                ret.spends[spend_idx].parent_id,
+                ret.spends[*spend_idx].parent_id,
                 ErrorCode::EphemeralRelativeCondition,
             ));
         }
@@ -1446,18 +1633,19 @@ pub fn validate_conditions(
     if !state.assert_puzzle.is_empty() {
         let mut announcements = HashSet::<Bytes32>::new();
 
-        for (puzzle_hash, announce) in state.announce_puzzle {
+        for (puzzle_hash, announce) in &state.announce_puzzle {
             let mut hasher = Sha256::new();
-            hasher.update(a.atom(puzzle_hash));
-            hasher.update(a.atom(announce));
+            hasher.update(a.atom(*puzzle_hash));
+            hasher.update(a.atom(*announce));
             let announcement_id: [u8; 32] = hasher.finalize().into();
             announcements.insert(announcement_id.into());
         }
 
-        for puzzle_assert in state.assert_puzzle {
-            if !announcements.contains(&a.atom(puzzle_assert).as_ref().try_into().unwrap()) {
+        for puzzle_assert in &state.assert_puzzle {
+            if !announcements.contains(&a.atom(*puzzle_assert).as_ref().try_into().unwrap()) {
+                fail_assertion(&mut state.trace, *puzzle_assert);
                 return Err(ValidationErr(
-                    puzzle_assert,
+                    *puzzle_assert,
                     ErrorCode::AssertPuzzleAnnouncementFailed,
                 ));
             }
@@ -1492,22 +1680,11 @@ pub fn validate_conditions(
     Ok(())
 }
 
-#[cfg(test)]
-fn u64_to_bytes(n: u64) -> Vec<u8> {
-    let mut buf = Vec::<u8>::new();
-    buf.extend_from_slice(&n.to_be_bytes());
-    if (buf[0] & 0x80) != 0 {
-        buf.insert(0, 0);
-    } else {
-        while buf.len() > 1 && buf[0] == 0 && (buf[1] & 0x80) == 0 {
-            buf.remove(0);
-        }
-    }
-    buf
-}
 #[cfg(test)]
 use crate::consensus_constants::TEST_CONSTANTS;
 #[cfg(test)]
+use crate::gen::coin_id::u64_to_bytes;
+#[cfg(test)]
 use crate::gen::flags::ENABLE_SOFTFORK_CONDITION;
 #[cfg(test)]
 use clvmr::number::Number;
@@ -2676,6 +2853,24 @@ fn test_single_create_coin() {
     assert_eq!(spend.flags, ELIGIBLE_FOR_DEDUP);
 }
 
+#[test]
+fn test_cost_breakdown() {
+    // the cost of the conditions is attributed to the spend that output them
+    let (_a, conds) = cond_test(
+        "((({h1} ({h2} (123 (((51 ({h2} (42 ) ((51 ({h1} (43 ))) (({h2} ({h1} (123 (((51 ({h2} (44 )))))",
+    )
+    .unwrap();
+
+    assert_eq!(conds.spends.len(), 2);
+    assert_eq!(conds.spends[0].condition_cost, 2 * CREATE_COIN_COST);
+    assert_eq!(conds.spends[1].condition_cost, CREATE_COIN_COST);
+    assert_eq!(conds.spends[0].execution_cost, 0);
+    assert_eq!(conds.condition_cost, 3 * CREATE_COIN_COST);
+    assert_eq!(conds.cost, conds.condition_cost);
+    assert_eq!(conds.byte_cost, 0);
+    assert_eq!(conds.execution_cost, 0);
+}
+
 #[test]
 fn test_create_coin_max_amount() {
     // CREATE_COIN
@@ -0,0 +1,683 @@
+use super::coin_id::compute_coin_id;
+use super::condition_sanitizers::parse_amount;
+use super::conditions::{parse_spends_with_state, ParseState, ParseTrace, SpendBundleConditions};
+use super::opcodes::{
+    parse_opcode, ConditionOpcode, ASSERT_COIN_ANNOUNCEMENT, ASSERT_CONCURRENT_PUZZLE,
+    ASSERT_CONCURRENT_SPEND, ASSERT_PUZZLE_ANNOUNCEMENT, CREATE_COIN_ANNOUNCEMENT,
+    CREATE_PUZZLE_ANNOUNCEMENT,
+};
+use super::owned_conditions::OwnedSpendBundleConditions;
+use super::run_block_generator::run_block_generator2_with_state;
+use super::spend_visitor::SpendVisitor;
+use super::validate_spend_bundle::{validate_spend_bundle_with_state, SpendBundleCost};
+use super::validation_error::{first, next, rest, ErrorCode, ValidationErr};
+use crate::allocator::make_allocator;
+use crate::consensus_constants::ConsensusConstants;
+use crate::fork_heights::get_flags_for_height;
+use chia_protocol::{Bytes, Bytes32, SpendBundle};
+use clvmr::allocator::{Allocator, NodePtr, SExp};
+use clvmr::chia_dialect::LIMIT_HEAP;
+use clvmr::cost::Cost;
+use clvmr::serde::node_to_bytes;
+use std::fmt;
+
+// A description of why a spend bundle failed condition validation. It
+// identifies the spend and condition that failed, when the failure can be
+// attributed to one. Failures that apply to the whole bundle (e.g.
+// MintingCoin) don't have a spend index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionFailure {
+    pub error: ErrorCode,
+    // the index of the spend in the spends list
+    pub spend_index: Option<usize>,
+    // None if the spend itself is malformed, and the coin ID can't be computed
+    pub coin_id: Option<Bytes32>,
+    // the index of the condition in the spend's condition list
+    pub condition_index: Option<usize>,
+    // None for unknown condition opcodes
+    pub opcode: Option<ConditionOpcode>,
+    // the condition's arguments. Atoms are included as-is, any argument
+    // that's a pair is included in its serialized form
+    pub args: Vec<Bytes>,
+    pub reason: String,
+}
+
+impl fmt::Display for ConditionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.error)?;
+        if let Some(idx) = self.spend_index {
+            write!(f, " in spend {idx}")?;
+        }
+        if let Some(coin_id) = &self.coin_id {
+            write!(f, " (coin {coin_id})")?;
+        }
+        if let Some(idx) = self.condition_index {
+            write!(f, " condition {idx}")?;
+            if let Some(op) = self.opcode {
+                write!(f, " (opcode {op})")?;
+            }
+            write!(f, " args: [")?;
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", hex::encode(arg))?;
+            }
+            write!(f, "]")?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+impl std::error::Error for ConditionFailure {}
+
+fn to_bytes32(a: &Allocator, n: NodePtr) -> Option<Bytes32> {
+    match a.sexp(n) {
+        SExp::Atom => a.atom(n).as_ref().try_into().ok(),
+        SExp::Pair(..) => None,
+    }
+}
+
+fn args_list(a: &Allocator, mut args: NodePtr) -> Vec<Bytes> {
+    let mut ret = Vec::<Bytes>::new();
+    while let SExp::Pair(arg, tail) = a.sexp(args) {
+        let buf = match a.sexp(arg) {
+            SExp::Atom => a.atom(arg).as_ref().to_vec(),
+            SExp::Pair(..) => node_to_bytes(a, arg).unwrap_or_default(),
+        };
+        ret.push(buf.into());
+        args = tail;
+    }
+    ret
+}
+
+// the properties of the spend bundle we may refer to when explaining a
+// failure
+#[derive(Default)]
+struct BundleSummary {
+    coin_announcements: usize,
+    puzzle_announcements: usize,
+}
+
+struct SpendInfo {
+    coin_id: Option<Bytes32>,
+    parent_id: Option<Bytes32>,
+    puzzle_hash: Option<Bytes32>,
+    amount: Option<u64>,
+}
+
+fn spend_info(a: &Allocator, parent: NodePtr, puzzle_hash: NodePtr, amount: NodePtr) -> SpendInfo {
+    let parent_id = to_bytes32(a, parent);
+    let puzzle_hash_buf = to_bytes32(a, puzzle_hash);
+    let amount_value = parse_amount(a, amount, ErrorCode::InvalidCoinAmount).ok();
+    let coin_id = match (parent_id, puzzle_hash_buf, amount_value) {
+        (Some(_), Some(_), Some(_)) => Some(compute_coin_id(
+            a,
+            parent,
+            puzzle_hash,
+            a.atom(amount).as_ref(),
+        )),
+        _ => None,
+    };
+    SpendInfo {
+        coin_id,
+        parent_id,
+        puzzle_hash: puzzle_hash_buf,
+        amount: amount_value,
+    }
+}
+
+fn reason(
+    a: &Allocator,
+    error: ErrorCode,
+    node: NodePtr,
+    spend: Option<&SpendInfo>,
+    summary: &BundleSummary,
+) -> String {
+    let hex_node = || to_bytes32(a, node).map_or_else(String::new, |h| h.to_string());
+    match (error, spend) {
+        (ErrorCode::AssertCoinAnnouncementFailed, _) => format!(
+            "no coin announcement matches {}. {} coin announcements were created in this spend bundle",
+            hex_node(),
+            summary.coin_announcements
+        ),
+        (ErrorCode::AssertPuzzleAnnouncementFailed, _) => format!(
+            "no puzzle announcement matches {}. {} puzzle announcements were created in this spend bundle",
+            hex_node(),
+            summary.puzzle_announcements
+        ),
+        (ErrorCode::AssertConcurrentSpendFailed, _) => {
+            format!("coin {} is not spent in this spend bundle", hex_node())
+        }
+        (ErrorCode::AssertConcurrentPuzzleFailed, _) => format!(
+            "no coin with puzzle hash {} is spent in this spend bundle",
+            hex_node()
+        ),
+        (ErrorCode::AssertMyCoinIdFailed, Some(SpendInfo { coin_id: Some(id), .. })) => {
+            format!("the coin ID is {id}")
+        }
+        (ErrorCode::AssertMyParentIdFailed, Some(SpendInfo { parent_id: Some(id), .. })) => {
+            format!("the parent coin ID is {id}")
+        }
+        (ErrorCode::AssertMyPuzzleHashFailed, Some(SpendInfo { puzzle_hash: Some(ph), .. })) => {
+            format!("the puzzle hash is {ph}")
+        }
+        (ErrorCode::AssertMyAmountFailed, Some(SpendInfo { amount: Some(amount), .. })) => {
+            format!("the coin amount is {amount}")
+        }
+        (ErrorCode::AssertEphemeralFailed, _) => {
+            "the coin was not created in this spend bundle".to_string()
+        }
+        (ErrorCode::EphemeralRelativeCondition, _) => {
+            "the coin was created in this spend bundle, and can't use relative time locks"
+                .to_string()
+        }
+        (ErrorCode::DoubleSpend, _) => "the coin is spent more than once".to_string(),
+        (ErrorCode::MintingCoin, _) => {
+            "the outputs exceed the value of the spent coins".to_string()
+        }
+        (ErrorCode::ReserveFeeConditionFailed, _) => {
+            "the fee is lower than the sum of all RESERVE_FEE conditions".to_string()
+        }
+        (ErrorCode::MessageNotSentOrReceived, _) => {
+            "a message was sent but not received, or received but not sent".to_string()
+        }
+        (ErrorCode::TooManyAnnouncements, _) => {
+            "the spend has more than 1024 announcements, assertions and messages".to_string()
+        }
+        (ErrorCode::CostExceeded, _) => "the conditions exceed the cost limit".to_string(),
+        (ErrorCode::WrongPuzzleHash, _) => {
+            "the puzzle reveal doesn't match the coin's puzzle hash".to_string()
+        }
+        (ErrorCode::BadAggregateSignature, _) => "the aggregate signature is invalid".to_string(),
+        _ => format!("{error:?}"),
+    }
+}
+
+// returns the condition at index idx in the condition list
+fn nth_condition(a: &Allocator, mut conds: NodePtr, idx: usize) -> Option<NodePtr> {
+    for _ in 0..idx {
+        conds = next(a, conds).ok()??.1;
+    }
+    next(a, conds).ok()?.map(|(c, _)| c)
+}
+
+fn summarize(a: &Allocator, trace: &ParseTrace, flags: u32) -> BundleSummary {
+    let mut summary = BundleSummary::default();
+    for spend in &trace.spends {
+        let mut iter = spend.conditions;
+        while let Ok(Some((c, tail))) = next(a, iter) {
+            iter = tail;
+            match first(a, c).ok().and_then(|op| parse_opcode(a, op, flags)) {
+                Some(CREATE_COIN_ANNOUNCEMENT) => summary.coin_announcements += 1,
+                Some(CREATE_PUZZLE_ANNOUNCEMENT) => summary.puzzle_announcements += 1,
+                _ => {}
+            }
+        }
+    }
+    summary
+}
+
+// Explains a validation error, using the trace recorded while parsing. The
+// trace identifies the spend and condition that failed
+fn explain(a: &Allocator, trace: &ParseTrace, flags: u32, err: ValidationErr) -> ConditionFailure {
+    let ValidationErr(node, error) = err;
+    let summary = summarize(a, trace, flags);
+
+    let Some(spend_index) = trace.spend_index else {
+        return ConditionFailure {
+            error,
+            spend_index: None,
+            coin_id: None,
+            condition_index: None,
+            opcode: None,
+            args: Vec::new(),
+            reason: reason(a, error, node, None, &summary),
+        };
+    };
+
+    // the spend may not have been traced, if its puzzle failed to run
+    let traced = trace.spends.get(spend_index);
+    let info = traced.map(|s| spend_info(a, s.parent_id, s.puzzle_hash, s.amount));
+    let coin_id = info.as_ref().and_then(|i| i.coin_id);
+
+    let condition = trace
+        .condition_index
+        .zip(traced)
+        .and_then(|(idx, s)| Some((idx, nth_condition(a, s.conditions, idx)?)));
+    let (condition_index, opcode, args) = match condition {
+        Some((idx, c)) => {
+            let opcode = first(a, c).ok().and_then(|op| parse_opcode(a, op, flags));
+            let args = rest(a, c).map_or_else(|_| Vec::new(), |args| args_list(a, args));
+            (Some(idx), opcode, args)
+        }
+        None => (None, None, Vec::new()),
+    };
+
+    // the assertions refer to the asserted value, which is more useful than
+    // the spend's own IDs
+    let spend = match opcode {
+        Some(
+            ASSERT_COIN_ANNOUNCEMENT
+            | ASSERT_PUZZLE_ANNOUNCEMENT
+            | ASSERT_CONCURRENT_SPEND
+            | ASSERT_CONCURRENT_PUZZLE,
+        ) => None,
+        _ => info.as_ref(),
+    };
+
+    let reason = if traced.is_none() && error == ErrorCode::GeneratorRuntimeError {
+        "running the puzzle failed".to_string()
+    } else {
+        reason(a, error, node, spend, &summary)
+    };
+
+    ConditionFailure {
+        error,
+        spend_index: Some(spend_index),
+        coin_id,
+        condition_index,
+        opcode,
+        args,
+        reason,
+    }
+}
+
+fn traced(state: &ParseState) -> &ParseTrace {
+    state.trace().expect("ParseState::with_trace()")
+}
+
+// Like parse_spends(), but failures identify the spend and condition that
+// failed
+pub fn parse_spends_explained<V: SpendVisitor>(
+    a: &Allocator,
+    spends: NodePtr,
+    max_cost: Cost,
+    flags: u32,
+    constants: &ConsensusConstants,
+) -> Result<SpendBundleConditions, ConditionFailure> {
+    let mut state = ParseState::with_trace();
+    parse_spends_with_state::<V>(a, spends, max_cost, flags, constants, &mut state)
+        .map_err(|err| explain(a, traced(&state), flags, err))
+}
+
+// Like run_block_generator2(), but failures identify the spend and condition
+// that failed
+pub fn run_block_generator2_explained<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
+    a: &mut Allocator,
+    program: &[u8],
+    block_refs: &[GenBuf],
+    max_cost: u64,
+    flags: u32,
+    constants: &ConsensusConstants,
+) -> Result<SpendBundleConditions, ConditionFailure> {
+    let mut state = ParseState::with_trace();
+    run_block_generator2_with_state::<GenBuf, V>(
+        a, program, block_refs, max_cost, flags, constants, &mut state,
+    )
+    .map_err(|err| explain(a, traced(&state), flags, err))
+}
+
+// Like validate_spend_bundle(), but failures identify the spend and condition
+// that failed. The spend index refers to the spend bundle's coin spends
+pub fn validate_spend_bundle_explained(
+    spend_bundle: &SpendBundle,
+    height: u32,
+    constants: &ConsensusConstants,
+    flags: u32,
+) -> Result<(OwnedSpendBundleConditions, SpendBundleCost), ConditionFailure> {
+    let mut a = make_allocator(LIMIT_HEAP);
+    let mut state = ParseState::with_trace();
+    validate_spend_bundle_with_state(&mut a, spend_bundle, height, constants, flags, &mut state)
+        .map_err(|err| {
+            let flags = get_flags_for_height(height, constants, true) | flags;
+            let mut failure = explain(&a, traced(&state), flags, err);
+            // solution_generator() lists the spends in reverse order
+            let num_spends = spend_bundle.coin_spends.len();
+            if let Some(idx) = failure.spend_index {
+                let idx = num_spends - 1 - idx;
+                failure.spend_index = Some(idx);
+                failure.coin_id = Some(spend_bundle.coin_spends[idx].coin.coin_id());
+            }
+            failure
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::conditions::MempoolVisitor;
+    use crate::gen::flags::MEMPOOL_MODE;
+    use crate::gen::opcodes::{
+        AGG_SIG_ME, ASSERT_EPHEMERAL, ASSERT_MY_AMOUNT, CREATE_COIN, REMARK,
+    };
+    use crate::gen::solution_generator::solution_generator;
+    use chia_bls::Signature;
+    use chia_protocol::{Coin, CoinSpend, Program};
+    use clvm_traits::ToClvm;
+    use clvm_utils::tree_hash;
+
+    const PH: [u8; 32] = [0x22; 32];
+
+    fn coin_id(parent: u8, amount: u64) -> Bytes32 {
+        chia_protocol::Coin::new([parent; 32].into(), PH.into(), amount).coin_id()
+    }
+
+    // builds the spends structure passed to parse_spends(). Each spend is
+    // (parent-byte, amount, conditions)
+    fn make_spends(a: &mut Allocator, spends: &[(u8, u64, NodePtr)]) -> NodePtr {
+        let mut list = NodePtr::NIL;
+        for (parent, amount, conds) in spends.iter().rev() {
+            let spend = (
+                Bytes32::from([*parent; 32]),
+                (Bytes32::from(PH), (*amount, (*conds, ()))),
+            )
+                .to_clvm(a)
+                .unwrap();
+            list = a.new_pair(spend, list).unwrap();
+        }
+        (list, ()).to_clvm(a).unwrap()
+    }
+
+    fn parse_explained(a: &Allocator, spends: NodePtr) -> ConditionFailure {
+        parse_spends_explained::<MempoolVisitor>(
+            a,
+            spends,
+            TEST_CONSTANTS.max_block_cost_clvm,
+            MEMPOOL_MODE,
+            &TEST_CONSTANTS,
+        )
+        .unwrap_err()
+    }
+
+    #[test]
+    fn test_missing_coin_announcement() {
+        let mut a = Allocator::new();
+        let announce = [(CREATE_COIN_ANNOUNCEMENT, (Bytes::from(b"msg".to_vec()), ()))]
+            .to_vec()
+            .to_clvm(&mut a)
+            .unwrap();
+        let asserted = Bytes32::from([0x33; 32]);
+        let assert = [
+            (REMARK, (Bytes::default(), ())),
+            (
+                ASSERT_COIN_ANNOUNCEMENT,
+                (Bytes::from(asserted.to_vec()), ()),
+            ),
+        ]
+        .to_vec()
+        .to_clvm(&mut a)
+        .unwrap();
+        let spends = make_spends(&mut a, &[(1, 1, announce), (2, 2, assert)]);
+
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::AssertCoinAnnouncementFailed);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(coin_id(2, 2)));
+        assert_eq!(failure.condition_index, Some(1));
+        assert_eq!(failure.opcode, Some(ASSERT_COIN_ANNOUNCEMENT));
+        assert_eq!(failure.args, vec![Bytes::from(asserted.to_vec())]);
+        assert_eq!(
+            failure.reason,
+            format!("no coin announcement matches {asserted}. 1 coin announcements were created in this spend bundle")
+        );
+        assert_eq!(
+            failure.to_string(),
+            format!(
+                "AssertCoinAnnouncementFailed in spend 1 (coin {}) condition 1 (opcode 61) args: [{}]: {}",
+                coin_id(2, 2),
+                hex::encode(asserted),
+                failure.reason
+            )
+        );
+    }
+
+    #[test]
+    fn test_assert_my_amount() {
+        let mut a = Allocator::new();
+        let conds = [(ASSERT_MY_AMOUNT, (1337_u64, ()))]
+            .to_vec()
+            .to_clvm(&mut a)
+            .unwrap();
+        let spends = make_spends(&mut a, &[(1, 1, NodePtr::NIL), (2, 1000, conds)]);
+
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::AssertMyAmountFailed);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(coin_id(2, 1000)));
+        assert_eq!(failure.condition_index, Some(0));
+        assert_eq!(failure.opcode, Some(ASSERT_MY_AMOUNT));
+        assert_eq!(failure.args, vec![Bytes::from(vec![0x05, 0x39])]);
+        assert_eq!(failure.reason, "the coin amount is 1000");
+    }
+
+    #[test]
+    fn test_double_spend() {
+        let mut a = Allocator::new();
+        let spends = make_spends(
+            &mut a,
+            &[
+                (1, 1, NodePtr::NIL),
+                (2, 1, NodePtr::NIL),
+                (1, 1, NodePtr::NIL),
+            ],
+        );
+
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::DoubleSpend);
+        assert_eq!(failure.spend_index, Some(2));
+        assert_eq!(failure.coin_id, Some(coin_id(1, 1)));
+        assert_eq!(failure.condition_index, None);
+        assert_eq!(failure.reason, "the coin is spent more than once");
+    }
+
+    #[test]
+    fn test_minting_coin() {
+        let mut a = Allocator::new();
+        let conds = [(CREATE_COIN, (Bytes32::from(PH), (2_u64, ())))]
+            .to_vec()
+            .to_clvm(&mut a)
+            .unwrap();
+        let spends = make_spends(&mut a, &[(1, 1, conds)]);
+
+        // this applies to the whole spend bundle
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::MintingCoin);
+        assert_eq!(failure.spend_index, None);
+        assert_eq!(failure.coin_id, None);
+        assert_eq!(
+            failure.to_string(),
+            "MintingCoin: the outputs exceed the value of the spent coins"
+        );
+    }
+
+    #[test]
+    fn test_success() {
+        let mut a = Allocator::new();
+        let spends = make_spends(&mut a, &[(1, 1, NodePtr::NIL)]);
+        let conds = parse_spends_explained::<MempoolVisitor>(
+            &a,
+            spends,
+            TEST_CONSTANTS.max_block_cost_clvm,
+            MEMPOOL_MODE,
+            &TEST_CONSTANTS,
+        )
+        .expect("parse_spends_explained");
+        assert_eq!(conds.spends.len(), 1);
+    }
+
+    #[test]
+    fn test_shared_small_atom() {
+        let mut a = Allocator::new();
+        // small atoms are not allocated, so both spends refer to the same
+        // NodePtr for 1. Only the second one is an invalid public key
+        let remark = [(REMARK, (1_u64, ()))].to_vec().to_clvm(&mut a).unwrap();
+        let agg_sig = [(AGG_SIG_ME, (1_u64, (Bytes::from(b"msg".to_vec()), ())))]
+            .to_vec()
+            .to_clvm(&mut a)
+            .unwrap();
+        let spends = make_spends(&mut a, &[(1, 1, remark), (2, 1, agg_sig)]);
+
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::InvalidPublicKey);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(coin_id(2, 1)));
+        assert_eq!(failure.condition_index, Some(0));
+        assert_eq!(failure.opcode, Some(AGG_SIG_ME));
+    }
+
+    #[test]
+    fn test_assertion_attributed_to_its_condition() {
+        let mut a = Allocator::new();
+        // both spends assert the same announcement, but from separately
+        // allocated atoms. The failure refers to the first one checked, which
+        // must be attributed to the spend it came from
+        let asserted = Bytes::from([0x33; 32].to_vec());
+        let first_assert = [(ASSERT_COIN_ANNOUNCEMENT, (asserted.clone(), ()))]
+            .to_vec()
+            .to_clvm(&mut a)
+            .unwrap();
+        let second_assert = [
+            (REMARK, (Bytes::default(), ())),
+            (REMARK, (Bytes::default(), ())),
+            (ASSERT_COIN_ANNOUNCEMENT, (asserted.clone(), ())),
+        ]
+        .to_vec()
+        .to_clvm(&mut a)
+        .unwrap();
+        let spends = make_spends(&mut a, &[(1, 1, first_assert), (2, 1, second_assert)]);
+
+        let failure = parse_explained(&a, spends);
+        assert_eq!(failure.error, ErrorCode::AssertCoinAnnouncementFailed);
+        assert_eq!(failure.opcode, Some(ASSERT_COIN_ANNOUNCEMENT));
+        assert_eq!(failure.args, vec![asserted]);
+        match failure.spend_index {
+            Some(0) => assert_eq!(failure.condition_index, Some(0)),
+            Some(1) => assert_eq!(failure.condition_index, Some(2)),
+            idx => panic!("unexpected spend index {idx:?}"),
+        }
+    }
+
+    // returns a coin spend whose puzzle returns the specified conditions
+    fn coin_spend(a: &mut Allocator, parent: u8, amount: u64, conds: NodePtr) -> CoinSpend {
+        let puzzle = a.new_pair(a.one(), conds).unwrap();
+        let puzzle_hash = tree_hash(a, puzzle);
+        let coin = Coin::new([parent; 32].into(), puzzle_hash.into(), amount);
+        let puzzle = Program::new(node_to_bytes(a, puzzle).unwrap().into());
+        CoinSpend::new(coin, puzzle, Program::from(vec![0x80]))
+    }
+
+    fn validate_explained(coin_spends: Vec<CoinSpend>) -> ConditionFailure {
+        let bundle = SpendBundle::new(coin_spends, Signature::default());
+        validate_spend_bundle_explained(&bundle, 0, &TEST_CONSTANTS, 0).unwrap_err()
+    }
+
+    #[test]
+    fn test_spend_bundle_missing_announcement() {
+        let mut a = Allocator::new();
+        let asserted = Bytes32::from([0x33; 32]);
+        let conds = [
+            (REMARK, (Bytes::default(), ())),
+            (
+                ASSERT_COIN_ANNOUNCEMENT,
+                (Bytes::from(asserted.to_vec()), ()),
+            ),
+        ]
+        .to_vec()
+        .to_clvm(&mut a)
+        .unwrap();
+        let spend1 = coin_spend(&mut a, 1, 1, NodePtr::NIL);
+        let spend2 = coin_spend(&mut a, 2, 1, conds);
+        let expected_coin = spend2.coin.coin_id();
+
+        let failure = validate_explained(vec![spend1, spend2]);
+        assert_eq!(failure.error, ErrorCode::AssertCoinAnnouncementFailed);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(expected_coin));
+        assert_eq!(failure.condition_index, Some(1));
+        assert_eq!(failure.opcode, Some(ASSERT_COIN_ANNOUNCEMENT));
+        assert_eq!(failure.args, vec![Bytes::from(asserted.to_vec())]);
+        assert_eq!(
+            failure.reason,
+            format!("no coin announcement matches {asserted}. 0 coin announcements were created in this spend bundle")
+        );
+    }
+
+    #[test]
+    fn test_spend_bundle_puzzle_failure() {
+        let mut a = Allocator::new();
+        let spend1 = coin_spend(&mut a, 1, 1, NodePtr::NIL);
+        // (x) raises an exception
+        let puzzle = Program::from(vec![0xff, 0x08, 0x80]);
+        let puzzle_node = node_from_program(&mut a, &puzzle);
+        let coin = Coin::new([2; 32].into(), tree_hash(&a, puzzle_node).into(), 1);
+        let spend2 = CoinSpend::new(coin, puzzle, Program::from(vec![0x80]));
+
+        let failure = validate_explained(vec![spend1, spend2]);
+        assert_eq!(failure.error, ErrorCode::GeneratorRuntimeError);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(coin.coin_id()));
+        assert_eq!(failure.condition_index, None);
+        assert_eq!(failure.reason, "running the puzzle failed");
+    }
+
+    fn node_from_program(a: &mut Allocator, p: &Program) -> NodePtr {
+        clvmr::serde::node_from_bytes(a, p.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_spend_bundle_wrong_puzzle_hash() {
+        let mut a = Allocator::new();
+        let spend1 = coin_spend(&mut a, 1, 1, NodePtr::NIL);
+        let mut spend2 = coin_spend(&mut a, 2, 1, NodePtr::NIL);
+        spend2.coin.puzzle_hash = PH.into();
+
+        let failure = validate_explained(vec![spend1, spend2.clone()]);
+        assert_eq!(failure.error, ErrorCode::WrongPuzzleHash);
+        assert_eq!(failure.spend_index, Some(1));
+        assert_eq!(failure.coin_id, Some(spend2.coin.coin_id()));
+        assert_eq!(failure.condition_index, None);
+    }
+
+    #[test]
+    fn test_run_block_generator2_ephemeral() {
+        let mut a = Allocator::new();
+        let conds = ((REMARK, ()), ((ASSERT_EPHEMERAL, ()), ((REMARK, ()), ())))
+            .to_clvm(&mut a)
+            .unwrap();
+        // solution_generator() lists the spends in reverse order, so this
+        // is the last spend in the generator
+        let spends = [
+            coin_spend(&mut a, 3, 1, conds),
+            coin_spend(&mut a, 2, 1, NodePtr::NIL),
+            coin_spend(&mut a, 1, 1, NodePtr::NIL),
+        ];
+        let generator = solution_generator(
+            spends
+                .iter()
+                .map(|cs| (cs.coin, cs.puzzle_reveal.as_slice(), cs.solution.as_slice())),
+        )
+        .unwrap();
+
+        let mut a = Allocator::new();
+        let failure = run_block_generator2_explained::<&[u8], MempoolVisitor>(
+            &mut a,
+            &generator,
+            &[],
+            TEST_CONSTANTS.max_block_cost_clvm,
+            MEMPOOL_MODE,
+            &TEST_CONSTANTS,
+        )
+        .unwrap_err();
+        assert_eq!(failure.error, ErrorCode::AssertEphemeralFailed);
+        assert_eq!(failure.spend_index, Some(2));
+        assert_eq!(failure.coin_id, Some(spends[0].coin.coin_id()));
+        assert_eq!(failure.condition_index, Some(1));
+        assert_eq!(failure.opcode, Some(ASSERT_EPHEMERAL));
+        assert_eq!(
+            failure.reason,
+            "the coin was not created in this spend bundle"
+        );
+    }
+}
@@ -0,0 +1,757 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::conditions::{
+    is_ephemeral, process_single_spend, ParseState, SpendBundleConditions, HAS_RELATIVE_CONDITION,
+};
+use crate::gen::flags::ALLOW_BACKREFS;
+use crate::gen::spend_visitor::SpendVisitor;
+use crate::gen::validation_error::{ErrorCode, ValidationErr};
+use chia_protocol::{Bytes32, Coin, SpendBundle};
+use clvm_utils::tree_hash;
+use clvmr::allocator::{Allocator, Checkpoint as AllocatorCheckpoint, NodePtr};
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::cost::Cost;
+use clvmr::reduction::Reduction;
+use clvmr::run_program::run_program;
+use clvmr::serde::{node_from_bytes, node_from_bytes_backrefs};
+use clvmr::sha2::{Digest, Sha256};
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::mem::take;
+use std::sync::Arc;
+
+// The conditions of a block that's being built up one spend bundle at a time.
+// Announcements, messages, concurrent spend/puzzle assertions and the
+// ephemeral checks may all span spend bundles. Every spend bundle is parsed on
+// its own, and then merged into the block. The block keeps the coins,
+// puzzle hashes and announcements of all spends added so far, so the
+// assertions of a new spend bundle can be checked without revisiting the
+// spends that were added before it.
+
+// add_spend_bundle() records the assertions of the new spend bundle, and
+// validate() checks all assertions recorded since the last successful call.
+// try_add_spend_bundle() does both, and rolls back the spend bundle if it
+// fails. Either way, the cost is proportional to the size of the new spend
+// bundle, not the size of the block.
+
+// All NodePtr in the conditions refer to the allocator passed in to
+// add_spend_bundle(). The same allocator must be used for every call.
+pub struct IncrementalConditions {
+    conds: SpendBundleConditions,
+
+    // all coins spent so far, mapping to their index in conds.spends
+    spent_coins: HashMap<Arc<Bytes32>, usize>,
+    spent_puzzles: HashSet<Bytes32>,
+    coin_announcements: HashSet<Bytes32>,
+    puzzle_announcements: HashSet<Bytes32>,
+
+    // the number of times each message has been sent minus the number of
+    // times it's been received, and the number of messages where that's not 0
+    messages: HashMap<Vec<u8>, i32>,
+    unbalanced_messages: usize,
+
+    // the checks that haven't been validated yet
+    pending: Pending,
+
+    // every insertion into the sets above (and message update), to be undone
+    // by rollback()
+    undo_log: Vec<Undo>,
+
+    max_cost: Cost,
+}
+
+// the assertions that haven't been validated yet
+#[derive(Default, Clone)]
+struct Pending {
+    coin_announcements: Vec<NodePtr>,
+    puzzle_announcements: Vec<NodePtr>,
+    concurrent_spends: Vec<NodePtr>,
+    concurrent_puzzles: Vec<NodePtr>,
+    // indices into conds.spends of spends that must be ephemeral and spends
+    // that must not be ephemeral (because they have relative conditions)
+    ephemeral: Vec<usize>,
+    not_ephemeral: Vec<usize>,
+}
+
+enum Undo {
+    SpentPuzzle(Bytes32),
+    CoinAnnouncement(Bytes32),
+    PuzzleAnnouncement(Bytes32),
+    Message(Vec<u8>, i32),
+}
+
+// A snapshot of IncrementalConditions and the allocator, to roll back to.
+// Taking one is cheap; it records the size of the block at the time, and
+// copies the checks that haven't been validated yet (if any).
+pub struct Checkpoint {
+    // the fields of SpendBundleConditions, except the spends and
+    // agg_sig_unsafe vectors. Those are truncated to these lengths
+    conds: SpendBundleConditions,
+    spends: usize,
+    agg_sig_unsafe: usize,
+    pending: Pending,
+    undo_log: usize,
+    allocator: AllocatorCheckpoint,
+}
+
+fn announcement_id(a: &Allocator, prefix: &[u8], msg: NodePtr) -> Bytes32 {
+    let mut hasher = Sha256::new();
+    hasher.update(prefix);
+    hasher.update(a.atom(msg));
+    let id: [u8; 32] = hasher.finalize().into();
+    id.into()
+}
+
+fn to_bytes32(a: &Allocator, n: NodePtr) -> Bytes32 {
+    a.atom(n).as_ref().try_into().expect("internal error")
+}
+
+impl IncrementalConditions {
+    // max_cost is the limit for all spend bundles combined
+    pub fn new(max_cost: Cost) -> Self {
+        Self {
+            conds: SpendBundleConditions::default(),
+            spent_coins: HashMap::new(),
+            spent_puzzles: HashSet::new(),
+            coin_announcements: HashSet::new(),
+            puzzle_announcements: HashSet::new(),
+            messages: HashMap::new(),
+            unbalanced_messages: 0,
+            pending: Pending::default(),
+            undo_log: Vec::new(),
+            max_cost,
+        }
+    }
+
+    // the conditions of all spends added so far. Note that these may not be
+    // valid, unless validate() has succeeded
+    pub fn conditions(&self) -> &SpendBundleConditions {
+        &self.conds
+    }
+
+    pub fn into_conditions(self) -> SpendBundleConditions {
+        self.conds
+    }
+
+    pub fn checkpoint(&self, a: &Allocator) -> Checkpoint {
+        let c = &self.conds;
+        Checkpoint {
+            conds: SpendBundleConditions {
+                spends: Vec::new(),
+                reserve_fee: c.reserve_fee,
+                height_absolute: c.height_absolute,
+                seconds_absolute: c.seconds_absolute,
+                agg_sig_unsafe: Vec::new(),
+                before_height_absolute: c.before_height_absolute,
+                before_seconds_absolute: c.before_seconds_absolute,
+                cost: c.cost,
+                byte_cost: c.byte_cost,
+                execution_cost: c.execution_cost,
+                condition_cost: c.condition_cost,
+                removal_amount: c.removal_amount,
+                addition_amount: c.addition_amount,
+            },
+            spends: c.spends.len(),
+            agg_sig_unsafe: c.agg_sig_unsafe.len(),
+            pending: self.pending.clone(),
+            undo_log: self.undo_log.len(),
+            allocator: a.checkpoint(),
+        }
+    }
+
+    // restores the state and the allocator to what they were when the
+    // checkpoint was taken. Checkpoints can only be rolled back in the order
+    // they were taken, most recent first.
+    pub fn rollback(&mut self, a: &mut Allocator, cp: Checkpoint) {
+        for spend in &self.conds.spends[cp.spends..] {
+            self.spent_coins.remove(&spend.coin_id);
+        }
+        while self.undo_log.len() > cp.undo_log {
+            match self.undo_log.pop().expect("undo log") {
+                Undo::SpentPuzzle(ph) => {
+                    self.spent_puzzles.remove(&ph);
+                }
+                Undo::CoinAnnouncement(id) => {
+                    self.coin_announcements.remove(&id);
+                }
+                Undo::PuzzleAnnouncement(id) => {
+                    self.puzzle_announcements.remove(&id);
+                }
+                Undo::Message(key, delta) => self.update_message(key, -delta),
+            }
+        }
+        let mut spends = take(&mut self.conds.spends);
+        let mut agg_sig_unsafe = take(&mut self.conds.agg_sig_unsafe);
+        spends.truncate(cp.spends);
+        agg_sig_unsafe.truncate(cp.agg_sig_unsafe);
+        self.conds = SpendBundleConditions {
+            spends,
+            agg_sig_unsafe,
+            ..cp.conds
+        };
+        self.pending = cp.pending;
+        a.restore_checkpoint(&cp.allocator);
+    }
+
+    fn update_message(&mut self, key: Vec<u8>, delta: i32) {
+        let count = self.messages.entry(key).or_insert(0);
+        let before = *count;
+        *count += delta;
+        if before == 0 && *count != 0 {
+            self.unbalanced_messages += 1;
+        } else if before != 0 && *count == 0 {
+            self.unbalanced_messages -= 1;
+        }
+    }
+
+    // runs all puzzles in the spend bundle and adds their conditions. The
+    // cost of the puzzles and conditions count against max_cost. There is no
+    // byte cost, since there is no generator. The signature is not validated.
+    // If this fails, the spend bundle is not added, but the allocator is not
+    // restored.
+    pub fn add_spend_bundle<V: SpendVisitor>(
+        &mut self,
+        a: &mut Allocator,
+        bundle: &SpendBundle,
+        flags: u32,
+        constants: &ConsensusConstants,
+    ) -> Result<(), ValidationErr> {
+        let deserialize = if (flags & ALLOW_BACKREFS) != 0 {
+            node_from_bytes_backrefs
+        } else {
+            node_from_bytes
+        };
+        let dialect = ChiaDialect::new(flags);
+        let mut cost_left = self.max_cost - self.conds.cost;
+
+        // the spend bundle is parsed on its own, and merged into the block
+        // once it's been parsed successfully
+        let mut conds = SpendBundleConditions::default();
+        let mut state = ParseState::default();
+
+        for cs in &bundle.coin_spends {
+            let puzzle = deserialize(a, cs.puzzle_reveal.as_slice())?;
+            let solution = deserialize(a, cs.solution.as_slice())?;
+
+            let Reduction(clvm_cost, conditions) =
+                run_program(a, &dialect, puzzle, solution, cost_left)?;
+            if clvm_cost > cost_left {
+                return Err(ValidationErr(a.nil(), ErrorCode::CostExceeded));
+            }
+            cost_left -= clvm_cost;
+
+            let puzzle_hash = tree_hash(a, puzzle);
+            if puzzle_hash != cs.coin.puzzle_hash.into() {
+                return Err(ValidationErr(puzzle, ErrorCode::WrongPuzzleHash));
+            }
+            let parent_id = a.new_atom(&cs.coin.parent_coin_info)?;
+            let puzzle_hash = a.new_atom(&puzzle_hash)?;
+            let amount = a.new_number(cs.coin.amount.into())?;
+
+            process_single_spend::<V>(
+                a,
+                &mut conds,
+                &mut state,
+                parent_id,
+                puzzle_hash,
+                amount,
+                conditions,
+                flags,
+                &mut cost_left,
+                constants,
+            )?;
+            conds.spends.last_mut().expect("spend").execution_cost = clvm_cost;
+            conds.execution_cost += clvm_cost;
+        }
+
+        for spend in &conds.spends {
+            if self.spent_coins.contains_key(&spend.coin_id) {
+                return Err(ValidationErr(spend.parent_id, ErrorCode::DoubleSpend));
+            }
+        }
+        let reserve_fee = self
+            .conds
+            .reserve_fee
+            .checked_add(conds.reserve_fee)
+            .ok_or(ValidationErr(a.nil(), ErrorCode::ReserveFeeConditionFailed))?;
+
+        self.merge(a, conds, state, reserve_fee);
+        self.conds.cost = self.max_cost - cost_left;
+        Ok(())
+    }
+
+    fn merge(
+        &mut self,
+        a: &Allocator,
+        conds: SpendBundleConditions,
+        state: ParseState,
+        reserve_fee: u64,
+    ) {
+        let offset = self.conds.spends.len();
+
+        let pending = &mut self.pending;
+        pending.coin_announcements.extend(state.assert_coin);
+        pending.puzzle_announcements.extend(state.assert_puzzle);
+        pending
+            .concurrent_spends
+            .extend(state.assert_concurrent_spend);
+        pending
+            .concurrent_puzzles
+            .extend(state.assert_concurrent_puzzle);
+        pending
+            .ephemeral
+            .extend(state.assert_ephemeral.iter().map(|idx| idx + offset));
+        pending
+            .not_ephemeral
+            .extend(state.assert_not_ephemeral.iter().map(|idx| idx + offset));
+
+        for (coin_id, msg) in &state.announce_coin {
+            let id = announcement_id(a, coin_id.as_ref(), *msg);
+            if self.coin_announcements.insert(id) {
+                self.undo_log.push(Undo::CoinAnnouncement(id));
+            }
+        }
+        for (puzzle_hash, msg) in &state.announce_puzzle {
+            let id = announcement_id(a, a.atom(*puzzle_hash).as_ref(), *msg);
+            if self.puzzle_announcements.insert(id) {
+                self.undo_log.push(Undo::PuzzleAnnouncement(id));
+            }
+        }
+        for puzzle_hash in &state.spent_puzzles {
+            let puzzle_hash = to_bytes32(a, *puzzle_hash);
+            if self.spent_puzzles.insert(puzzle_hash) {
+                self.undo_log.push(Undo::SpentPuzzle(puzzle_hash));
+            }
+        }
+        for msg in &state.messages {
+            let key = msg.make_key(a);
+            let delta = i32::from(msg.counter);
+            self.update_message(key.clone(), delta);
+            self.undo_log.push(Undo::Message(key, delta));
+        }
+
+        for (idx, spend) in conds.spends.iter().enumerate() {
+            self.spent_coins.insert(spend.coin_id.clone(), idx + offset);
+        }
+        // a coin that was spent earlier, with a relative condition, becomes
+        // ephemeral if its parent is spent now
+        for spend in &conds.spends {
+            for new_coin in &spend.create_coin {
+                let coin_id =
+                    Coin::new(*spend.coin_id, new_coin.puzzle_hash, new_coin.amount).coin_id();
+                if let Some(idx) = self.spent_coins.get(&coin_id) {
+                    if *idx < offset
+                        && (self.conds.spends[*idx].flags & HAS_RELATIVE_CONDITION) != 0
+                    {
+                        self.pending.not_ephemeral.push(*idx);
+                    }
+                }
+            }
+        }
+
+        let c = &mut self.conds;
+        c.spends.extend(conds.spends);
+        c.agg_sig_unsafe.extend(conds.agg_sig_unsafe);
+        c.reserve_fee = reserve_fee;
+        c.height_absolute = max(c.height_absolute, conds.height_absolute);
+        c.seconds_absolute = max(c.seconds_absolute, conds.seconds_absolute);
+        c.before_height_absolute = match (c.before_height_absolute, conds.before_height_absolute) {
+            (Some(h1), Some(h2)) => Some(min(h1, h2)),
+            (h1, h2) => h1.or(h2),
+        };
+        c.before_seconds_absolute = match (c.before_seconds_absolute, conds.before_seconds_absolute)
+        {
+            (Some(s1), Some(s2)) => Some(min(s1, s2)),
+            (s1, s2) => s1.or(s2),
+        };
+        c.execution_cost += conds.execution_cost;
+        c.condition_cost += conds.condition_cost;
+        c.removal_amount += conds.removal_amount;
+        c.addition_amount += conds.addition_amount;
+    }
+
+    // validates the assertions that may span spend bundles (announcements,
+    // messages, concurrent spends and ephemeral coins) of the spend bundles
+    // added since the last successful call, against all spends added so far.
+    // The checks are the same as validate_conditions() performs on a whole
+    // block
+    pub fn validate(&mut self, a: &Allocator) -> Result<(), ValidationErr> {
+        let c = &self.conds;
+        if c.removal_amount < c.addition_amount {
+            return Err(ValidationErr(a.nil(), ErrorCode::MintingCoin));
+        }
+        if c.removal_amount - c.addition_amount < c.reserve_fee as u128 {
+            return Err(ValidationErr(a.nil(), ErrorCode::ReserveFeeConditionFailed));
+        }
+        if c.before_height_absolute
+            .is_some_and(|bh| bh <= c.height_absolute)
+        {
+            return Err(ValidationErr(
+                a.nil(),
+                ErrorCode::ImpossibleHeightAbsoluteConstraints,
+            ));
+        }
+        if c.before_seconds_absolute
+            .is_some_and(|bs| bs <= c.seconds_absolute)
+        {
+            return Err(ValidationErr(
+                a.nil(),
+                ErrorCode::ImpossibleSecondsAbsoluteConstraints,
+            ));
+        }
+
+        let p = &self.pending;
+        for coin_id in &p.concurrent_spends {
+            if !self.spent_coins.contains_key(&to_bytes32(a, *coin_id)) {
+                return Err(ValidationErr(
+                    *coin_id,
+                    ErrorCode::AssertConcurrentSpendFailed,
+                ));
+            }
+        }
+        for puzzle_hash in &p.concurrent_puzzles {
+            if !self.spent_puzzles.contains(&to_bytes32(a, *puzzle_hash)) {
+                return Err(ValidationErr(
+                    *puzzle_hash,
+                    ErrorCode::AssertConcurrentPuzzleFailed,
+                ));
+            }
+        }
+        for id in &p.coin_announcements {
+            if !self.coin_announcements.contains(&to_bytes32(a, *id)) {
+                return Err(ValidationErr(*id, ErrorCode::AssertCoinAnnouncementFailed));
+            }
+        }
+        for idx in &p.ephemeral {
+            if !is_ephemeral(a, *idx, &self.spent_coins, &c.spends) {
+                return Err(ValidationErr(
+                    c.spends[*idx].parent_id,
+                    ErrorCode::AssertEphemeralFailed,
+                ));
+            }
+        }
+        for idx in &p.not_ephemeral {
+            if is_ephemeral(a, *idx, &self.spent_coins, &c.spends) {
+                return Err(ValidationErr(
+                    c.spends[*idx].parent_id,
+                    ErrorCode::EphemeralRelativeCondition,
+                ));
+            }
+        }
+        for id in &p.puzzle_announcements {
+            if !self.puzzle_announcements.contains(&to_bytes32(a, *id)) {
+                return Err(ValidationErr(
+                    *id,
+                    ErrorCode::AssertPuzzleAnnouncementFailed,
+                ));
+            }
+        }
+        if self.unbalanced_messages != 0 {
+            return Err(ValidationErr(
+                NodePtr::NIL,
+                ErrorCode::MessageNotSentOrReceived,
+            ));
+        }
+
+        self.pending = Pending::default();
+        Ok(())
+    }
+
+    // adds the spend bundle and validates the combined conditions. On failure,
+    // the spend bundle is rolled back and the state is left unchanged
+    pub fn try_add_spend_bundle<V: SpendVisitor>(
+        &mut self,
+        a: &mut Allocator,
+        bundle: &SpendBundle,
+        flags: u32,
+        constants: &ConsensusConstants,
+    ) -> Result<(), ValidationErr> {
+        let cp = self.checkpoint(a);
+        let result = self
+            .add_spend_bundle::<V>(a, bundle, flags, constants)
+            .and_then(|()| self.validate(a));
+        if result.is_err() {
+            self.rollback(a, cp);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::conditions::MempoolVisitor;
+    use crate::gen::flags::MEMPOOL_MODE;
+    use crate::gen::owned_conditions::OwnedCondition;
+    use chia_bls::Signature;
+    use chia_protocol::{Bytes, Bytes32, Coin, CoinSpend, Program};
+    use clvm_traits::ToClvm;
+    use clvmr::serde::node_to_bytes;
+    use clvmr::sha2::{Digest, Sha256};
+
+    const MAX_COST: Cost = 11_000_000_000;
+
+    // a coin spend whose puzzle returns the specified conditions
+    fn make_spend(parent: Bytes32, amount: u64, conds: &[OwnedCondition]) -> CoinSpend {
+        let mut a = Allocator::new();
+        let conds = conds.to_clvm(&mut a).expect("to_clvm");
+        let puzzle = a.new_pair(a.one(), conds).expect("new_pair");
+        let puzzle_hash = tree_hash(&a, puzzle);
+        let puzzle = node_to_bytes(&a, puzzle).expect("node_to_bytes");
+        CoinSpend::new(
+            Coin::new(parent, puzzle_hash.into(), amount),
+            Program::new(puzzle.into()),
+            Program::new(vec![0x80].into()),
+        )
+    }
+
+    fn make_bundle(spends: Vec<CoinSpend>) -> SpendBundle {
+        SpendBundle::new(spends, Signature::default())
+    }
+
+    fn add(
+        ic: &mut IncrementalConditions,
+        a: &mut Allocator,
+        bundle: &SpendBundle,
+    ) -> Result<(), ErrorCode> {
+        ic.try_add_spend_bundle::<MempoolVisitor>(a, bundle, MEMPOOL_MODE, &TEST_CONSTANTS)
+            .map_err(|e| e.1)
+    }
+
+    fn announcement_id(coin_id: Bytes32, message: &[u8]) -> Bytes32 {
+        let mut hasher = Sha256::new();
+        hasher.update(coin_id);
+        hasher.update(message);
+        Bytes32::new(hasher.finalize().into())
+    }
+
+    #[test]
+    fn test_announcement_across_bundles() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        let announce = make_spend(
+            Bytes32::from([1; 32]),
+            1,
+            &[OwnedCondition::CreateCoinAnnouncement {
+                message: Bytes::from(b"hello".to_vec()),
+            }],
+        );
+        let id = announcement_id(announce.coin.coin_id(), b"hello");
+        let assert = make_spend(
+            Bytes32::from([2; 32]),
+            1,
+            &[OwnedCondition::AssertCoinAnnouncement {
+                announcement_id: id,
+            }],
+        );
+
+        // the announcement hasn't been made yet
+        let assert = make_bundle(vec![assert]);
+        assert_eq!(
+            add(&mut ic, &mut a, &assert),
+            Err(ErrorCode::AssertCoinAnnouncementFailed)
+        );
+        assert!(ic.conditions().spends.is_empty());
+        assert_eq!(ic.conditions().cost, 0);
+
+        add(&mut ic, &mut a, &make_bundle(vec![announce])).expect("announce");
+        add(&mut ic, &mut a, &assert).expect("assert");
+        assert_eq!(ic.conditions().spends.len(), 2);
+    }
+
+    #[test]
+    fn test_ephemeral_across_bundles() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        let child = make_spend(Bytes32::default(), 1, &[OwnedCondition::AssertEphemeral]);
+        let parent = make_spend(
+            Bytes32::from([1; 32]),
+            3,
+            &[OwnedCondition::CreateCoin {
+                puzzle_hash: child.coin.puzzle_hash,
+                amount: 1,
+                memos: None,
+            }],
+        );
+        let child = CoinSpend::new(
+            Coin::new(parent.coin.coin_id(), child.coin.puzzle_hash, 1),
+            child.puzzle_reveal,
+            child.solution,
+        );
+        let child = make_bundle(vec![child]);
+
+        assert_eq!(
+            add(&mut ic, &mut a, &child),
+            Err(ErrorCode::AssertEphemeralFailed)
+        );
+        add(&mut ic, &mut a, &make_bundle(vec![parent])).expect("parent");
+        add(&mut ic, &mut a, &child).expect("child");
+        assert_eq!(ic.conditions().spends.len(), 2);
+    }
+
+    #[test]
+    fn test_double_spend_across_bundles() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+        let spend = make_bundle(vec![make_spend(Bytes32::from([1; 32]), 1, &[])]);
+
+        add(&mut ic, &mut a, &spend).expect("spend");
+        assert_eq!(add(&mut ic, &mut a, &spend), Err(ErrorCode::DoubleSpend));
+        assert_eq!(ic.conditions().spends.len(), 1);
+        ic.validate(&a).expect("validate");
+    }
+
+    #[test]
+    fn test_relative_condition_becomes_ephemeral() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        let child = make_spend(
+            Bytes32::default(),
+            1,
+            &[OwnedCondition::AssertHeightRelative { height: 1 }],
+        );
+        let parent = make_spend(
+            Bytes32::from([1; 32]),
+            3,
+            &[OwnedCondition::CreateCoin {
+                puzzle_hash: child.coin.puzzle_hash,
+                amount: 1,
+                memos: None,
+            }],
+        );
+        let child = CoinSpend::new(
+            Coin::new(parent.coin.coin_id(), child.coin.puzzle_hash, 1),
+            child.puzzle_reveal,
+            child.solution,
+        );
+
+        // the child is valid on its own, but adding its parent would make it
+        // ephemeral, and relative conditions aren't allowed on ephemeral coins
+        add(&mut ic, &mut a, &make_bundle(vec![child])).expect("child");
+        assert_eq!(
+            add(&mut ic, &mut a, &make_bundle(vec![parent])),
+            Err(ErrorCode::EphemeralRelativeCondition)
+        );
+        assert_eq!(ic.conditions().spends.len(), 1);
+    }
+
+    #[test]
+    fn test_rollback_announcement() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        let announce = make_spend(
+            Bytes32::from([1; 32]),
+            1,
+            &[OwnedCondition::CreateCoinAnnouncement {
+                message: Bytes::from(b"hello".to_vec()),
+            }],
+        );
+        let id = announcement_id(announce.coin.coin_id(), b"hello");
+        let assert = make_bundle(vec![make_spend(
+            Bytes32::from([2; 32]),
+            1,
+            &[OwnedCondition::AssertCoinAnnouncement {
+                announcement_id: id,
+            }],
+        )]);
+
+        let cp = ic.checkpoint(&a);
+        add(&mut ic, &mut a, &make_bundle(vec![announce.clone()])).expect("announce");
+        ic.rollback(&mut a, cp);
+
+        // the announcement was rolled back, and so was the spent coin
+        assert_eq!(
+            add(&mut ic, &mut a, &assert),
+            Err(ErrorCode::AssertCoinAnnouncementFailed)
+        );
+        add(&mut ic, &mut a, &make_bundle(vec![announce])).expect("announce");
+        add(&mut ic, &mut a, &assert).expect("assert");
+    }
+
+    #[test]
+    fn test_validate_pending() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        let assert = make_bundle(vec![make_spend(
+            Bytes32::from([2; 32]),
+            1,
+            &[OwnedCondition::AssertConcurrentSpend {
+                coin_id: Bytes32::from([3; 32]),
+            }],
+        )]);
+
+        // the assertion is checked by validate(), and remains until it passes
+        ic.add_spend_bundle::<MempoolVisitor>(&mut a, &assert, MEMPOOL_MODE, &TEST_CONSTANTS)
+            .expect("add");
+        assert_eq!(
+            ic.validate(&a).map_err(|e| e.1),
+            Err(ErrorCode::AssertConcurrentSpendFailed)
+        );
+        assert_eq!(
+            ic.validate(&a).map_err(|e| e.1),
+            Err(ErrorCode::AssertConcurrentSpendFailed)
+        );
+    }
+
+    fn spend(parent: u8) -> SpendBundle {
+        make_bundle(vec![make_spend(
+            Bytes32::from([parent; 32]),
+            1,
+            &[OwnedCondition::CreateCoin {
+                puzzle_hash: Bytes32::from([parent; 32]),
+                amount: 1,
+                memos: None,
+            }],
+        )])
+    }
+
+    #[test]
+    fn test_cost_exceeded() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+        add(&mut ic, &mut a, &spend(1)).expect("spend");
+
+        // the limit is for all spend bundles combined
+        let mut ic = IncrementalConditions::new(ic.conditions().cost * 2 - 1);
+        add(&mut ic, &mut a, &spend(1)).expect("spend");
+        assert_eq!(
+            add(&mut ic, &mut a, &spend(2)),
+            Err(ErrorCode::CostExceeded)
+        );
+        assert_eq!(ic.conditions().spends.len(), 1);
+    }
+
+    #[test]
+    fn test_checkpoint_rollback() {
+        let mut a = Allocator::new();
+        let mut ic = IncrementalConditions::new(MAX_COST);
+
+        add(&mut ic, &mut a, &spend(1)).expect("spend");
+        let cost = ic.conditions().cost;
+        let addition_amount = ic.conditions().addition_amount;
+        let cp = ic.checkpoint(&a);
+
+        add(&mut ic, &mut a, &spend(2)).expect("spend");
+        assert_eq!(ic.conditions().spends.len(), 2);
+        assert_eq!(ic.conditions().cost, cost * 2);
+
+        ic.rollback(&mut a, cp);
+        assert_eq!(ic.conditions().spends.len(), 1);
+        assert_eq!(ic.conditions().cost, cost);
+        assert_eq!(ic.conditions().addition_amount, addition_amount);
+
+        // the spend can be added again after the rollback
+        add(&mut ic, &mut a, &spend(2)).expect("spend");
+        let conds = ic.into_conditions();
+        assert_eq!(conds.spends.len(), 2);
+        assert_eq!(
+            conds.cost,
+            conds.execution_cost + conds.condition_cost + conds.byte_cost
+        );
+    }
+}
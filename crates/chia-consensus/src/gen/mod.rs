@@ -1,8 +1,10 @@
 mod coin_id;
 mod condition_sanitizers;
 pub mod conditions;
+pub mod explain;
 pub mod flags;
 pub mod get_puzzle_and_solution;
+pub mod incremental;
 pub mod messages;
 pub mod opcodes;
 pub mod owned_conditions;
@@ -11,6 +13,8 @@ pub mod run_puzzle;
 pub mod sanitize_int;
 pub mod solution_generator;
 pub mod spend_visitor;
+pub mod validate_signature;
+pub mod validate_spend_bundle;
 pub mod validation_error;
 
 // these tests are large and expensive. They take a long time to run in
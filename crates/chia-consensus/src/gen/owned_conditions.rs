@@ -1,9 +1,23 @@
 use chia_bls::PublicKey;
 use chia_protocol::{Bytes, Bytes32};
 use chia_streamable_macro::Streamable;
+use clvm_traits::{
+    clvm_list, ClvmDecoder, ClvmEncoder, FromClvm, FromClvmError, ToClvm, ToClvmError,
+};
 use clvmr::{Allocator, NodePtr};
 
 use super::conditions::{Spend, SpendBundleConditions};
+use super::opcodes::{
+    AGG_SIG_AMOUNT, AGG_SIG_ME, AGG_SIG_PARENT, AGG_SIG_PARENT_AMOUNT, AGG_SIG_PARENT_PUZZLE,
+    AGG_SIG_PUZZLE, AGG_SIG_PUZZLE_AMOUNT, AGG_SIG_UNSAFE, ASSERT_BEFORE_HEIGHT_ABSOLUTE,
+    ASSERT_BEFORE_HEIGHT_RELATIVE, ASSERT_BEFORE_SECONDS_ABSOLUTE, ASSERT_BEFORE_SECONDS_RELATIVE,
+    ASSERT_COIN_ANNOUNCEMENT, ASSERT_CONCURRENT_PUZZLE, ASSERT_CONCURRENT_SPEND, ASSERT_EPHEMERAL,
+    ASSERT_HEIGHT_ABSOLUTE, ASSERT_HEIGHT_RELATIVE, ASSERT_MY_AMOUNT, ASSERT_MY_BIRTH_HEIGHT,
+    ASSERT_MY_BIRTH_SECONDS, ASSERT_MY_COIN_ID, ASSERT_MY_PARENT_ID, ASSERT_MY_PUZZLEHASH,
+    ASSERT_PUZZLE_ANNOUNCEMENT, ASSERT_SECONDS_ABSOLUTE, ASSERT_SECONDS_RELATIVE, CREATE_COIN,
+    CREATE_COIN_ANNOUNCEMENT, CREATE_PUZZLE_ANNOUNCEMENT, RECEIVE_MESSAGE, REMARK, RESERVE_FEE,
+    SEND_MESSAGE, SOFTFORK,
+};
 
 #[cfg(feature = "py-bindings")]
 use chia_py_streamable_macro::{PyJsonDict, PyStreamable};
@@ -34,6 +48,8 @@ pub struct OwnedSpend {
     pub agg_sig_parent_amount: Vec<(PublicKey, Bytes)>,
     pub agg_sig_parent_puzzle: Vec<(PublicKey, Bytes)>,
     pub flags: u32,
+    pub execution_cost: u64,
+    pub condition_cost: u64,
 }
 
 #[derive(Streamable, Hash, Debug, Clone, Eq, PartialEq)]
@@ -60,6 +76,10 @@ pub struct OwnedSpendBundleConditions {
     pub removal_amount: u128,
     // the sum of all amounts of CREATE_COIN conditions
     pub addition_amount: u128,
+    // the breakdown of cost. cost is the sum of these
+    pub byte_cost: u64,
+    pub execution_cost: u64,
+    pub condition_cost: u64,
 }
 
 impl OwnedSpend {
@@ -106,6 +126,8 @@ impl OwnedSpend {
             agg_sig_parent_amount: convert_agg_sigs(a, &spend.agg_sig_parent_amount),
             agg_sig_parent_puzzle: convert_agg_sigs(a, &spend.agg_sig_parent_puzzle),
             flags: spend.flags,
+            execution_cost: spend.execution_cost,
+            condition_cost: spend.condition_cost,
         }
     }
 }
@@ -133,6 +155,75 @@ impl OwnedSpendBundleConditions {
             cost: sb.cost,
             removal_amount: sb.removal_amount,
             addition_amount: sb.addition_amount,
+            byte_cost: sb.byte_cost,
+            execution_cost: sb.execution_cost,
+            condition_cost: sb.condition_cost,
+        }
+    }
+}
+
+// test fixtures: a spend of the specified coin, creating the specified coins,
+// with no other conditions
+#[cfg(test)]
+impl OwnedSpend {
+    pub(crate) fn for_coin(
+        coin: &chia_protocol::Coin,
+        create_coin: &[chia_protocol::Coin],
+    ) -> Self {
+        Self {
+            coin_id: coin.coin_id(),
+            parent_id: coin.parent_coin_info,
+            puzzle_hash: coin.puzzle_hash,
+            coin_amount: coin.amount,
+            height_relative: None,
+            seconds_relative: None,
+            before_height_relative: None,
+            before_seconds_relative: None,
+            birth_height: None,
+            birth_seconds: None,
+            create_coin: create_coin
+                .iter()
+                .map(|c| (c.puzzle_hash, c.amount, None))
+                .collect(),
+            agg_sig_me: Vec::new(),
+            agg_sig_parent: Vec::new(),
+            agg_sig_puzzle: Vec::new(),
+            agg_sig_amount: Vec::new(),
+            agg_sig_puzzle_amount: Vec::new(),
+            agg_sig_parent_amount: Vec::new(),
+            agg_sig_parent_puzzle: Vec::new(),
+            flags: 0,
+            execution_cost: 0,
+            condition_cost: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl OwnedSpendBundleConditions {
+    // the removal and addition amounts are the sums of the spent and created
+    // coins
+    pub(crate) fn for_spends(spends: Vec<OwnedSpend>) -> Self {
+        let removal_amount = spends.iter().map(|s| u128::from(s.coin_amount)).sum();
+        let addition_amount = spends
+            .iter()
+            .flat_map(|s| s.create_coin.iter())
+            .map(|c| u128::from(c.1))
+            .sum();
+        Self {
+            spends,
+            reserve_fee: 0,
+            height_absolute: 0,
+            seconds_absolute: 0,
+            before_height_absolute: None,
+            before_seconds_absolute: None,
+            agg_sig_unsafe: Vec::new(),
+            cost: 0,
+            removal_amount,
+            addition_amount,
+            byte_cost: 0,
+            execution_cost: 0,
+            condition_cost: 0,
         }
     }
 }
@@ -144,3 +235,664 @@ fn convert_agg_sigs(a: &Allocator, agg_sigs: &[(PublicKey, NodePtr)]) -> Vec<(Pu
     }
     ret
 }
+
+// A single condition, as output by a puzzle. Unlike the Condition enum in
+// conditions.rs, this does not refer to an Allocator and can be used both to
+// build condition lists and to decode them. Conditions are encoded as a list
+// whose first element is the opcode. The free-form arguments of REMARK,
+// SOFTFORK and unknown conditions are of type T.
+// Decoding with FromClvm is strict, like the mempool; conditions with extra
+// trailing arguments fail to parse. Use from_clvm_lenient() to ignore them, the
+// way consensus does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedCondition<T = NodePtr> {
+    Remark(T),
+    AggSigParent {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigPuzzle {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigAmount {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigPuzzleAmount {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigParentAmount {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigParentPuzzle {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigUnsafe {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    AggSigMe {
+        public_key: PublicKey,
+        message: Bytes,
+    },
+    // the first memo is the hint, if it's 32 bytes
+    CreateCoin {
+        puzzle_hash: Bytes32,
+        amount: u64,
+        memos: Option<Vec<Bytes>>,
+    },
+    ReserveFee {
+        amount: u64,
+    },
+    CreateCoinAnnouncement {
+        message: Bytes,
+    },
+    AssertCoinAnnouncement {
+        announcement_id: Bytes32,
+    },
+    CreatePuzzleAnnouncement {
+        message: Bytes,
+    },
+    AssertPuzzleAnnouncement {
+        announcement_id: Bytes32,
+    },
+    AssertConcurrentSpend {
+        coin_id: Bytes32,
+    },
+    AssertConcurrentPuzzle {
+        puzzle_hash: Bytes32,
+    },
+    // mode is the 6 bit sender and receiver mode. The remaining arguments
+    // identify the other side of the message, as determined by the mode
+    SendMessage {
+        mode: u8,
+        message: Bytes,
+        data: Vec<Bytes>,
+    },
+    ReceiveMessage {
+        mode: u8,
+        message: Bytes,
+        data: Vec<Bytes>,
+    },
+    AssertMyCoinId {
+        coin_id: Bytes32,
+    },
+    AssertMyParentId {
+        parent_id: Bytes32,
+    },
+    AssertMyPuzzleHash {
+        puzzle_hash: Bytes32,
+    },
+    AssertMyAmount {
+        amount: u64,
+    },
+    AssertMyBirthSeconds {
+        seconds: u64,
+    },
+    AssertMyBirthHeight {
+        height: u32,
+    },
+    AssertEphemeral,
+    AssertSecondsRelative {
+        seconds: u64,
+    },
+    AssertSecondsAbsolute {
+        seconds: u64,
+    },
+    AssertHeightRelative {
+        height: u32,
+    },
+    AssertHeightAbsolute {
+        height: u32,
+    },
+    AssertBeforeSecondsRelative {
+        seconds: u64,
+    },
+    AssertBeforeSecondsAbsolute {
+        seconds: u64,
+    },
+    AssertBeforeHeightRelative {
+        height: u32,
+    },
+    AssertBeforeHeightAbsolute {
+        height: u32,
+    },
+    // the cost is specified in units of 10000
+    Softfork {
+        cost: u64,
+        rest: T,
+    },
+    // any other opcode (that fits in 16 bits). Consensus ignores these, other
+    // than their cost
+    Unknown {
+        opcode: u16,
+        args: T,
+    },
+}
+
+// reads the arguments of a condition, one at a time
+struct Args<'a, D: ClvmDecoder> {
+    decoder: &'a D,
+    node: D::Node,
+}
+
+impl<'a, D: ClvmDecoder> Args<'a, D> {
+    fn next<V: FromClvm<D::Node>>(&mut self) -> Result<V, FromClvmError> {
+        let (first, rest) = self.decoder.decode_pair(&self.node)?;
+        self.node = rest;
+        V::from_clvm(self.decoder, first)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.decoder.decode_pair(&self.node).is_err()
+    }
+
+    fn rest<V: FromClvm<D::Node>>(self) -> Result<V, FromClvmError> {
+        V::from_clvm(self.decoder, self.node)
+    }
+
+    // in strict mode, the argument list must end here
+    fn end<V>(self, value: V, strict: bool) -> Result<V, FromClvmError> {
+        if strict {
+            <()>::from_clvm(self.decoder, self.node)?;
+        }
+        Ok(value)
+    }
+}
+
+impl<T> OwnedCondition<T> {
+    // decodes a condition, ignoring any trailing arguments. This takes the node
+    // by value to mirror FromClvm::from_clvm()
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn from_clvm_lenient<N>(
+        decoder: &impl ClvmDecoder<Node = N>,
+        node: N,
+    ) -> Result<Self, FromClvmError>
+    where
+        T: FromClvm<N>,
+    {
+        Self::decode(decoder, &node, false)
+    }
+
+    fn decode<D: ClvmDecoder>(
+        decoder: &D,
+        node: &D::Node,
+        strict: bool,
+    ) -> Result<Self, FromClvmError>
+    where
+        T: FromClvm<D::Node>,
+    {
+        let (opcode, node) = decoder.decode_pair(node)?;
+        let opcode = u16::from_clvm(decoder, opcode)?;
+        let mut args = Args { decoder, node };
+        let cond = match opcode {
+            REMARK => return Ok(Self::Remark(args.rest()?)),
+            AGG_SIG_PARENT => Self::AggSigParent {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_PUZZLE => Self::AggSigPuzzle {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_AMOUNT => Self::AggSigAmount {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_PUZZLE_AMOUNT => Self::AggSigPuzzleAmount {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_PARENT_AMOUNT => Self::AggSigParentAmount {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_PARENT_PUZZLE => Self::AggSigParentPuzzle {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_UNSAFE => Self::AggSigUnsafe {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            AGG_SIG_ME => Self::AggSigMe {
+                public_key: args.next()?,
+                message: args.next()?,
+            },
+            CREATE_COIN => Self::CreateCoin {
+                puzzle_hash: args.next()?,
+                amount: args.next()?,
+                memos: if args.is_empty() {
+                    None
+                } else {
+                    Some(args.next()?)
+                },
+            },
+            RESERVE_FEE => Self::ReserveFee {
+                amount: args.next()?,
+            },
+            CREATE_COIN_ANNOUNCEMENT => Self::CreateCoinAnnouncement {
+                message: args.next()?,
+            },
+            ASSERT_COIN_ANNOUNCEMENT => Self::AssertCoinAnnouncement {
+                announcement_id: args.next()?,
+            },
+            CREATE_PUZZLE_ANNOUNCEMENT => Self::CreatePuzzleAnnouncement {
+                message: args.next()?,
+            },
+            ASSERT_PUZZLE_ANNOUNCEMENT => Self::AssertPuzzleAnnouncement {
+                announcement_id: args.next()?,
+            },
+            ASSERT_CONCURRENT_SPEND => Self::AssertConcurrentSpend {
+                coin_id: args.next()?,
+            },
+            ASSERT_CONCURRENT_PUZZLE => Self::AssertConcurrentPuzzle {
+                puzzle_hash: args.next()?,
+            },
+            SEND_MESSAGE => {
+                let mode = args.next()?;
+                let message = args.next()?;
+                return Ok(Self::SendMessage {
+                    mode,
+                    message,
+                    data: args.rest()?,
+                });
+            }
+            RECEIVE_MESSAGE => {
+                let mode = args.next()?;
+                let message = args.next()?;
+                return Ok(Self::ReceiveMessage {
+                    mode,
+                    message,
+                    data: args.rest()?,
+                });
+            }
+            ASSERT_MY_COIN_ID => Self::AssertMyCoinId {
+                coin_id: args.next()?,
+            },
+            ASSERT_MY_PARENT_ID => Self::AssertMyParentId {
+                parent_id: args.next()?,
+            },
+            ASSERT_MY_PUZZLEHASH => Self::AssertMyPuzzleHash {
+                puzzle_hash: args.next()?,
+            },
+            ASSERT_MY_AMOUNT => Self::AssertMyAmount {
+                amount: args.next()?,
+            },
+            ASSERT_MY_BIRTH_SECONDS => Self::AssertMyBirthSeconds {
+                seconds: args.next()?,
+            },
+            ASSERT_MY_BIRTH_HEIGHT => Self::AssertMyBirthHeight {
+                height: args.next()?,
+            },
+            ASSERT_EPHEMERAL => Self::AssertEphemeral,
+            ASSERT_SECONDS_RELATIVE => Self::AssertSecondsRelative {
+                seconds: args.next()?,
+            },
+            ASSERT_SECONDS_ABSOLUTE => Self::AssertSecondsAbsolute {
+                seconds: args.next()?,
+            },
+            ASSERT_HEIGHT_RELATIVE => Self::AssertHeightRelative {
+                height: args.next()?,
+            },
+            ASSERT_HEIGHT_ABSOLUTE => Self::AssertHeightAbsolute {
+                height: args.next()?,
+            },
+            ASSERT_BEFORE_SECONDS_RELATIVE => Self::AssertBeforeSecondsRelative {
+                seconds: args.next()?,
+            },
+            ASSERT_BEFORE_SECONDS_ABSOLUTE => Self::AssertBeforeSecondsAbsolute {
+                seconds: args.next()?,
+            },
+            ASSERT_BEFORE_HEIGHT_RELATIVE => Self::AssertBeforeHeightRelative {
+                height: args.next()?,
+            },
+            ASSERT_BEFORE_HEIGHT_ABSOLUTE => Self::AssertBeforeHeightAbsolute {
+                height: args.next()?,
+            },
+            SOFTFORK => {
+                let cost = args.next()?;
+                return Ok(Self::Softfork {
+                    cost,
+                    rest: args.rest()?,
+                });
+            }
+            _ => {
+                return Ok(Self::Unknown {
+                    opcode,
+                    args: args.rest()?,
+                })
+            }
+        };
+        args.end(cond, strict)
+    }
+}
+
+impl<N, T: FromClvm<N>> FromClvm<N> for OwnedCondition<T> {
+    fn from_clvm(decoder: &impl ClvmDecoder<Node = N>, node: N) -> Result<Self, FromClvmError> {
+        Self::decode(decoder, &node, true)
+    }
+}
+
+impl<N, T: ToClvm<N>> ToClvm<N> for OwnedCondition<T> {
+    fn to_clvm(&self, encoder: &mut impl ClvmEncoder<Node = N>) -> Result<N, ToClvmError> {
+        match self {
+            Self::Remark(rest) => (REMARK, rest).to_clvm(encoder),
+            Self::AggSigParent {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_PARENT, public_key, message).to_clvm(encoder),
+            Self::AggSigPuzzle {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_PUZZLE, public_key, message).to_clvm(encoder),
+            Self::AggSigAmount {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_AMOUNT, public_key, message).to_clvm(encoder),
+            Self::AggSigPuzzleAmount {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_PUZZLE_AMOUNT, public_key, message).to_clvm(encoder),
+            Self::AggSigParentAmount {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_PARENT_AMOUNT, public_key, message).to_clvm(encoder),
+            Self::AggSigParentPuzzle {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_PARENT_PUZZLE, public_key, message).to_clvm(encoder),
+            Self::AggSigUnsafe {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_UNSAFE, public_key, message).to_clvm(encoder),
+            Self::AggSigMe {
+                public_key,
+                message,
+            } => clvm_list!(AGG_SIG_ME, public_key, message).to_clvm(encoder),
+            Self::CreateCoin {
+                puzzle_hash,
+                amount,
+                memos: None,
+            } => clvm_list!(CREATE_COIN, puzzle_hash, amount).to_clvm(encoder),
+            Self::CreateCoin {
+                puzzle_hash,
+                amount,
+                memos: Some(memos),
+            } => clvm_list!(CREATE_COIN, puzzle_hash, amount, memos).to_clvm(encoder),
+            Self::ReserveFee { amount } => clvm_list!(RESERVE_FEE, amount).to_clvm(encoder),
+            Self::CreateCoinAnnouncement { message } => {
+                clvm_list!(CREATE_COIN_ANNOUNCEMENT, message).to_clvm(encoder)
+            }
+            Self::AssertCoinAnnouncement { announcement_id } => {
+                clvm_list!(ASSERT_COIN_ANNOUNCEMENT, announcement_id).to_clvm(encoder)
+            }
+            Self::CreatePuzzleAnnouncement { message } => {
+                clvm_list!(CREATE_PUZZLE_ANNOUNCEMENT, message).to_clvm(encoder)
+            }
+            Self::AssertPuzzleAnnouncement { announcement_id } => {
+                clvm_list!(ASSERT_PUZZLE_ANNOUNCEMENT, announcement_id).to_clvm(encoder)
+            }
+            Self::AssertConcurrentSpend { coin_id } => {
+                clvm_list!(ASSERT_CONCURRENT_SPEND, coin_id).to_clvm(encoder)
+            }
+            Self::AssertConcurrentPuzzle { puzzle_hash } => {
+                clvm_list!(ASSERT_CONCURRENT_PUZZLE, puzzle_hash).to_clvm(encoder)
+            }
+            Self::SendMessage {
+                mode,
+                message,
+                data,
+            } => (SEND_MESSAGE, (mode, (message, data))).to_clvm(encoder),
+            Self::ReceiveMessage {
+                mode,
+                message,
+                data,
+            } => (RECEIVE_MESSAGE, (mode, (message, data))).to_clvm(encoder),
+            Self::AssertMyCoinId { coin_id } => {
+                clvm_list!(ASSERT_MY_COIN_ID, coin_id).to_clvm(encoder)
+            }
+            Self::AssertMyParentId { parent_id } => {
+                clvm_list!(ASSERT_MY_PARENT_ID, parent_id).to_clvm(encoder)
+            }
+            Self::AssertMyPuzzleHash { puzzle_hash } => {
+                clvm_list!(ASSERT_MY_PUZZLEHASH, puzzle_hash).to_clvm(encoder)
+            }
+            Self::AssertMyAmount { amount } => {
+                clvm_list!(ASSERT_MY_AMOUNT, amount).to_clvm(encoder)
+            }
+            Self::AssertMyBirthSeconds { seconds } => {
+                clvm_list!(ASSERT_MY_BIRTH_SECONDS, seconds).to_clvm(encoder)
+            }
+            Self::AssertMyBirthHeight { height } => {
+                clvm_list!(ASSERT_MY_BIRTH_HEIGHT, height).to_clvm(encoder)
+            }
+            Self::AssertEphemeral => clvm_list!(ASSERT_EPHEMERAL).to_clvm(encoder),
+            Self::AssertSecondsRelative { seconds } => {
+                clvm_list!(ASSERT_SECONDS_RELATIVE, seconds).to_clvm(encoder)
+            }
+            Self::AssertSecondsAbsolute { seconds } => {
+                clvm_list!(ASSERT_SECONDS_ABSOLUTE, seconds).to_clvm(encoder)
+            }
+            Self::AssertHeightRelative { height } => {
+                clvm_list!(ASSERT_HEIGHT_RELATIVE, height).to_clvm(encoder)
+            }
+            Self::AssertHeightAbsolute { height } => {
+                clvm_list!(ASSERT_HEIGHT_ABSOLUTE, height).to_clvm(encoder)
+            }
+            Self::AssertBeforeSecondsRelative { seconds } => {
+                clvm_list!(ASSERT_BEFORE_SECONDS_RELATIVE, seconds).to_clvm(encoder)
+            }
+            Self::AssertBeforeSecondsAbsolute { seconds } => {
+                clvm_list!(ASSERT_BEFORE_SECONDS_ABSOLUTE, seconds).to_clvm(encoder)
+            }
+            Self::AssertBeforeHeightRelative { height } => {
+                clvm_list!(ASSERT_BEFORE_HEIGHT_RELATIVE, height).to_clvm(encoder)
+            }
+            Self::AssertBeforeHeightAbsolute { height } => {
+                clvm_list!(ASSERT_BEFORE_HEIGHT_ABSOLUTE, height).to_clvm(encoder)
+            }
+            Self::Softfork { cost, rest } => (SOFTFORK, (cost, rest)).to_clvm(encoder),
+            Self::Unknown { opcode, args } => (opcode, args).to_clvm(encoder),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clvmr::serde::{node_from_bytes, node_to_bytes};
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    const PUBKEY: [u8; 48] = hex!("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb");
+
+    fn round_trip(cond: &OwnedCondition, expected: &str) {
+        let mut a = Allocator::new();
+        let node = cond.to_clvm(&mut a).expect("to_clvm");
+        assert_eq!(hex::encode(node_to_bytes(&a, node).unwrap()), expected);
+
+        let node = node_from_bytes(&mut a, &hex::decode(expected).unwrap()).unwrap();
+        let parsed = OwnedCondition::<NodePtr>::from_clvm(&a, node).expect("from_clvm");
+        // NodePtrs are only equal if they're the same node, compare the
+        // serialization instead
+        let node = parsed.to_clvm(&mut a).expect("to_clvm");
+        assert_eq!(hex::encode(node_to_bytes(&a, node).unwrap()), expected);
+    }
+
+    fn pk() -> PublicKey {
+        PublicKey::from_bytes(&PUBKEY).unwrap()
+    }
+
+    #[rstest]
+    #[case(OwnedCondition::AggSigParent { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff2b")]
+    #[case(OwnedCondition::AggSigPuzzle { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff2c")]
+    #[case(OwnedCondition::AggSigAmount { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff2d")]
+    #[case(OwnedCondition::AggSigPuzzleAmount { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff2e")]
+    #[case(OwnedCondition::AggSigParentAmount { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff2f")]
+    #[case(OwnedCondition::AggSigParentPuzzle { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff30")]
+    #[case(OwnedCondition::AggSigUnsafe { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff31")]
+    #[case(OwnedCondition::AggSigMe { public_key: pk(), message: Bytes::from(b"msg".as_slice()) }, "ff32")]
+    fn test_agg_sig(#[case] cond: OwnedCondition, #[case] prefix: &str) {
+        round_trip(
+            &cond,
+            &format!("{prefix}ffb0{}ff836d736780", hex::encode(PUBKEY)),
+        );
+    }
+
+    #[rstest]
+    #[case(OwnedCondition::ReserveFee { amount: 100 }, "ff34ff6480")]
+    #[case(OwnedCondition::CreateCoinAnnouncement { message: Bytes::from(b"msg".as_slice()) }, "ff3cff836d736780")]
+    #[case(OwnedCondition::AssertCoinAnnouncement { announcement_id: Bytes32::from([0x11; 32]) }, "ff3dffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::CreatePuzzleAnnouncement { message: Bytes::from(b"msg".as_slice()) }, "ff3eff836d736780")]
+    #[case(OwnedCondition::AssertPuzzleAnnouncement { announcement_id: Bytes32::from([0x11; 32]) }, "ff3fffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::AssertConcurrentSpend { coin_id: Bytes32::from([0x11; 32]) }, "ff40ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::AssertConcurrentPuzzle { puzzle_hash: Bytes32::from([0x11; 32]) }, "ff41ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::SendMessage { mode: 0x3f, message: Bytes::from(b"msg".as_slice()), data: vec![Bytes::from([0x11; 32].as_slice())] }, "ff42ff3fff836d7367ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::ReceiveMessage { mode: 0x12, message: Bytes::from(b"msg".as_slice()), data: vec![] }, "ff43ff12ff836d736780")]
+    #[case(OwnedCondition::AssertMyCoinId { coin_id: Bytes32::from([0x11; 32]) }, "ff46ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::AssertMyParentId { parent_id: Bytes32::from([0x11; 32]) }, "ff47ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::AssertMyPuzzleHash { puzzle_hash: Bytes32::from([0x11; 32]) }, "ff48ffa0111111111111111111111111111111111111111111111111111111111111111180")]
+    #[case(OwnedCondition::AssertMyAmount { amount: 0x80 }, "ff49ff82008080")]
+    #[case(OwnedCondition::AssertMyBirthSeconds { seconds: 1000 }, "ff4aff8203e880")]
+    #[case(OwnedCondition::AssertMyBirthHeight { height: 1000 }, "ff4bff8203e880")]
+    #[case(OwnedCondition::AssertEphemeral, "ff4c80")]
+    #[case(OwnedCondition::AssertSecondsRelative { seconds: 1 }, "ff50ff0180")]
+    #[case(OwnedCondition::AssertSecondsAbsolute { seconds: 1 }, "ff51ff0180")]
+    #[case(OwnedCondition::AssertHeightRelative { height: 1 }, "ff52ff0180")]
+    #[case(OwnedCondition::AssertHeightAbsolute { height: 1 }, "ff53ff0180")]
+    #[case(OwnedCondition::AssertBeforeSecondsRelative { seconds: 1 }, "ff54ff0180")]
+    #[case(OwnedCondition::AssertBeforeSecondsAbsolute { seconds: 1 }, "ff55ff0180")]
+    #[case(OwnedCondition::AssertBeforeHeightRelative { height: 1 }, "ff56ff0180")]
+    #[case(OwnedCondition::AssertBeforeHeightAbsolute { height: 1 }, "ff57ff0180")]
+    fn test_round_trip(#[case] cond: OwnedCondition, #[case] expected: &str) {
+        round_trip(&cond, expected);
+    }
+
+    #[test]
+    fn test_create_coin() {
+        let ph = Bytes32::from([0x11; 32]);
+        let cond = OwnedCondition::CreateCoin {
+            puzzle_hash: ph,
+            amount: 1,
+            memos: None,
+        };
+        round_trip(
+            &cond,
+            "ff33ffa01111111111111111111111111111111111111111111111111111111111111111ff0180",
+        );
+
+        let cond = OwnedCondition::CreateCoin {
+            puzzle_hash: ph,
+            amount: 1,
+            memos: Some(vec![
+                Bytes::from([0x22; 32].as_slice()),
+                Bytes::from(b"memo".as_slice()),
+            ]),
+        };
+        round_trip(
+            &cond,
+            "ff33ffa01111111111111111111111111111111111111111111111111111111111111111ff01ff\
+             ffa02222222222222222222222222222222222222222222222222222222222222222ff846d656d6f8080",
+        );
+    }
+
+    #[test]
+    fn test_remark_and_softfork() {
+        let mut a = Allocator::new();
+        let args = a.new_atom(b"foobar").unwrap();
+
+        let node = OwnedCondition::Remark(args).to_clvm(&mut a).unwrap();
+        assert_eq!(
+            hex::encode(node_to_bytes(&a, node).unwrap()),
+            "ff0186666f6f626172"
+        );
+        assert_eq!(
+            OwnedCondition::from_clvm(&a, node).unwrap(),
+            OwnedCondition::Remark(args)
+        );
+
+        let node = OwnedCondition::Softfork {
+            cost: 100,
+            rest: args,
+        }
+        .to_clvm(&mut a)
+        .unwrap();
+        assert_eq!(
+            hex::encode(node_to_bytes(&a, node).unwrap()),
+            "ff5aff6486666f6f626172"
+        );
+        assert_eq!(
+            OwnedCondition::from_clvm(&a, node).unwrap(),
+            OwnedCondition::Softfork {
+                cost: 100,
+                rest: args
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        let mut a = Allocator::new();
+        let args = a.new_atom(b"foobar").unwrap();
+        let cond = OwnedCondition::Unknown { opcode: 2, args };
+        let node = cond.to_clvm(&mut a).unwrap();
+        assert_eq!(
+            hex::encode(node_to_bytes(&a, node).unwrap()),
+            "ff0286666f6f626172"
+        );
+        assert_eq!(OwnedCondition::from_clvm(&a, node).unwrap(), cond);
+
+        // 2-byte opcodes are unknown too
+        let node = node_from_bytes(&mut a, &hex!("ff82010080")).unwrap();
+        assert!(matches!(
+            OwnedCondition::<NodePtr>::from_clvm(&a, node).unwrap(),
+            OwnedCondition::Unknown { opcode: 0x100, .. }
+        ));
+    }
+
+    #[rstest]
+    // extra argument
+    #[case("ff34ff64ff0180", OwnedCondition::ReserveFee { amount: 100 })]
+    #[case("ff4cff0180", OwnedCondition::AssertEphemeral)]
+    // extra argument after the memos
+    #[case(
+        "ff33ffa01111111111111111111111111111111111111111111111111111111111111111ff01ff80ff0180",
+        OwnedCondition::CreateCoin {
+            puzzle_hash: Bytes32::from([0x11; 32]),
+            amount: 1,
+            memos: Some(Vec::new()),
+        }
+    )]
+    fn test_lenient(#[case] input: &str, #[case] expected: OwnedCondition) {
+        let mut a = Allocator::new();
+        let node = node_from_bytes(&mut a, &hex::decode(input).unwrap()).unwrap();
+        assert!(OwnedCondition::<NodePtr>::from_clvm(&a, node).is_err());
+        assert_eq!(
+            OwnedCondition::from_clvm_lenient(&a, node).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    // opcode doesn't fit in 16 bits
+    #[case("ff8301000080")]
+    // invalid public key
+    #[case("ff32ff01ff836d736780")]
+    // puzzle hash is not 32 bytes
+    #[case("ff33ff01ff0180")]
+    // missing argument
+    #[case("ff3480")]
+    // not a list
+    #[case("34")]
+    fn test_invalid(#[case] input: &str) {
+        let mut a = Allocator::new();
+        let node = node_from_bytes(&mut a, &hex::decode(input).unwrap()).unwrap();
+        assert!(OwnedCondition::<NodePtr>::from_clvm(&a, node).is_err());
+        assert!(OwnedCondition::<NodePtr>::from_clvm_lenient(&a, node).is_err());
+    }
+}
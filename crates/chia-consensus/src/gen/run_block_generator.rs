@@ -80,6 +80,10 @@ pub fn run_block_generator<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
     // cost of a condition brings us over the cost limit
     let mut result = parse_spends::<V>(a, generator_output, cost_left, flags, constants)?;
     result.cost += max_cost - cost_left;
+    // the puzzles are run by the generator ROM, so the execution cost can only
+    // be attributed to the block as a whole
+    result.byte_cost = byte_cost;
+    result.execution_cost = clvm_cost;
     Ok(result)
 }
 
@@ -119,6 +123,28 @@ pub fn run_block_generator2<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
     max_cost: u64,
     flags: u32,
     constants: &ConsensusConstants,
+) -> Result<SpendBundleConditions, ValidationErr> {
+    run_block_generator2_with_state::<GenBuf, V>(
+        a,
+        program,
+        block_refs,
+        max_cost,
+        flags,
+        constants,
+        &mut ParseState::default(),
+    )
+}
+
+// Like run_block_generator2(), but with the ParseState passed in. This allows
+// the caller to trace where failures come from (see ParseState::with_trace())
+pub fn run_block_generator2_with_state<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
+    a: &mut Allocator,
+    program: &[u8],
+    block_refs: &[GenBuf],
+    max_cost: u64,
+    flags: u32,
+    constants: &ConsensusConstants,
+    state: &mut ParseState,
 ) -> Result<SpendBundleConditions, ValidationErr> {
     let byte_cost = program.len() as u64 * constants.cost_per_byte;
 
@@ -157,11 +183,11 @@ pub fn run_block_generator2<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
     // where extra may be nil, or additional extension data
 
     let mut ret = SpendBundleConditions::default();
-    let mut state = ParseState::default();
     let mut cache = HashMap::<NodePtr, TreeHash>::new();
 
     while let Some((spend, rest)) = a.next(all_spends) {
         all_spends = rest;
+        state.trace_spend_index(ret.spends.len());
         // process the spend
         let [parent_id, puzzle, amount, solution, _spend_level_extra] =
             extract_n::<5>(a, spend, ErrorCode::InvalidCondition)?;
@@ -177,7 +203,7 @@ pub fn run_block_generator2<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
         process_single_spend::<V>(
             a,
             &mut ret,
-            &mut state,
+            state,
             parent_id,
             puzzle_hash,
             amount,
@@ -186,6 +212,7 @@ pub fn run_block_generator2<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
             &mut cost_left,
             constants,
         )?;
+        ret.spends.last_mut().expect("spend").execution_cost = clvm_cost;
     }
     if a.atom_len(all_spends) != 0 {
         return Err(ValidationErr(all_spends, ErrorCode::GeneratorRuntimeError));
@@ -194,5 +221,9 @@ pub fn run_block_generator2<GenBuf: AsRef<[u8]>, V: SpendVisitor>(
     validate_conditions(a, &ret, state, a.nil(), flags)?;
 
     ret.cost = max_cost - cost_left;
+    // the generator's execution cost is whatever isn't accounted for by the
+    // puzzles and conditions
+    ret.byte_cost = byte_cost;
+    ret.execution_cost = ret.cost - byte_cost - ret.condition_cost;
     Ok(ret)
 }
@@ -37,6 +37,7 @@ pub fn run_puzzle<V: SpendVisitor>(
 
     let mut ret = SpendBundleConditions {
         removal_amount: amount as u128,
+        execution_cost: clvm_cost,
         ..Default::default()
     };
     let mut state = ParseState::default();
@@ -57,6 +58,7 @@ pub fn run_puzzle<V: SpendVisitor>(
         a.new_atom(&puzzle_hash)?,
         coin_id,
     );
+    spend.execution_cost = clvm_cost;
 
     let mut visitor = V::new_spend(&mut spend);
 
@@ -143,6 +143,19 @@ fn print_diff(output: &str, expected: &str) {
     }
 }
 
+// the cost is the sum of its components, and the condition cost is the sum
+// of the condition cost of all spends
+fn check_cost_breakdown(conds: &SpendBundleConditions) {
+    assert_eq!(
+        conds.cost,
+        conds.byte_cost + conds.execution_cost + conds.condition_cost
+    );
+    let spends_cost: u64 = conds.spends.iter().map(|s| s.condition_cost).sum();
+    assert_eq!(conds.condition_cost, spends_cost);
+    let spends_cost: u64 = conds.spends.iter().map(|s| s.execution_cost).sum();
+    assert!(conds.execution_cost >= spends_cost);
+}
+
 #[rstest]
 #[case("infinity-g1")]
 #[case("block-1ee588dc")]
@@ -234,7 +247,10 @@ fn run_generator(#[case] name: &str) {
         );
 
         let (expected_cost, output) = match conds {
-            Ok(conditions) => (conditions.cost, print_conditions(&a, &conditions)),
+            Ok(conditions) => {
+                check_cost_breakdown(&conditions);
+                (conditions.cost, print_conditions(&a, &conditions))
+            }
             Err(code) => (0, format!("FAILED: {}\n", u32::from(code.1))),
         };
 
@@ -249,6 +265,7 @@ fn run_generator(#[case] name: &str) {
         );
         let output_hard_fork = match conds {
             Ok(mut conditions) => {
+                check_cost_breakdown(&conditions);
                 // in the hard fork, the cost of running the genrator +
                 // puzzles should never be higher than before the hard-fork
                 // but it's likely less.
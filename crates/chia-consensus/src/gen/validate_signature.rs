@@ -0,0 +1,302 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::coin_id::u64_to_bytes;
+use crate::gen::conditions::SpendBundleConditions;
+use crate::gen::validation_error::{ErrorCode, ValidationErr};
+use chia_bls::{aggregate_verify, BlsCache, PublicKey, Signature};
+use clvmr::allocator::{Allocator, NodePtr};
+
+fn make_message(a: &Allocator, msg: NodePtr, parts: &[&[u8]], additional_data: &[u8]) -> Vec<u8> {
+    let mut ret = a.atom(msg).as_ref().to_vec();
+    for p in parts {
+        ret.extend_from_slice(p);
+    }
+    ret.extend_from_slice(additional_data);
+    ret
+}
+
+// collects all public keys and fully augmented messages that the aggregate
+// signature of a spend bundle (or block) must cover. Each AGG_SIG_* condition's
+// message is extended with the properties of the spend it commits to followed
+// by the corresponding additional data from the consensus constants.
+// AGG_SIG_UNSAFE messages are used as-is.
+pub fn pkm_pairs(
+    a: &Allocator,
+    conditions: &SpendBundleConditions,
+    constants: &ConsensusConstants,
+) -> Vec<(PublicKey, Vec<u8>)> {
+    let mut ret = Vec::<(PublicKey, Vec<u8>)>::new();
+
+    for (pk, msg) in &conditions.agg_sig_unsafe {
+        ret.push((*pk, a.atom(*msg).as_ref().to_vec()));
+    }
+
+    for spend in &conditions.spends {
+        let coin_id = spend.coin_id.as_ref().as_ref();
+        let parent = a.atom(spend.parent_id);
+        let parent = parent.as_ref();
+        let puzzle = a.atom(spend.puzzle_hash);
+        let puzzle = puzzle.as_ref();
+        let amount = u64_to_bytes(spend.coin_amount);
+        let amount = amount.as_slice();
+
+        let mut push =
+            |agg_sigs: &[(PublicKey, NodePtr)], parts: &[&[u8]], additional_data: &[u8]| {
+                for (pk, msg) in agg_sigs {
+                    ret.push((*pk, make_message(a, *msg, parts, additional_data)));
+                }
+            };
+
+        push(
+            &spend.agg_sig_me,
+            &[coin_id],
+            constants.agg_sig_me_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_parent,
+            &[parent],
+            constants.agg_sig_parent_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_puzzle,
+            &[puzzle],
+            constants.agg_sig_puzzle_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_amount,
+            &[amount],
+            constants.agg_sig_amount_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_puzzle_amount,
+            &[puzzle, amount],
+            constants.agg_sig_puzzle_amount_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_parent_amount,
+            &[parent, amount],
+            constants.agg_sig_parent_amount_additional_data.as_ref(),
+        );
+        push(
+            &spend.agg_sig_parent_puzzle,
+            &[parent, puzzle],
+            constants.agg_sig_parent_puzzle_additional_data.as_ref(),
+        );
+    }
+    ret
+}
+
+// validates the aggregate signature of a spend bundle (or block) against all
+// AGG_SIG_* conditions in the SpendBundleConditions. If a BlsCache is passed
+// in, pairings are looked up in (and added to) the cache. This is preferable
+// when validating transactions for the mempool, since the same pairings are
+// likely to be needed again once the transaction is included in a block.
+pub fn validate_signature(
+    a: &Allocator,
+    conditions: &SpendBundleConditions,
+    signature: &Signature,
+    constants: &ConsensusConstants,
+    cache: Option<&mut BlsCache>,
+) -> Result<(), ValidationErr> {
+    let pairs = pkm_pairs(a, conditions, constants);
+
+    let valid = if let Some(cache) = cache {
+        cache.aggregate_verify(
+            pairs.iter().map(|(pk, _)| pk),
+            pairs.iter().map(|(_, msg)| msg),
+            signature,
+        )
+    } else {
+        aggregate_verify(
+            signature,
+            pairs.iter().map(|(pk, msg)| (pk, msg.as_slice())),
+        )
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationErr(
+            NodePtr::NIL,
+            ErrorCode::BadAggregateSignature,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::conditions::Spend;
+    use chia_bls::{sign, SecretKey};
+    use chia_protocol::{Bytes32, Coin};
+    use rstest::rstest;
+    use std::sync::Arc;
+
+    const PARENT: [u8; 32] = [0x11; 32];
+    const PUZZLE: [u8; 32] = [0x22; 32];
+
+    fn make_spend(a: &mut Allocator, amount: u64) -> Spend {
+        let coin = Coin::new(PARENT.into(), PUZZLE.into(), amount);
+        let parent_id = a.new_atom(&PARENT).unwrap();
+        let puzzle_hash = a.new_atom(&PUZZLE).unwrap();
+        Spend::new(parent_id, amount, puzzle_hash, Arc::new(coin.coin_id()))
+    }
+
+    // the kind of AGG_SIG_* condition to add to the spend, along with the
+    // message the signer is expected to sign
+    fn add_condition(
+        a: &mut Allocator,
+        spend: &mut Spend,
+        conds: &mut SpendBundleConditions,
+        kind: u8,
+        pk: PublicKey,
+        msg: &[u8],
+    ) -> Vec<u8> {
+        let c = &TEST_CONSTANTS;
+        let coin_id: Bytes32 = *spend.coin_id;
+        let amount = u64_to_bytes(spend.coin_amount);
+        let node = a.new_atom(msg).unwrap();
+        let (list, suffix): (&mut Vec<(PublicKey, NodePtr)>, Vec<u8>) = match kind {
+            0 => {
+                conds.agg_sig_unsafe.push((pk, node));
+                return msg.to_vec();
+            }
+            1 => (
+                &mut spend.agg_sig_me,
+                [coin_id.as_ref(), c.agg_sig_me_additional_data.as_ref()].concat(),
+            ),
+            2 => (
+                &mut spend.agg_sig_parent,
+                [&PARENT, c.agg_sig_parent_additional_data.as_ref()].concat(),
+            ),
+            3 => (
+                &mut spend.agg_sig_puzzle,
+                [&PUZZLE, c.agg_sig_puzzle_additional_data.as_ref()].concat(),
+            ),
+            4 => (
+                &mut spend.agg_sig_amount,
+                [amount.as_slice(), c.agg_sig_amount_additional_data.as_ref()].concat(),
+            ),
+            5 => (
+                &mut spend.agg_sig_puzzle_amount,
+                [
+                    &PUZZLE,
+                    amount.as_slice(),
+                    c.agg_sig_puzzle_amount_additional_data.as_ref(),
+                ]
+                .concat(),
+            ),
+            6 => (
+                &mut spend.agg_sig_parent_amount,
+                [
+                    &PARENT,
+                    amount.as_slice(),
+                    c.agg_sig_parent_amount_additional_data.as_ref(),
+                ]
+                .concat(),
+            ),
+            7 => (
+                &mut spend.agg_sig_parent_puzzle,
+                [
+                    &PARENT,
+                    &PUZZLE,
+                    c.agg_sig_parent_puzzle_additional_data.as_ref(),
+                ]
+                .concat(),
+            ),
+            _ => panic!("unexpected"),
+        };
+        list.push((pk, node));
+        [msg, suffix.as_slice()].concat()
+    }
+
+    #[rstest]
+    fn test_validate_signature(
+        #[values(0, 1, 2, 3, 4, 5, 6, 7)] kind: u8,
+        #[values(0, 1, 0x80, 0xffff_ffff)] amount: u64,
+        #[values(true, false)] use_cache: bool,
+    ) {
+        let mut a = Allocator::new();
+        let sk = SecretKey::from_seed(&[0x42; 32]);
+        let pk = sk.public_key();
+
+        let mut conds = SpendBundleConditions::default();
+        let mut spend = make_spend(&mut a, amount);
+        let signed_msg = add_condition(&mut a, &mut spend, &mut conds, kind, pk, b"foobar");
+        conds.spends.push(spend);
+
+        let mut cache = BlsCache::default();
+        let sig = sign(&sk, signed_msg);
+        validate_signature(
+            &a,
+            &conds,
+            &sig,
+            &TEST_CONSTANTS,
+            use_cache.then_some(&mut cache),
+        )
+        .expect("validate_signature");
+
+        // a signature over just the raw message is not valid (unless this is
+        // an AGG_SIG_UNSAFE condition)
+        let sig = sign(&sk, b"foobar");
+        let ret = validate_signature(
+            &a,
+            &conds,
+            &sig,
+            &TEST_CONSTANTS,
+            use_cache.then_some(&mut cache),
+        );
+        if kind == 0 {
+            assert!(ret.is_ok());
+        } else {
+            assert_eq!(ret.unwrap_err().1, ErrorCode::BadAggregateSignature);
+        }
+    }
+
+    #[test]
+    fn test_multiple_signatures() {
+        let mut a = Allocator::new();
+        let sk1 = SecretKey::from_seed(&[1; 32]);
+        let sk2 = SecretKey::from_seed(&[2; 32]);
+
+        let mut conds = SpendBundleConditions::default();
+        let mut spend = make_spend(&mut a, 1337);
+        let msg1 = add_condition(&mut a, &mut spend, &mut conds, 1, sk1.public_key(), b"1");
+        let msg2 = add_condition(&mut a, &mut spend, &mut conds, 5, sk2.public_key(), b"2");
+        let msg3 = add_condition(&mut a, &mut spend, &mut conds, 0, sk2.public_key(), b"3");
+        conds.spends.push(spend);
+
+        let mut sig = sign(&sk1, &msg1);
+        sig.aggregate(&sign(&sk2, &msg2));
+        sig.aggregate(&sign(&sk2, msg3));
+
+        assert!(validate_signature(&a, &conds, &sig, &TEST_CONSTANTS, None).is_ok());
+
+        // missing one of the signatures
+        let mut sig = sign(&sk1, &msg1);
+        sig.aggregate(&sign(&sk2, &msg2));
+        assert_eq!(
+            validate_signature(&a, &conds, &sig, &TEST_CONSTANTS, None)
+                .unwrap_err()
+                .1,
+            ErrorCode::BadAggregateSignature
+        );
+    }
+
+    #[test]
+    fn test_no_conditions() {
+        let a = Allocator::new();
+        let conds = SpendBundleConditions::default();
+        assert!(
+            validate_signature(&a, &conds, &Signature::default(), &TEST_CONSTANTS, None).is_ok()
+        );
+
+        let sk = SecretKey::from_seed(&[1; 32]);
+        assert_eq!(
+            validate_signature(&a, &conds, &sign(&sk, b"foo"), &TEST_CONSTANTS, None)
+                .unwrap_err()
+                .1,
+            ErrorCode::BadAggregateSignature
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::allocator::make_allocator;
+use crate::consensus_constants::ConsensusConstants;
+use crate::fork_heights::get_flags_for_height;
+use crate::gen::conditions::{MempoolVisitor, ParseState};
+use crate::gen::owned_conditions::OwnedSpendBundleConditions;
+use crate::gen::run_block_generator::run_block_generator2_with_state;
+use crate::gen::solution_generator::solution_generator;
+use crate::gen::validate_signature::validate_signature;
+use crate::gen::validation_error::{ErrorCode, ValidationErr};
+use chia_protocol::{Bytes32, SpendBundle};
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::chia_dialect::LIMIT_HEAP;
+use std::collections::HashSet;
+
+// the break-down of the total cost of a spend bundle. The sum of the three
+// fields is the same as the cost field of the SpendBundleConditions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpendBundleCost {
+    // the cost of the serialized generator (cost_per_byte for every byte)
+    pub byte_cost: u64,
+    // the CLVM cost of running the generator and all puzzles
+    pub execution_cost: u64,
+    // the fixed cost of CREATE_COIN and AGG_SIG_* conditions
+    pub condition_cost: u64,
+}
+
+// validates a spend bundle the way the mempool does. A generator is built from
+// the coin spends, it's run with the rules in effect at the specified height
+// (in mempool mode) and the aggregate signature is validated against the
+// resulting conditions. The cost limit is max_block_cost_clvm. Any additional
+// flags are applied on top of the ones derived from the height.
+// On success, returns the conditions along with a break-down of the cost
+pub fn validate_spend_bundle(
+    spend_bundle: &SpendBundle,
+    height: u32,
+    constants: &ConsensusConstants,
+    flags: u32,
+) -> Result<(OwnedSpendBundleConditions, SpendBundleCost), ErrorCode> {
+    let mut a = make_allocator(LIMIT_HEAP);
+    validate_spend_bundle_with_state(
+        &mut a,
+        spend_bundle,
+        height,
+        constants,
+        flags,
+        &mut ParseState::default(),
+    )
+    .map_err(|e| e.1)
+}
+
+// Like validate_spend_bundle(), but with the allocator and ParseState passed
+// in. This allows the caller to trace where failures come from (see
+// ParseState::with_trace()). The allocator should be created with
+// make_allocator(LIMIT_HEAP)
+pub fn validate_spend_bundle_with_state(
+    a: &mut Allocator,
+    spend_bundle: &SpendBundle,
+    height: u32,
+    constants: &ConsensusConstants,
+    flags: u32,
+    state: &mut ParseState,
+) -> Result<(OwnedSpendBundleConditions, SpendBundleCost), ValidationErr> {
+    let flags = get_flags_for_height(height, constants, true) | flags;
+
+    let generator = solution_generator(
+        spend_bundle
+            .coin_spends
+            .iter()
+            .map(|cs| (cs.coin, cs.puzzle_reveal.as_slice(), cs.solution.as_slice())),
+    )
+    .map_err(|_| ValidationErr(NodePtr::NIL, ErrorCode::InvalidSpendBundle))?;
+
+    let conds = run_block_generator2_with_state::<&[u8], MempoolVisitor>(
+        a,
+        &generator,
+        &[],
+        constants.max_block_cost_clvm,
+        flags,
+        constants,
+        state,
+    )?;
+
+    // the generator only commits to the puzzle reveal, not the puzzle hash
+    // of the coins being spent, so we need to make sure they match
+    let spent_coins: HashSet<Bytes32> = conds.spends.iter().map(|s| *s.coin_id).collect();
+    for (idx, cs) in spend_bundle.coin_spends.iter().enumerate() {
+        if !spent_coins.contains(&cs.coin.coin_id()) {
+            // solution_generator() lists the spends in reverse order
+            state.trace_spend_index(spend_bundle.coin_spends.len() - 1 - idx);
+            return Err(ValidationErr(NodePtr::NIL, ErrorCode::WrongPuzzleHash));
+        }
+    }
+
+    validate_signature(
+        a,
+        &conds,
+        &spend_bundle.aggregated_signature,
+        constants,
+        None,
+    )?;
+
+    let cost = SpendBundleCost {
+        byte_cost: conds.byte_cost,
+        execution_cost: conds.execution_cost,
+        condition_cost: conds.condition_cost,
+    };
+    Ok((OwnedSpendBundleConditions::from(a, conds), cost))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::opcodes::{
+        ConditionOpcode, AGG_SIG_COST, AGG_SIG_ME, CREATE_COIN, CREATE_COIN_COST, RESERVE_FEE,
+    };
+    use chia_bls::{sign, SecretKey, Signature};
+    use chia_protocol::{Bytes, Coin, CoinSpend, Program};
+    use clvm_traits::ToClvm;
+    use clvm_utils::tree_hash_from_bytes;
+    use clvmr::serde::node_to_bytes;
+    use clvmr::Allocator;
+
+    // returns a puzzle that ignores its solution and just returns the
+    // specified conditions (i.e. a quoted list)
+    fn quoted_puzzle(conditions: &[(ConditionOpcode, Vec<u8>, u64)]) -> Program {
+        let mut a = Allocator::new();
+        let conds: Vec<_> = conditions
+            .iter()
+            .map(|(op, arg, amount)| {
+                if *op == CREATE_COIN {
+                    (*op, (Bytes::from(arg.clone()), (*amount, ()))).to_clvm(&mut a)
+                } else if *op == RESERVE_FEE {
+                    (*op, (*amount, ())).to_clvm(&mut a)
+                } else {
+                    (
+                        *op,
+                        (Bytes::from(arg.clone()), (Bytes::from(b"msg".to_vec()), ())),
+                    )
+                        .to_clvm(&mut a)
+                }
+                .unwrap()
+            })
+            .collect();
+        let quoted = (1, conds).to_clvm(&mut a).unwrap();
+        Program::new(node_to_bytes(&a, quoted).unwrap().into())
+    }
+
+    fn make_bundle(sk: &SecretKey, amount: u64, sign_it: bool) -> SpendBundle {
+        let pk = sk.public_key();
+        let puzzle = quoted_puzzle(&[
+            (CREATE_COIN, vec![0x22; 32], amount - 10),
+            (RESERVE_FEE, vec![], 10),
+            (AGG_SIG_ME, pk.to_bytes().to_vec(), 0),
+        ]);
+        let puzzle_hash = tree_hash_from_bytes(puzzle.as_ref()).unwrap();
+        let coin = Coin::new(Bytes32::from([0x11; 32]), puzzle_hash.into(), amount);
+        let sig = if sign_it {
+            let msg = [
+                b"msg".as_slice(),
+                coin.coin_id().as_ref(),
+                TEST_CONSTANTS.agg_sig_me_additional_data.as_ref(),
+            ]
+            .concat();
+            sign(sk, msg)
+        } else {
+            Signature::default()
+        };
+        SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle, Program::from(vec![0x80]))],
+            sig,
+        )
+    }
+
+    #[test]
+    fn test_validate_spend_bundle() {
+        let sk = SecretKey::from_seed(&[0x42; 32]);
+        let bundle = make_bundle(&sk, 1000, true);
+        let (conds, cost) =
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).expect("validate_spend_bundle");
+
+        assert_eq!(conds.spends.len(), 1);
+        assert_eq!(conds.reserve_fee, 10);
+        assert_eq!(conds.removal_amount, 1000);
+        assert_eq!(conds.addition_amount, 990);
+        assert_eq!(
+            conds.spends[0].coin_id,
+            bundle.coin_spends[0].coin.coin_id()
+        );
+        assert_eq!(conds.spends[0].agg_sig_me.len(), 1);
+
+        assert_eq!(cost.condition_cost, CREATE_COIN_COST + AGG_SIG_COST);
+        assert!(cost.byte_cost > 0);
+        assert!(cost.execution_cost > 0);
+        assert_eq!(
+            cost.byte_cost + cost.execution_cost + cost.condition_cost,
+            conds.cost
+        );
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let sk = SecretKey::from_seed(&[0x42; 32]);
+        let bundle = make_bundle(&sk, 1000, false);
+        assert_eq!(
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).unwrap_err(),
+            ErrorCode::BadAggregateSignature
+        );
+    }
+
+    #[test]
+    fn test_invalid_condition() {
+        // an unknown condition is not allowed in mempool mode
+        let puzzle = quoted_puzzle(&[(200, vec![0x11; 32], 0)]);
+        let puzzle_hash = tree_hash_from_bytes(puzzle.as_ref()).unwrap();
+        let coin = Coin::new(Bytes32::from([0x11; 32]), puzzle_hash.into(), 1);
+        let bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle, Program::from(vec![0x80]))],
+            Signature::default(),
+        );
+        assert_eq!(
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).unwrap_err(),
+            ErrorCode::InvalidConditionOpcode
+        );
+    }
+
+    #[test]
+    fn test_wrong_puzzle_hash() {
+        let sk = SecretKey::from_seed(&[0x42; 32]);
+        let mut bundle = make_bundle(&sk, 1000, true);
+        // the puzzle reveal doesn't match the coin's puzzle hash
+        bundle.coin_spends[0].coin.puzzle_hash = Bytes32::from([0x33; 32]);
+        assert_eq!(
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).unwrap_err(),
+            ErrorCode::WrongPuzzleHash
+        );
+    }
+
+    #[test]
+    fn test_aggregated_bundles() {
+        let sk1 = SecretKey::from_seed(&[1; 32]);
+        let sk2 = SecretKey::from_seed(&[2; 32]);
+        let bundle =
+            SpendBundle::aggregate(&[make_bundle(&sk1, 1000, true), make_bundle(&sk2, 2000, true)]);
+        let (conds, cost) =
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).expect("validate_spend_bundle");
+
+        assert_eq!(conds.spends.len(), 2);
+        assert_eq!(conds.reserve_fee, 20);
+        assert_eq!(conds.removal_amount, 3000);
+        assert_eq!(conds.addition_amount, 2980);
+        assert_eq!(cost.condition_cost, 2 * (CREATE_COIN_COST + AGG_SIG_COST));
+        assert_eq!(
+            cost.byte_cost + cost.execution_cost + cost.condition_cost,
+            conds.cost
+        );
+
+        // if one of the signatures is missing, the whole bundle is invalid
+        let bundle = SpendBundle::aggregate(&[
+            make_bundle(&sk1, 1000, true),
+            make_bundle(&sk2, 2000, false),
+        ]);
+        assert_eq!(
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).unwrap_err(),
+            ErrorCode::BadAggregateSignature
+        );
+    }
+}
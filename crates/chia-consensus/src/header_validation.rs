@@ -0,0 +1,1490 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::difficulty_adjustment::{can_finish_sub_and_full_epoch, get_block, BlockRecords};
+use crate::gen::validation_error::ErrorCode;
+use crate::pot_iterations::{
+    block_ip_iters, calculate_ip_iters, calculate_required_iters, calculate_sp_iters,
+    ip_sub_slot_total_iters, is_overflow_block,
+};
+use crate::proof_of_space::{sha256, verify_and_get_quality_string};
+use chia_bls::verify;
+use chia_protocol::{
+    BlockRecord, Bytes32, EndOfSubSlotBundle, Foliage, FoliageTransactionBlock, HeaderBlock,
+    RewardChainBlockUnfinished, SubEpochSummary, UnfinishedHeaderBlock, VDFProof,
+};
+use chia_traits::Streamable;
+use std::borrow::Cow;
+
+fn hash<T: Streamable>(value: &T) -> Bytes32 {
+    Bytes32::from(value.hash())
+}
+
+// The block the header block builds on, or None for the genesis block
+fn get_prev_block<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    prev_header_hash: &Bytes32,
+) -> Result<Option<BlockRecord>, ErrorCode> {
+    match blocks.block_record(prev_header_hash) {
+        Some(prev_b) => Ok(Some(prev_b)),
+        None if *prev_header_hash == constants.genesis_challenge => Ok(None),
+        None => Err(ErrorCode::InvalidPrevBlockHash),
+    }
+}
+
+// The challenge chain sub-slot hashes finished before the block after prev_b,
+// most recent first. We stop once we have at least count of them, or when we
+// reach the genesis block, whose first sub-slot starts at the genesis
+// challenge
+fn reversed_challenge_slot_hashes<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    prev_b: &BlockRecord,
+    count: usize,
+) -> Result<Vec<Bytes32>, ErrorCode> {
+    let mut ret = Vec::new();
+    let mut curr = prev_b.clone();
+    loop {
+        if let Some(hashes) = &curr.finished_challenge_slot_hashes {
+            ret.extend(hashes.iter().rev());
+            if ret.len() >= count {
+                return Ok(ret);
+            }
+        }
+        if curr.height == 0 {
+            ret.push(constants.genesis_challenge);
+            return Ok(ret);
+        }
+        curr = get_block(blocks, &curr.prev_hash)?;
+    }
+}
+
+// The challenge hash of the sub-slot the block's signage point is in. For
+// overflow blocks, that's the sub-slot before the one it's infused in
+pub fn get_block_challenge<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    finished_sub_slots: &[EndOfSubSlotBundle],
+    prev_b: Option<&BlockRecord>,
+    overflow: bool,
+) -> Result<Bytes32, ErrorCode> {
+    if let Some(last) = finished_sub_slots.last() {
+        if overflow {
+            Ok(last
+                .challenge_chain
+                .challenge_chain_end_of_slot_vdf
+                .challenge)
+        } else {
+            Ok(hash(&last.challenge_chain))
+        }
+    } else if let Some(prev_b) = prev_b {
+        let count = if overflow { 2 } else { 1 };
+        reversed_challenge_slot_hashes(constants, blocks, prev_b, count)?
+            .get(count - 1)
+            .copied()
+            .ok_or(ErrorCode::InvalidCcChallenge)
+    } else {
+        Ok(constants.genesis_challenge)
+    }
+}
+
+// The sub-epoch summary included in the first sub-slot after the block at
+// blocks_included_height - 1, whose parent is prev_prev_block. It refers to
+// the previous summary and the reward chain at the end of the sub-epoch
+// before the one that just finished
+pub fn make_sub_epoch_summary<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    blocks_included_height: u32,
+    prev_prev_block: &BlockRecord,
+    new_difficulty: Option<u64>,
+    new_sub_slot_iters: Option<u64>,
+) -> Result<SubEpochSummary, ErrorCode> {
+    if prev_prev_block.height + 2 != blocks_included_height {
+        return Err(ErrorCode::InvalidHeight);
+    }
+    // the first sub-epoch. Assuming fewer than MAX_SUB_SLOT_BLOCKS overflow
+    // blocks are infused in the first two sub-slots, this is exact
+    if (blocks_included_height + constants.max_sub_slot_blocks) / constants.sub_epoch_blocks <= 1 {
+        return Ok(SubEpochSummary::new(
+            constants.genesis_challenge,
+            constants.genesis_challenge,
+            0,
+            None,
+            None,
+        ));
+    }
+    let mut curr = prev_prev_block.clone();
+    while curr.sub_epoch_summary_included.is_none() {
+        curr = get_block(blocks, &curr.prev_hash)?;
+    }
+    let reward_chain_hash = curr
+        .finished_reward_slot_hashes
+        .as_ref()
+        .and_then(|hashes| hashes.last().copied())
+        .ok_or(ErrorCode::InvalidSubEpochSummary)?;
+    let prev_ses = curr.sub_epoch_summary_included.as_ref().expect("loop");
+    Ok(SubEpochSummary::new(
+        hash(prev_ses),
+        reward_chain_hash,
+        (curr.height % constants.sub_epoch_blocks) as u8,
+        new_difficulty,
+        new_sub_slot_iters,
+    ))
+}
+
+// The deficit of a block at height, after prev_b. It counts down from
+// MIN_BLOCKS_PER_CHALLENGE_BLOCK - 1 at the challenge block, to 0. Once it
+// reaches 0, the next sub-slot starts a new countdown. An overflow block
+// after the challenge block, still in the same sub-slot, keeps the deficit at
+// MIN_BLOCKS_PER_CHALLENGE_BLOCK
+pub fn calculate_deficit(
+    constants: &ConsensusConstants,
+    height: u32,
+    prev_b: Option<&BlockRecord>,
+    overflow: bool,
+    num_finished_sub_slots: usize,
+) -> u8 {
+    let min_blocks = constants.min_blocks_per_challenge_block;
+    let Some(prev_b) = prev_b.filter(|_| height > 0) else {
+        return min_blocks - 1;
+    };
+    match prev_b.deficit {
+        d if d == min_blocks => {
+            if overflow && num_finished_sub_slots == 0 {
+                d
+            } else {
+                d - 1
+            }
+        }
+        0 => match num_finished_sub_slots {
+            0 => 0,
+            1 if overflow => min_blocks,
+            _ => min_blocks - 1,
+        },
+        d => d - 1,
+    }
+}
+
+// The challenge and number of iterations of the infused challenge chain in
+// the first sub-slot finished after prev_b, or None if there's no infused
+// challenge chain. It only exists while the deficit is below
+// MIN_BLOCKS_PER_CHALLENGE_BLOCK, and starts at the challenge block, or at
+// the end of the previous sub-slot if the challenge block was in an earlier
+// one
+fn infused_challenge<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    prev_b: &BlockRecord,
+) -> Result<Option<(Bytes32, u64)>, ErrorCode> {
+    let min_blocks = constants.min_blocks_per_challenge_block;
+    if prev_b.deficit >= min_blocks {
+        return Ok(None);
+    }
+    let mut curr = prev_b.clone();
+    while !curr.is_challenge_block(min_blocks) && !curr.first_in_sub_slot() {
+        curr = get_block(blocks, &curr.prev_hash)?;
+    }
+    if curr.is_challenge_block(min_blocks) {
+        let iters = prev_b
+            .sub_slot_iters
+            .checked_sub(block_ip_iters(constants, &curr)?)
+            .ok_or(ErrorCode::InvalidIccEosVdf)?;
+        Ok(Some((curr.challenge_block_info_hash, iters)))
+    } else {
+        let challenge = curr
+            .finished_infused_challenge_slot_hashes
+            .as_ref()
+            .and_then(|hashes| hashes.last().copied())
+            .ok_or(ErrorCode::ShouldHaveIcc)?;
+        Ok(Some((challenge, prev_b.sub_slot_iters)))
+    }
+}
+
+// Validates the end of sub-slot bundles finished since prev_b: they must
+// continue the challenge, infused challenge and reward chains, have the right
+// number of iterations and deficit, and include a sub-epoch summary (and a
+// new difficulty and sub_slot_iters) exactly when one is due. The VDF proofs
+// themselves are not checked here
+fn validate_finished_sub_slots<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    finished_sub_slots: &[EndOfSubSlotBundle],
+    prev_b: Option<&BlockRecord>,
+    expected_difficulty: u64,
+    expected_sub_slot_iters: u64,
+) -> Result<(), ErrorCode> {
+    let (can_finish_se, can_finish_epoch) = match prev_b {
+        Some(prev_b) if !finished_sub_slots.is_empty() => can_finish_sub_and_full_epoch(
+            constants,
+            blocks,
+            prev_b.height,
+            &prev_b.prev_hash,
+            prev_b.deficit,
+            prev_b.sub_epoch_summary_included.is_some(),
+        )?,
+        _ => (false, false),
+    };
+
+    for (n, sub_slot) in finished_sub_slots.iter().enumerate() {
+        let cc = &sub_slot.challenge_chain;
+        let icc = &sub_slot.infused_challenge_chain;
+        let rc = &sub_slot.reward_chain;
+
+        // the first sub-slot ends the one prev_b was infused in, the others
+        // follow the previous sub-slot in this block
+        let (cc_challenge, rc_challenge, cc_iters, rc_iters) = if n > 0 {
+            let prev_slot = &finished_sub_slots[n - 1];
+            (
+                hash(&prev_slot.challenge_chain),
+                hash(&prev_slot.reward_chain),
+                expected_sub_slot_iters,
+                expected_sub_slot_iters,
+            )
+        } else if let Some(prev_b) = prev_b {
+            let cc_challenge = reversed_challenge_slot_hashes(constants, blocks, prev_b, 1)?[0];
+            let rc_iters = prev_b
+                .sub_slot_iters
+                .checked_sub(block_ip_iters(constants, prev_b)?)
+                .ok_or(ErrorCode::InvalidRcEosVdf)?;
+            (
+                cc_challenge,
+                prev_b.reward_infusion_new_challenge,
+                prev_b.sub_slot_iters,
+                rc_iters,
+            )
+        } else {
+            (
+                constants.genesis_challenge,
+                constants.genesis_challenge,
+                expected_sub_slot_iters,
+                expected_sub_slot_iters,
+            )
+        };
+
+        if cc.challenge_chain_end_of_slot_vdf.challenge != cc_challenge {
+            return Err(ErrorCode::InvalidPrevChallengeSlotHash);
+        }
+        if cc.challenge_chain_end_of_slot_vdf.number_of_iterations != cc_iters {
+            return Err(ErrorCode::InvalidCcEosVdf);
+        }
+        if rc.end_of_slot_vdf.challenge != rc_challenge
+            || rc.end_of_slot_vdf.number_of_iterations != rc_iters
+        {
+            return Err(ErrorCode::InvalidRcEosVdf);
+        }
+        if rc.challenge_chain_sub_slot_hash != hash(cc) {
+            return Err(ErrorCode::InvalidChallengeSlotHashRc);
+        }
+
+        // the infused challenge chain continues from the previous sub-slot
+        // as long as its deficit is below MIN_BLOCKS_PER_CHALLENGE_BLOCK
+        let expected_icc = if n > 0 {
+            let prev_slot = &finished_sub_slots[n - 1];
+            if prev_slot.reward_chain.deficit < constants.min_blocks_per_challenge_block {
+                let prev_icc = prev_slot
+                    .infused_challenge_chain
+                    .as_ref()
+                    .ok_or(ErrorCode::ShouldHaveIcc)?;
+                Some((hash(prev_icc), expected_sub_slot_iters))
+            } else {
+                None
+            }
+        } else if let Some(prev_b) = prev_b {
+            infused_challenge(constants, blocks, prev_b)?
+        } else {
+            None
+        };
+        match (icc, expected_icc) {
+            (None, None) => {}
+            (Some(_), None) => return Err(ErrorCode::ShouldNotHaveIcc),
+            (None, Some(_)) => return Err(ErrorCode::ShouldHaveIcc),
+            (Some(icc), Some((icc_challenge, icc_iters))) => {
+                let vdf = &icc.infused_challenge_chain_end_of_slot_vdf;
+                if vdf.challenge != icc_challenge || vdf.number_of_iterations != icc_iters {
+                    return Err(ErrorCode::InvalidIccEosVdf);
+                }
+            }
+        }
+        if icc.is_some() != sub_slot.proofs.infused_challenge_chain_slot_proof.is_some() {
+            return Err(ErrorCode::InvalidIccEosVdf);
+        }
+
+        // the reward chain always commits to the infused challenge chain, the
+        // challenge chain only when the deficit resets
+        let icc_hash = icc.as_ref().map(hash);
+        if rc.infused_challenge_chain_sub_slot_hash != icc_hash {
+            return Err(ErrorCode::InvalidIccHashRc);
+        }
+        let cc_icc_hash =
+            icc_hash.filter(|_| rc.deficit == constants.min_blocks_per_challenge_block);
+        if cc.infused_challenge_chain_sub_slot_hash != cc_icc_hash {
+            return Err(ErrorCode::InvalidIccHashCc);
+        }
+
+        // the deficit resets once it reaches 0. Otherwise it carries over
+        // to the end of the sub-slot
+        let expected_deficit = match prev_b {
+            Some(prev_b) if prev_b.deficit > 0 => prev_b.deficit,
+            _ => constants.min_blocks_per_challenge_block,
+        };
+        if rc.deficit != expected_deficit {
+            return Err(ErrorCode::InvalidDeficit);
+        }
+
+        // only the first sub-slot can finish a sub-epoch
+        match (cc.subepoch_summary_hash, prev_b) {
+            (Some(ses_hash), Some(prev_b)) if n == 0 => {
+                if !can_finish_se {
+                    return Err(ErrorCode::InvalidSubEpochSummaryHash);
+                }
+                let prev_prev_b = get_block(blocks, &prev_b.prev_hash)?;
+                let expected_ses = make_sub_epoch_summary(
+                    constants,
+                    blocks,
+                    prev_b.height + 1,
+                    &prev_prev_b,
+                    can_finish_epoch.then_some(expected_difficulty),
+                    can_finish_epoch.then_some(expected_sub_slot_iters),
+                )?;
+                if hash(&expected_ses) != ses_hash {
+                    return Err(ErrorCode::InvalidSubEpochSummary);
+                }
+            }
+            (Some(_), _) => return Err(ErrorCode::InvalidSubEpochSummaryHash),
+            (None, _) => {
+                if n == 0 && can_finish_se {
+                    return Err(ErrorCode::InvalidSubEpochSummary);
+                }
+            }
+        }
+
+        // and only the first sub-slot of an epoch sets the new difficulty
+        // and sub_slot_iters
+        let finishes_epoch = n == 0 && can_finish_epoch;
+        if cc.new_sub_slot_iters != finishes_epoch.then_some(expected_sub_slot_iters) {
+            return Err(ErrorCode::InvalidNewSubSlotIters);
+        }
+        if cc.new_difficulty != finishes_epoch.then_some(expected_difficulty) {
+            return Err(ErrorCode::InvalidNewDifficulty);
+        }
+    }
+    Ok(())
+}
+
+// the parts of a header block checked by validate_unfinished_header_block().
+// They're borrowed, so a HeaderBlock can be validated the same way without
+// converting it into an UnfinishedHeaderBlock
+struct UnfinishedParts<'a> {
+    finished_sub_slots: &'a [EndOfSubSlotBundle],
+    reward_chain_block: Cow<'a, RewardChainBlockUnfinished>,
+    challenge_chain_sp_proof: Option<&'a VDFProof>,
+    reward_chain_sp_proof: Option<&'a VDFProof>,
+    foliage: &'a Foliage,
+    foliage_transaction_block: Option<&'a FoliageTransactionBlock>,
+    transactions_filter: &'a [u8],
+}
+
+impl<'a> From<&'a UnfinishedHeaderBlock> for UnfinishedParts<'a> {
+    fn from(block: &'a UnfinishedHeaderBlock) -> Self {
+        Self {
+            finished_sub_slots: &block.finished_sub_slots,
+            reward_chain_block: Cow::Borrowed(&block.reward_chain_block),
+            challenge_chain_sp_proof: block.challenge_chain_sp_proof.as_ref(),
+            reward_chain_sp_proof: block.reward_chain_sp_proof.as_ref(),
+            foliage: &block.foliage,
+            foliage_transaction_block: block.foliage_transaction_block.as_ref(),
+            transactions_filter: &block.transactions_filter,
+        }
+    }
+}
+
+impl<'a> From<&'a HeaderBlock> for UnfinishedParts<'a> {
+    fn from(block: &'a HeaderBlock) -> Self {
+        Self {
+            finished_sub_slots: &block.finished_sub_slots,
+            reward_chain_block: Cow::Owned(block.reward_chain_block.get_unfinished()),
+            challenge_chain_sp_proof: block.challenge_chain_sp_proof.as_ref(),
+            reward_chain_sp_proof: block.reward_chain_sp_proof.as_ref(),
+            foliage: &block.foliage,
+            foliage_transaction_block: block.foliage_transaction_block.as_ref(),
+            transactions_filter: &block.transactions_filter,
+        }
+    }
+}
+
+// Validates an unfinished header block against the chain it builds on, in
+// blocks. expected_difficulty and expected_sub_slot_iters are the values for
+// this block (see get_next_sub_slot_iters_and_difficulty) and current_time is
+// used to reject timestamps too far in the future. The filter hash is only
+// checked when check_filter is set. Returns the required iterations of the
+// proof of space.
+// This does not validate the VDF proofs or the reward chain signage point
+// signature, which depend on the VDF outputs of earlier blocks.
+pub fn validate_unfinished_header_block<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    header_block: &UnfinishedHeaderBlock,
+    check_filter: bool,
+    expected_difficulty: u64,
+    expected_sub_slot_iters: u64,
+    current_time: u64,
+) -> Result<u64, ErrorCode> {
+    validate_unfinished_parts(
+        constants,
+        blocks,
+        &UnfinishedParts::from(header_block),
+        check_filter,
+        expected_difficulty,
+        expected_sub_slot_iters,
+        current_time,
+    )
+}
+
+fn validate_unfinished_parts<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    header_block: &UnfinishedParts<'_>,
+    check_filter: bool,
+    expected_difficulty: u64,
+    expected_sub_slot_iters: u64,
+    current_time: u64,
+) -> Result<u64, ErrorCode> {
+    let prev_b = get_prev_block(constants, blocks, &header_block.foliage.prev_block_hash)?;
+    let prev_b = prev_b.as_ref();
+    let height = prev_b.map_or(0, |b| b.height + 1);
+    let rcb = &*header_block.reward_chain_block;
+    let pos = &rcb.proof_of_space;
+    let finished_sub_slots = header_block.finished_sub_slots;
+    let overflow = is_overflow_block(constants, rcb.signage_point_index)?;
+
+    // 1. the end of sub-slot bundles finished since prev_b
+    validate_finished_sub_slots(
+        constants,
+        blocks,
+        finished_sub_slots,
+        prev_b,
+        expected_difficulty,
+        expected_sub_slot_iters,
+    )?;
+
+    // the signage point of an overflow block would be in the previous epoch
+    if overflow
+        && finished_sub_slots.len() == 1
+        && finished_sub_slots[0]
+            .challenge_chain
+            .new_sub_slot_iters
+            .is_some()
+    {
+        return Err(ErrorCode::NoOverflowsInFirstSubSlotNewEpoch);
+    }
+
+    // 2. the signage point and proof of space
+    let challenge = get_block_challenge(constants, blocks, finished_sub_slots, prev_b, overflow)?;
+    if rcb.pos_ss_cc_challenge_hash != challenge {
+        return Err(ErrorCode::InvalidCcChallenge);
+    }
+    if rcb.challenge_chain_sp_vdf.is_some() != header_block.challenge_chain_sp_proof.is_some() {
+        return Err(ErrorCode::InvalidCcSpVdf);
+    }
+    if rcb.reward_chain_sp_vdf.is_some() != header_block.reward_chain_sp_proof.is_some() {
+        return Err(ErrorCode::InvalidRcSpVdf);
+    }
+    // the first signage point of a sub-slot is its challenge
+    let cc_sp_hash = match (&rcb.challenge_chain_sp_vdf, rcb.signage_point_index) {
+        (None, 0) => challenge,
+        (Some(cc_sp_vdf), index) if index > 0 => hash(&cc_sp_vdf.output),
+        _ => return Err(ErrorCode::InvalidSpIndex),
+    };
+    let quality_string =
+        verify_and_get_quality_string(pos, constants, &challenge, &cc_sp_hash, height)
+            .ok_or(ErrorCode::InvalidPospace)?;
+    let required_iters = calculate_required_iters(
+        constants,
+        &quality_string,
+        pos.size,
+        expected_difficulty,
+        &cc_sp_hash,
+        expected_sub_slot_iters,
+    )?;
+    if !verify(
+        &rcb.challenge_chain_sp_signature,
+        &pos.plot_public_key,
+        cc_sp_hash,
+    ) {
+        return Err(ErrorCode::InvalidCcSignature);
+    }
+
+    // 3. total iterations, from the start of the sub-slot the block is
+    // infused in
+    let ip_iters = calculate_ip_iters(
+        constants,
+        expected_sub_slot_iters,
+        rcb.signage_point_index,
+        required_iters,
+    )?;
+    let num_sub_slots = finished_sub_slots.len() as u128;
+    let sub_slot_start = match prev_b {
+        None => u128::from(expected_sub_slot_iters) * num_sub_slots,
+        Some(prev_b) => {
+            let start = ip_sub_slot_total_iters(constants, prev_b)?;
+            if num_sub_slots > 0 {
+                start
+                    + u128::from(prev_b.sub_slot_iters)
+                    + u128::from(expected_sub_slot_iters) * (num_sub_slots - 1)
+            } else {
+                start
+            }
+        }
+    };
+    if rcb.total_iters != sub_slot_start + u128::from(ip_iters) {
+        return Err(ErrorCode::InvalidTotalIters);
+    }
+
+    // 4. the foliage and pool target
+    let foliage = header_block.foliage;
+    let block_data = &foliage.foliage_block_data;
+    if block_data.unfinished_reward_block_hash != hash(rcb) {
+        return Err(ErrorCode::InvalidUrsbHash);
+    }
+    let pool_target = &block_data.pool_target;
+    if pool_target.max_height != 0 && pool_target.max_height < height {
+        return Err(ErrorCode::OldPoolTarget);
+    }
+    if prev_b.is_none() {
+        if pool_target.puzzle_hash != constants.genesis_pre_farm_pool_puzzle_hash
+            || block_data.farmer_reward_puzzle_hash != constants.genesis_pre_farm_farmer_puzzle_hash
+        {
+            return Err(ErrorCode::InvalidPrefarm);
+        }
+    } else if let Some(pool_public_key) = &pos.pool_public_key {
+        let pool_signature = block_data
+            .pool_signature
+            .as_ref()
+            .ok_or(ErrorCode::InvalidPoolSignature)?;
+        let msg = pool_target
+            .to_bytes()
+            .map_err(|_| ErrorCode::InvalidPoolTarget)?;
+        if !verify(pool_signature, pool_public_key, msg) {
+            return Err(ErrorCode::InvalidPoolSignature);
+        }
+    } else {
+        if Some(pool_target.puzzle_hash) != pos.pool_contract_puzzle_hash {
+            return Err(ErrorCode::InvalidPoolTarget);
+        }
+        if block_data.pool_signature.is_some() {
+            return Err(ErrorCode::InvalidPoolSignature);
+        }
+    }
+    if !verify(
+        &foliage.foliage_block_data_signature,
+        &pos.plot_public_key,
+        hash(block_data),
+    ) {
+        return Err(ErrorCode::InvalidPlotSignature);
+    }
+
+    // 5. the foliage transaction block. This is a transaction block if its
+    // signage point is after the infusion point of the previous transaction
+    // block. The genesis block always is
+    let prev_tx_b = match prev_b {
+        Some(prev_b) => {
+            let mut curr = prev_b.clone();
+            while !curr.is_transaction_block() {
+                curr = get_block(blocks, &curr.prev_hash)?;
+            }
+            Some(curr)
+        }
+        None => None,
+    };
+    let sp_iters = calculate_sp_iters(constants, expected_sub_slot_iters, rcb.signage_point_index)?;
+    let sp_sub_slot_start = if overflow {
+        sub_slot_start.saturating_sub(u128::from(expected_sub_slot_iters))
+    } else {
+        sub_slot_start
+    };
+    let is_transaction_block = prev_tx_b.as_ref().map_or(true, |b| {
+        sp_sub_slot_start + u128::from(sp_iters) > b.total_iters
+    });
+
+    let Some(foliage_transaction_block_hash) = foliage.foliage_transaction_block_hash else {
+        if header_block.foliage_transaction_block.is_some() {
+            return Err(ErrorCode::NotBlockButHasData);
+        }
+        if foliage.foliage_transaction_block_signature.is_some() {
+            return Err(ErrorCode::InvalidFoliageBlockPresence);
+        }
+        if is_transaction_block {
+            return Err(ErrorCode::InvalidIsTransactionBlock);
+        }
+        return Ok(required_iters);
+    };
+    if !is_transaction_block {
+        return Err(ErrorCode::InvalidIsTransactionBlock);
+    }
+    let ftb = header_block
+        .foliage_transaction_block
+        .ok_or(ErrorCode::IsTransactionBlockButNoData)?;
+    if foliage_transaction_block_hash != hash(ftb) {
+        return Err(ErrorCode::InvalidFoliageBlockHash);
+    }
+    let ftb_signature = foliage
+        .foliage_transaction_block_signature
+        .as_ref()
+        .ok_or(ErrorCode::InvalidFoliageBlockPresence)?;
+    if !verify(
+        ftb_signature,
+        &pos.plot_public_key,
+        foliage_transaction_block_hash,
+    ) {
+        return Err(ErrorCode::InvalidPlotSignature);
+    }
+    if check_filter && ftb.filter_hash != sha256(header_block.transactions_filter) {
+        return Err(ErrorCode::InvalidTransactionsFilterHash);
+    }
+
+    // 6. the previous transaction block and the timestamp, which must be
+    // after the previous transaction block's. Like current chia, we only
+    // compare against that one block. constants.number_of_timestamps is
+    // intentionally unused, chia doesn't use it for validation either
+    if let Some(prev_tx_b) = &prev_tx_b {
+        if ftb.prev_transaction_block_hash != prev_tx_b.header_hash {
+            return Err(ErrorCode::InvalidPrevBlockHash);
+        }
+        let prev_timestamp = prev_tx_b.timestamp.ok_or(ErrorCode::InvalidPrevBlockHash)?;
+        if ftb.timestamp <= prev_timestamp {
+            return Err(ErrorCode::TimestampTooFarInPast);
+        }
+    } else if ftb.prev_transaction_block_hash != constants.genesis_challenge {
+        return Err(ErrorCode::InvalidPrevBlockHash);
+    }
+    if ftb.timestamp > current_time.saturating_add(u64::from(constants.max_future_time2)) {
+        return Err(ErrorCode::TimestampTooFarInFuture);
+    }
+
+    Ok(required_iters)
+}
+
+// Validates a header block, including its unfinished part (see
+// validate_unfinished_header_block), against the chain it builds on. On top
+// of that, the height and weight must continue from the previous block, the
+// block must be infused in the infused challenge chain exactly when its
+// deficit calls for it, and the finished reward chain block and transactions
+// info must match the foliage. Returns the required iterations of the proof
+// of space.
+pub fn validate_finished_header_block<B: BlockRecords + ?Sized>(
+    constants: &ConsensusConstants,
+    blocks: &B,
+    header_block: &HeaderBlock,
+    check_filter: bool,
+    expected_difficulty: u64,
+    expected_sub_slot_iters: u64,
+    current_time: u64,
+) -> Result<u64, ErrorCode> {
+    let required_iters = validate_unfinished_parts(
+        constants,
+        blocks,
+        &UnfinishedParts::from(header_block),
+        check_filter,
+        expected_difficulty,
+        expected_sub_slot_iters,
+        current_time,
+    )?;
+    let prev_b = get_prev_block(constants, blocks, &header_block.prev_header_hash())?;
+    let rcb = &header_block.reward_chain_block;
+
+    let (height, prev_weight) = prev_b.as_ref().map_or((0, 0), |b| (b.height + 1, b.weight));
+    if rcb.height != height {
+        return Err(ErrorCode::InvalidHeight);
+    }
+    if rcb.weight != prev_weight + u128::from(expected_difficulty) {
+        return Err(ErrorCode::InvalidWeight);
+    }
+
+    // the block is infused in the infused challenge chain while the deficit
+    // is below MIN_BLOCKS_PER_CHALLENGE_BLOCK - 1, i.e. after the challenge
+    // block
+    let overflow = is_overflow_block(constants, rcb.signage_point_index)?;
+    let deficit = calculate_deficit(
+        constants,
+        height,
+        prev_b.as_ref(),
+        overflow,
+        header_block.finished_sub_slots.len(),
+    );
+    let has_icc = deficit < constants.min_blocks_per_challenge_block - 1;
+    if rcb.infused_challenge_chain_ip_vdf.is_some() != has_icc
+        || header_block.infused_challenge_chain_ip_proof.is_some() != has_icc
+    {
+        return Err(ErrorCode::InvalidIccVdf);
+    }
+
+    if header_block.foliage.reward_block_hash != hash(rcb) {
+        return Err(ErrorCode::InvalidRewardBlockHash);
+    }
+    if rcb.is_transaction_block
+        != header_block
+            .foliage
+            .foliage_transaction_block_hash
+            .is_some()
+    {
+        return Err(ErrorCode::InvalidFoliageBlockPresence);
+    }
+    match (
+        &header_block.foliage_transaction_block,
+        &header_block.transactions_info,
+    ) {
+        (Some(ftb), Some(info)) => {
+            if ftb.transactions_info_hash != hash(info) {
+                return Err(ErrorCode::InvalidTransactionsInfoHash);
+            }
+        }
+        (Some(_), None) => return Err(ErrorCode::IsTransactionBlockButNoData),
+        (None, Some(_)) => return Err(ErrorCode::NotBlockButHasData),
+        (None, None) => {}
+    }
+
+    Ok(required_iters)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::difficulty_adjustment::tests::TestBlocks;
+    use crate::pot_iterations::tests::make_block_record;
+    use crate::proof_of_space::tests::{encode_proof, find_proof};
+    use crate::proof_of_space::{calculate_plot_id_ph, calculate_pos_challenge};
+    use crate::weight_proof::tests::{vdf_info, vdf_proof};
+    use chia_bls::{sign, SecretKey, Signature};
+    use chia_protocol::{
+        Bytes, ChallengeChainSubSlot, ClassgroupElement, Foliage, FoliageBlockData,
+        FoliageTransactionBlock, InfusedChallengeChainSubSlot, PoolTarget, ProofOfSpace,
+        RewardChainBlock, RewardChainSubSlot, SubSlotProofs, TransactionsInfo, VDFInfo,
+    };
+    use rstest::rstest;
+    use std::sync::OnceLock;
+
+    pub const K: u8 = 12;
+    const SUB_SLOT_ITERS: u64 = 1 << 27;
+    const DIFFICULTY: u64 = 7;
+    const GENESIS_TIME: u64 = 1_700_000_000;
+    const NOW: u64 = GENESIS_TIME + 1000;
+
+    // a tiny plot, with a low enough difficulty constant factor that every
+    // proof is good enough to make a block
+    fn constants() -> ConsensusConstants {
+        TEST_CONSTANTS
+            .with_overrides([
+                ("MIN_PLOT_SIZE", "12"),
+                ("NUMBER_ZERO_BITS_PLOT_FILTER", "0"),
+                ("DIFFICULTY_CONSTANT_FACTOR", "1073741824"),
+            ])
+            .expect("with_overrides")
+    }
+
+    pub struct Farmer {
+        pub sk: SecretKey,
+        pub pool_ph: Bytes32,
+        pub plot_id: Bytes32,
+        pub proof: Bytes,
+        pub f7: u64,
+    }
+
+    // finding the proof is slow, so all tests share the same farmer and plot
+    pub fn farmer() -> &'static Farmer {
+        static FARMER: OnceLock<Farmer> = OnceLock::new();
+        FARMER.get_or_init(|| {
+            let sk = SecretKey::from_seed(&[1; 32]);
+            let pool_ph = Bytes32::from([2; 32]);
+            let plot_id = calculate_plot_id_ph(&pool_ph, &sk.public_key());
+            let (xs, f7) = find_proof(K, &plot_id);
+            Farmer {
+                sk,
+                pool_ph,
+                plot_id,
+                proof: Bytes::new(encode_proof(K, &xs)),
+                f7,
+            }
+        })
+    }
+
+    // updates the foliage hashes and signatures after modifying the block
+    fn sign_foliage(block: &mut HeaderBlock) {
+        let sk = &farmer().sk;
+        let foliage = &mut block.foliage;
+        foliage.foliage_block_data.unfinished_reward_block_hash =
+            hash(&block.reward_chain_block.get_unfinished());
+        foliage.reward_block_hash = hash(&block.reward_chain_block);
+        foliage.foliage_block_data_signature = sign(sk, hash(&foliage.foliage_block_data));
+        if let Some(ftb) = &block.foliage_transaction_block {
+            foliage.foliage_transaction_block_hash = Some(hash(ftb));
+            foliage.foliage_transaction_block_signature = Some(sign(sk, hash(ftb)));
+        }
+    }
+
+    // A valid transaction block after prev, at the specified signage point.
+    // Returns the block and its required iterations
+    fn make_header_block(
+        constants: &ConsensusConstants,
+        blocks: &TestBlocks,
+        prev: Option<&BlockRecord>,
+        finished_sub_slots: Vec<EndOfSubSlotBundle>,
+        signage_point_index: u8,
+        timestamp: u64,
+    ) -> (HeaderBlock, u64) {
+        let farmer = farmer();
+        let height = prev.map_or(0, |b| b.height + 1);
+        let overflow = is_overflow_block(constants, signage_point_index).unwrap();
+        let challenge =
+            get_block_challenge(constants, blocks, &finished_sub_slots, prev, overflow).unwrap();
+
+        // pick a signage point the plot has a proof for
+        let sp_iters = calculate_sp_iters(constants, SUB_SLOT_ITERS, signage_point_index).unwrap();
+        let cc_sp_vdf = (0..u32::MAX)
+            .map(|i| {
+                let mut output = [0_u8; 100];
+                output[..4].copy_from_slice(&i.to_be_bytes());
+                VDFInfo::new(challenge, sp_iters, ClassgroupElement::new(output.into()))
+            })
+            .find(|vdf| {
+                let pos_challenge =
+                    calculate_pos_challenge(&farmer.plot_id, &challenge, &hash(&vdf.output));
+                u64::from(u16::from_be_bytes([pos_challenge[0], pos_challenge[1]]) >> 4)
+                    == farmer.f7
+            })
+            .unwrap();
+        let cc_sp_hash = hash(&cc_sp_vdf.output);
+        let pos = ProofOfSpace::new(
+            calculate_pos_challenge(&farmer.plot_id, &challenge, &cc_sp_hash),
+            None,
+            Some(farmer.pool_ph),
+            farmer.sk.public_key(),
+            K,
+            farmer.proof.clone(),
+        );
+        let quality =
+            verify_and_get_quality_string(&pos, constants, &challenge, &cc_sp_hash, height)
+                .unwrap();
+        let required_iters = calculate_required_iters(
+            constants,
+            &quality,
+            K,
+            DIFFICULTY,
+            &cc_sp_hash,
+            SUB_SLOT_ITERS,
+        )
+        .unwrap();
+        let ip_iters = calculate_ip_iters(
+            constants,
+            SUB_SLOT_ITERS,
+            signage_point_index,
+            required_iters,
+        )
+        .unwrap();
+        let num_sub_slots = finished_sub_slots.len() as u128;
+        let sub_slot_start = prev.map_or(0, |b| ip_sub_slot_total_iters(constants, b).unwrap())
+            + u128::from(SUB_SLOT_ITERS) * num_sub_slots;
+        let deficit =
+            calculate_deficit(constants, height, prev, overflow, finished_sub_slots.len());
+        let has_icc = deficit < constants.min_blocks_per_challenge_block - 1;
+
+        let rcb = RewardChainBlock::new(
+            prev.map_or(0, |b| b.weight) + u128::from(DIFFICULTY),
+            height,
+            sub_slot_start + u128::from(ip_iters),
+            signage_point_index,
+            challenge,
+            pos,
+            Some(cc_sp_vdf),
+            sign(&farmer.sk, cc_sp_hash),
+            vdf_info(),
+            Some(vdf_info()),
+            Signature::default(),
+            VDFInfo::new(
+                Bytes32::from([height as u8; 32]),
+                0,
+                ClassgroupElement::default(),
+            ),
+            has_icc.then(vdf_info),
+            true,
+        );
+        let (pool_ph, farmer_ph) = match prev {
+            None => (
+                constants.genesis_pre_farm_pool_puzzle_hash,
+                constants.genesis_pre_farm_farmer_puzzle_hash,
+            ),
+            Some(_) => (farmer.pool_ph, Bytes32::from([3; 32])),
+        };
+        let prev_hash = prev.map_or(constants.genesis_challenge, |b| b.header_hash);
+        let transactions_filter = Bytes::new(vec![1, 2, 3]);
+        let transactions_info = TransactionsInfo::new(
+            Bytes32::default(),
+            Bytes32::default(),
+            Signature::default(),
+            0,
+            0,
+            Vec::new(),
+        );
+        let ftb = FoliageTransactionBlock::new(
+            prev_hash,
+            timestamp,
+            sha256(&transactions_filter),
+            Bytes32::default(),
+            Bytes32::default(),
+            hash(&transactions_info),
+        );
+        let foliage = Foliage::new(
+            prev_hash,
+            Bytes32::default(),
+            FoliageBlockData::new(
+                Bytes32::default(),
+                PoolTarget::new(pool_ph, 0),
+                None,
+                farmer_ph,
+                Bytes32::default(),
+            ),
+            Signature::default(),
+            None,
+            None,
+        );
+        let mut block = HeaderBlock::new(
+            finished_sub_slots,
+            rcb,
+            Some(vdf_proof()),
+            vdf_proof(),
+            Some(vdf_proof()),
+            vdf_proof(),
+            has_icc.then(vdf_proof),
+            foliage,
+            Some(ftb),
+            transactions_filter,
+            Some(transactions_info),
+        );
+        sign_foliage(&mut block);
+        (block, required_iters)
+    }
+
+    // the block record of a header block built by make_header_block()
+    fn header_block_record(
+        constants: &ConsensusConstants,
+        blocks: &TestBlocks,
+        block: &HeaderBlock,
+        required_iters: u64,
+    ) -> BlockRecord {
+        let rcb = &block.reward_chain_block;
+        let ftb = block.foliage_transaction_block.as_ref();
+        let slot_hashes = |f: fn(&EndOfSubSlotBundle) -> Option<Bytes32>| {
+            block
+                .first_in_sub_slot()
+                .then(|| block.finished_sub_slots.iter().filter_map(f).collect())
+        };
+        let mut b = make_block_record(
+            rcb.height,
+            rcb.total_iters,
+            SUB_SLOT_ITERS,
+            rcb.signage_point_index,
+            required_iters,
+        );
+        b.header_hash = block.header_hash();
+        b.prev_hash = block.prev_header_hash();
+        b.weight = rcb.weight;
+        b.reward_infusion_new_challenge = hash(&rcb.reward_chain_ip_vdf);
+        b.challenge_block_info_hash = hash(&rcb.get_unfinished());
+        b.pool_puzzle_hash = block.foliage.foliage_block_data.pool_target.puzzle_hash;
+        b.farmer_puzzle_hash = block.foliage.foliage_block_data.farmer_reward_puzzle_hash;
+        b.overflow = is_overflow_block(constants, rcb.signage_point_index).unwrap();
+        b.deficit = calculate_deficit(
+            constants,
+            rcb.height,
+            blocks.block_record(&b.prev_hash).as_ref(),
+            b.overflow,
+            block.finished_sub_slots.len(),
+        );
+        b.prev_transaction_block_height = rcb.height.saturating_sub(1);
+        b.timestamp = ftb.map(|ftb| ftb.timestamp);
+        b.prev_transaction_block_hash = ftb.map(|ftb| ftb.prev_transaction_block_hash);
+        b.fees = ftb.map(|_| 0);
+        b.finished_challenge_slot_hashes = slot_hashes(|s| Some(hash(&s.challenge_chain)));
+        b.finished_infused_challenge_slot_hashes =
+            slot_hashes(|s| s.infused_challenge_chain.as_ref().map(hash));
+        b.finished_reward_slot_hashes = slot_hashes(|s| Some(hash(&s.reward_chain)));
+        b
+    }
+
+    // the end of the sub-slot prev was infused in
+    fn end_of_slot(
+        constants: &ConsensusConstants,
+        blocks: &TestBlocks,
+        prev: &BlockRecord,
+    ) -> EndOfSubSlotBundle {
+        let challenge = get_block_challenge(constants, blocks, &[], Some(prev), false).unwrap();
+        let deficit = if prev.deficit > 0 {
+            prev.deficit
+        } else {
+            constants.min_blocks_per_challenge_block
+        };
+        let icc = infused_challenge(constants, blocks, prev)
+            .unwrap()
+            .map(|(challenge, iters)| {
+                InfusedChallengeChainSubSlot::new(VDFInfo::new(
+                    challenge,
+                    iters,
+                    ClassgroupElement::default(),
+                ))
+            });
+        let icc_hash = icc.as_ref().map(hash);
+        let cc = ChallengeChainSubSlot::new(
+            VDFInfo::new(challenge, SUB_SLOT_ITERS, ClassgroupElement::default()),
+            icc_hash.filter(|_| deficit == constants.min_blocks_per_challenge_block),
+            None,
+            None,
+            None,
+        );
+        let ip_iters = block_ip_iters(constants, prev).unwrap();
+        let rc = RewardChainSubSlot::new(
+            VDFInfo::new(
+                prev.reward_infusion_new_challenge,
+                SUB_SLOT_ITERS - ip_iters,
+                ClassgroupElement::default(),
+            ),
+            hash(&cc),
+            icc_hash,
+            deficit,
+        );
+        let icc_proof = icc.as_ref().map(|_| vdf_proof());
+        EndOfSubSlotBundle::new(
+            cc,
+            icc,
+            rc,
+            SubSlotProofs::new(vdf_proof(), icc_proof, vdf_proof()),
+        )
+    }
+
+    // the genesis block, a block in the same sub-slot and a block in the
+    // next sub-slot. Only the first two are added to the block records
+    fn make_chain() -> (ConsensusConstants, TestBlocks, Vec<(HeaderBlock, u64)>) {
+        let constants = constants();
+        let mut blocks = TestBlocks::new();
+        let mut chain = Vec::new();
+
+        let genesis = make_header_block(&constants, &blocks, None, Vec::new(), 1, GENESIS_TIME);
+        let genesis_b = header_block_record(&constants, &blocks, &genesis.0, genesis.1);
+        blocks.add(genesis_b.clone());
+        chain.push(genesis);
+
+        let block1 = make_header_block(
+            &constants,
+            &blocks,
+            Some(&genesis_b),
+            Vec::new(),
+            5,
+            GENESIS_TIME + 20,
+        );
+        let block1_b = header_block_record(&constants, &blocks, &block1.0, block1.1);
+        blocks.add(block1_b.clone());
+        chain.push(block1);
+
+        let slots = vec![end_of_slot(&constants, &blocks, &block1_b)];
+        let block2 = make_header_block(
+            &constants,
+            &blocks,
+            Some(&block1_b),
+            slots,
+            2,
+            GENESIS_TIME + 40,
+        );
+        chain.push(block2);
+        (constants, blocks, chain)
+    }
+
+    fn validate(
+        constants: &ConsensusConstants,
+        blocks: &TestBlocks,
+        block: &HeaderBlock,
+    ) -> Result<u64, ErrorCode> {
+        validate_finished_header_block(
+            constants,
+            blocks,
+            block,
+            true,
+            DIFFICULTY,
+            SUB_SLOT_ITERS,
+            NOW,
+        )
+    }
+
+    #[test]
+    fn test_valid_chain() {
+        let (constants, blocks, chain) = make_chain();
+        for (block, required_iters) in &chain {
+            assert_eq!(validate(&constants, &blocks, block), Ok(*required_iters));
+            assert_eq!(
+                validate_unfinished_header_block(
+                    &constants,
+                    &blocks,
+                    &block.clone().into_unfinished_header_block(),
+                    true,
+                    DIFFICULTY,
+                    SUB_SLOT_ITERS,
+                    NOW,
+                ),
+                Ok(*required_iters)
+            );
+        }
+    }
+
+    // turns a transaction block into a non-transaction block
+    fn strip_transactions(block: &mut HeaderBlock) {
+        block.reward_chain_block.is_transaction_block = false;
+        block.foliage_transaction_block = None;
+        block.foliage.foliage_transaction_block_hash = None;
+        block.foliage.foliage_transaction_block_signature = None;
+        block.transactions_info = None;
+        sign_foliage(block);
+    }
+
+    #[test]
+    fn test_non_transaction_block() {
+        let (constants, blocks, chain) = make_chain();
+
+        // the signage point is before the infusion point of block 1, so this
+        // can't be a transaction block
+        let block1 = blocks.block_record(&chain[1].0.header_hash()).unwrap();
+        let (mut block, required_iters) =
+            make_header_block(&constants, &blocks, Some(&block1), Vec::new(), 6, NOW);
+        assert_eq!(
+            validate(&constants, &blocks, &block),
+            Err(ErrorCode::InvalidIsTransactionBlock)
+        );
+        strip_transactions(&mut block);
+        assert_eq!(validate(&constants, &blocks, &block), Ok(required_iters));
+
+        block
+            .transactions_info
+            .clone_from(&chain[0].0.transactions_info);
+        assert_eq!(
+            validate(&constants, &blocks, &block),
+            Err(ErrorCode::NotBlockButHasData)
+        );
+
+        // the signage point of the last block is in the next sub-slot, so it
+        // must be a transaction block
+        let mut block = chain[2].0.clone();
+        strip_transactions(&mut block);
+        assert_eq!(
+            validate(&constants, &blocks, &block),
+            Err(ErrorCode::InvalidIsTransactionBlock)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_limits() {
+        let (constants, blocks, chain) = make_chain();
+        let check = |timestamp: u64| {
+            let mut block = chain[2].0.clone();
+            block.foliage_transaction_block.as_mut().unwrap().timestamp = timestamp;
+            sign_foliage(&mut block);
+            validate(&constants, &blocks, &block).map(|_| ())
+        };
+        // the previous transaction block's timestamp is GENESIS_TIME + 20
+        assert_eq!(check(GENESIS_TIME + 21), Ok(()));
+        assert_eq!(
+            check(GENESIS_TIME + 20),
+            Err(ErrorCode::TimestampTooFarInPast)
+        );
+        assert_eq!(check(NOW + 120), Ok(()));
+        assert_eq!(check(NOW + 121), Err(ErrorCode::TimestampTooFarInFuture));
+    }
+
+    // These modify the last block of the chain and re-sign its foliage
+    #[rstest]
+    #[case::height(|b: &mut HeaderBlock| b.reward_chain_block.height += 1, ErrorCode::InvalidHeight)]
+    #[case::weight(|b: &mut HeaderBlock| b.reward_chain_block.weight += 1, ErrorCode::InvalidWeight)]
+    #[case::total_iters(|b: &mut HeaderBlock| b.reward_chain_block.total_iters += 1, ErrorCode::InvalidTotalIters)]
+    #[case::prev_hash(|b: &mut HeaderBlock| b.foliage.prev_block_hash = Bytes32::from([5; 32]), ErrorCode::InvalidPrevBlockHash)]
+    #[case::prev_tx_hash(
+        |b: &mut HeaderBlock| b.foliage_transaction_block.as_mut().unwrap().prev_transaction_block_hash = Bytes32::default(),
+        ErrorCode::InvalidPrevBlockHash
+    )]
+    #[case::filter(|b: &mut HeaderBlock| b.transactions_filter = Bytes::new(vec![4]), ErrorCode::InvalidTransactionsFilterHash)]
+    #[case::transactions_info(
+        |b: &mut HeaderBlock| b.transactions_info.as_mut().unwrap().fees = 1,
+        ErrorCode::InvalidTransactionsInfoHash
+    )]
+    #[case::no_transactions_info(|b: &mut HeaderBlock| b.transactions_info = None, ErrorCode::IsTransactionBlockButNoData)]
+    #[case::no_foliage_transaction_block(
+        |b: &mut HeaderBlock| b.foliage_transaction_block = None,
+        ErrorCode::IsTransactionBlockButNoData
+    )]
+    #[case::is_transaction_block(
+        |b: &mut HeaderBlock| b.reward_chain_block.is_transaction_block = false,
+        ErrorCode::InvalidFoliageBlockPresence
+    )]
+    #[case::cc_challenge(
+        |b: &mut HeaderBlock| b.reward_chain_block.pos_ss_cc_challenge_hash = Bytes32::default(),
+        ErrorCode::InvalidCcChallenge
+    )]
+    #[case::sp_index(|b: &mut HeaderBlock| b.reward_chain_block.signage_point_index = 0, ErrorCode::InvalidSpIndex)]
+    #[case::cc_sp_proof(|b: &mut HeaderBlock| b.challenge_chain_sp_proof = None, ErrorCode::InvalidCcSpVdf)]
+    #[case::rc_sp_proof(|b: &mut HeaderBlock| b.reward_chain_sp_proof = None, ErrorCode::InvalidRcSpVdf)]
+    #[case::pos_size(|b: &mut HeaderBlock| b.reward_chain_block.proof_of_space.size = 13, ErrorCode::InvalidPospace)]
+    #[case::cc_sp_signature(
+        |b: &mut HeaderBlock| b.reward_chain_block.challenge_chain_sp_signature = Signature::default(),
+        ErrorCode::InvalidCcSignature
+    )]
+    #[case::pool_target(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.pool_target.puzzle_hash = Bytes32::from([9; 32]),
+        ErrorCode::InvalidPoolTarget
+    )]
+    #[case::old_pool_target(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.pool_target.max_height = 1,
+        ErrorCode::OldPoolTarget
+    )]
+    #[case::pool_signature(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.pool_signature = Some(Signature::default()),
+        ErrorCode::InvalidPoolSignature
+    )]
+    #[case::eos_cc_challenge(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].challenge_chain.challenge_chain_end_of_slot_vdf.challenge = Bytes32::default(),
+        ErrorCode::InvalidPrevChallengeSlotHash
+    )]
+    #[case::eos_cc_iters(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].challenge_chain.challenge_chain_end_of_slot_vdf.number_of_iterations += 1,
+        ErrorCode::InvalidCcEosVdf
+    )]
+    #[case::eos_rc_iters(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].reward_chain.end_of_slot_vdf.number_of_iterations += 1,
+        ErrorCode::InvalidRcEosVdf
+    )]
+    #[case::eos_rc_challenge(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].reward_chain.end_of_slot_vdf.challenge = Bytes32::default(),
+        ErrorCode::InvalidRcEosVdf
+    )]
+    #[case::eos_cc_hash(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].reward_chain.challenge_chain_sub_slot_hash = Bytes32::default(),
+        ErrorCode::InvalidChallengeSlotHashRc
+    )]
+    #[case::eos_icc_hash_rc(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].reward_chain.infused_challenge_chain_sub_slot_hash = Some(Bytes32::default()),
+        ErrorCode::InvalidIccHashRc
+    )]
+    #[case::eos_icc_hash_cc(
+        |b: &mut HeaderBlock| {
+            let slot = &mut b.finished_sub_slots[0];
+            slot.challenge_chain.infused_challenge_chain_sub_slot_hash = Some(Bytes32::default());
+            slot.reward_chain.challenge_chain_sub_slot_hash = hash(&slot.challenge_chain);
+        },
+        ErrorCode::InvalidIccHashCc
+    )]
+    #[case::eos_icc_proof(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].proofs.infused_challenge_chain_slot_proof = None,
+        ErrorCode::InvalidIccEosVdf
+    )]
+    #[case::eos_icc_challenge(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].infused_challenge_chain.as_mut().unwrap().infused_challenge_chain_end_of_slot_vdf.challenge = Bytes32::default(),
+        ErrorCode::InvalidIccEosVdf
+    )]
+    #[case::eos_icc_iters(
+        |b: &mut HeaderBlock| b.finished_sub_slots[0].infused_challenge_chain.as_mut().unwrap().infused_challenge_chain_end_of_slot_vdf.number_of_iterations += 1,
+        ErrorCode::InvalidIccEosVdf
+    )]
+    #[case::eos_no_icc(|b: &mut HeaderBlock| b.finished_sub_slots[0].infused_challenge_chain = None, ErrorCode::ShouldHaveIcc)]
+    #[case::icc_ip_vdf(|b: &mut HeaderBlock| b.reward_chain_block.infused_challenge_chain_ip_vdf = None, ErrorCode::InvalidIccVdf)]
+    #[case::icc_ip_proof(|b: &mut HeaderBlock| b.infused_challenge_chain_ip_proof = None, ErrorCode::InvalidIccVdf)]
+    #[case::eos_deficit(|b: &mut HeaderBlock| b.finished_sub_slots[0].reward_chain.deficit = 15, ErrorCode::InvalidDeficit)]
+    #[case::eos_ses_hash(
+        |b: &mut HeaderBlock| {
+            let slot = &mut b.finished_sub_slots[0];
+            slot.challenge_chain.subepoch_summary_hash = Some(Bytes32::default());
+            slot.reward_chain.challenge_chain_sub_slot_hash = hash(&slot.challenge_chain);
+        },
+        ErrorCode::InvalidSubEpochSummaryHash
+    )]
+    #[case::eos_new_difficulty(
+        |b: &mut HeaderBlock| {
+            let slot = &mut b.finished_sub_slots[0];
+            slot.challenge_chain.new_difficulty = Some(DIFFICULTY);
+            slot.reward_chain.challenge_chain_sub_slot_hash = hash(&slot.challenge_chain);
+        },
+        ErrorCode::InvalidNewDifficulty
+    )]
+    #[case::eos_new_sub_slot_iters(
+        |b: &mut HeaderBlock| {
+            let slot = &mut b.finished_sub_slots[0];
+            slot.challenge_chain.new_sub_slot_iters = Some(SUB_SLOT_ITERS);
+            slot.reward_chain.challenge_chain_sub_slot_hash = hash(&slot.challenge_chain);
+        },
+        ErrorCode::InvalidNewSubSlotIters
+    )]
+    fn test_invalid_block(#[case] modify: fn(&mut HeaderBlock), #[case] expected: ErrorCode) {
+        let (constants, blocks, chain) = make_chain();
+        let mut block = chain[2].0.clone();
+        modify(&mut block);
+        sign_foliage(&mut block);
+        assert_eq!(validate(&constants, &blocks, &block), Err(expected));
+    }
+
+    // These modify the foliage of the last block without re-signing it
+    #[rstest]
+    #[case::ursb_hash(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.unfinished_reward_block_hash = Bytes32::default(),
+        ErrorCode::InvalidUrsbHash
+    )]
+    #[case::reward_block_hash(
+        |b: &mut HeaderBlock| b.foliage.reward_block_hash = Bytes32::default(),
+        ErrorCode::InvalidRewardBlockHash
+    )]
+    #[case::block_data_signature(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data_signature = Signature::default(),
+        ErrorCode::InvalidPlotSignature
+    )]
+    #[case::ftb_hash(
+        |b: &mut HeaderBlock| b.foliage.foliage_transaction_block_hash = Some(Bytes32::default()),
+        ErrorCode::InvalidFoliageBlockHash
+    )]
+    #[case::ftb_signature(
+        |b: &mut HeaderBlock| b.foliage.foliage_transaction_block_signature = Some(Signature::default()),
+        ErrorCode::InvalidPlotSignature
+    )]
+    #[case::no_ftb_signature(
+        |b: &mut HeaderBlock| b.foliage.foliage_transaction_block_signature = None,
+        ErrorCode::InvalidFoliageBlockPresence
+    )]
+    #[case::no_ftb_hash(|b: &mut HeaderBlock| b.foliage.foliage_transaction_block_hash = None, ErrorCode::NotBlockButHasData)]
+    fn test_invalid_foliage(#[case] modify: fn(&mut HeaderBlock), #[case] expected: ErrorCode) {
+        let (constants, blocks, chain) = make_chain();
+        let mut block = chain[2].0.clone();
+        modify(&mut block);
+        assert_eq!(validate(&constants, &blocks, &block), Err(expected));
+    }
+
+    #[rstest]
+    #[case::pool_target(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.pool_target.puzzle_hash = Bytes32::from([2; 32]),
+        ErrorCode::InvalidPrefarm
+    )]
+    #[case::farmer_puzzle_hash(
+        |b: &mut HeaderBlock| b.foliage.foliage_block_data.farmer_reward_puzzle_hash = Bytes32::from([3; 32]),
+        ErrorCode::InvalidPrefarm
+    )]
+    #[case::prev_tx_hash(
+        |b: &mut HeaderBlock| b.foliage_transaction_block.as_mut().unwrap().prev_transaction_block_hash = Bytes32::default(),
+        ErrorCode::InvalidPrevBlockHash
+    )]
+    #[case::height(|b: &mut HeaderBlock| b.reward_chain_block.height = 1, ErrorCode::InvalidHeight)]
+    #[case::icc_ip_vdf(
+        |b: &mut HeaderBlock| b.reward_chain_block.infused_challenge_chain_ip_vdf = Some(vdf_info()),
+        ErrorCode::InvalidIccVdf
+    )]
+    #[case::not_transaction_block(strip_transactions, ErrorCode::InvalidIsTransactionBlock)]
+    fn test_invalid_genesis(#[case] modify: fn(&mut HeaderBlock), #[case] expected: ErrorCode) {
+        let (constants, blocks, chain) = make_chain();
+        let mut block = chain[0].0.clone();
+        modify(&mut block);
+        sign_foliage(&mut block);
+        assert_eq!(validate(&constants, &blocks, &block), Err(expected));
+    }
+
+    #[test]
+    fn test_unknown_prev_block() {
+        let (constants, _, chain) = make_chain();
+        let blocks = TestBlocks::new();
+        assert_eq!(
+            validate(&constants, &blocks, &chain[1].0),
+            Err(ErrorCode::InvalidPrevBlockHash)
+        );
+    }
+
+    #[rstest]
+    // the genesis block is the first challenge block
+    #[case(0, None, false, 0, 15)]
+    #[case(5, Some(15), false, 0, 14)]
+    #[case(5, Some(1), true, 2, 0)]
+    #[case(5, Some(0), false, 0, 0)]
+    // the next sub-slot starts a new countdown, unless an overflow block is
+    // infused in the sub-slot right after the previous one
+    #[case(5, Some(0), false, 1, 15)]
+    #[case(5, Some(0), true, 1, 16)]
+    #[case(5, Some(0), true, 2, 15)]
+    // an overflow block after that only counts once it's in a new sub-slot
+    #[case(5, Some(16), true, 0, 16)]
+    #[case(5, Some(16), true, 1, 15)]
+    #[case(5, Some(16), false, 0, 15)]
+    fn test_calculate_deficit(
+        #[case] height: u32,
+        #[case] prev_deficit: Option<u8>,
+        #[case] overflow: bool,
+        #[case] num_finished_sub_slots: usize,
+        #[case] expected: u8,
+    ) {
+        let prev_b = prev_deficit.map(|deficit| {
+            let mut b = make_block_record(height - 1, 0, SUB_SLOT_ITERS, 0, 1);
+            b.deficit = deficit;
+            b
+        });
+        assert_eq!(
+            calculate_deficit(
+                &TEST_CONSTANTS,
+                height,
+                prev_b.as_ref(),
+                overflow,
+                num_finished_sub_slots
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_make_sub_epoch_summary() {
+        let constants = TEST_CONSTANTS
+            .with_overrides([("SUB_EPOCH_BLOCKS", "20"), ("MAX_SUB_SLOT_BLOCKS", "4")])
+            .expect("with_overrides");
+        let included = SubEpochSummary::new(
+            Bytes32::from([1; 32]),
+            Bytes32::from([2; 32]),
+            0,
+            None,
+            None,
+        );
+        let header_hash = |height: u32| Bytes32::from([height.wrapping_add(1) as u8; 32]);
+        let mut blocks = TestBlocks::new();
+        for height in 0..42 {
+            let mut b = make_block_record(height, 0, SUB_SLOT_ITERS, 0, 1);
+            b.header_hash = header_hash(height);
+            b.prev_hash = header_hash(height.wrapping_sub(1));
+            if height == 21 {
+                b.sub_epoch_summary_included = Some(included.clone());
+                b.finished_reward_slot_hashes = Some(vec![Bytes32::from([3; 32])]);
+            }
+            blocks.add(b);
+        }
+        let block_at = |height| blocks.block_record(&header_hash(height)).unwrap();
+
+        // the first sub-epoch
+        assert_eq!(
+            make_sub_epoch_summary(&constants, &blocks, 12, &block_at(10), None, None),
+            Ok(SubEpochSummary::new(
+                constants.genesis_challenge,
+                constants.genesis_challenge,
+                0,
+                None,
+                None
+            ))
+        );
+        // the summary refers back to the one included at height 21
+        assert_eq!(
+            make_sub_epoch_summary(&constants, &blocks, 41, &block_at(39), Some(5), Some(6)),
+            Ok(SubEpochSummary::new(
+                hash(&included),
+                Bytes32::from([3; 32]),
+                1,
+                Some(5),
+                Some(6)
+            ))
+        );
+        assert_eq!(
+            make_sub_epoch_summary(&constants, &blocks, 41, &block_at(38), None, None),
+            Err(ErrorCode::InvalidHeight)
+        );
+    }
+}
@@ -1,10 +1,25 @@
 #![doc = include_str!("../README.md")]
 
 pub mod allocator;
+pub mod block_builder;
+pub mod block_rewards;
+pub mod block_roots;
+pub mod coin_store;
 pub mod consensus_constants;
+pub mod difficulty_adjustment;
 pub mod error;
 pub mod fast_forward;
+pub mod fork_heights;
 pub mod gen;
 pub mod generator_rom;
+pub mod header_validation;
+pub mod mempool;
 pub mod merkle_set;
 pub mod merkle_tree;
+pub mod pot_iterations;
+pub mod proof_of_space;
+pub mod spend_bundle_dedup;
+pub mod time_locks;
+pub mod transactions_filter;
+pub mod vdf;
+pub mod weight_proof;
@@ -0,0 +1,759 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::conditions::{ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_FF};
+use crate::gen::owned_conditions::{OwnedSpend, OwnedSpendBundleConditions};
+use crate::gen::validation_error::ErrorCode;
+use crate::time_locks::{CoinConfirmation, TimeLocks};
+use chia_protocol::{Bytes32, CoinSpend, SpendBundle};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// when replacing transactions in the mempool, the new transaction must
+// increase the total fee by at least this amount (in mojos)
+pub const MEMPOOL_MIN_FEE_INCREASE: u64 = 10_000_000;
+
+// The fee per cost of a mempool item. Comparisons are exact (no floating
+// point) by cross multiplying the fee and cost
+#[derive(Debug, Clone, Copy)]
+pub struct FeePerCost {
+    pub fee: u64,
+    pub cost: u64,
+}
+
+impl FeePerCost {
+    pub fn new(fee: u64, cost: u64) -> Self {
+        Self { fee, cost }
+    }
+}
+
+impl Ord for FeePerCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = u128::from(self.fee) * u128::from(other.cost);
+        let rhs = u128::from(other.fee) * u128::from(self.cost);
+        lhs.cmp(&rhs)
+    }
+}
+
+impl PartialOrd for FeePerCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FeePerCost {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FeePerCost {}
+
+// A validated spend bundle, ready to be added to the mempool. The conditions
+// are expected to have been computed in mempool mode (e.g. by
+// validate_spend_bundle()), with a valid signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolItem {
+    pub spend_bundle: SpendBundle,
+    pub spend_bundle_id: Bytes32,
+    pub conds: OwnedSpendBundleConditions,
+    pub fee: u64,
+    pub height_added: u32,
+    // the absolute and relative time-locks of the spend bundle, combined
+    pub time_locks: TimeLocks,
+}
+
+impl MempoolItem {
+    // coins is the confirmation height and timestamp of every coin spent by
+    // the bundle. It's used to turn relative time-locks into absolute ones
+    pub fn new(
+        spend_bundle: SpendBundle,
+        conds: OwnedSpendBundleConditions,
+        height_added: u32,
+        coins: &HashMap<Bytes32, CoinConfirmation>,
+    ) -> Result<Self, ErrorCode> {
+        if conds.addition_amount > conds.removal_amount {
+            return Err(ErrorCode::MintingCoin);
+        }
+        let fee = u64::try_from(conds.removal_amount - conds.addition_amount)
+            .map_err(|_| ErrorCode::InvalidBlockFeeAmount)?;
+        if conds.reserve_fee > fee {
+            return Err(ErrorCode::ReserveFeeConditionFailed);
+        }
+
+        let time_locks = TimeLocks::new(&conds, coins)?;
+
+        Ok(Self {
+            spend_bundle_id: spend_bundle.name(),
+            spend_bundle,
+            conds,
+            fee,
+            height_added,
+            time_locks,
+        })
+    }
+
+    pub fn cost(&self) -> u64 {
+        self.conds.cost
+    }
+
+    pub fn fee_per_cost(&self) -> FeePerCost {
+        FeePerCost::new(self.fee, self.conds.cost)
+    }
+
+    pub fn removals(&self) -> impl Iterator<Item = &Bytes32> {
+        self.conds.spends.iter().map(|s| &s.coin_id)
+    }
+
+    pub fn spend(&self, coin_id: &Bytes32) -> Option<&OwnedSpend> {
+        self.conds.spends.iter().find(|s| s.coin_id == *coin_id)
+    }
+
+    pub fn coin_spend(&self, coin_id: &Bytes32) -> Option<&CoinSpend> {
+        self.spend_bundle
+            .coin_spends
+            .iter()
+            .find(|cs| cs.coin.coin_id() == *coin_id)
+    }
+
+    // returns true if this item has expired at the specified peak height and
+    // timestamp, i.e. it can no longer be included in a block
+    pub fn is_expired(&self, peak_height: u32, peak_timestamp: u64) -> bool {
+        self.time_locks.is_expired(peak_height, peak_timestamp)
+    }
+}
+
+// The mempool holds validated spend bundles (MempoolItems) that are candidates
+// for inclusion in the next block. Items are indexed by the coins they spend
+// and ordered by fee-per-cost. Two items spending the same coin are in
+// conflict, unless both spends are eligible for identical-spend-dedup (with
+// the same solution) or both are eligible for singleton fast-forward. A
+// conflicting item is only admitted if it satisfies the replace-by-fee rules,
+// in which case the items it conflicts with are removed.
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    items: HashMap<Bytes32, MempoolItem>,
+    by_coin: HashMap<Bytes32, HashSet<Bytes32>>,
+    by_fee_per_cost: BTreeSet<(FeePerCost, Bytes32)>,
+    total_cost: u64,
+    max_total_cost: u64,
+    peak_height: u32,
+    peak_timestamp: u64,
+}
+
+impl Mempool {
+    pub fn new(max_total_cost: u64) -> Self {
+        Self {
+            items: HashMap::new(),
+            by_coin: HashMap::new(),
+            by_fee_per_cost: BTreeSet::new(),
+            total_cost: 0,
+            max_total_cost,
+            peak_height: 0,
+            peak_timestamp: 0,
+        }
+    }
+
+    // the mempool can hold mempool_block_buffer blocks worth of transactions
+    pub fn from_constants(constants: &ConsensusConstants) -> Self {
+        Self::new(constants.max_block_cost_clvm * u64::from(constants.mempool_block_buffer))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn total_cost(&self) -> u64 {
+        self.total_cost
+    }
+
+    pub fn max_total_cost(&self) -> u64 {
+        self.max_total_cost
+    }
+
+    pub fn peak(&self) -> (u32, u64) {
+        (self.peak_height, self.peak_timestamp)
+    }
+
+    pub fn get(&self, spend_bundle_id: &Bytes32) -> Option<&MempoolItem> {
+        self.items.get(spend_bundle_id)
+    }
+
+    pub fn contains(&self, spend_bundle_id: &Bytes32) -> bool {
+        self.items.contains_key(spend_bundle_id)
+    }
+
+    // all items spending the specified coin
+    pub fn items_spending(&self, coin_id: &Bytes32) -> impl Iterator<Item = &MempoolItem> {
+        self.by_coin
+            .get(coin_id)
+            .into_iter()
+            .flatten()
+            .map(|id| &self.items[id])
+    }
+
+    // all items, highest fee-per-cost first
+    pub fn items_by_fee_per_cost(&self) -> impl Iterator<Item = &MempoolItem> {
+        self.by_fee_per_cost
+            .iter()
+            .rev()
+            .map(|(_, id)| &self.items[id])
+    }
+
+    // returns the IDs of the items in the mempool that conflict with the
+    // specified item. Spends of the same coin are not in conflict if both are
+    // eligible for dedup (and have identical solutions) or both are eligible
+    // for fast-forward
+    fn conflicts(&self, item: &MempoolItem) -> HashSet<Bytes32> {
+        let mut ret = HashSet::<Bytes32>::new();
+        for spend in &item.conds.spends {
+            let Some(ids) = self.by_coin.get(&spend.coin_id) else {
+                continue;
+            };
+            for id in ids {
+                if ret.contains(id) {
+                    continue;
+                }
+                let existing = &self.items[id];
+                let existing_spend = existing
+                    .spend(&spend.coin_id)
+                    .expect("internal error, inconsistent coin index");
+
+                // a spend may be eligible for both, in which case either is
+                // enough to not be in conflict
+                let both_flags = spend.flags & existing_spend.flags;
+                let fast_forward = (both_flags & ELIGIBLE_FOR_FF) != 0;
+                let dedup = (both_flags & ELIGIBLE_FOR_DEDUP) != 0
+                    && existing.coin_spend(&spend.coin_id).map(|cs| &cs.solution)
+                        == item.coin_spend(&spend.coin_id).map(|cs| &cs.solution);
+                if !fast_forward && !dedup {
+                    ret.insert(*id);
+                }
+            }
+        }
+        ret
+    }
+
+    // the replace-by-fee rules. The new item must:
+    // * spend every coin spent by the items it replaces
+    // * have a higher fee-per-cost than the replaced items combined
+    // * increase the total fee by at least MEMPOOL_MIN_FEE_INCREASE
+    // * have the same time-locks as all the items it replaces
+    fn can_replace(&self, item: &MempoolItem, conflicts: &HashSet<Bytes32>) -> bool {
+        let removals: HashSet<&Bytes32> = item.removals().collect();
+        let mut conflicting_fee: u64 = 0;
+        let mut conflicting_cost: u64 = 0;
+        for id in conflicts {
+            let existing = &self.items[id];
+            if !existing.removals().all(|c| removals.contains(c)) {
+                return false;
+            }
+            if existing.time_locks != item.time_locks {
+                return false;
+            }
+            conflicting_fee = conflicting_fee.saturating_add(existing.fee);
+            conflicting_cost = conflicting_cost.saturating_add(existing.cost());
+        }
+
+        if item.fee_per_cost() <= FeePerCost::new(conflicting_fee, conflicting_cost) {
+            return false;
+        }
+        item.fee.saturating_sub(conflicting_fee) >= MEMPOOL_MIN_FEE_INCREASE
+    }
+
+    // attempts to add the item to the mempool. On success, returns the items
+    // that were removed to make room for it, either because they were replaced
+    // (by fee) or evicted because the mempool is full.
+    pub fn add(&mut self, item: MempoolItem) -> Result<Vec<MempoolItem>, ErrorCode> {
+        if self.items.contains_key(&item.spend_bundle_id) {
+            return Err(ErrorCode::AlreadyIncludingTransaction);
+        }
+        if item.cost() > self.max_total_cost {
+            return Err(ErrorCode::CostExceeded);
+        }
+
+        // the item must be valid in the next block
+        let locks = &item.time_locks;
+        if self.peak_height < locks.assert_height {
+            return Err(ErrorCode::AssertHeightAbsoluteFailed);
+        }
+        if self.peak_timestamp < locks.assert_seconds {
+            return Err(ErrorCode::AssertSecondsAbsoluteFailed);
+        }
+        if locks
+            .assert_before_height
+            .is_some_and(|h| self.peak_height >= h)
+        {
+            return Err(ErrorCode::AssertBeforeHeightAbsoluteFailed);
+        }
+        if locks
+            .assert_before_seconds
+            .is_some_and(|s| self.peak_timestamp >= s)
+        {
+            return Err(ErrorCode::AssertBeforeSecondsAbsoluteFailed);
+        }
+
+        let conflicts = self.conflicts(&item);
+        if !conflicts.is_empty() && !self.can_replace(&item, &conflicts) {
+            return Err(ErrorCode::MempoolConflict);
+        }
+
+        // if the mempool is full, evict the lowest fee-per-cost items, as
+        // long as they pay less than the new item
+        let mut total_cost = self.total_cost
+            - conflicts
+                .iter()
+                .map(|id| self.items[id].cost())
+                .sum::<u64>();
+        let mut evict = Vec::<Bytes32>::new();
+        let fee_per_cost = item.fee_per_cost();
+        for (fpc, id) in &self.by_fee_per_cost {
+            if total_cost + item.cost() <= self.max_total_cost {
+                break;
+            }
+            if conflicts.contains(id) {
+                continue;
+            }
+            if *fpc >= fee_per_cost {
+                return Err(ErrorCode::InvalidFeeLowFee);
+            }
+            total_cost -= self.items[id].cost();
+            evict.push(*id);
+        }
+
+        let mut removed = Vec::<MempoolItem>::new();
+        for id in conflicts.iter().chain(evict.iter()) {
+            removed.extend(self.remove(id));
+        }
+
+        for coin_id in item.removals() {
+            self.by_coin
+                .entry(*coin_id)
+                .or_default()
+                .insert(item.spend_bundle_id);
+        }
+        self.by_fee_per_cost
+            .insert((item.fee_per_cost(), item.spend_bundle_id));
+        self.total_cost += item.cost();
+        self.items.insert(item.spend_bundle_id, item);
+        Ok(removed)
+    }
+
+    pub fn remove(&mut self, spend_bundle_id: &Bytes32) -> Option<MempoolItem> {
+        let item = self.items.remove(spend_bundle_id)?;
+        for coin_id in item.removals() {
+            if let Some(ids) = self.by_coin.get_mut(coin_id) {
+                ids.remove(spend_bundle_id);
+                if ids.is_empty() {
+                    self.by_coin.remove(coin_id);
+                }
+            }
+        }
+        self.by_fee_per_cost
+            .remove(&(item.fee_per_cost(), *spend_bundle_id));
+        self.total_cost -= item.cost();
+        Some(item)
+    }
+
+    // called when a new transaction block is added to the chain. Items that
+    // spend any of the coins spent by the block are removed, as well as items
+    // that have expired. Returns the removed items
+    pub fn new_peak<'a>(
+        &mut self,
+        height: u32,
+        timestamp: u64,
+        spent_coins: impl IntoIterator<Item = &'a Bytes32>,
+    ) -> Vec<MempoolItem> {
+        self.peak_height = height;
+        self.peak_timestamp = timestamp;
+
+        let mut to_remove = HashSet::<Bytes32>::new();
+        for coin_id in spent_coins {
+            if let Some(ids) = self.by_coin.get(coin_id) {
+                to_remove.extend(ids.iter().copied());
+            }
+        }
+        for (id, item) in &self.items {
+            if item.is_expired(height, timestamp) {
+                to_remove.insert(*id);
+            }
+        }
+        to_remove.iter().filter_map(|id| self.remove(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chia_bls::Signature;
+    use chia_protocol::{Coin, Program};
+    use rstest::rstest;
+
+    fn coin(idx: u8) -> Coin {
+        Coin::new([idx; 32].into(), [0x22; 32].into(), 1_000_000_000)
+    }
+
+    // creates a mempool item spending the specified coins, paying the
+    // specified fee. The solution is used to make items unique
+    fn make_item(coins: &[(u8, u32)], fee: u64, cost: u64, solution: u8) -> MempoolItem {
+        let mut coin_spends = Vec::new();
+        let mut spends = Vec::new();
+        let mut confirmations = HashMap::new();
+        for (idx, flags) in coins {
+            let c = coin(*idx);
+            coin_spends.push(CoinSpend::new(
+                c,
+                Program::from(vec![1_u8]),
+                Program::from(vec![solution]),
+            ));
+            spends.push(OwnedSpend {
+                flags: *flags,
+                ..OwnedSpend::for_coin(&c, &[])
+            });
+            confirmations.insert(c.coin_id(), CoinConfirmation::default());
+        }
+        let mut conds = OwnedSpendBundleConditions::for_spends(spends);
+        conds.cost = cost;
+        conds.addition_amount = conds.removal_amount - u128::from(fee);
+        let bundle = SpendBundle::new(coin_spends, Signature::default());
+        MempoolItem::new(bundle, conds, 0, &confirmations).expect("MempoolItem::new")
+    }
+
+    fn ids(items: &[MempoolItem]) -> HashSet<Bytes32> {
+        items.iter().map(|i| i.spend_bundle_id).collect()
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut mempool = Mempool::new(100_000_000);
+        let item1 = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        let item2 = make_item(&[(2, 0), (3, 0)], 300, 1_000_000, 0);
+        let id1 = item1.spend_bundle_id;
+        let id2 = item2.spend_bundle_id;
+
+        assert!(mempool.add(item1.clone()).unwrap().is_empty());
+        assert!(mempool.add(item2).unwrap().is_empty());
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.total_cost(), 2_000_000);
+        assert_eq!(
+            mempool.add(item1).unwrap_err(),
+            ErrorCode::AlreadyIncludingTransaction
+        );
+
+        // highest fee-per-cost first
+        let order: Vec<Bytes32> = mempool
+            .items_by_fee_per_cost()
+            .map(|i| i.spend_bundle_id)
+            .collect();
+        assert_eq!(order, vec![id2, id1]);
+
+        let coin3 = coin(3).coin_id();
+        assert_eq!(mempool.items_spending(&coin3).count(), 1);
+
+        assert_eq!(mempool.remove(&id2).unwrap().spend_bundle_id, id2);
+        assert!(mempool.remove(&id2).is_none());
+        assert_eq!(mempool.items_spending(&coin3).count(), 0);
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.total_cost(), 1_000_000);
+    }
+
+    #[test]
+    fn test_fee_per_cost_order() {
+        assert!(FeePerCost::new(1, 3) < FeePerCost::new(1, 2));
+        assert!(FeePerCost::new(2, 4) == FeePerCost::new(1, 2));
+        assert!(FeePerCost::new(u64::MAX, 1) > FeePerCost::new(u64::MAX - 1, 1));
+        assert!(FeePerCost::new(0, 1) < FeePerCost::new(1, u64::MAX));
+    }
+
+    #[test]
+    fn test_invalid_fee() {
+        let mut item = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        let coins = HashMap::from([(coin(1).coin_id(), CoinConfirmation::default())]);
+
+        item.conds.addition_amount = item.conds.removal_amount + 1;
+        assert_eq!(
+            MempoolItem::new(item.spend_bundle.clone(), item.conds.clone(), 0, &coins).unwrap_err(),
+            ErrorCode::MintingCoin
+        );
+
+        item.conds.addition_amount = item.conds.removal_amount - 100;
+        item.conds.reserve_fee = 101;
+        assert_eq!(
+            MempoolItem::new(item.spend_bundle.clone(), item.conds.clone(), 0, &coins).unwrap_err(),
+            ErrorCode::ReserveFeeConditionFailed
+        );
+
+        item.conds.reserve_fee = 100;
+        assert_eq!(
+            MempoolItem::new(
+                item.spend_bundle.clone(),
+                item.conds.clone(),
+                0,
+                &HashMap::new()
+            )
+            .unwrap_err(),
+            ErrorCode::UnknownUnspent
+        );
+    }
+
+    #[rstest]
+    // the new item pays a higher fee, spends a superset of coins
+    #[case(&[(1, 0), (2, 0)], 20_000_100, true)]
+    // the fee increase is too small
+    #[case(&[(1, 0), (2, 0)], 10_000_099, false)]
+    // the new item does not spend coin 2
+    #[case(&[(1, 0)], 20_000_100, false)]
+    fn test_replace_by_fee(
+        #[case] coins: &[(u8, u32)],
+        #[case] fee: u64,
+        #[case] expect_replace: bool,
+    ) {
+        let mut mempool = Mempool::new(100_000_000);
+        let existing = make_item(&[(1, 0), (2, 0)], 100, 1_000_000, 0);
+        let existing_id = existing.spend_bundle_id;
+        mempool.add(existing).unwrap();
+
+        let new_item = make_item(coins, fee, 1_000_000, 1);
+        let new_id = new_item.spend_bundle_id;
+        if expect_replace {
+            let removed = mempool.add(new_item).expect("add");
+            assert_eq!(ids(&removed), HashSet::from([existing_id]));
+            assert!(mempool.contains(&new_id));
+            assert!(!mempool.contains(&existing_id));
+        } else {
+            assert_eq!(
+                mempool.add(new_item).unwrap_err(),
+                ErrorCode::MempoolConflict
+            );
+            assert!(mempool.contains(&existing_id));
+        }
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_replace_lower_fee_per_cost() {
+        let mut mempool = Mempool::new(100_000_000);
+        mempool
+            .add(make_item(&[(1, 0)], 20_000_000, 1_000_000, 0))
+            .unwrap();
+
+        // higher fee, but a lower fee-per-cost
+        let new_item = make_item(&[(1, 0)], 40_000_000, 3_000_000, 1);
+        assert_eq!(
+            mempool.add(new_item).unwrap_err(),
+            ErrorCode::MempoolConflict
+        );
+    }
+
+    #[test]
+    fn test_replace_multiple() {
+        let mut mempool = Mempool::new(100_000_000);
+        let item1 = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        let item2 = make_item(&[(2, 0)], 100, 1_000_000, 0);
+        let item3 = make_item(&[(3, 0)], 100, 1_000_000, 0);
+        let expected = ids(&[item1.clone(), item2.clone()]);
+        mempool.add(item1).unwrap();
+        mempool.add(item2).unwrap();
+        mempool.add(item3).unwrap();
+
+        let removed = mempool
+            .add(make_item(&[(1, 0), (2, 0)], 30_000_000, 1_000_000, 1))
+            .expect("add");
+        assert_eq!(ids(&removed), expected);
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.total_cost(), 2_000_000);
+    }
+
+    #[rstest]
+    #[case(0, 0, true)]
+    #[case(ELIGIBLE_FOR_DEDUP, 0, true)]
+    #[case(0, ELIGIBLE_FOR_DEDUP, true)]
+    #[case(ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_DEDUP, false)]
+    #[case(ELIGIBLE_FOR_FF, 0, true)]
+    #[case(0, ELIGIBLE_FOR_FF, true)]
+    #[case(ELIGIBLE_FOR_FF, ELIGIBLE_FOR_FF, false)]
+    #[case(ELIGIBLE_FOR_FF, ELIGIBLE_FOR_DEDUP, true)]
+    #[case(ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_FF, true)]
+    #[case(ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, false)]
+    #[case(ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_DEDUP, false)]
+    #[case(ELIGIBLE_FOR_FF, ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, false)]
+    #[case(ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_FF, false)]
+    #[case(ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, ELIGIBLE_FOR_FF | ELIGIBLE_FOR_DEDUP, false)]
+    fn test_dedup_ff_conflicts(
+        #[case] existing_flags: u32,
+        #[case] new_flags: u32,
+        #[case] conflict: bool,
+    ) {
+        let mut mempool = Mempool::new(100_000_000);
+        // both items spend coin 1, with the same solution, but they also
+        // spend one coin each that's unique, to make them different
+        mempool
+            .add(make_item(&[(1, existing_flags), (2, 0)], 100, 1_000_000, 0))
+            .unwrap();
+        let ret = mempool.add(make_item(&[(1, new_flags), (3, 0)], 100, 1_000_000, 0));
+        if conflict {
+            assert_eq!(ret.unwrap_err(), ErrorCode::MempoolConflict);
+            assert_eq!(mempool.len(), 1);
+        } else {
+            assert!(ret.unwrap().is_empty());
+            assert_eq!(mempool.len(), 2);
+            assert_eq!(mempool.items_spending(&coin(1).coin_id()).count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_dedup_different_solution() {
+        let mut mempool = Mempool::new(100_000_000);
+        mempool
+            .add(make_item(
+                &[(1, ELIGIBLE_FOR_DEDUP), (2, 0)],
+                100,
+                1_000_000,
+                0,
+            ))
+            .unwrap();
+        // the solution for coin 1 differs, so this is a conflict
+        assert_eq!(
+            mempool
+                .add(make_item(
+                    &[(1, ELIGIBLE_FOR_DEDUP), (3, 0)],
+                    100,
+                    1_000_000,
+                    1
+                ))
+                .unwrap_err(),
+            ErrorCode::MempoolConflict
+        );
+    }
+
+    #[test]
+    fn test_full_mempool() {
+        let mut mempool = Mempool::new(3_000_000);
+        let low = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        let low_id = low.spend_bundle_id;
+        mempool.add(low).unwrap();
+        mempool
+            .add(make_item(&[(2, 0)], 300, 1_000_000, 0))
+            .unwrap();
+        mempool
+            .add(make_item(&[(3, 0)], 300, 1_000_000, 0))
+            .unwrap();
+
+        // the mempool is full, and the new item doesn't pay more than the
+        // lowest item
+        assert_eq!(
+            mempool
+                .add(make_item(&[(4, 0)], 100, 1_000_000, 0))
+                .unwrap_err(),
+            ErrorCode::InvalidFeeLowFee
+        );
+
+        // this one pays more, and evicts the lowest item
+        let removed = mempool
+            .add(make_item(&[(4, 0)], 200, 1_000_000, 0))
+            .expect("add");
+        assert_eq!(ids(&removed), HashSet::from([low_id]));
+        assert_eq!(mempool.total_cost(), 3_000_000);
+
+        assert_eq!(
+            mempool
+                .add(make_item(&[(5, 0)], 1_000_000, 3_000_001, 0))
+                .unwrap_err(),
+            ErrorCode::CostExceeded
+        );
+    }
+
+    #[test]
+    fn test_expiration() {
+        let mut mempool = Mempool::new(100_000_000);
+        mempool.new_peak(10, 1000, &[]);
+
+        let mut expiring = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        expiring.time_locks.assert_before_height = Some(10);
+        assert_eq!(
+            mempool.add(expiring.clone()).unwrap_err(),
+            ErrorCode::AssertBeforeHeightAbsoluteFailed
+        );
+        expiring.time_locks.assert_before_height = Some(12);
+        mempool.add(expiring.clone()).unwrap();
+
+        let mut seconds = make_item(&[(2, 0)], 100, 1_000_000, 0);
+        seconds.time_locks.assert_before_seconds = Some(1000);
+        assert_eq!(
+            mempool.add(seconds.clone()).unwrap_err(),
+            ErrorCode::AssertBeforeSecondsAbsoluteFailed
+        );
+        seconds.time_locks.assert_before_seconds = Some(2000);
+        mempool.add(seconds.clone()).unwrap();
+
+        let spent = make_item(&[(3, 0)], 100, 1_000_000, 0);
+        mempool.add(spent.clone()).unwrap();
+        mempool
+            .add(make_item(&[(4, 0)], 100, 1_000_000, 0))
+            .unwrap();
+        assert_eq!(mempool.len(), 4);
+
+        // coin 3 is spent by the block
+        let removed = mempool.new_peak(11, 1500, &[coin(3).coin_id()]);
+        assert_eq!(ids(&removed), ids(&[spent]));
+
+        // at peak height 12, the item has expired
+        let removed = mempool.new_peak(12, 2000, &[]);
+        assert_eq!(ids(&removed), ids(&[expiring, seconds]));
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.peak(), (12, 2000));
+    }
+
+    #[test]
+    fn test_not_yet_valid() {
+        let mut mempool = Mempool::new(100_000_000);
+        mempool.new_peak(10, 1000, &[]);
+
+        let mut item = make_item(&[(1, 0)], 100, 1_000_000, 0);
+        item.time_locks.assert_height = 11;
+        assert_eq!(
+            mempool.add(item.clone()).unwrap_err(),
+            ErrorCode::AssertHeightAbsoluteFailed
+        );
+        item.time_locks.assert_height = 10;
+        item.time_locks.assert_seconds = 1001;
+        assert_eq!(
+            mempool.add(item.clone()).unwrap_err(),
+            ErrorCode::AssertSecondsAbsoluteFailed
+        );
+        item.time_locks.assert_seconds = 1000;
+        mempool.add(item).unwrap();
+    }
+
+    #[test]
+    fn test_relative_expiration() {
+        let mut item = make_item(&[(1, 0), (2, 0)], 100, 1_000_000, 0);
+        item.conds.before_height_absolute = Some(200);
+        item.conds.spends[0].before_height_relative = Some(50);
+        item.conds.spends[1].before_seconds_relative = Some(600);
+        let coins = HashMap::from([
+            (
+                coin(1).coin_id(),
+                CoinConfirmation {
+                    height: 100,
+                    timestamp: 5000,
+                },
+            ),
+            (
+                coin(2).coin_id(),
+                CoinConfirmation {
+                    height: 120,
+                    timestamp: 6000,
+                },
+            ),
+        ]);
+        let item = MempoolItem::new(item.spend_bundle, item.conds, 0, &coins).unwrap();
+        assert_eq!(item.time_locks.assert_before_height, Some(150));
+        assert_eq!(item.time_locks.assert_before_seconds, Some(6600));
+        assert!(!item.is_expired(149, 6599));
+        assert!(item.is_expired(150, 0));
+        assert!(item.is_expired(0, 6600));
+    }
+}
@@ -40,6 +40,10 @@ pub struct MerkleSet {
     // additional complexity to support round-tripping proofs, but we don't use
     // it or need it anywhere.
     from_proof: bool,
+    // insert() and remove() append the nodes they change to the end of
+    // nodes_vec, leaving the old ones behind. This counts those unreachable
+    // nodes, to know when it's time to compact the vector
+    unused_nodes: usize,
 }
 
 const EMPTY: u8 = 0;
@@ -270,6 +274,106 @@ impl MerkleSet {
         }
     }
 
+    // produces a single proof for all the leafs, sharing the nodes their paths
+    // have in common. Every leaf is either proven included or excluded. The
+    // proof has the same format as the ones produced by generate_proof() and
+    // can be parsed by from_proof(). The returned bools say whether each leaf
+    // is included.
+    pub fn generate_multiproof(
+        &self,
+        leafs: &[[u8; 32]],
+    ) -> Result<(Vec<bool>, Vec<u8>), SetError> {
+        if self.from_proof {
+            return Err(SetError);
+        }
+        let mut sorted = leafs.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let mut proof = Vec::new();
+        let mut included = Vec::new();
+        self.generate_multiproof_impl(
+            self.nodes_vec.len() - 1,
+            &sorted,
+            &mut proof,
+            &mut included,
+            0,
+        )?;
+        // included is sorted, since we visit the leafs in order
+        Ok((
+            leafs
+                .iter()
+                .map(|leaf| included.binary_search(leaf).is_ok())
+                .collect(),
+            proof,
+        ))
+    }
+
+    // leafs are sorted, which means the ones whose bit at depth is 0 come first
+    fn generate_multiproof_impl(
+        &self,
+        current_node_index: usize,
+        leafs: &[[u8; 32]],
+        proof: &mut Vec<u8>,
+        included: &mut Vec<[u8; 32]>,
+        depth: u8,
+    ) -> Result<(), SetError> {
+        if leafs.is_empty() {
+            self.other_included(current_node_index, proof);
+            return Ok(());
+        }
+        match self.nodes_vec[current_node_index].0 {
+            ArrayTypes::Empty => {
+                proof.push(EMPTY);
+            }
+            ArrayTypes::Leaf => {
+                let leaf = &self.nodes_vec[current_node_index].1;
+                proof.push(TERMINAL);
+                proof.extend_from_slice(leaf);
+                if leafs.contains(leaf) {
+                    included.push(*leaf);
+                }
+            }
+            ArrayTypes::Middle(left, right) => {
+                if matches!(
+                    (
+                        self.nodes_vec[left as usize].0,
+                        self.nodes_vec[right as usize].0
+                    ),
+                    (ArrayTypes::Leaf, ArrayTypes::Leaf)
+                ) {
+                    let left = &self.nodes_vec[left as usize].1;
+                    let right = &self.nodes_vec[right as usize].1;
+                    pad_middles_for_proof_gen(proof, left, right, depth);
+                    for leaf in [left, right] {
+                        if leafs.contains(leaf) {
+                            included.push(*leaf);
+                        }
+                    }
+                    return Ok(());
+                }
+
+                proof.push(MIDDLE);
+                let split = leafs.partition_point(|leaf| !get_bit(leaf, depth));
+                self.generate_multiproof_impl(
+                    left as usize,
+                    &leafs[..split],
+                    proof,
+                    included,
+                    depth + 1,
+                )?;
+                self.generate_multiproof_impl(
+                    right as usize,
+                    &leafs[split..],
+                    proof,
+                    included,
+                    depth + 1,
+                )?;
+            }
+            ArrayTypes::Truncated => return Err(SetError),
+        }
+        Ok(())
+    }
+
     // this function builds the proof of the subtree we are not traversing
     // even though this sub-tree does not hold any proof-value, we need it to
     // compute and validate the root hash. When computing hashes, we collapse
@@ -340,6 +444,24 @@ pub fn validate_merkle_proof(
     Ok(tree.generate_proof(item)?.0)
 }
 
+// returns whether each item is included in the tree with the specified root,
+// given a proof produced by generate_multiproof(). If any of the items can't
+// be proven included or excluded, it fails with SetError
+pub fn validate_merkle_multiproof(
+    proof: &[u8],
+    items: &[[u8; 32]],
+    root: &[u8; 32],
+) -> Result<Vec<bool>, SetError> {
+    let tree = MerkleSet::from_proof(proof)?;
+    if tree.get_root() != *root {
+        return Err(SetError);
+    }
+    items
+        .iter()
+        .map(|item| Ok(tree.generate_proof(item)?.0))
+        .collect()
+}
+
 #[cfg(feature = "py-bindings")]
 #[pymethods]
 impl MerkleSet {
@@ -372,6 +494,25 @@ impl MerkleSet {
             Err(_) => Err(PyValueError::new_err("invalid proof")),
         }
     }
+
+    #[pyo3(name = "generate_multiproof")]
+    pub fn py_generate_multiproof(
+        &self,
+        py: Python<'_>,
+        leafs: &Bound<'_, PyList>,
+    ) -> PyResult<(Vec<bool>, PyObject)> {
+        let mut data: Vec<[u8; 32]> = Vec::with_capacity(leafs.len());
+        for leaf in leafs {
+            data.push(
+                leaf.extract::<[u8; 32]>()
+                    .map_err(|_| PyValueError::new_err("invalid leaf"))?,
+            );
+        }
+        match self.generate_multiproof(&data) {
+            Ok((included, proof)) => Ok((included, PyBytes::new_bound(py, &proof).into())),
+            Err(_) => Err(PyValueError::new_err("invalid proof")),
+        }
+    }
 }
 
 impl From<ArrayTypes> for NodeType {
@@ -554,6 +695,370 @@ impl MerkleSet {
     }
 }
 
+// The nodes_vec of a MerkleSet that was built from leafs (rather than from a
+// proof) has a regular shape, that insert() and remove() rely on. A middle
+// node either splits its sub tree by the bit at its depth, with one node per
+// tree layer, or it has exactly two leaf children, in which case the layers
+// between it and the bit where the leafs diverge are collapsed. A middle node
+// only has an empty child if the other child is a middle node with more than
+// two leafs below it.
+impl MerkleSet {
+    // adds leaf to the set, updating the root hash. Returns false if the leaf
+    // was already in the set. Sets built from proofs can't be modified
+    pub fn insert(&mut self, leaf: &[u8; 32]) -> Result<bool, SetError> {
+        if self.from_proof {
+            return Err(SetError);
+        }
+        let root = self.nodes_vec.len() - 1;
+        let Some(new_root) = self.insert_impl(root, leaf, 0)? else {
+            return Ok(false);
+        };
+        self.set_root(new_root);
+        Ok(true)
+    }
+
+    // removes leaf from the set, updating the root hash. Returns false if the
+    // leaf was not in the set. Sets built from proofs can't be modified
+    pub fn remove(&mut self, leaf: &[u8; 32]) -> Result<bool, SetError> {
+        if self.from_proof {
+            return Err(SetError);
+        }
+        let root = self.nodes_vec.len() - 1;
+        let Some(new_root) = self.remove_impl(root, leaf, 0)? else {
+            return Ok(false);
+        };
+        self.set_root(new_root);
+        Ok(true)
+    }
+
+    // the new root may be an existing node, but the root is always expected to
+    // be the last entry in nodes_vec
+    fn set_root(&mut self, index: usize) {
+        if index != self.nodes_vec.len() - 1 {
+            self.unused_nodes += 1;
+            self.nodes_vec.push(self.nodes_vec[index]);
+        }
+        if self.unused_nodes > self.nodes_vec.len() / 2 {
+            let mut nodes = Vec::with_capacity(self.nodes_vec.len() - self.unused_nodes);
+            self.copy_subtree(self.nodes_vec.len() - 1, &mut nodes);
+            self.nodes_vec = nodes;
+            self.unused_nodes = 0;
+        }
+    }
+
+    // appends the sub tree at index to nodes, in post-order
+    fn copy_subtree(&self, index: usize, nodes: &mut Vec<(ArrayTypes, [u8; 32])>) -> u32 {
+        let (node, node_hash) = self.nodes_vec[index];
+        let node = match node {
+            ArrayTypes::Middle(left, right) => ArrayTypes::Middle(
+                self.copy_subtree(left as usize, nodes),
+                self.copy_subtree(right as usize, nodes),
+            ),
+            _ => node,
+        };
+        nodes.push((node, node_hash));
+        nodes.len() as u32 - 1
+    }
+
+    // returns the two leafs of a middle node whose children are both leafs
+    fn leaf_pair(&self, index: usize) -> Option<([u8; 32], [u8; 32])> {
+        let ArrayTypes::Middle(left, right) = self.nodes_vec[index].0 else {
+            return None;
+        };
+        match (
+            self.nodes_vec[left as usize],
+            self.nodes_vec[right as usize],
+        ) {
+            ((ArrayTypes::Leaf, left), (ArrayTypes::Leaf, right)) => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    fn push_node(&mut self, node: ArrayTypes, node_hash: [u8; 32]) -> usize {
+        self.nodes_vec.push((node, node_hash));
+        self.nodes_vec.len() - 1
+    }
+
+    // the hash a node contributes to its parent's hash
+    fn child_hash(&self, index: usize) -> [u8; 32] {
+        match self.nodes_vec[index].0 {
+            ArrayTypes::Empty => BLANK,
+            _ => self.nodes_vec[index].1,
+        }
+    }
+
+    fn push_middle(&mut self, left: usize, right: usize) -> usize {
+        let node_hash = hash(
+            self.nodes_vec[left].0.into(),
+            self.nodes_vec[right].0.into(),
+            &self.child_hash(left),
+            &self.child_hash(right),
+        );
+        self.push_node(ArrayTypes::Middle(left as u32, right as u32), node_hash)
+    }
+
+    // returns the index of the new sub tree, or None if the leaf was already
+    // in it
+    fn insert_impl(
+        &mut self,
+        index: usize,
+        leaf: &[u8; 32],
+        depth: u8,
+    ) -> Result<Option<usize>, SetError> {
+        if let Some((left, right)) = self.leaf_pair(index) {
+            if *leaf == left || *leaf == right {
+                return Ok(None);
+            }
+            // the two leafs may have been collapsed from any number of layers
+            // below this one, so we rebuild this sub tree from scratch
+            self.unused_nodes += 3;
+            self.generate_merkle_tree_recurse(&mut [left, right, *leaf], depth);
+            return Ok(Some(self.nodes_vec.len() - 1));
+        }
+
+        let (node, existing) = self.nodes_vec[index];
+        match node {
+            ArrayTypes::Empty => {
+                self.unused_nodes += 1;
+                Ok(Some(self.push_node(ArrayTypes::Leaf, *leaf)))
+            }
+            ArrayTypes::Leaf => {
+                if existing == *leaf {
+                    return Ok(None);
+                }
+                // the first bit that differs decides which leaf goes to the
+                // left, which is the same as comparing them
+                let new_leaf = self.push_node(ArrayTypes::Leaf, *leaf);
+                if existing < *leaf {
+                    Ok(Some(self.push_middle(index, new_leaf)))
+                } else {
+                    Ok(Some(self.push_middle(new_leaf, index)))
+                }
+            }
+            ArrayTypes::Middle(left, right) => {
+                if depth == 255 {
+                    return Err(SetError);
+                }
+                let (left, right) = (left as usize, right as usize);
+                if get_bit(leaf, depth) {
+                    let Some(right) = self.insert_impl(right, leaf, depth + 1)? else {
+                        return Ok(None);
+                    };
+                    self.unused_nodes += 1;
+                    Ok(Some(self.push_middle(left, right)))
+                } else {
+                    let Some(left) = self.insert_impl(left, leaf, depth + 1)? else {
+                        return Ok(None);
+                    };
+                    self.unused_nodes += 1;
+                    Ok(Some(self.push_middle(left, right)))
+                }
+            }
+            ArrayTypes::Truncated => Err(SetError),
+        }
+    }
+
+    // returns the index of the new sub tree, or None if the leaf wasn't in it
+    fn remove_impl(
+        &mut self,
+        index: usize,
+        leaf: &[u8; 32],
+        depth: u8,
+    ) -> Result<Option<usize>, SetError> {
+        if let Some((left, right)) = self.leaf_pair(index) {
+            let ArrayTypes::Middle(left_index, right_index) = self.nodes_vec[index].0 else {
+                unreachable!();
+            };
+            // the remaining leaf replaces the pair
+            let remaining = if *leaf == left {
+                right_index
+            } else if *leaf == right {
+                left_index
+            } else {
+                return Ok(None);
+            };
+            self.unused_nodes += 2;
+            return Ok(Some(remaining as usize));
+        }
+
+        let (node, existing) = self.nodes_vec[index];
+        match node {
+            ArrayTypes::Empty => Ok(None),
+            ArrayTypes::Leaf => {
+                if existing != *leaf {
+                    return Ok(None);
+                }
+                self.unused_nodes += 1;
+                Ok(Some(self.push_node(ArrayTypes::Empty, BLANK)))
+            }
+            ArrayTypes::Middle(left, right) => {
+                if depth == 255 {
+                    return Err(SetError);
+                }
+                let (mut left, mut right) = (left as usize, right as usize);
+                if get_bit(leaf, depth) {
+                    let Some(new_right) = self.remove_impl(right, leaf, depth + 1)? else {
+                        return Ok(None);
+                    };
+                    right = new_right;
+                } else {
+                    let Some(new_left) = self.remove_impl(left, leaf, depth + 1)? else {
+                        return Ok(None);
+                    };
+                    left = new_left;
+                }
+                self.unused_nodes += 1;
+
+                // if only one side is left, and it's a single leaf or a pair
+                // of leafs, this layer collapses into it
+                let remaining = match (self.nodes_vec[left].0, self.nodes_vec[right].0) {
+                    (ArrayTypes::Empty, _) => right,
+                    (_, ArrayTypes::Empty) => left,
+                    _ => return Ok(Some(self.push_middle(left, right))),
+                };
+                if matches!(self.nodes_vec[remaining].0, ArrayTypes::Leaf)
+                    || self.leaf_pair(remaining).is_some()
+                {
+                    self.unused_nodes += 1;
+                    Ok(Some(remaining))
+                } else {
+                    Ok(Some(self.push_middle(left, right)))
+                }
+            }
+            ArrayTypes::Truncated => Err(SetError),
+        }
+    }
+
+    // The serialized form lists the nodes of the tree in post-order, i.e.
+    // children before their parent. Each node is a type byte (EMPTY, TERMINAL
+    // or MIDDLE) followed by the leaf for terminal nodes and the node hash for
+    // middle nodes. Storing the hashes of middle nodes allows loading the set
+    // back without hashing anything.
+    pub fn serialize(&self) -> Result<Vec<u8>, SetError> {
+        if self.from_proof {
+            return Err(SetError);
+        }
+        let mut nodes = Vec::with_capacity(self.nodes_vec.len() - self.unused_nodes);
+        self.copy_subtree(self.nodes_vec.len() - 1, &mut nodes);
+
+        let mut ret = Vec::with_capacity(nodes.len() * 33);
+        for (node, node_hash) in nodes {
+            match node {
+                ArrayTypes::Empty => ret.push(EMPTY),
+                ArrayTypes::Leaf => {
+                    ret.push(TERMINAL);
+                    ret.extend_from_slice(&node_hash);
+                }
+                ArrayTypes::Middle(_, _) => {
+                    ret.push(MIDDLE);
+                    ret.extend_from_slice(&node_hash);
+                }
+                ArrayTypes::Truncated => return Err(SetError),
+            }
+        }
+        Ok(ret)
+    }
+
+    // loads a set produced by serialize(). The shape of the tree and the
+    // position of every leaf is validated, but the node hashes are not, so
+    // this must only be used with trusted data
+    pub fn deserialize(buf: &[u8]) -> Result<MerkleSet, SetError> {
+        let mut merkle_tree = MerkleSet::default();
+        // the indices and heights of the sub trees that don't have a parent
+        // yet
+        let mut stack = Vec::<(u32, u32)>::new();
+        let mut pos = 0;
+        while pos < buf.len() {
+            let node_type = buf[pos];
+            pos += 1;
+            let node = match node_type {
+                EMPTY => (ArrayTypes::Empty, BLANK),
+                TERMINAL | MIDDLE => {
+                    let node_hash: [u8; 32] =
+                        buf.get(pos..pos + 32).ok_or(SetError)?.try_into().unwrap();
+                    pos += 32;
+                    if node_type == TERMINAL {
+                        (ArrayTypes::Leaf, node_hash)
+                    } else {
+                        let (Some(right), Some(left)) = (stack.pop(), stack.pop()) else {
+                            return Err(SetError);
+                        };
+                        let height = std::cmp::max(left.1, right.1) + 1;
+                        if height > 257 {
+                            return Err(SetError);
+                        }
+                        stack.push((merkle_tree.nodes_vec.len() as u32, height));
+                        merkle_tree
+                            .nodes_vec
+                            .push((ArrayTypes::Middle(left.0, right.0), node_hash));
+                        continue;
+                    }
+                }
+                _ => return Err(SetError),
+            };
+            stack.push((merkle_tree.nodes_vec.len() as u32, 0));
+            merkle_tree.nodes_vec.push(node);
+        }
+        if stack.len() != 1 {
+            return Err(SetError);
+        }
+        merkle_tree.audit_subtree(merkle_tree.nodes_vec.len() - 1, &[0; 32], 0)?;
+        Ok(merkle_tree)
+    }
+
+    // ensures the sub tree has the shape insert() and remove() expect, and
+    // that every leaf is positioned according to its bits
+    fn audit_subtree(&self, index: usize, path: &[u8; 32], depth: u16) -> Result<(), SetError> {
+        let matches_path = |leaf: &[u8; 32]| {
+            (0..depth).all(|bit| get_bit(leaf, bit as u8) == get_bit(path, bit as u8))
+        };
+
+        if let Some((left, right)) = self.leaf_pair(index) {
+            return if left < right && matches_path(&left) && matches_path(&right) {
+                Ok(())
+            } else {
+                Err(SetError)
+            };
+        }
+        let (node, leaf) = self.nodes_vec[index];
+        match node {
+            ArrayTypes::Leaf => {
+                if matches_path(&leaf) {
+                    Ok(())
+                } else {
+                    Err(SetError)
+                }
+            }
+            ArrayTypes::Middle(left, right) => {
+                // only a pair of leafs can be split by the last bit
+                if depth >= 255 {
+                    return Err(SetError);
+                }
+                let (left, right) = (left as usize, right as usize);
+                let is_large_middle = |i: usize| {
+                    matches!(self.nodes_vec[i].0, ArrayTypes::Middle(_, _))
+                        && self.leaf_pair(i).is_none()
+                };
+                let children_ok = match (self.nodes_vec[left].0, self.nodes_vec[right].0) {
+                    (ArrayTypes::Empty, _) => is_large_middle(right),
+                    (_, ArrayTypes::Empty) => is_large_middle(left),
+                    _ => true,
+                };
+                if !children_ok {
+                    return Err(SetError);
+                }
+                let mut right_path = *path;
+                right_path[(depth / 8) as usize] |= 0x80 >> (depth & 7);
+                self.audit_subtree(left, path, depth + 1)?;
+                self.audit_subtree(right, &right_path, depth + 1)
+            }
+            // the parent has already checked where empty nodes are allowed
+            ArrayTypes::Empty => Ok(()),
+            ArrayTypes::Truncated => Err(SetError),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -769,4 +1274,312 @@ mod tests {
             assert_eq!(MerkleSet::from_leafs(&mut leafs).get_root(), root);
         }
     }
+
+    fn random_leafs(rng: &mut SmallRng, count: usize) -> Vec<[u8; 32]> {
+        let mut leafs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut leaf = [0_u8; 32];
+            rng.fill(&mut leaf);
+            leafs.push(leaf);
+        }
+        leafs
+    }
+
+    // ensures the tree is identical to one built from scratch with the same
+    // leafs, in root hash, proofs and size
+    fn check_against_from_leafs(tree: &MerkleSet, leafs: &[[u8; 32]]) {
+        let mut leafs = leafs.to_vec();
+        let root = compute_merkle_set_root(&mut leafs);
+        assert_eq!(tree.get_root(), root);
+        assert_eq!(tree.get_merkle_root_old(), root);
+        let expected = MerkleSet::from_leafs(&mut leafs);
+        for item in &leafs {
+            assert_eq!(
+                tree.generate_proof(item).unwrap(),
+                expected.generate_proof(item).unwrap()
+            );
+        }
+        assert!(tree.nodes_vec.len() <= 2 * expected.nodes_vec.len());
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut rng = SmallRng::seed_from_u64(1337);
+        for _ in 0..TEST_ITERS / 10 {
+            let count = rng.gen_range(0..=100);
+            let mut leafs = random_leafs(&mut rng, count);
+            let mut tree = MerkleSet::from_leafs(&mut []);
+            for i in 0..leafs.len() {
+                assert!(tree.insert(&leafs[i]).unwrap());
+                check_against_from_leafs(&tree, &leafs[..=i]);
+            }
+            for leaf in &leafs {
+                assert!(!tree.insert(leaf).unwrap());
+            }
+            for leaf in random_leafs(&mut rng, 10) {
+                let (included, proof) = tree.generate_proof(&leaf).unwrap();
+                assert!(!included);
+                assert!(!validate_merkle_proof(&proof, &leaf, &tree.get_root()).unwrap());
+                assert!(!tree.remove(&leaf).unwrap());
+            }
+
+            // remove the leafs in a different order than they were inserted
+            for i in (1..leafs.len()).rev() {
+                leafs.swap(i, rng.gen_range(0..=i));
+            }
+            while let Some(leaf) = leafs.pop() {
+                assert!(tree.remove(&leaf).unwrap());
+                assert!(!tree.remove(&leaf).unwrap());
+                check_against_from_leafs(&tree, &leafs);
+            }
+            assert_eq!(tree.get_root(), BLANK);
+        }
+    }
+
+    #[test]
+    fn test_insert_remove_merkle_set() {
+        // these test cases include leafs sharing long prefixes, which exercise
+        // the collapsing of layers
+        for (root, mut leafs) in merkle_set_test_cases() {
+            leafs.sort_unstable();
+            leafs.dedup();
+            let mut tree = MerkleSet::from_leafs(&mut []);
+            for leaf in leafs.iter().rev() {
+                tree.insert(leaf).unwrap();
+            }
+            assert_eq!(tree.get_root(), root);
+            check_against_from_leafs(&tree, &leafs);
+
+            // remove and re-insert every leaf
+            for (i, leaf) in leafs.iter().enumerate() {
+                let mut remaining = leafs.clone();
+                remaining.remove(i);
+                let mut tree = MerkleSet::from_leafs(&mut leafs.clone());
+                assert!(tree.remove(leaf).unwrap());
+                check_against_from_leafs(&tree, &remaining);
+                assert!(tree.insert(leaf).unwrap());
+                assert_eq!(tree.get_root(), root);
+            }
+        }
+    }
+
+    #[test]
+    fn test_last_bit() {
+        // these leafs differ only in the very last bit
+        let a = [0_u8; 32];
+        let mut b = [0_u8; 32];
+        b[31] = 1;
+        let mut c = [0_u8; 32];
+        c[31] = 2;
+        let mut tree = MerkleSet::from_leafs(&mut []);
+        for leaf in [b, c, a] {
+            tree.insert(&leaf).unwrap();
+        }
+        check_against_from_leafs(&tree, &[a, b, c]);
+        tree.remove(&c).unwrap();
+        check_against_from_leafs(&tree, &[a, b]);
+        tree.remove(&a).unwrap();
+        check_against_from_leafs(&tree, &[b]);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        for count in [0, 1, 2, 3, 100, 1000] {
+            let mut leafs = random_leafs(&mut rng, count);
+            let mut tree = MerkleSet::from_leafs(&mut leafs.clone());
+            // leave some unused nodes behind in the tree
+            for leaf in leafs.drain(..count / 2) {
+                tree.remove(&leaf).unwrap();
+            }
+
+            let buf = tree.serialize().unwrap();
+            let mut loaded = MerkleSet::deserialize(&buf).unwrap();
+            assert_eq!(loaded.serialize().unwrap(), buf);
+            assert_eq!(
+                MerkleSet::from_leafs(&mut leafs.clone())
+                    .serialize()
+                    .unwrap(),
+                buf
+            );
+            check_against_from_leafs(&loaded, &leafs);
+
+            // the loaded set can be modified
+            for leaf in random_leafs(&mut rng, 10) {
+                loaded.insert(&leaf).unwrap();
+                leafs.push(leaf);
+            }
+            check_against_from_leafs(&loaded, &leafs);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        let a = hex!("4000000000000000000000000000000000000000000000000000000000000000");
+        let b = hex!("8000000000000000000000000000000000000000000000000000000000000000");
+        let c = hex!("c000000000000000000000000000000000000000000000000000000000000000");
+        let leaf = |l: &[u8; 32]| [&[TERMINAL], l.as_slice()].concat();
+        let middle = |h: &[u8; 32]| [&[MIDDLE], h.as_slice()].concat();
+        let any_hash = [0x11; 32];
+
+        let valid = [
+            leaf(&a),
+            leaf(&b),
+            leaf(&c),
+            middle(&any_hash),
+            middle(&any_hash),
+        ]
+        .concat();
+        assert!(MerkleSet::deserialize(&valid).is_ok());
+
+        let cases = [
+            // nothing
+            vec![],
+            // truncated
+            valid[..valid.len() - 1].to_vec(),
+            // two roots
+            [leaf(&a), leaf(&b)].concat(),
+            // trailing data
+            [valid.clone(), vec![EMPTY]].concat(),
+            // a middle node without children
+            [leaf(&a), middle(&any_hash)].concat(),
+            // truncated nodes are only valid in proofs
+            [vec![TRUNCATED], any_hash.to_vec()].concat(),
+            vec![4],
+            // a pair of leafs in the wrong order
+            [leaf(&b), leaf(&a), middle(&any_hash)].concat(),
+            // a leaf on the wrong side
+            [
+                leaf(&c),
+                leaf(&a),
+                leaf(&b),
+                middle(&any_hash),
+                middle(&any_hash),
+            ]
+            .concat(),
+            // these layers should have been collapsed
+            [vec![EMPTY], leaf(&b)].concat(),
+            [leaf(&a), vec![EMPTY], middle(&any_hash)].concat(),
+            [
+                vec![EMPTY],
+                leaf(&a),
+                leaf(&b),
+                middle(&any_hash),
+                middle(&any_hash),
+            ]
+            .concat(),
+            [vec![EMPTY, EMPTY], middle(&any_hash)].concat(),
+        ];
+        for buf in cases {
+            assert!(matches!(MerkleSet::deserialize(&buf), Err(SetError)));
+        }
+
+        let malicious = [[EMPTY].repeat(40000), [MIDDLE].repeat(40000)].concat();
+        assert!(MerkleSet::deserialize(&malicious).is_err());
+    }
+
+    #[test]
+    fn test_modify_proof() {
+        let mut leafs = [[1_u8; 32], [2_u8; 32]];
+        let tree = MerkleSet::from_leafs(&mut leafs);
+        let (_, proof) = tree.generate_proof(&leafs[0]).unwrap();
+        let mut rebuilt = MerkleSet::from_proof(&proof).unwrap();
+        assert!(rebuilt.insert(&[3_u8; 32]).is_err());
+        assert!(rebuilt.remove(&leafs[0]).is_err());
+        assert!(rebuilt.serialize().is_err());
+        assert_eq!(rebuilt.get_root(), tree.get_root());
+    }
+
+    #[test]
+    fn test_multiproof() {
+        let mut rng = SmallRng::seed_from_u64(1337);
+        for count in [0, 1, 2, 3, 10, 500] {
+            let mut leafs = random_leafs(&mut rng, count);
+            let tree = MerkleSet::from_leafs(&mut leafs.clone());
+            let root = tree.get_root();
+
+            // half the leafs in the set, and as many that aren't
+            let mut items = leafs[..count / 2].to_vec();
+            items.extend(random_leafs(&mut rng, count / 2 + 1));
+            let expected: Vec<bool> = items.iter().map(|item| leafs.contains(item)).collect();
+
+            let (included, proof) = tree.generate_multiproof(&items).unwrap();
+            assert_eq!(included, expected);
+            assert_eq!(
+                validate_merkle_multiproof(&proof, &items, &root).unwrap(),
+                expected
+            );
+
+            let rebuilt = MerkleSet::from_proof(&proof).unwrap();
+            assert_eq!(rebuilt.get_root(), root);
+            let mut single_proofs_len = 0;
+            for (item, expected) in items.iter().zip(&expected) {
+                assert_eq!(rebuilt.generate_proof(item).unwrap(), (*expected, vec![]));
+                single_proofs_len += tree.generate_proof(item).unwrap().1.len();
+            }
+            if count >= 10 {
+                assert!(proof.len() < single_proofs_len);
+            }
+
+            // a proof of a single leaf is the same as the regular one
+            leafs.push([0x42; 32]);
+            for item in &leafs {
+                let (included, proof) = tree.generate_multiproof(&[*item]).unwrap();
+                assert_eq!((included[0], proof), tree.generate_proof(item).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiproof_merkle_set() {
+        for (root, leafs) in merkle_set_test_cases() {
+            let tree = MerkleSet::from_leafs(&mut leafs.clone());
+            let (included, proof) = tree.generate_multiproof(&leafs).unwrap();
+            assert!(included.iter().all(|i| *i));
+            assert_eq!(MerkleSet::from_proof(&proof).unwrap().get_root(), root);
+            assert_eq!(
+                validate_merkle_multiproof(&proof, &leafs, &root).unwrap(),
+                included
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_multiproof() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut leafs = random_leafs(&mut rng, 100);
+        let tree = MerkleSet::from_leafs(&mut leafs);
+        let root = tree.get_root();
+        let items: Vec<[u8; 32]> = leafs
+            .iter()
+            .filter(|leaf| get_bit(leaf, 0))
+            .copied()
+            .collect();
+        let (_, proof) = tree.generate_multiproof(&items).unwrap();
+        assert!(validate_merkle_multiproof(&proof, &items, &root).is_ok());
+
+        // wrong root
+        assert!(validate_merkle_multiproof(&proof, &items, &[0; 32]).is_err());
+
+        // the left half of the tree is truncated
+        let other: Vec<[u8; 32]> = leafs
+            .iter()
+            .filter(|leaf| !get_bit(leaf, 0))
+            .copied()
+            .collect();
+        assert!(validate_merkle_multiproof(&proof, &other[..1], &root).is_err());
+
+        // truncated proof
+        assert!(validate_merkle_multiproof(&proof[..proof.len() - 1], &items, &root).is_err());
+
+        // a tampered leaf
+        let mut tampered = proof.clone();
+        let pos = tampered.len() - 1;
+        tampered[pos] ^= 1;
+        assert!(validate_merkle_multiproof(&tampered, &items, &root).is_err());
+
+        // proofs can only be produced from the full set
+        let rebuilt = MerkleSet::from_proof(&proof).unwrap();
+        assert!(rebuilt.generate_multiproof(&items).is_err());
+    }
 }
@@ -0,0 +1,316 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::validation_error::ErrorCode;
+use crate::proof_of_space::calculate_iterations_quality;
+use chia_protocol::{BlockRecord, Bytes32};
+
+// the last NUM_SP_INTERVALS_EXTRA signage points of a sub-slot are infused in
+// the next sub-slot
+pub fn is_overflow_block(
+    constants: &ConsensusConstants,
+    signage_point_index: u8,
+) -> Result<bool, ErrorCode> {
+    let index = u32::from(signage_point_index);
+    if index >= constants.num_sps_sub_slot {
+        return Err(ErrorCode::InvalidSpIndex);
+    }
+    Ok(index >= constants.num_sps_sub_slot - u32::from(constants.num_sp_intervals_extra))
+}
+
+// the number of iterations between two signage points
+pub fn calculate_sp_interval_iters(
+    constants: &ConsensusConstants,
+    sub_slot_iters: u64,
+) -> Result<u64, ErrorCode> {
+    let num_sps = u64::from(constants.num_sps_sub_slot);
+    if num_sps == 0 || sub_slot_iters == 0 || sub_slot_iters % num_sps != 0 {
+        return Err(ErrorCode::InvalidNewSubSlotIters);
+    }
+    Ok(sub_slot_iters / num_sps)
+}
+
+// the number of iterations from the start of the sub-slot to the signage point
+pub fn calculate_sp_iters(
+    constants: &ConsensusConstants,
+    sub_slot_iters: u64,
+    signage_point_index: u8,
+) -> Result<u64, ErrorCode> {
+    if u32::from(signage_point_index) >= constants.num_sps_sub_slot {
+        return Err(ErrorCode::InvalidSpIndex);
+    }
+    Ok(calculate_sp_interval_iters(constants, sub_slot_iters)? * u64::from(signage_point_index))
+}
+
+// the number of iterations from the start of the sub-slot to the infusion
+// point. For overflow blocks, this wraps around into the next sub-slot
+pub fn calculate_ip_iters(
+    constants: &ConsensusConstants,
+    sub_slot_iters: u64,
+    signage_point_index: u8,
+    required_iters: u64,
+) -> Result<u64, ErrorCode> {
+    let sp_iters = calculate_sp_iters(constants, sub_slot_iters, signage_point_index)?;
+    let sp_interval_iters = calculate_sp_interval_iters(constants, sub_slot_iters)?;
+    if sp_iters % sp_interval_iters != 0 || sp_iters >= sub_slot_iters {
+        return Err(ErrorCode::InvalidSpIndex);
+    }
+    if required_iters >= sp_interval_iters || required_iters == 0 {
+        return Err(ErrorCode::InvalidRequiredIters);
+    }
+    let ip_iters = u128::from(sp_iters)
+        + u128::from(constants.num_sp_intervals_extra) * u128::from(sp_interval_iters)
+        + u128::from(required_iters);
+    Ok((ip_iters % u128::from(sub_slot_iters)) as u64)
+}
+
+// The number of iterations required for a proof of space with the specified
+// quality string. A proof of space is only good enough to make a block if
+// this is less than the iterations between two signage points.
+pub fn calculate_required_iters(
+    constants: &ConsensusConstants,
+    quality_string: &Bytes32,
+    size: u8,
+    difficulty: u64,
+    cc_sp_output_hash: &Bytes32,
+    sub_slot_iters: u64,
+) -> Result<u64, ErrorCode> {
+    let required_iters = calculate_iterations_quality(
+        constants.difficulty_constant_factor,
+        quality_string,
+        size,
+        difficulty,
+        cc_sp_output_hash,
+    )
+    .ok_or(ErrorCode::InvalidPospace)?;
+    if required_iters >= calculate_sp_interval_iters(constants, sub_slot_iters)? {
+        return Err(ErrorCode::InvalidRequiredIters);
+    }
+    Ok(required_iters)
+}
+
+// these mirror the iteration helpers on the python BlockRecord
+
+pub fn block_sp_iters(
+    constants: &ConsensusConstants,
+    block: &BlockRecord,
+) -> Result<u64, ErrorCode> {
+    calculate_sp_iters(constants, block.sub_slot_iters, block.signage_point_index)
+}
+
+pub fn block_ip_iters(
+    constants: &ConsensusConstants,
+    block: &BlockRecord,
+) -> Result<u64, ErrorCode> {
+    calculate_ip_iters(
+        constants,
+        block.sub_slot_iters,
+        block.signage_point_index,
+        block.required_iters,
+    )
+}
+
+// the total iterations at the start of the sub-slot the block was infused in
+pub fn ip_sub_slot_total_iters(
+    constants: &ConsensusConstants,
+    block: &BlockRecord,
+) -> Result<u128, ErrorCode> {
+    block
+        .total_iters
+        .checked_sub(u128::from(block_ip_iters(constants, block)?))
+        .ok_or(ErrorCode::InvalidTotalIters)
+}
+
+// the total iterations at the start of the sub-slot of the block's signage
+// point. For overflow blocks, that's the sub-slot before the infusion point's
+pub fn sp_sub_slot_total_iters(
+    constants: &ConsensusConstants,
+    block: &BlockRecord,
+) -> Result<u128, ErrorCode> {
+    let ret = ip_sub_slot_total_iters(constants, block)?;
+    if block.overflow {
+        ret.checked_sub(u128::from(block.sub_slot_iters))
+            .ok_or(ErrorCode::InvalidTotalIters)
+    } else {
+        Ok(ret)
+    }
+}
+
+// the total iterations at the block's signage point
+pub fn sp_total_iters(
+    constants: &ConsensusConstants,
+    block: &BlockRecord,
+) -> Result<u128, ErrorCode> {
+    Ok(sp_sub_slot_total_iters(constants, block)? + u128::from(block_sp_iters(constants, block)?))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use chia_protocol::ClassgroupElement;
+    use rstest::rstest;
+
+    // a block record with the specified iteration parameters. All other
+    // fields are placeholders
+    pub fn make_block_record(
+        height: u32,
+        total_iters: u128,
+        sub_slot_iters: u64,
+        signage_point_index: u8,
+        required_iters: u64,
+    ) -> BlockRecord {
+        BlockRecord::new(
+            Bytes32::from([0; 32]),
+            Bytes32::from([0; 32]),
+            height,
+            0,
+            total_iters,
+            signage_point_index,
+            ClassgroupElement::default(),
+            None,
+            Bytes32::default(),
+            Bytes32::default(),
+            sub_slot_iters,
+            Bytes32::default(),
+            Bytes32::default(),
+            required_iters,
+            0,
+            is_overflow_block(&TEST_CONSTANTS, signage_point_index).unwrap_or(false),
+            0,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[rstest]
+    #[case(0, Ok(false))]
+    #[case(60, Ok(false))]
+    #[case(61, Ok(true))]
+    #[case(63, Ok(true))]
+    #[case(64, Err(ErrorCode::InvalidSpIndex))]
+    #[case(255, Err(ErrorCode::InvalidSpIndex))]
+    fn test_is_overflow_block(#[case] index: u8, #[case] expected: Result<bool, ErrorCode>) {
+        assert_eq!(is_overflow_block(&TEST_CONSTANTS, index), expected);
+    }
+
+    #[rstest]
+    #[case(134_217_728, 0, Ok(0))]
+    #[case(134_217_728, 1, Ok(2_097_152))]
+    #[case(134_217_728, 63, Ok(132_120_576))]
+    #[case(134_217_728, 64, Err(ErrorCode::InvalidSpIndex))]
+    #[case(134_217_729, 1, Err(ErrorCode::InvalidNewSubSlotIters))]
+    #[case(0, 1, Err(ErrorCode::InvalidNewSubSlotIters))]
+    fn test_calculate_sp_iters(
+        #[case] ssi: u64,
+        #[case] index: u8,
+        #[case] expected: Result<u64, ErrorCode>,
+    ) {
+        assert_eq!(calculate_sp_iters(&TEST_CONSTANTS, ssi, index), expected);
+    }
+
+    #[rstest]
+    // sp_iters + 3 intervals + required_iters
+    #[case(134_217_728, 0, 1, Ok(6_291_457))]
+    #[case(134_217_728, 10, 1000, Ok(27_263_976))]
+    #[case(134_217_728, 60, 2_097_151, Ok(134_217_727))]
+    // overflow blocks wrap around into the next sub-slot
+    #[case(134_217_728, 61, 1, Ok(1))]
+    #[case(134_217_728, 63, 100, Ok(4_194_404))]
+    #[case(134_217_728, 0, 0, Err(ErrorCode::InvalidRequiredIters))]
+    #[case(134_217_728, 0, 2_097_152, Err(ErrorCode::InvalidRequiredIters))]
+    #[case(134_217_728, 64, 1, Err(ErrorCode::InvalidSpIndex))]
+    #[case(0, 0, 1, Err(ErrorCode::InvalidNewSubSlotIters))]
+    #[case(134_217_729, 0, 1, Err(ErrorCode::InvalidNewSubSlotIters))]
+    fn test_calculate_ip_iters(
+        #[case] ssi: u64,
+        #[case] index: u8,
+        #[case] required_iters: u64,
+        #[case] expected: Result<u64, ErrorCode>,
+    ) {
+        assert_eq!(
+            calculate_ip_iters(&TEST_CONSTANTS, ssi, index, required_iters),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_calculate_required_iters() {
+        // with the real difficulty constant factor, a random quality string
+        // is very unlikely to be good enough at any difficulty
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.difficulty_constant_factor = 1 << 50;
+        let quality = Bytes32::from([0x42; 32]);
+        let sp = Bytes32::from([0x17; 32]);
+        let ssi = constants.sub_slot_iters_starting;
+        let expected = calculate_iterations_quality(
+            constants.difficulty_constant_factor,
+            &quality,
+            32,
+            100,
+            &sp,
+        )
+        .unwrap();
+        assert!(expected < ssi / 64);
+        assert_eq!(
+            calculate_required_iters(&constants, &quality, 32, 100, &sp, ssi),
+            Ok(expected)
+        );
+        // at a high enough difficulty, the proof isn't good enough
+        assert_eq!(
+            calculate_required_iters(&constants, &quality, 32, u64::MAX, &sp, ssi),
+            Err(ErrorCode::InvalidRequiredIters)
+        );
+        assert_eq!(
+            calculate_required_iters(&TEST_CONSTANTS, &quality, 32, 100, &sp, ssi),
+            Err(ErrorCode::InvalidRequiredIters)
+        );
+        // a plot size of 0 is invalid
+        assert_eq!(
+            calculate_required_iters(&constants, &quality, 0, 100, &sp, ssi),
+            Err(ErrorCode::InvalidPospace)
+        );
+    }
+
+    #[rstest]
+    // the infusion point is 3 intervals plus 1000 iterations into the
+    // sub-slot, which starts at 10 * ssi
+    #[case(5, 10, 5 * 2_097_152)]
+    // an overflow block's signage point is in the previous sub-slot
+    #[case(62, 9, 62 * 2_097_152)]
+    fn test_block_total_iters(
+        #[case] index: u8,
+        #[case] sp_sub_slot: u128,
+        #[case] sp_offset: u128,
+    ) {
+        let ssi: u64 = 134_217_728;
+        let ip_iters = calculate_ip_iters(&TEST_CONSTANTS, ssi, index, 1000).unwrap();
+        let total_iters = 10 * u128::from(ssi) + u128::from(ip_iters);
+        let block = make_block_record(100, total_iters, ssi, index, 1000);
+
+        assert_eq!(block_ip_iters(&TEST_CONSTANTS, &block), Ok(ip_iters));
+        assert_eq!(
+            ip_sub_slot_total_iters(&TEST_CONSTANTS, &block),
+            Ok(10 * u128::from(ssi))
+        );
+        assert_eq!(
+            sp_sub_slot_total_iters(&TEST_CONSTANTS, &block),
+            Ok(sp_sub_slot * u128::from(ssi))
+        );
+        assert_eq!(
+            sp_total_iters(&TEST_CONSTANTS, &block),
+            Ok(sp_sub_slot * u128::from(ssi) + sp_offset)
+        );
+
+        // not enough total iterations
+        let block = make_block_record(100, u128::from(ip_iters) - 1, ssi, index, 1000);
+        assert_eq!(
+            ip_sub_slot_total_iters(&TEST_CONSTANTS, &block),
+            Err(ErrorCode::InvalidTotalIters)
+        );
+    }
+}
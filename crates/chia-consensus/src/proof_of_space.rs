@@ -0,0 +1,816 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::fork_heights::get_plot_filter_bits;
+use chia_bls::PublicKey;
+use chia_protocol::{Bytes32, ProofOfSpace};
+use clvmr::sha2::{Digest, Sha256};
+use num_bigint::BigUint;
+
+// these are the parameters of the chiapos plot format. y values are k + 6
+// bits, and are grouped into buckets of K_BC values. Entries in adjacent
+// buckets match if their y values satisfy the condition in matches()
+const EXTRA_BITS: u8 = 6;
+const K_B: u64 = 119;
+const K_C: u64 = 127;
+const K_BC: u64 = K_B * K_C;
+
+// the number of k-sized metadata values carried by the entries of each table
+const VECTOR_LENS: [usize; 8] = [0, 0, 1, 2, 4, 4, 3, 2];
+
+// chiapos supports k up to 50, since the metadata of table 5 is taken from a
+// single BLAKE3 hash
+const MAX_K: u8 = 50;
+
+pub(crate) fn sha256(buf: &[u8]) -> Bytes32 {
+    let mut hasher = Sha256::new();
+    hasher.update(buf);
+    Bytes32::new(hasher.finalize().into())
+}
+
+pub fn calculate_plot_id_pk(pool_public_key: &PublicKey, plot_public_key: &PublicKey) -> Bytes32 {
+    sha256(&[pool_public_key.to_bytes(), plot_public_key.to_bytes()].concat())
+}
+
+pub fn calculate_plot_id_ph(
+    pool_contract_puzzle_hash: &Bytes32,
+    plot_public_key: &PublicKey,
+) -> Bytes32 {
+    sha256(&[&pool_contract_puzzle_hash[..], &plot_public_key.to_bytes()].concat())
+}
+
+// the plot id is derived from the plot public key and either the pool public
+// key or the pool contract puzzle hash. Exactly one of them must be set,
+// otherwise the proof of space is invalid and None is returned.
+pub fn get_plot_id(pos: &ProofOfSpace) -> Option<Bytes32> {
+    match (&pos.pool_public_key, &pos.pool_contract_puzzle_hash) {
+        (Some(pool_pk), None) => Some(calculate_plot_id_pk(pool_pk, &pos.plot_public_key)),
+        (None, Some(pool_ph)) => Some(calculate_plot_id_ph(pool_ph, &pos.plot_public_key)),
+        _ => None,
+    }
+}
+
+pub fn calculate_plot_filter_input(
+    plot_id: &Bytes32,
+    challenge_hash: &Bytes32,
+    signage_point: &Bytes32,
+) -> Bytes32 {
+    sha256(&[&plot_id[..], &challenge_hash[..], &signage_point[..]].concat())
+}
+
+// the challenge the proof of space must be a proof for
+pub fn calculate_pos_challenge(
+    plot_id: &Bytes32,
+    challenge_hash: &Bytes32,
+    signage_point: &Bytes32,
+) -> Bytes32 {
+    sha256(&calculate_plot_filter_input(
+        plot_id,
+        challenge_hash,
+        signage_point,
+    ))
+}
+
+// a plot passes the filter if the first prefix_bits bits of the filter input
+// are zero
+pub fn passes_plot_filter(
+    prefix_bits: u8,
+    plot_id: &Bytes32,
+    challenge_hash: &Bytes32,
+    signage_point: &Bytes32,
+) -> bool {
+    let input = calculate_plot_filter_input(plot_id, challenge_hash, signage_point);
+    let mut remaining = u32::from(prefix_bits);
+    for byte in input.as_ref() {
+        if remaining == 0 {
+            return true;
+        }
+        let bits = std::cmp::min(remaining, 8);
+        if byte >> (8 - bits) != 0 {
+            return false;
+        }
+        remaining -= bits;
+    }
+    remaining == 0
+}
+
+// a big-endian string of bits, with the same layout as the bit strings in
+// chiapos
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Bits {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn from_bytes(buf: &[u8]) -> Self {
+        Self {
+            data: buf.to_vec(),
+            len: buf.len() * 8,
+        }
+    }
+
+    fn get(&self, idx: usize) -> bool {
+        (self.data[idx / 8] >> (7 - idx % 8)) & 1 == 1
+    }
+
+    fn push(&mut self, bit: bool) {
+        if self.len % 8 == 0 {
+            self.data.push(0);
+        }
+        if bit {
+            self.data[self.len / 8] |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    fn push_value(&mut self, value: u64, bits: usize) {
+        for i in (0..bits).rev() {
+            self.push((value >> i) & 1 == 1);
+        }
+    }
+
+    fn append(&mut self, other: &Self) {
+        for i in 0..other.len {
+            self.push(other.get(i));
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> Self {
+        let mut ret = Self::default();
+        for i in start..end {
+            ret.push(self.get(i));
+        }
+        ret
+    }
+
+    // the value of bits [start, end), at most 64 bits
+    fn value(&self, start: usize, end: usize) -> u64 {
+        (start..end).fold(0, |acc, i| (acc << 1) | u64::from(self.get(i)))
+    }
+}
+
+fn chacha8_block(key: &[u8; 32], counter: u64) -> [u8; 64] {
+    #[allow(clippy::many_single_char_names)]
+    fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(16);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(12);
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(8);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(7);
+    }
+
+    let mut input = [0_u32; 16];
+    input[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (word, chunk) in input[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().expect("4 bytes"));
+    }
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+
+    let mut x = input;
+    for _ in 0..4 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut ret = [0_u8; 64];
+    for (i, chunk) in ret.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&x[i].wrapping_add(input[i]).to_le_bytes());
+    }
+    ret
+}
+
+// BLAKE3 of an input of at most 64 bytes, which fits in a single block. This
+// is all chiapos needs, since the inputs of the f functions are small
+fn blake3_small(input: &[u8]) -> [u8; 32] {
+    const IV: [u32; 8] = [
+        0x6A09_E667,
+        0xBB67_AE85,
+        0x3C6E_F372,
+        0xA54F_F53A,
+        0x510E_527F,
+        0x9B05_688C,
+        0x1F83_D9AB,
+        0x5BE0_CD19,
+    ];
+    const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+    // CHUNK_START | CHUNK_END | ROOT
+    const FLAGS: u32 = 1 | 2 | 8;
+
+    #[allow(clippy::many_single_char_names)]
+    fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+        state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+        state[d] = (state[d] ^ state[a]).rotate_right(16);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_right(12);
+        state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+        state[d] = (state[d] ^ state[a]).rotate_right(8);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_right(7);
+    }
+
+    assert!(input.len() <= 64);
+    let mut block = [0_u8; 64];
+    block[..input.len()].copy_from_slice(input);
+    let mut m = [0_u32; 16];
+    for (word, chunk) in m.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().expect("4 bytes"));
+    }
+
+    let mut state = [0_u32; 16];
+    state[..8].copy_from_slice(&IV);
+    state[8..12].copy_from_slice(&IV[..4]);
+    // the counter (words 12 and 13) is zero
+    state[14] = input.len() as u32;
+    state[15] = FLAGS;
+
+    for round in 0..7 {
+        g(&mut state, 0, 4, 8, 12, m[0], m[1]);
+        g(&mut state, 1, 5, 9, 13, m[2], m[3]);
+        g(&mut state, 2, 6, 10, 14, m[4], m[5]);
+        g(&mut state, 3, 7, 11, 15, m[6], m[7]);
+        g(&mut state, 0, 5, 10, 15, m[8], m[9]);
+        g(&mut state, 1, 6, 11, 12, m[10], m[11]);
+        g(&mut state, 2, 7, 8, 13, m[12], m[13]);
+        g(&mut state, 3, 4, 9, 14, m[14], m[15]);
+        if round < 6 {
+            m = MSG_PERMUTATION.map(|i| m[i]);
+        }
+    }
+
+    let mut ret = [0_u8; 32];
+    for (i, chunk) in ret.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&(state[i] ^ state[i + 8]).to_le_bytes());
+    }
+    ret
+}
+
+// the f1 function of chiapos. The y value is k bits of the ChaCha8 keystream
+// (keyed by the plot id) at offset x * k, followed by the top 6 bits of x.
+fn f1(k: u8, key: &[u8; 32], x: u64) -> u64 {
+    let k_bits = usize::from(k);
+    let counter_bit = x * u64::from(k);
+    let counter = counter_bit / 512;
+    let bits_before_x = (counter_bit % 512) as usize;
+
+    let mut keystream = Bits::from_bytes(&chacha8_block(key, counter));
+    if bits_before_x + k_bits > 512 {
+        keystream.append(&Bits::from_bytes(&chacha8_block(key, counter + 1)));
+    }
+    let output = keystream.value(bits_before_x, bits_before_x + k_bits);
+    let extra = if k >= EXTRA_BITS {
+        x >> (k - EXTRA_BITS)
+    } else {
+        x << (EXTRA_BITS - k)
+    };
+    (output << EXTRA_BITS) | extra
+}
+
+// the f functions of tables 2 through 7. The y value and the metadata of the
+// new entry are computed from the BLAKE3 hash of the left entry's y value
+// and both entries' metadata.
+fn fx(k: u8, table: usize, y: u64, left: &Bits, right: &Bits) -> (u64, Bits) {
+    let k_bits = usize::from(k);
+    let extra = if table < 7 {
+        usize::from(EXTRA_BITS)
+    } else {
+        0
+    };
+    let mut input = Bits::default();
+    input.push_value(y, k_bits + usize::from(EXTRA_BITS));
+    input.append(left);
+    input.append(right);
+    let hash = blake3_small(&input.data);
+
+    let f = u64::from_be_bytes(hash[..8].try_into().expect("8 bytes")) >> (64 - k_bits - extra);
+    let metadata = if table < 4 {
+        let mut metadata = left.clone();
+        metadata.append(right);
+        metadata
+    } else if table < 7 {
+        let start = k_bits + extra;
+        Bits::from_bytes(&hash).slice(start, start + k_bits * VECTOR_LENS[table + 1])
+    } else {
+        Bits::default()
+    };
+    (f, metadata)
+}
+
+// whether the entries with the y values left and right match. The right
+// entry must be in the bucket following the left entry's
+fn matches(left: u64, right: u64) -> bool {
+    let bucket = left / K_BC;
+    if right / K_BC != bucket + 1 {
+        return false;
+    }
+    let parity = bucket % 2;
+    let left = left % K_BC;
+    let right = right % K_BC;
+    (0..64_u64).any(|m| {
+        let target = ((left / K_C + m) % K_B) * K_C + ((2 * m + parity).pow(2) + left) % K_C;
+        right == target
+    })
+}
+
+// compares two lists of k-bit values, starting from the last value. Returns
+// true if left sorts before right
+fn compare_proof_bits(left: &Bits, right: &Bits, k: usize) -> bool {
+    for i in (0..left.len / k).rev() {
+        let left_val = left.value(k * i, k * (i + 1));
+        let right_val = right.value(k * i, k * (i + 1));
+        if left_val != right_val {
+            return left_val < right_val;
+        }
+    }
+    false
+}
+
+// the quality string is the hash of the challenge and two adjacent x values
+// of the proof, once the proof has been converted from proof order to plot
+// order. The last 5 bits of the challenge pick the two x values.
+fn get_quality_string(k: u8, proof: &Bits, challenge: &Bytes32) -> Bytes32 {
+    let k_bits = usize::from(k);
+    let mut proof = proof.clone();
+    for table in 1..7 {
+        let size = k_bits << (table - 1);
+        let mut new_proof = Bits::default();
+        for j in (0..(1 << (7 - table))).step_by(2) {
+            let left = proof.slice(j * size, (j + 1) * size);
+            let right = proof.slice((j + 1) * size, (j + 2) * size);
+            if compare_proof_bits(&left, &right, k_bits) {
+                new_proof.append(&left);
+                new_proof.append(&right);
+            } else {
+                new_proof.append(&right);
+                new_proof.append(&left);
+            }
+        }
+        proof = new_proof;
+    }
+
+    let quality_index = usize::from(challenge[31] & 0x1f) * 2;
+    let xs = proof.slice(k_bits * quality_index, k_bits * (quality_index + 2));
+    sha256(&[&challenge[..], &xs.data].concat())
+}
+
+// Validates a chiapos proof of space, of 64 k-bit x values, for the plot id
+// and challenge. Returns the quality string of the proof, or None if it's
+// invalid.
+pub fn validate_proof(
+    plot_id: &Bytes32,
+    k: u8,
+    challenge: &Bytes32,
+    proof: &[u8],
+) -> Option<Bytes32> {
+    if k == 0 || k > MAX_K || proof.len() != usize::from(k) * 8 {
+        return None;
+    }
+    let k_bits = usize::from(k);
+    let proof_bits = Bits::from_bytes(proof);
+
+    let mut key = [0_u8; 32];
+    key[0] = 1;
+    key[1..].copy_from_slice(&plot_id[..31]);
+
+    let mut ys = Vec::with_capacity(64);
+    let mut metadata = Vec::with_capacity(64);
+    for i in 0..64 {
+        let x = proof_bits.slice(k_bits * i, k_bits * (i + 1));
+        ys.push(f1(k, &key, x.value(0, k_bits)));
+        metadata.push(x);
+    }
+
+    for table in 2..8 {
+        let mut new_ys = Vec::with_capacity(ys.len() / 2);
+        let mut new_metadata = Vec::with_capacity(ys.len() / 2);
+        for i in (0..ys.len()).step_by(2) {
+            if !matches(ys[i], ys[i + 1]) {
+                return None;
+            }
+            let (y, meta) = fx(k, table, ys[i], &metadata[i], &metadata[i + 1]);
+            new_ys.push(y);
+            new_metadata.push(meta);
+        }
+        ys = new_ys;
+        metadata = new_metadata;
+    }
+
+    // the output of f7 must match the first k bits of the challenge
+    if ys[0] != Bits::from_bytes(challenge).value(0, k_bits) {
+        return None;
+    }
+    Some(get_quality_string(k, &proof_bits, challenge))
+}
+
+// Validates the proof of space for the specified challenge hash and signage
+// point, at the specified height. This checks the plot id, the plot size, the
+// proof's challenge, the plot filter and finally the proof itself. Returns
+// the quality string, or None if the proof of space is invalid.
+pub fn verify_and_get_quality_string(
+    pos: &ProofOfSpace,
+    constants: &ConsensusConstants,
+    original_challenge_hash: &Bytes32,
+    signage_point: &Bytes32,
+    height: u32,
+) -> Option<Bytes32> {
+    let plot_id = get_plot_id(pos)?;
+    if pos.size < constants.min_plot_size || pos.size > constants.max_plot_size {
+        return None;
+    }
+    if calculate_pos_challenge(&plot_id, original_challenge_hash, signage_point) != pos.challenge {
+        return None;
+    }
+    let prefix_bits = get_plot_filter_bits(height, constants);
+    if !passes_plot_filter(
+        prefix_bits,
+        &plot_id,
+        original_challenge_hash,
+        signage_point,
+    ) {
+        return None;
+    }
+    validate_proof(&plot_id, pos.size, &pos.challenge, &pos.proof)
+}
+
+// the expected size of a plot, of size k, in bytes. Returns None if k isn't a
+// valid plot size
+pub fn expected_plot_size(k: u8) -> Option<u64> {
+    if k == 0 || k > MAX_K {
+        return None;
+    }
+    Some((2 * u64::from(k) + 1) << (k - 1))
+}
+
+// The number of iterations required for a proof of space with the specified
+// quality string, at the specified difficulty and signage point. It's at
+// least 1, and saturates at u64::MAX. Returns None if the plot size is
+// invalid.
+pub fn calculate_iterations_quality(
+    difficulty_constant_factor: u128,
+    quality_string: &Bytes32,
+    size: u8,
+    difficulty: u64,
+    cc_sp_output_hash: &Bytes32,
+) -> Option<u64> {
+    let plot_size = expected_plot_size(size)?;
+    let sp_quality_string = sha256(&[&quality_string[..], &cc_sp_output_hash[..]].concat());
+    let numerator = BigUint::from(difficulty)
+        * BigUint::from(difficulty_constant_factor)
+        * BigUint::from_bytes_be(&sp_quality_string);
+    let denominator = (BigUint::from(1_u32) << 256) * BigUint::from(plot_size);
+    let iters = u64::try_from(numerator / denominator).unwrap_or(u64::MAX);
+    Some(std::cmp::max(iters, 1))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use chia_bls::SecretKey;
+    use chia_protocol::Bytes;
+    use hex_literal::hex;
+    use rstest::rstest;
+    use std::collections::HashMap;
+
+    fn public_key(seed: u8) -> PublicKey {
+        SecretKey::from_seed(&[seed; 32]).public_key()
+    }
+
+    // Builds all tables of a (tiny) plot in memory and returns the x values
+    // of one proof, in proof order, and the output of f7
+    pub fn find_proof(k: u8, plot_id: &Bytes32) -> (Vec<u64>, u64) {
+        let mut key = [0_u8; 32];
+        key[0] = 1;
+        key[1..].copy_from_slice(&plot_id[..31]);
+
+        let mut entries: Vec<(u64, Bits, Vec<u64>)> = (0..1_u64 << k)
+            .map(|x| {
+                let mut meta = Bits::default();
+                meta.push_value(x, usize::from(k));
+                (f1(k, &key, x), meta, vec![x])
+            })
+            .collect();
+
+        for table in 2..8 {
+            let mut by_y = HashMap::<u64, Vec<usize>>::new();
+            for (i, entry) in entries.iter().enumerate() {
+                by_y.entry(entry.0).or_default().push(i);
+            }
+            let mut new_entries = Vec::new();
+            for left in &entries {
+                // the y values in the next bucket that match
+                let bucket = left.0 / K_BC;
+                let l = left.0 % K_BC;
+                for m in 0..64 {
+                    let target = (bucket + 1) * K_BC
+                        + ((l / K_C + m) % K_B) * K_C
+                        + ((2 * m + bucket % 2).pow(2) + l) % K_C;
+                    for idx in by_y.get(&target).into_iter().flatten() {
+                        let right = &entries[*idx];
+                        assert!(matches(left.0, right.0));
+                        let (y, meta) = fx(k, table, left.0, &left.1, &right.1);
+                        let xs = [&left.2[..], &right.2[..]].concat();
+                        new_entries.push((y, meta, xs));
+                    }
+                }
+            }
+            entries = new_entries;
+        }
+        let (y, _, xs) = entries.into_iter().next().expect("no proof in plot");
+        (xs, y)
+    }
+
+    pub fn encode_proof(k: u8, xs: &[u64]) -> Vec<u8> {
+        let mut bits = Bits::default();
+        for x in xs {
+            bits.push_value(*x, usize::from(k));
+        }
+        bits.data
+    }
+
+    // a challenge whose first k bits are f7
+    fn make_challenge(k: u8, f7: u64, tail: u8) -> Bytes32 {
+        let mut bits = Bits::default();
+        bits.push_value(f7, usize::from(k));
+        while bits.len < 256 {
+            bits.push(false);
+        }
+        bits.data[31] = tail;
+        Bytes32::new(bits.data.try_into().unwrap())
+    }
+
+    #[test]
+    fn test_chacha8() {
+        assert_eq!(
+            chacha8_block(&[0; 32], 0),
+            hex!("3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e984ce172b9216f419f445367456d5619314a42a3da86b001387bfdb80e0cfe42")
+        );
+        assert_eq!(
+            chacha8_block(&[0; 32], 5),
+            hex!("8c146d00fe2e1caec31b159fc42dcd7e06865c6fa5267c6ca9c5284e651e175a362f469b6e722347de959f76533315542ffa440d37cde8862da3b3331e53b60d")
+        );
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8 * 7 + 1);
+        assert_eq!(
+            chacha8_block(&key, 5),
+            hex!("6dd07d3c3e6c74bab44071ddf527ffa5baea60ffdd0df3842ff61b3be8b9fbace7dec35974ced9cf43a4270ce71e5278fb608686b49a5b87d24a050121cb1101")
+        );
+    }
+
+    #[rstest]
+    #[case(
+        b"",
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    )]
+    #[case(
+        b"abc",
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    )]
+    fn test_blake3(#[case] input: &[u8], #[case] expected: &str) {
+        assert_eq!(hex::encode(blake3_small(input)), expected);
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut bits = Bits::default();
+        bits.push_value(0b101, 3);
+        bits.push_value(0x1ff, 9);
+        assert_eq!(bits.len, 12);
+        assert_eq!(bits.data, [0b1011_1111, 0b1111_0000]);
+        assert_eq!(bits.value(0, 3), 0b101);
+        assert_eq!(bits.value(2, 12), 0x3ff);
+        assert_eq!(bits.slice(1, 4), {
+            let mut b = Bits::default();
+            b.push_value(0b011, 3);
+            b
+        });
+    }
+
+    #[test]
+    fn test_plot_id() {
+        let pool_pk = public_key(1);
+        let plot_pk = public_key(2);
+        let pool_ph = Bytes32::from([3; 32]);
+        let pk_id = calculate_plot_id_pk(&pool_pk, &plot_pk);
+        let ph_id = calculate_plot_id_ph(&pool_ph, &plot_pk);
+        assert_eq!(
+            pk_id,
+            sha256(&[pool_pk.to_bytes(), plot_pk.to_bytes()].concat())
+        );
+        assert_eq!(
+            ph_id,
+            sha256(&[[3; 32].as_slice(), &plot_pk.to_bytes()].concat())
+        );
+
+        let mut pos = ProofOfSpace::new(
+            Bytes32::default(),
+            Some(pool_pk),
+            None,
+            plot_pk,
+            32,
+            Bytes::default(),
+        );
+        assert_eq!(get_plot_id(&pos), Some(pk_id));
+        pos.pool_contract_puzzle_hash = Some(pool_ph);
+        assert_eq!(get_plot_id(&pos), None);
+        pos.pool_public_key = None;
+        assert_eq!(get_plot_id(&pos), Some(ph_id));
+        pos.pool_contract_puzzle_hash = None;
+        assert_eq!(get_plot_id(&pos), None);
+    }
+
+    #[test]
+    fn test_passes_plot_filter() {
+        let plot_id = Bytes32::from([1; 32]);
+        let sp = Bytes32::from([2; 32]);
+        let mut passed = [0_u32; 10];
+        for i in 0..=255 {
+            let challenge = Bytes32::from([i; 32]);
+            let input = calculate_plot_filter_input(&plot_id, &challenge, &sp);
+            let leading_zeros =
+                u128::from_be_bytes(input[..16].try_into().unwrap()).leading_zeros();
+            for (bits, count) in passed.iter_mut().enumerate() {
+                let expected = leading_zeros >= bits as u32;
+                assert_eq!(
+                    passes_plot_filter(bits as u8, &plot_id, &challenge, &sp),
+                    expected
+                );
+                *count += u32::from(expected);
+            }
+        }
+        assert_eq!(passed[0], 256);
+        assert!(passed[1] < 256 && passed[1] > 64);
+    }
+
+    #[rstest]
+    #[case(12)]
+    #[case(14)]
+    fn test_validate_proof(#[case] k: u8) {
+        let plot_id = Bytes32::from([k; 32]);
+        let (xs, f7) = find_proof(k, &plot_id);
+        assert_eq!(xs.len(), 64);
+        let proof = encode_proof(k, &xs);
+        let challenge = make_challenge(k, f7, 0x15);
+
+        let quality = validate_proof(&plot_id, k, &challenge, &proof).expect("valid proof");
+        // the quality string depends on the last 5 bits of the challenge
+        let other_challenge = make_challenge(k, f7, 0x16);
+        let other_quality = validate_proof(&plot_id, k, &other_challenge, &proof).unwrap();
+        assert_ne!(quality, other_quality);
+        let same_index = make_challenge(k, f7, 0x35);
+        assert_ne!(validate_proof(&plot_id, k, &same_index, &proof), None);
+
+        // wrong challenge
+        let wrong = make_challenge(k, f7 ^ 1, 0x15);
+        assert_eq!(validate_proof(&plot_id, k, &wrong, &proof), None);
+        // wrong plot id
+        let wrong = Bytes32::from([k + 1; 32]);
+        assert_eq!(validate_proof(&wrong, k, &challenge, &proof), None);
+        // wrong size
+        assert_eq!(validate_proof(&plot_id, k + 1, &challenge, &proof), None);
+        assert_eq!(validate_proof(&plot_id, k, &challenge, &proof[1..]), None);
+        // swapping two x values breaks the proof
+        let mut swapped = xs.clone();
+        swapped.swap(0, 1);
+        let bad = encode_proof(k, &swapped);
+        assert_eq!(validate_proof(&plot_id, k, &challenge, &bad), None);
+        // a modified x value
+        let mut modified = xs;
+        modified[40] ^= 1;
+        let bad = encode_proof(k, &modified);
+        assert_eq!(validate_proof(&plot_id, k, &challenge, &bad), None);
+    }
+
+    #[test]
+    fn test_verify_and_get_quality_string() {
+        let k = 12;
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.min_plot_size = 12;
+        constants.number_zero_bits_plot_filter = 0;
+        let pool_ph = Bytes32::from([7; 32]);
+        let plot_pk = public_key(8);
+        let plot_id = calculate_plot_id_ph(&pool_ph, &plot_pk);
+        let (xs, f7) = find_proof(k, &plot_id);
+
+        // search for a challenge hash and signage point for which the proof
+        // is valid, i.e. where the pos challenge starts with f7
+        let sp = Bytes32::from([9; 32]);
+        let challenge_hash = (0..u32::MAX)
+            .map(|i| {
+                let mut buf = [0_u8; 32];
+                buf[..4].copy_from_slice(&i.to_be_bytes());
+                Bytes32::from(buf)
+            })
+            .find(|ch| {
+                let challenge = calculate_pos_challenge(&plot_id, ch, &sp);
+                Bits::from_bytes(&challenge).value(0, usize::from(k)) == f7
+            })
+            .unwrap();
+        let challenge = calculate_pos_challenge(&plot_id, &challenge_hash, &sp);
+        let mut pos = ProofOfSpace::new(
+            challenge,
+            None,
+            Some(pool_ph),
+            plot_pk,
+            k,
+            Bytes::new(encode_proof(k, &xs)),
+        );
+        let quality = validate_proof(&plot_id, k, &challenge, &pos.proof).unwrap();
+        assert_eq!(
+            verify_and_get_quality_string(&pos, &constants, &challenge_hash, &sp, 0),
+            Some(quality)
+        );
+
+        // a different signage point
+        let other_sp = Bytes32::from([10; 32]);
+        assert_eq!(
+            verify_and_get_quality_string(&pos, &constants, &challenge_hash, &other_sp, 0),
+            None
+        );
+
+        // the plot is too small
+        assert_eq!(
+            verify_and_get_quality_string(&pos, &TEST_CONSTANTS, &challenge_hash, &sp, 0),
+            None
+        );
+
+        // the plot filter
+        constants.number_zero_bits_plot_filter = 8;
+        let filter_bits = |height| get_plot_filter_bits(height, &constants);
+        let passes =
+            |height| passes_plot_filter(filter_bits(height), &plot_id, &challenge_hash, &sp);
+        for height in [
+            0,
+            constants.hard_fork_height,
+            constants.plot_filter_32_height,
+        ] {
+            let expected = if passes(height) { Some(quality) } else { None };
+            assert_eq!(
+                verify_and_get_quality_string(&pos, &constants, &challenge_hash, &sp, height),
+                expected
+            );
+        }
+
+        // neither a pool public key nor a pool contract puzzle hash
+        pos.pool_contract_puzzle_hash = None;
+        assert_eq!(
+            verify_and_get_quality_string(&pos, &constants, &challenge_hash, &sp, 0),
+            None
+        );
+    }
+
+    #[rstest]
+    #[case(32, 139_586_437_120)]
+    #[case(25, 855_638_016)]
+    #[case(18, 4_849_664)]
+    #[case(50, 101 << 49)]
+    fn test_expected_plot_size(#[case] k: u8, #[case] expected: u64) {
+        assert_eq!(expected_plot_size(k), Some(expected));
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(51)]
+    #[case(255)]
+    fn test_invalid_plot_size(#[case] k: u8) {
+        assert_eq!(expected_plot_size(k), None);
+        let quality = Bytes32::from([0x42; 32]);
+        let sp = Bytes32::from([0x17; 32]);
+        assert_eq!(calculate_iterations_quality(1, &quality, k, 1, &sp), None);
+    }
+
+    #[test]
+    fn test_calculate_iterations_quality() {
+        let quality = Bytes32::from([0x42; 32]);
+        let sp = Bytes32::from([0x17; 32]);
+        let dcf = TEST_CONSTANTS.difficulty_constant_factor;
+        let sp_quality = sha256(&[[0x42_u8; 32], [0x17; 32]].concat());
+
+        let expected =
+            BigUint::from(1000_u32) * BigUint::from(dcf) * BigUint::from_bytes_be(&sp_quality)
+                / ((BigUint::from(1_u32) << 256) * BigUint::from(expected_plot_size(32).unwrap()));
+        let iters = calculate_iterations_quality(dcf, &quality, 32, 1000, &sp).unwrap();
+        assert_eq!(BigUint::from(iters), expected);
+
+        // the iterations scale with the difficulty
+        let double = calculate_iterations_quality(dcf, &quality, 32, 2000, &sp).unwrap();
+        assert!(double == iters * 2 || double == iters * 2 + 1);
+        // and are never zero
+        assert_eq!(
+            calculate_iterations_quality(1, &quality, 32, 1, &sp),
+            Some(1)
+        );
+        // or saturate
+        assert_eq!(
+            calculate_iterations_quality(u128::MAX, &quality, 18, u64::MAX, &sp),
+            Some(u64::MAX)
+        );
+    }
+}
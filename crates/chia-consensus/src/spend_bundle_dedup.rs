@@ -0,0 +1,207 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::conditions::ELIGIBLE_FOR_DEDUP;
+use crate::gen::owned_conditions::{OwnedSpend, OwnedSpendBundleConditions};
+use crate::gen::validation_error::ErrorCode;
+use chia_bls::Signature;
+use chia_protocol::{Bytes32, CoinSpend, Program, SpendBundle};
+use std::collections::HashMap;
+
+// the cost of a single spend, that's saved by not including it in the block.
+// This is the cost of running the puzzle, the cost of its conditions and the
+// cost of the bytes of its puzzle reveal and solution.
+fn spend_cost(constants: &ConsensusConstants, cs: &CoinSpend, spend: &OwnedSpend) -> u64 {
+    let byte_cost = (cs.puzzle_reveal.len() + cs.solution.len()) as u64 * constants.cost_per_byte;
+    spend.execution_cost + spend.condition_cost + byte_cost
+}
+
+// Aggregates multiple spend bundles into one, merging identical spends. Two
+// spends are identical if they spend the same coin with the same solution.
+// Only spends that are eligible for dedup (as determined by the
+// MempoolVisitor) can be merged, since they don't have any AGG_SIG_*
+// conditions (and don't send or receive messages). This means the aggregate
+// signature of all bundles is still valid for the resulting bundle.
+// The conditions for each spend bundle must have been computed with
+// MempoolVisitor, by validate_spend_bundle(), since the cost saved is based on
+// the execution and condition cost recorded for each spend.
+// Spending the same coin more than once, where the spends cannot be merged, is
+// a DoubleSpend error.
+// Returns the aggregated spend bundle and the cost saved by removing the
+// duplicate spends.
+pub fn dedup_spend_bundles<'a, I>(
+    bundles: I,
+    constants: &ConsensusConstants,
+) -> Result<(SpendBundle, u64), ErrorCode>
+where
+    I: IntoIterator<Item = (&'a SpendBundle, &'a OwnedSpendBundleConditions)>,
+{
+    let mut coin_spends = Vec::<CoinSpend>::new();
+    let mut signature = Signature::default();
+    let mut cost_saved: u64 = 0;
+
+    // maps coin ID to the solution of the spend we've included, if it's
+    // eligible for dedup, otherwise None
+    let mut spent = HashMap::<Bytes32, Option<&'a Program>>::new();
+
+    for (bundle, conds) in bundles {
+        let spends: HashMap<&Bytes32, &OwnedSpend> =
+            conds.spends.iter().map(|s| (&s.coin_id, s)).collect();
+
+        for cs in &bundle.coin_spends {
+            let coin_id = cs.coin.coin_id();
+            let spend = spends.get(&coin_id).ok_or(ErrorCode::InvalidSpendBundle)?;
+            let dedup = (spend.flags & ELIGIBLE_FOR_DEDUP) != 0;
+
+            match spent.get(&coin_id) {
+                None => {
+                    spent.insert(coin_id, dedup.then_some(&cs.solution));
+                    coin_spends.push(cs.clone());
+                }
+                Some(Some(solution)) if dedup && *solution == &cs.solution => {
+                    cost_saved += spend_cost(constants, cs, spend);
+                }
+                Some(_) => {
+                    return Err(ErrorCode::DoubleSpend);
+                }
+            }
+        }
+        signature.aggregate(&bundle.aggregated_signature);
+    }
+
+    Ok((SpendBundle::new(coin_spends, signature), cost_saved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::gen::opcodes::{ConditionOpcode, AGG_SIG_ME, CREATE_COIN, CREATE_COIN_COST};
+    use crate::gen::validate_spend_bundle::validate_spend_bundle;
+    use chia_bls::{sign, SecretKey};
+    use chia_protocol::{Bytes, Coin};
+    use clvm_traits::ToClvm;
+    use clvm_utils::tree_hash_from_bytes;
+    use clvmr::serde::node_to_bytes;
+    use clvmr::Allocator;
+
+    // a coin spend whose puzzle ignores the solution and returns a
+    // CREATE_COIN condition. If a secret key is passed, the spend will also
+    // have an AGG_SIG_ME condition, and the signature is returned
+    fn make_spend(parent: u8, solution: u8, sk: Option<&SecretKey>) -> (CoinSpend, Signature) {
+        let mut a = Allocator::new();
+        let mut conds = vec![(CREATE_COIN, (Bytes32::from([0x22; 32]), (1_u64, ())))
+            .to_clvm(&mut a)
+            .unwrap()];
+        if let Some(sk) = sk {
+            let agg_sig: (ConditionOpcode, _) = (
+                AGG_SIG_ME,
+                (sk.public_key(), (Bytes::from(b"msg".to_vec()), ())),
+            );
+            conds.push(agg_sig.to_clvm(&mut a).unwrap());
+        }
+        let quoted = (1, conds).to_clvm(&mut a).unwrap();
+        let puzzle = Program::new(node_to_bytes(&a, quoted).unwrap().into());
+        let puzzle_hash = tree_hash_from_bytes(puzzle.as_ref()).unwrap();
+        let coin = Coin::new([parent; 32].into(), puzzle_hash.into(), 2);
+        let sig = sk.map_or_else(Signature::default, |sk| {
+            let msg = [
+                b"msg".as_slice(),
+                coin.coin_id().as_ref(),
+                TEST_CONSTANTS.agg_sig_me_additional_data.as_ref(),
+            ]
+            .concat();
+            sign(sk, msg)
+        });
+        let cs = CoinSpend::new(coin, puzzle, Program::from(vec![solution]));
+        (cs, sig)
+    }
+
+    fn make_bundle(spends: &[(CoinSpend, Signature)]) -> (SpendBundle, OwnedSpendBundleConditions) {
+        let mut sig = Signature::default();
+        for (_, s) in spends {
+            sig.aggregate(s);
+        }
+        let bundle = SpendBundle::new(spends.iter().map(|s| s.0.clone()).collect(), sig);
+        let (conds, _) =
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).expect("validate_spend_bundle");
+        (bundle, conds)
+    }
+
+    #[test]
+    fn test_dedup() {
+        let sk1 = SecretKey::from_seed(&[1; 32]);
+        let sk2 = SecretKey::from_seed(&[2; 32]);
+        let shared = make_spend(1, 0x80, None);
+        let bundle1 = make_bundle(&[shared.clone(), make_spend(2, 0x80, Some(&sk1))]);
+        let bundle2 = make_bundle(&[make_spend(3, 0x80, Some(&sk2)), shared.clone()]);
+        let bundle3 = make_bundle(&[shared.clone()]);
+
+        let (bundle, cost_saved) = dedup_spend_bundles(
+            [&bundle1, &bundle2, &bundle3].map(|(b, c)| (b, c)),
+            &TEST_CONSTANTS,
+        )
+        .expect("dedup_spend_bundles");
+
+        assert_eq!(bundle.coin_spends.len(), 3);
+        assert_eq!(bundle.coin_spends[0], shared.0);
+
+        // the resulting bundle is still valid
+        let (conds, _) =
+            validate_spend_bundle(&bundle, 0, &TEST_CONSTANTS, 0).expect("validate_spend_bundle");
+        assert_eq!(conds.spends.len(), 3);
+
+        // the shared spend was removed twice. The cost of the spend is the
+        // cost of running its puzzle, its conditions and its bytes
+        let spend = &bundle3.1.spends[0];
+        let mut a = Allocator::new();
+        let (clvm_cost, _) = shared
+            .0
+            .puzzle_reveal
+            .run(
+                &mut a,
+                0,
+                TEST_CONSTANTS.max_block_cost_clvm,
+                &shared.0.solution,
+            )
+            .expect("run");
+        assert_eq!(spend.execution_cost, clvm_cost);
+        assert_eq!(spend.condition_cost, CREATE_COIN_COST);
+        let byte_cost = (shared.0.puzzle_reveal.len() + shared.0.solution.len()) as u64
+            * TEST_CONSTANTS.cost_per_byte;
+        assert_eq!(cost_saved, 2 * (clvm_cost + CREATE_COIN_COST + byte_cost));
+    }
+
+    #[test]
+    fn test_different_solutions() {
+        let bundle1 = make_bundle(&[make_spend(1, 0x80, None)]);
+        let bundle2 = make_bundle(&[make_spend(1, 1, None)]);
+        assert_eq!(
+            dedup_spend_bundles([&bundle1, &bundle2].map(|(b, c)| (b, c)), &TEST_CONSTANTS)
+                .unwrap_err(),
+            ErrorCode::DoubleSpend
+        );
+    }
+
+    #[test]
+    fn test_not_eligible() {
+        // the spends are identical, but they have AGG_SIG_ME conditions, so
+        // they can't be deduplicated
+        let sk = SecretKey::from_seed(&[1; 32]);
+        let bundle1 = make_bundle(&[make_spend(1, 0x80, Some(&sk))]);
+        let bundle2 = bundle1.clone();
+        assert_eq!(
+            dedup_spend_bundles([&bundle1, &bundle2].map(|(b, c)| (b, c)), &TEST_CONSTANTS)
+                .unwrap_err(),
+            ErrorCode::DoubleSpend
+        );
+    }
+
+    #[test]
+    fn test_missing_conditions() {
+        let (bundle, _) = make_bundle(&[make_spend(1, 0x80, None)]);
+        let conds = make_bundle(&[make_spend(2, 0x80, None)]).1;
+        assert_eq!(
+            dedup_spend_bundles([(&bundle, &conds)], &TEST_CONSTANTS).unwrap_err(),
+            ErrorCode::InvalidSpendBundle
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use crate::gen::owned_conditions::OwnedSpendBundleConditions;
+use crate::gen::validation_error::ErrorCode;
+use chia_protocol::Bytes32;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+// the height and timestamp of the block a coin was created in. This is what
+// relative time-locks are measured from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoinConfirmation {
+    pub height: u32,
+    pub timestamp: u64,
+}
+
+// The combined effect of all absolute and relative time-lock conditions of a
+// spend bundle, expressed as absolute heights and timestamps. The heights and
+// timestamps are compared against the previous transaction block (i.e. the
+// peak) when including the spend bundle in a new block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeLocks {
+    // the spend bundle becomes valid once the peak height and timestamp are
+    // at least these
+    pub assert_height: u32,
+    pub assert_seconds: u64,
+    // the spend bundle expires once the peak height or timestamp reaches these
+    pub assert_before_height: Option<u32>,
+    pub assert_before_seconds: Option<u64>,
+}
+
+fn lowest<T: Ord + Copy>(existing: Option<T>, v: T) -> T {
+    existing.map_or(v, |e| min(e, v))
+}
+
+fn get_coin<'a, S: BuildHasher>(
+    coins: &'a HashMap<Bytes32, CoinConfirmation, S>,
+    coin_id: &Bytes32,
+) -> Result<&'a CoinConfirmation, ErrorCode> {
+    coins.get(coin_id).ok_or(ErrorCode::UnknownUnspent)
+}
+
+// a single time-lock condition, converted to an absolute height or
+// timestamp, along with the error for when it's not satisfied
+#[derive(Clone, Copy)]
+enum TimeLock {
+    Height(u32, ErrorCode),
+    Seconds(u64, ErrorCode),
+    BeforeHeight(u32, ErrorCode),
+    BeforeSeconds(u64, ErrorCode),
+}
+
+// calls f with every time-lock of the spend bundle, absolute ones first. The
+// ASSERT_MY_BIRTH_* conditions are checked along the way, since they don't
+// depend on the peak
+fn visit_time_locks<S: BuildHasher>(
+    conds: &OwnedSpendBundleConditions,
+    coins: &HashMap<Bytes32, CoinConfirmation, S>,
+    mut f: impl FnMut(TimeLock) -> Result<(), ErrorCode>,
+) -> Result<(), ErrorCode> {
+    f(TimeLock::Height(
+        conds.height_absolute,
+        ErrorCode::AssertHeightAbsoluteFailed,
+    ))?;
+    f(TimeLock::Seconds(
+        conds.seconds_absolute,
+        ErrorCode::AssertSecondsAbsoluteFailed,
+    ))?;
+    if let Some(h) = conds.before_height_absolute {
+        f(TimeLock::BeforeHeight(
+            h,
+            ErrorCode::AssertBeforeHeightAbsoluteFailed,
+        ))?;
+    }
+    if let Some(s) = conds.before_seconds_absolute {
+        f(TimeLock::BeforeSeconds(
+            s,
+            ErrorCode::AssertBeforeSecondsAbsoluteFailed,
+        ))?;
+    }
+
+    for spend in &conds.spends {
+        let coin = get_coin(coins, &spend.coin_id)?;
+        if spend.birth_height.is_some_and(|h| h != coin.height) {
+            return Err(ErrorCode::AssertMyBirthHeightFailed);
+        }
+        if spend.birth_seconds.is_some_and(|s| s != coin.timestamp) {
+            return Err(ErrorCode::AssertMyBirthSecondsFailed);
+        }
+        if let Some(h) = spend.height_relative {
+            f(TimeLock::Height(
+                coin.height.saturating_add(h),
+                ErrorCode::AssertHeightRelativeFailed,
+            ))?;
+        }
+        if let Some(s) = spend.seconds_relative {
+            f(TimeLock::Seconds(
+                coin.timestamp.saturating_add(s),
+                ErrorCode::AssertSecondsRelativeFailed,
+            ))?;
+        }
+        if let Some(h) = spend.before_height_relative {
+            f(TimeLock::BeforeHeight(
+                coin.height.saturating_add(h),
+                ErrorCode::AssertBeforeHeightRelativeFailed,
+            ))?;
+        }
+        if let Some(s) = spend.before_seconds_relative {
+            f(TimeLock::BeforeSeconds(
+                coin.timestamp.saturating_add(s),
+                ErrorCode::AssertBeforeSecondsRelativeFailed,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+impl TimeLocks {
+    // coins is the confirmation height and timestamp of every coin spent by
+    // the bundle. The ASSERT_MY_BIRTH_* conditions are also checked, since
+    // they don't depend on the peak
+    pub fn new<S: BuildHasher>(
+        conds: &OwnedSpendBundleConditions,
+        coins: &HashMap<Bytes32, CoinConfirmation, S>,
+    ) -> Result<Self, ErrorCode> {
+        let mut ret = Self::default();
+        visit_time_locks(conds, coins, |lock| {
+            match lock {
+                TimeLock::Height(h, _) => ret.assert_height = max(ret.assert_height, h),
+                TimeLock::Seconds(s, _) => ret.assert_seconds = max(ret.assert_seconds, s),
+                TimeLock::BeforeHeight(h, _) => {
+                    ret.assert_before_height = Some(lowest(ret.assert_before_height, h));
+                }
+                TimeLock::BeforeSeconds(s, _) => {
+                    ret.assert_before_seconds = Some(lowest(ret.assert_before_seconds, s));
+                }
+            }
+            Ok(())
+        })?;
+        Ok(ret)
+    }
+
+    // returns true if the spend bundle can be included in a block whose
+    // previous transaction block has the specified height and timestamp
+    pub fn is_valid_at(&self, peak_height: u32, peak_timestamp: u64) -> bool {
+        peak_height >= self.assert_height
+            && peak_timestamp >= self.assert_seconds
+            && !self.is_expired(peak_height, peak_timestamp)
+    }
+
+    pub fn is_expired(&self, peak_height: u32, peak_timestamp: u64) -> bool {
+        self.assert_before_height.is_some_and(|h| peak_height >= h)
+            || self
+                .assert_before_seconds
+                .is_some_and(|s| peak_timestamp >= s)
+    }
+
+    // returns true if there is no peak height and timestamp where the spend
+    // bundle is valid
+    pub fn is_impossible(&self) -> bool {
+        self.assert_before_height
+            .is_some_and(|h| h <= self.assert_height)
+            || self
+                .assert_before_seconds
+                .is_some_and(|s| s <= self.assert_seconds)
+    }
+}
+
+// Checks all time-lock conditions (absolute and relative) of a spend bundle
+// against the peak (the previous transaction block) height and timestamp.
+// coins is the confirmation height and timestamp of every coin spent by the
+// bundle. Returns the error code of the first condition that fails.
+pub fn check_time_locks<S: BuildHasher>(
+    conds: &OwnedSpendBundleConditions,
+    coins: &HashMap<Bytes32, CoinConfirmation, S>,
+    peak_height: u32,
+    peak_timestamp: u64,
+) -> Result<(), ErrorCode> {
+    visit_time_locks(conds, coins, |lock| match lock {
+        TimeLock::Height(h, err) if peak_height < h => Err(err),
+        TimeLock::Seconds(s, err) if peak_timestamp < s => Err(err),
+        TimeLock::BeforeHeight(h, err) if peak_height >= h => Err(err),
+        TimeLock::BeforeSeconds(s, err) if peak_timestamp >= s => Err(err),
+        _ => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::owned_conditions::OwnedSpend;
+    use chia_protocol::Coin;
+    use rstest::rstest;
+
+    const COIN_HEIGHT: u32 = 100;
+    const COIN_TIMESTAMP: u64 = 10_000;
+
+    fn make_spend(coin_id: Bytes32) -> OwnedSpend {
+        let coin = Coin::new(Bytes32::default(), Bytes32::default(), 1);
+        OwnedSpend {
+            coin_id,
+            ..OwnedSpend::for_coin(&coin, &[])
+        }
+    }
+
+    fn coins() -> HashMap<Bytes32, CoinConfirmation> {
+        HashMap::from([(
+            Bytes32::from([1; 32]),
+            CoinConfirmation {
+                height: COIN_HEIGHT,
+                timestamp: COIN_TIMESTAMP,
+            },
+        )])
+    }
+
+    // condition is one of:
+    // 0: ASSERT_HEIGHT_ABSOLUTE 150
+    // 1: ASSERT_SECONDS_ABSOLUTE 15000
+    // 2: ASSERT_BEFORE_HEIGHT_ABSOLUTE 150
+    // 3: ASSERT_BEFORE_SECONDS_ABSOLUTE 15000
+    // 4: ASSERT_HEIGHT_RELATIVE 50
+    // 5: ASSERT_SECONDS_RELATIVE 5000
+    // 6: ASSERT_BEFORE_HEIGHT_RELATIVE 50
+    // 7: ASSERT_BEFORE_SECONDS_RELATIVE 5000
+    fn conds_with(condition: u8) -> OwnedSpendBundleConditions {
+        let mut spend = make_spend(Bytes32::from([1; 32]));
+        match condition {
+            4 => spend.height_relative = Some(50),
+            5 => spend.seconds_relative = Some(5000),
+            6 => spend.before_height_relative = Some(50),
+            7 => spend.before_seconds_relative = Some(5000),
+            _ => {}
+        }
+        let mut conds = OwnedSpendBundleConditions::for_spends(vec![spend]);
+        match condition {
+            0 => conds.height_absolute = 150,
+            1 => conds.seconds_absolute = 15000,
+            2 => conds.before_height_absolute = Some(150),
+            3 => conds.before_seconds_absolute = Some(15000),
+            _ => {}
+        }
+        conds
+    }
+
+    #[rstest]
+    #[case(0, 149, 0, Some(ErrorCode::AssertHeightAbsoluteFailed))]
+    #[case(0, 150, 0, None)]
+    #[case(1, 0, 14999, Some(ErrorCode::AssertSecondsAbsoluteFailed))]
+    #[case(1, 0, 15000, None)]
+    #[case(2, 149, 0, None)]
+    #[case(2, 150, 0, Some(ErrorCode::AssertBeforeHeightAbsoluteFailed))]
+    #[case(3, 0, 14999, None)]
+    #[case(3, 0, 15000, Some(ErrorCode::AssertBeforeSecondsAbsoluteFailed))]
+    #[case(4, 149, 0, Some(ErrorCode::AssertHeightRelativeFailed))]
+    #[case(4, 150, 0, None)]
+    #[case(5, 0, 14999, Some(ErrorCode::AssertSecondsRelativeFailed))]
+    #[case(5, 0, 15000, None)]
+    #[case(6, 149, 0, None)]
+    #[case(6, 150, 0, Some(ErrorCode::AssertBeforeHeightRelativeFailed))]
+    #[case(7, 0, 14999, None)]
+    #[case(7, 0, 15000, Some(ErrorCode::AssertBeforeSecondsRelativeFailed))]
+    fn test_check_time_locks(
+        #[case] condition: u8,
+        #[case] peak_height: u32,
+        #[case] peak_timestamp: u64,
+        #[case] expected: Option<ErrorCode>,
+    ) {
+        let conds = conds_with(condition);
+        let coins = coins();
+        let ret = check_time_locks(&conds, &coins, peak_height, peak_timestamp);
+        assert_eq!(ret.err(), expected);
+
+        // the TimeLocks summary agrees with check_time_locks()
+        let locks = TimeLocks::new(&conds, &coins).expect("TimeLocks::new");
+        assert_eq!(
+            locks.is_valid_at(peak_height, peak_timestamp),
+            expected.is_none()
+        );
+        assert!(!locks.is_impossible());
+    }
+
+    #[rstest]
+    #[case(0, 150, 0, None, None)]
+    #[case(1, 0, 15000, None, None)]
+    #[case(2, 0, 0, Some(150), None)]
+    #[case(3, 0, 0, None, Some(15000))]
+    #[case(4, 150, 0, None, None)]
+    #[case(5, 0, 15000, None, None)]
+    #[case(6, 0, 0, Some(150), None)]
+    #[case(7, 0, 0, None, Some(15000))]
+    fn test_time_locks(
+        #[case] condition: u8,
+        #[case] assert_height: u32,
+        #[case] assert_seconds: u64,
+        #[case] assert_before_height: Option<u32>,
+        #[case] assert_before_seconds: Option<u64>,
+    ) {
+        let locks = TimeLocks::new(&conds_with(condition), &coins()).expect("TimeLocks::new");
+        assert_eq!(
+            locks,
+            TimeLocks {
+                assert_height,
+                assert_seconds,
+                assert_before_height,
+                assert_before_seconds,
+            }
+        );
+    }
+
+    #[test]
+    fn test_combined() {
+        // the most restrictive of the absolute and relative conditions apply
+        let mut spend = make_spend(Bytes32::from([1; 32]));
+        spend.height_relative = Some(10);
+        spend.before_height_relative = Some(80);
+        spend.seconds_relative = Some(1000);
+        let mut conds = OwnedSpendBundleConditions::for_spends(vec![spend]);
+        conds.height_absolute = 120;
+        conds.before_height_absolute = Some(170);
+        conds.seconds_absolute = 10_500;
+
+        let coins = coins();
+        let locks = TimeLocks::new(&conds, &coins).expect("TimeLocks::new");
+        assert_eq!(locks.assert_height, 120);
+        assert_eq!(locks.assert_seconds, 11_000);
+        assert_eq!(locks.assert_before_height, Some(170));
+        assert_eq!(locks.assert_before_seconds, None);
+
+        assert!(!locks.is_valid_at(119, 11_000));
+        assert!(!locks.is_valid_at(120, 10_999));
+        assert!(locks.is_valid_at(120, 11_000));
+        assert!(locks.is_valid_at(169, 11_000));
+        assert!(!locks.is_valid_at(170, 11_000));
+        assert!(locks.is_expired(170, 0));
+
+        // the relative before-height is stricter than the absolute one
+        conds.spends[0].before_height_relative = Some(30);
+        let locks = TimeLocks::new(&conds, &coins).expect("TimeLocks::new");
+        assert_eq!(locks.assert_before_height, Some(130));
+        assert_eq!(
+            check_time_locks(&conds, &coins, 130, 11_000).unwrap_err(),
+            ErrorCode::AssertBeforeHeightRelativeFailed
+        );
+
+        // the spend must happen before it's valid
+        conds.spends[0].before_height_relative = Some(20);
+        let locks = TimeLocks::new(&conds, &coins).expect("TimeLocks::new");
+        assert!(locks.is_impossible());
+    }
+
+    #[test]
+    fn test_birth() {
+        let coins = coins();
+        let mut spend = make_spend(Bytes32::from([1; 32]));
+        spend.birth_height = Some(COIN_HEIGHT);
+        spend.birth_seconds = Some(COIN_TIMESTAMP);
+        let mut conds = OwnedSpendBundleConditions::for_spends(vec![spend]);
+        assert!(check_time_locks(&conds, &coins, 0, 0).is_ok());
+        assert!(TimeLocks::new(&conds, &coins).is_ok());
+
+        conds.spends[0].birth_height = Some(COIN_HEIGHT + 1);
+        assert_eq!(
+            check_time_locks(&conds, &coins, 0, 0).unwrap_err(),
+            ErrorCode::AssertMyBirthHeightFailed
+        );
+        assert_eq!(
+            TimeLocks::new(&conds, &coins).unwrap_err(),
+            ErrorCode::AssertMyBirthHeightFailed
+        );
+
+        conds.spends[0].birth_height = None;
+        conds.spends[0].birth_seconds = Some(COIN_TIMESTAMP - 1);
+        assert_eq!(
+            check_time_locks(&conds, &coins, 0, 0).unwrap_err(),
+            ErrorCode::AssertMyBirthSecondsFailed
+        );
+    }
+
+    #[test]
+    fn test_unknown_coin() {
+        let conds =
+            OwnedSpendBundleConditions::for_spends(vec![make_spend(Bytes32::from([2; 32]))]);
+        assert_eq!(
+            check_time_locks(&conds, &coins(), 0, 0).unwrap_err(),
+            ErrorCode::UnknownUnspent
+        );
+        assert_eq!(
+            TimeLocks::new(&conds, &coins()).unwrap_err(),
+            ErrorCode::UnknownUnspent
+        );
+    }
+}
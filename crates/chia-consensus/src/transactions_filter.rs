@@ -0,0 +1,503 @@
+use crate::error::{Error, Result};
+use chia_protocol::{Bytes32, Coin};
+
+// The parameters of the BIP158 basic filter. The full node builds the
+// transactions filter with these, and an all-zero SipHash key
+const FILTER_P: u8 = 19;
+const FILTER_M: u64 = 784_931;
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+// SipHash-2-4
+fn siphash(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+    let mut compress = |m: u64| {
+        v[3] ^= m;
+        sip_round(&mut v);
+        sip_round(&mut v);
+        v[0] ^= m;
+    };
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        compress(u64::from_le_bytes(chunk.try_into().expect("chunk")));
+    }
+    // the last block holds the remaining bytes and the length of the input
+    let mut last = [0_u8; 8];
+    last[..tail.len()].copy_from_slice(tail);
+    last[7] = data.len() as u8;
+    compress(u64::from_le_bytes(last));
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+// maps the hash of an element uniformly into [0, range)
+fn hash_to_range(key: (u64, u64), element: &[u8], range: u64) -> u64 {
+    ((u128::from(siphash(key.0, key.1, element)) * u128::from(range)) >> 64) as u64
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        out.push(n as u8);
+    } else if n <= 0xffff {
+        out.push(0xfd);
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        out.push(0xfe);
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+// returns the value and the number of bytes it was encoded in. Like bitcoin,
+// we only accept the shortest encoding of every value
+fn read_compact_size(buf: &[u8]) -> Option<(u64, usize)> {
+    let (len, min) = match *buf.first()? {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        0xff => (8, 0x1_0000_0000),
+        n => return Some((u64::from(n), 1)),
+    };
+    let mut value = [0_u8; 8];
+    value[..len].copy_from_slice(buf.get(1..=len)?);
+    let value = u64::from_le_bytes(value);
+    if value < min {
+        return None;
+    }
+    Some((value, len + 1))
+}
+
+// writes bits most significant first, padding the last byte with zeros
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    used: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u8) {
+        for i in (0..bits).rev() {
+            if self.used % 8 == 0 {
+                self.data.push(0);
+                self.used = 0;
+            }
+            if (value >> i) & 1 == 1 {
+                *self.data.last_mut().expect("byte") |= 0x80 >> self.used;
+            }
+            self.used += 1;
+        }
+    }
+
+    // Golomb-Rice coding: the quotient in unary, followed by the remainder
+    fn write_golomb_rice(&mut self, value: u64) {
+        let mut quotient = value >> FILTER_P;
+        while quotient > 0 {
+            let bits = std::cmp::min(quotient, 64);
+            self.write(u64::MAX, bits as u8);
+            quotient -= bits;
+        }
+        self.write(0, 1);
+        self.write(value, FILTER_P);
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, bits: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.pos / 8)?;
+            value = (value << 1) | u64::from((byte >> (7 - self.pos % 8)) & 1);
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn read_golomb_rice(&mut self) -> Option<u64> {
+        let mut quotient = 0_u64;
+        while self.read(1)? == 1 {
+            quotient += 1;
+        }
+        let remainder = self.read(FILTER_P)?;
+        quotient
+            .checked_shl(FILTER_P.into())
+            .filter(|q| q >> FILTER_P == quotient)
+            .map(|q| q + remainder)
+    }
+
+    fn bytes_read(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+}
+
+// A compact probabilistic set of the puzzle hashes of the coins added in a
+// block and the IDs of the coins it removes. This is the Golomb-coded set
+// from BIP158, which is what the transactions_filter of a block holds. An
+// element that was added to the filter always matches, other elements only
+// match with a probability of 1/784931
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionsFilter {
+    num_elements: u64,
+    encoded: Vec<u8>,
+}
+
+impl TransactionsFilter {
+    fn build<'a, I>(key: (u64, u64), elements: I) -> Self
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut elements: Vec<&[u8]> = elements.into_iter().collect();
+        elements.sort_unstable();
+        elements.dedup();
+
+        let num_elements = elements.len() as u64;
+        let range = num_elements * FILTER_M;
+        let mut values: Vec<u64> = elements
+            .iter()
+            .map(|element| hash_to_range(key, element, range))
+            .collect();
+        values.sort_unstable();
+
+        let mut encoded = Vec::new();
+        write_compact_size(&mut encoded, num_elements);
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for value in values {
+            writer.write_golomb_rice(value - last);
+            last = value;
+        }
+        encoded.extend(writer.data);
+        Self {
+            num_elements,
+            encoded,
+        }
+    }
+
+    fn matches_hashed(&self, key: (u64, u64), elements: &[&[u8]]) -> bool {
+        let range = self.num_elements * FILTER_M;
+        let mut queries: Vec<u64> = elements
+            .iter()
+            .map(|element| hash_to_range(key, element, range))
+            .collect();
+        queries.sort_unstable();
+
+        let (_, offset) = read_compact_size(&self.encoded).expect("validated filter");
+        let mut reader = BitReader {
+            data: &self.encoded[offset..],
+            pos: 0,
+        };
+        let mut queries = queries.into_iter().peekable();
+        let mut value = 0_u64;
+        for _ in 0..self.num_elements {
+            value += reader.read_golomb_rice().expect("validated filter");
+            while let Some(query) = queries.peek() {
+                if *query == value {
+                    return true;
+                }
+                if *query > value {
+                    break;
+                }
+                queries.next();
+            }
+        }
+        false
+    }
+
+    // a filter of arbitrary elements
+    pub fn new<'a, I>(elements: I) -> Self
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        Self::build((0, 0), elements)
+    }
+
+    // The filter the full node includes in a block: the puzzle hashes of the
+    // coins it adds (including the reward coins) and the IDs of the coins it
+    // spends
+    pub fn from_additions_and_removals<'a, I>(additions: I, removals: &[Bytes32]) -> Self
+    where
+        I: IntoIterator<Item = &'a Coin>,
+    {
+        let puzzle_hashes: Vec<Bytes32> = additions.into_iter().map(|c| c.puzzle_hash).collect();
+        Self::new(
+            puzzle_hashes
+                .iter()
+                .chain(removals.iter())
+                .map(Bytes32::as_ref),
+        )
+    }
+
+    // parses an encoded filter, e.g. the transactions_filter of a block
+    pub fn parse(encoded: &[u8]) -> Result<Self> {
+        let (num_elements, offset) =
+            read_compact_size(encoded).ok_or(Error::InvalidTransactionsFilter)?;
+        if num_elements > u64::from(u32::MAX) {
+            return Err(Error::InvalidTransactionsFilter);
+        }
+        let mut reader = BitReader {
+            data: &encoded[offset..],
+            pos: 0,
+        };
+        let mut value = 0_u64;
+        for _ in 0..num_elements {
+            let delta = reader
+                .read_golomb_rice()
+                .ok_or(Error::InvalidTransactionsFilter)?;
+            value = value
+                .checked_add(delta)
+                .ok_or(Error::InvalidTransactionsFilter)?;
+        }
+        if offset + reader.bytes_read() != encoded.len() {
+            return Err(Error::InvalidTransactionsFilter);
+        }
+        Ok(Self {
+            num_elements,
+            encoded: encoded.to_vec(),
+        })
+    }
+
+    pub fn encoded(&self) -> &[u8] {
+        &self.encoded
+    }
+
+    pub fn num_elements(&self) -> u64 {
+        self.num_elements
+    }
+
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.matches_hashed((0, 0), &[element])
+    }
+
+    // Whether any of the elements may be in the filter. This is how a light
+    // wallet checks whether a block may add coins with any of its puzzle
+    // hashes or spend any of its coins. It's cheaper than matching the
+    // elements one at a time, since the filter is only decoded once
+    pub fn matches_any<'a, I>(&self, elements: I) -> bool
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let elements: Vec<&[u8]> = elements.into_iter().collect();
+        self.matches_hashed((0, 0), &elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+    use rstest::rstest;
+
+    // the test vectors from the SipHash paper use the key 00 01 02 ... 0f
+    // and the messages 00 01 02 ... of increasing length
+    #[rstest]
+    #[case(0, 0x726f_db47_dd0e_0e31)]
+    #[case(1, 0x74f8_39c5_93dc_67fd)]
+    #[case(2, 0x0d6c_8009_d9a9_4f5a)]
+    #[case(15, 0xa129_ca61_49be_45e5)]
+    fn test_siphash(#[case] len: u8, #[case] expected: u64) {
+        let message: Vec<u8> = (0..len).collect();
+        let k0 = u64::from_le_bytes([0, 1, 2, 3, 4, 5, 6, 7]);
+        let k1 = u64::from_le_bytes([8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(siphash(k0, k1, &message), expected);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_siphash_std() {
+        use std::hash::{Hasher, SipHasher};
+
+        let mut rng = SmallRng::seed_from_u64(1337);
+        for len in 0..100 {
+            let message: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let (k0, k1) = (rng.gen(), rng.gen());
+            let mut hasher = SipHasher::new_with_keys(k0, k1);
+            hasher.write(&message);
+            assert_eq!(siphash(k0, k1, &message), hasher.finish());
+        }
+    }
+
+    #[rstest]
+    #[case(0, &[0])]
+    #[case(0xfc, &[0xfc])]
+    #[case(0xfd, &[0xfd, 0xfd, 0])]
+    #[case(0xffff, &[0xfd, 0xff, 0xff])]
+    #[case(0x1_0000, &[0xfe, 0, 0, 1, 0])]
+    #[case(0x1_0000_0000, &[0xff, 0, 0, 0, 0, 1, 0, 0, 0])]
+    fn test_compact_size(#[case] n: u64, #[case] expected: &[u8]) {
+        let mut buf = Vec::new();
+        write_compact_size(&mut buf, n);
+        assert_eq!(buf, expected);
+        assert_eq!(read_compact_size(&buf), Some((n, buf.len())));
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&[0xfd, 0xfc, 0])]
+    #[case(&[0xfe, 0xff, 0xff, 0, 0])]
+    #[case(&[0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0])]
+    #[case(&[0xfd, 0xff])]
+    fn test_invalid_compact_size(#[case] buf: &[u8]) {
+        assert_eq!(read_compact_size(buf), None);
+    }
+
+    #[test]
+    fn test_golomb_rice() {
+        let values = [0, 1, 0x7_ffff, 0x8_0000, 0x12_3456, 1 << 40];
+        let mut writer = BitWriter::default();
+        for value in values {
+            writer.write_golomb_rice(value);
+        }
+        // a quotient of q takes q + 1 bits, the remainder 19
+        let bits: u64 = values.iter().map(|v| (v >> 19) + 20).sum();
+        assert_eq!(writer.data.len() as u64, bits.div_ceil(8));
+
+        let mut reader = BitReader {
+            data: &writer.data,
+            pos: 0,
+        };
+        for value in values {
+            assert_eq!(reader.read_golomb_rice(), Some(value));
+        }
+        assert_eq!(reader.bytes_read(), writer.data.len());
+        assert_eq!(reader.read_golomb_rice(), None);
+    }
+
+    // the filter of the bitcoin testnet genesis block, from the BIP158 test
+    // vectors. Bitcoin keys SipHash with the block hash, in internal byte
+    // order
+    #[test]
+    fn test_bip158_vector() {
+        let mut block_hash =
+            hex!("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943");
+        block_hash.reverse();
+        let key = (
+            u64::from_le_bytes(block_hash[..8].try_into().unwrap()),
+            u64::from_le_bytes(block_hash[8..16].try_into().unwrap()),
+        );
+        let script = hex!(
+            "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac"
+        );
+        let filter = TransactionsFilter::build(key, [script.as_ref()]);
+        assert_eq!(filter.encoded(), hex!("019dfca8"));
+        assert!(filter.matches_hashed(key, &[script.as_ref()]));
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = TransactionsFilter::new([]);
+        assert_eq!(filter.encoded(), &[0]);
+        assert_eq!(filter.num_elements(), 0);
+        assert!(!filter.matches(&[0; 32]));
+        assert!(!filter.matches_any([]));
+        assert_eq!(TransactionsFilter::parse(&[0]), Ok(filter));
+    }
+
+    #[test]
+    fn test_additions_and_removals() {
+        let coin = |i: u8| Coin::new([i; 32].into(), [i + 100; 32].into(), 1);
+        let additions: Vec<Coin> = (0..50).map(coin).collect();
+        let removals: Vec<Bytes32> = (50..100).map(|i| coin(i).coin_id()).collect();
+        let filter = TransactionsFilter::from_additions_and_removals(&additions, &removals);
+        assert_eq!(filter.num_elements(), 100);
+
+        for c in &additions {
+            assert!(filter.matches(&c.puzzle_hash));
+        }
+        for id in &removals {
+            assert!(filter.matches(id));
+        }
+        // the filter has puzzle hashes of additions, not their IDs. With
+        // these inputs, there are no false positives
+        for c in &additions {
+            assert!(!filter.matches(&c.coin_id()));
+        }
+
+        let ours = [Bytes32::from([1; 32]), additions[7].puzzle_hash];
+        assert!(filter.matches_any(ours.iter().map(Bytes32::as_ref)));
+        assert!(!filter.matches_any(ours[..1].iter().map(Bytes32::as_ref)));
+
+        // the same puzzle hash in multiple additions only counts once
+        let twice = [additions[0], additions[0]];
+        let filter = TransactionsFilter::from_additions_and_removals(&twice, &[]);
+        assert_eq!(filter.num_elements(), 1);
+    }
+
+    #[test]
+    fn test_false_positives() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let elements: Vec<[u8; 32]> = (0..1000).map(|_| rng.gen()).collect();
+        let filter = TransactionsFilter::new(elements.iter().map(<[u8; 32]>::as_ref));
+        assert!(filter.matches_any(elements.iter().map(<[u8; 32]>::as_ref)));
+        for element in &elements {
+            assert!(filter.matches(element));
+        }
+
+        // we expect about 1 in 784931 random queries to match
+        let queries: Vec<[u8; 32]> = (0..10_000).map(|_| rng.gen()).collect();
+        let false_positives = queries
+            .iter()
+            .filter(|q| filter.matches(q.as_ref()))
+            .count();
+        assert!(false_positives < 5);
+        // matching them all at once gives the same answer
+        assert_eq!(
+            filter.matches_any(queries.iter().map(<[u8; 32]>::as_ref)),
+            false_positives > 0
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let elements: Vec<[u8; 32]> = (0..300).map(|_| rng.gen()).collect();
+        let filter = TransactionsFilter::new(elements.iter().map(<[u8; 32]>::as_ref));
+        // more than 0xfc elements take 3 bytes to encode
+        assert_eq!(&filter.encoded()[..3], &[0xfd, 0x2c, 0x01]);
+
+        let parsed = TransactionsFilter::parse(filter.encoded()).unwrap();
+        assert_eq!(parsed, filter);
+        assert!(parsed.matches(&elements[123]));
+
+        let encoded = filter.encoded();
+        for bad in [
+            &encoded[..encoded.len() - 1],
+            &[encoded, &[0]].concat(),
+            &[],
+            // a non-canonical element count
+            &[&[0xfe, 0x2c, 0x01, 0, 0], &encoded[3..]].concat(),
+        ] {
+            assert_eq!(
+                TransactionsFilter::parse(bad),
+                Err(Error::InvalidTransactionsFilter)
+            );
+        }
+    }
+}
@@ -0,0 +1,859 @@
+use crate::consensus_constants::ConsensusConstants;
+use chia_protocol::{ClassgroupElement, VDFInfo, VDFProof};
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::{ExtendedGcd, Integer};
+use num_traits::{One, Signed, Zero};
+use sha2::{Digest, Sha256};
+
+// Verification of Wesolowski VDF proofs over the class group of binary
+// quadratic forms of a negative prime discriminant. This is compatible with
+// the chiavdf verifier, including its compressed (bqfc) serialization of
+// forms, which is what a ClassgroupElement holds.
+
+// the size, in bytes, of a serialized form (and of a ClassgroupElement)
+const FORM_SIZE: usize = 100;
+
+// each segment of an n-wesolowski proof is the number of iterations
+// (8 bytes), B (33 bytes) and the proof form
+const B_BYTES: usize = 33;
+const SEGMENT_SIZE: usize = 8 + B_BYTES + FORM_SIZE;
+
+// flags in the first byte of a serialized form
+const BQFC_B_SIGN: u8 = 0x01;
+const BQFC_T_SIGN: u8 = 0x02;
+const BQFC_IS_1: u8 = 0x04;
+const BQFC_IS_GEN: u8 = 0x08;
+
+// small primes used for trial division before running Miller-Rabin
+const SMALL_PRIMES: [u32; 54] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251,
+];
+
+// the number of Miller-Rabin rounds (with the first small primes as bases)
+const MILLER_RABIN_ROUNDS: usize = 20;
+
+fn is_probable_prime(n: &BigUint) -> bool {
+    let two = BigUint::from(2_u32);
+    if *n < two {
+        return false;
+    }
+    for p in SMALL_PRIMES {
+        let p = BigUint::from(p);
+        if *n == p {
+            return true;
+        }
+        if (n % &p).is_zero() {
+            return false;
+        }
+    }
+
+    let n_minus_one = n - 1_u32;
+    let s = n_minus_one.trailing_zeros().unwrap_or(0);
+    let d = &n_minus_one >> s;
+    'outer: for base in &SMALL_PRIMES[..MILLER_RABIN_ROUNDS] {
+        let mut x = BigUint::from(*base).modpow(&d, n);
+        if x.is_one() || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n_minus_one {
+                continue 'outer;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+// Generates a prime of the specified number of bits from the seed. The seed
+// is used as a big-endian counter, which is incremented before each hash. The
+// hashes are concatenated to form the candidate, which has the bits in
+// bitmask set. The first candidate that is prime is returned.
+fn hash_prime(seed: &[u8], length: usize, bitmask: &[u64]) -> BigUint {
+    assert!(length % 8 == 0);
+    let mut sprout = seed.to_vec();
+    loop {
+        let mut blob = Vec::<u8>::with_capacity(length / 8);
+        while blob.len() < length / 8 {
+            for byte in sprout.iter_mut().rev() {
+                *byte = byte.wrapping_add(1);
+                if *byte != 0 {
+                    break;
+                }
+            }
+            let hash: [u8; 32] = Sha256::digest(&sprout).into();
+            let len = std::cmp::min(hash.len(), length / 8 - blob.len());
+            blob.extend_from_slice(&hash[..len]);
+        }
+        let mut p = BigUint::from_bytes_be(&blob);
+        for bit in bitmask {
+            p.set_bit(*bit, true);
+        }
+        if is_probable_prime(&p) {
+            return p;
+        }
+    }
+}
+
+// the discriminant of the class group for the specified challenge. It's a
+// negative prime, congruent to 1 mod 8 (which makes (2, 1) a valid form).
+// The size must be a (non-zero) multiple of 8 bits
+pub fn create_discriminant(challenge: &[u8], size_bits: u16) -> Option<BigInt> {
+    if size_bits == 0 || size_bits % 8 != 0 {
+        return None;
+    }
+    let size = u64::from(size_bits);
+    let p = hash_prime(challenge, size_bits as usize, &[0, 1, 2, size - 1]);
+    Some(-BigInt::from(p))
+}
+
+// a binary quadratic form ax^2 + bxy + cy^2
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Form {
+    a: BigInt,
+    b: BigInt,
+    c: BigInt,
+}
+
+impl Form {
+    fn from_ab(a: BigInt, b: BigInt, d: &BigInt) -> Option<Self> {
+        if !a.is_positive() {
+            return None;
+        }
+        let (c, rem) = (&b * &b - d).div_rem(&(&a << 2));
+        if !rem.is_zero() {
+            return None;
+        }
+        Some(Self { a, b, c })
+    }
+
+    // fails if the discriminant isn't 1 mod 4
+    fn identity(d: &BigInt) -> Option<Self> {
+        Self::from_ab(BigInt::one(), BigInt::one(), d)
+    }
+
+    fn generator(d: &BigInt) -> Option<Self> {
+        Self::from_ab(BigInt::from(2), BigInt::one(), d)
+    }
+
+    // brings b into the range (-a, a]
+    fn normalize(&mut self) {
+        let two_a = &self.a << 1;
+        let r = (&self.a - &self.b).div_floor(&two_a);
+        if r.is_zero() {
+            return;
+        }
+        self.c = &self.a * &r * &r + &self.b * &r + &self.c;
+        self.b += &two_a * &r;
+    }
+
+    fn reduce(&mut self) {
+        self.normalize();
+        while self.a > self.c || (self.a == self.c && self.b.is_negative()) {
+            std::mem::swap(&mut self.a, &mut self.c);
+            self.b = -&self.b;
+            self.normalize();
+        }
+    }
+
+    #[cfg(test)]
+    fn is_reduced(&self) -> bool {
+        let b_abs = self.b.abs();
+        b_abs <= self.a
+            && self.a <= self.c
+            && !((b_abs == self.a || self.a == self.c) && self.b.is_negative())
+    }
+
+    // the (reduced) composition of two forms of discriminant d
+    #[allow(clippy::many_single_char_names)]
+    fn compose(&self, other: &Self, d: &BigInt) -> Self {
+        // e = gcd(a1, a2, (b1 + b2) / 2) = u * a1 + v * a2 + w * (b1 + b2) / 2
+        let s = (&self.b + &other.b) >> 1;
+        let ExtendedGcd {
+            gcd: g,
+            x: x1,
+            y: y1,
+            ..
+        } = self.a.extended_gcd(&other.a);
+        let ExtendedGcd {
+            gcd: e,
+            x: x2,
+            y: w,
+            ..
+        } = g.extended_gcd(&s);
+        let u = &x2 * x1;
+        let v = x2 * y1;
+
+        let a = (&self.a * &other.a) / (&e * &e);
+        let b: BigInt =
+            (u * &self.a * &other.b + v * &other.a * &self.b + w * ((&self.b * &other.b + d) >> 1))
+                / &e;
+        let b = b.mod_floor(&(&a << 1));
+        let c = (&b * &b - d) / (&a << 2);
+        let mut ret = Self { a, b, c };
+        ret.reduce();
+        ret
+    }
+
+    fn pow(&self, exp: &BigUint, d: &BigInt) -> Option<Self> {
+        let mut ret = Self::identity(d)?;
+        for i in (0..exp.bits()).rev() {
+            ret = ret.compose(&ret, d);
+            if exp.bit(i) {
+                ret = ret.compose(self, d);
+            }
+        }
+        Some(ret)
+    }
+}
+
+// writes the absolute value of n, little-endian, into buf. Fails if it
+// doesn't fit
+fn export_le(buf: &mut [u8], n: &BigInt) -> Option<()> {
+    if n.is_zero() {
+        return Some(());
+    }
+    let bytes = n.magnitude().to_bytes_le();
+    buf.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+    Some(())
+}
+
+// the size, in bits, of the discriminant rounded up to a multiple of 32. The
+// field sizes of serialized forms are based on this
+fn rounded_bits(d_bits: u64) -> usize {
+    ((d_bits + 31) / 32 * 32) as usize
+}
+
+// runs the extended Euclidean algorithm on (a, b) until the remainder is no
+// greater than limit. Returns the remainder r and co such that
+// r = -co * b (mod a)
+fn partial_xgcd(a: &BigInt, b: &BigInt, limit: &BigInt) -> (BigInt, BigInt) {
+    let (mut r2, mut r1) = (a.clone(), b.clone());
+    let (mut co2, mut co1) = (BigInt::zero(), -BigInt::one());
+    while !r1.is_zero() && r1 > *limit {
+        let (q, r) = r2.div_rem(&r1);
+        r2 = std::mem::replace(&mut r1, r);
+        let co = &co2 - &q * &co1;
+        co2 = std::mem::replace(&mut co1, co);
+    }
+    (r1, co1)
+}
+
+// serializes a form into the compressed bqfc format. The form is reduced
+// first. Forms are represented by a' = a / g, t' = t / g, g and b0, where t is
+// such that t * b = r (mod a) for a small r. This lets the deserializer
+// recover b from a square root of t^2 * d mod a.
+fn serialize_form(form: &Form, d_bits: u64) -> Option<[u8; FORM_SIZE]> {
+    let mut f = form.clone();
+    f.reduce();
+    let mut out = [0_u8; FORM_SIZE];
+    if f.b.is_one() {
+        if f.a.is_one() {
+            out[0] = BQFC_IS_1;
+            return Some(out);
+        }
+        if f.a == BigInt::from(2) {
+            out[0] = BQFC_IS_GEN;
+            return Some(out);
+        }
+    }
+
+    let (a_prime, t_prime, g, b0) = if f.a == f.b {
+        (f.a.clone(), BigInt::zero(), BigInt::zero(), BigInt::zero())
+    } else {
+        let b_abs = f.b.abs();
+        let (_, co) = partial_xgcd(&f.a, &b_abs, &f.a.sqrt());
+        let t = -co;
+        let g = f.a.gcd(&t);
+        let a_prime = &f.a / &g;
+        let t_prime = &t / &g;
+        let b0 = &b_abs / &a_prime;
+        (a_prime, t_prime, g, b0)
+    };
+
+    let d = rounded_bits(d_bits);
+    let g_size = (std::cmp::max(g.bits(), 1) as usize + 7) / 8 - 1;
+    if g_size >= d / 32 {
+        return None;
+    }
+    out[0] = (u8::from(f.b.is_negative()) * BQFC_B_SIGN)
+        | (u8::from(t_prime.is_negative()) * BQFC_T_SIGN);
+    out[1] = g_size as u8;
+    let mut offset = 2;
+    for (value, size) in [
+        (&a_prime, d / 16 - g_size),
+        (&t_prime, d / 32 - g_size),
+        (&g, g_size + 1),
+        (&b0, g_size + 1),
+    ] {
+        export_le(out.get_mut(offset..offset + size)?, value)?;
+        offset += size;
+    }
+    Some(out)
+}
+
+// deserializes a form from the compressed bqfc format. Only the canonical
+// encoding of a form is accepted
+#[allow(clippy::many_single_char_names)]
+fn deserialize_form(d: &BigInt, buf: &[u8]) -> Option<Form> {
+    if buf.len() != FORM_SIZE {
+        return None;
+    }
+    let d_bits = d.bits();
+    let form = if buf[0] & BQFC_IS_1 != 0 {
+        Form::identity(d)?
+    } else if buf[0] & BQFC_IS_GEN != 0 {
+        Form::generator(d)?
+    } else {
+        let rounded = rounded_bits(d_bits);
+        let g_size = buf[1] as usize;
+        if g_size >= rounded / 32 {
+            return None;
+        }
+        let mut fields = Vec::with_capacity(4);
+        let mut offset = 2;
+        for size in [
+            rounded / 16 - g_size,
+            rounded / 32 - g_size,
+            g_size + 1,
+            g_size + 1,
+        ] {
+            let bytes = buf.get(offset..offset + size)?;
+            fields.push(BigInt::from_bytes_le(Sign::Plus, bytes));
+            offset += size;
+        }
+        let b0 = fields.pop()?;
+        let g = fields.pop()?;
+        let mut t_prime = fields.pop()?;
+        let a_prime = fields.pop()?;
+        if buf[0] & BQFC_T_SIGN != 0 {
+            t_prime = -t_prime;
+        }
+
+        let (a, mut b) = if t_prime.is_zero() {
+            (a_prime.clone(), a_prime)
+        } else {
+            if g.is_zero() {
+                return None;
+            }
+            let t = &t_prime * &g;
+            let a = &a_prime * &g;
+            let x = (&t * &t * d).mod_floor(&a);
+            let s = x.sqrt();
+            if &s * &s != x {
+                return None;
+            }
+            let (s, rem) = s.div_rem(&g);
+            if !rem.is_zero() {
+                return None;
+            }
+            let ExtendedGcd { gcd, x: t_inv, .. } =
+                t_prime.mod_floor(&a_prime).extended_gcd(&a_prime);
+            if !gcd.is_one() {
+                return None;
+            }
+            let b = (s * t_inv).mod_floor(&a_prime) + b0 * &a_prime;
+            (a, b)
+        };
+        if buf[0] & BQFC_B_SIGN != 0 {
+            b = -b;
+        }
+        Form::from_ab(a, b, d)?
+    };
+
+    if serialize_form(&form, d_bits)?[..] != *buf {
+        return None;
+    }
+    Some(form)
+}
+
+// the prime B for the Wesolowski proof of x -> y, derived from both forms
+fn get_b(d: &BigInt, x: &Form, y: &Form) -> Option<BigUint> {
+    let mut seed = serialize_form(x, d.bits())?.to_vec();
+    seed.extend_from_slice(&serialize_form(y, d.bits())?);
+    Some(hash_prime(&seed, B_BYTES * 8, &[B_BYTES as u64 * 8 - 1]))
+}
+
+// computes y = proof^B * x^(2^iters mod B)
+fn wesolowski_output(d: &BigInt, x: &Form, proof: &Form, b: &BigUint, iters: u64) -> Option<Form> {
+    let r = BigUint::from(2_u32).modpow(&BigUint::from(iters), b);
+    Some(proof.pow(b, d)?.compose(&x.pow(&r, d)?, d))
+}
+
+fn verify_wesolowski(d: &BigInt, x: &Form, y: &Form, proof: &Form, iters: u64) -> bool {
+    let Some(b) = get_b(d, x, y) else {
+        return false;
+    };
+    let Some(out) = wesolowski_output(d, x, proof, &b, iters) else {
+        return false;
+    };
+    out.a == y.a && out.b == y.b
+}
+
+// Verifies an n-wesolowski proof of iters squarings of the input form x,
+// resulting in output. The witness is the proof of the last segment followed
+// by recursion_depth segments, each proving a part of the iterations. The
+// segments are stored in reverse order, so the last one starts at x.
+// The discriminant must be negative and 1 mod 4, like the ones returned by
+// create_discriminant(). Any other discriminant fails verification.
+pub fn verify_n_wesolowski(
+    discriminant: &BigInt,
+    x: &ClassgroupElement,
+    output: &ClassgroupElement,
+    witness: &[u8],
+    iters: u64,
+    recursion_depth: u8,
+) -> bool {
+    if witness.len() != FORM_SIZE + recursion_depth as usize * SEGMENT_SIZE {
+        return false;
+    }
+    let d = discriminant;
+    if !d.is_negative() || d.mod_floor(&BigInt::from(4)) != BigInt::one() {
+        return false;
+    }
+    let Some(mut x) = deserialize_form(d, x.data.as_ref()) else {
+        return false;
+    };
+    let mut iters = iters;
+    for segment in witness[FORM_SIZE..].chunks_exact(SEGMENT_SIZE).rev() {
+        let segment_iters = u64::from_be_bytes(segment[..8].try_into().expect("8 bytes"));
+        let b = BigUint::from_bytes_be(&segment[8..8 + B_BYTES]);
+        let Some(proof) = deserialize_form(d, &segment[8 + B_BYTES..]) else {
+            return false;
+        };
+        if b.is_zero() || segment_iters > iters {
+            return false;
+        }
+        let Some(x_new) = wesolowski_output(d, &x, &proof, &b, segment_iters) else {
+            return false;
+        };
+        if get_b(d, &x, &x_new) != Some(b) {
+            return false;
+        }
+        x = x_new;
+        iters -= segment_iters;
+    }
+
+    let Some(y) = deserialize_form(d, output.data.as_ref()) else {
+        return false;
+    };
+    let Some(proof) = deserialize_form(d, &witness[..FORM_SIZE]) else {
+        return false;
+    };
+    verify_wesolowski(d, &x, &y, &proof, iters)
+}
+
+// Validates a VDF proof for the VDF described by info, starting at input.
+// Proofs that are normalized to identity start at the default element
+// instead. witness_type is the recursion depth of the proof, which is limited
+// by max_vdf_witness_size.
+pub fn validate_vdf_proof(
+    constants: &ConsensusConstants,
+    proof: &VDFProof,
+    input: &ClassgroupElement,
+    info: &VDFInfo,
+) -> bool {
+    if u16::from(proof.witness_type) + 1 > u16::from(constants.max_vdf_witness_size) {
+        return false;
+    }
+    let input = if proof.normalized_to_identity {
+        ClassgroupElement::default()
+    } else {
+        *input
+    };
+    let Some(discriminant) = create_discriminant(&info.challenge, constants.discriminant_size_bits)
+    else {
+        return false;
+    };
+    verify_n_wesolowski(
+        &discriminant,
+        &input,
+        &info.output,
+        &proof.witness,
+        info.number_of_iterations,
+        proof.witness_type,
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use chia_protocol::{Bytes, Bytes32};
+    use rstest::rstest;
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    fn element(form: &Form, d: &BigInt) -> ClassgroupElement {
+        ClassgroupElement::new(serialize_form(form, d.bits()).unwrap().into())
+    }
+
+    fn square(x: &Form, d: &BigInt, iters: u64) -> Form {
+        let mut y = x.clone();
+        for _ in 0..iters {
+            y = y.compose(&y, d);
+        }
+        y
+    }
+
+    // computes y = x^(2^iters) and the proof x^floor(2^iters / B)
+    fn prove(x: &Form, d: &BigInt, iters: u64) -> (Form, Form, BigUint) {
+        let y = square(x, d, iters);
+        let b = get_b(d, x, &y).unwrap();
+        let proof = x.pow(&((BigUint::one() << iters) / &b), d).unwrap();
+        (y, proof, b)
+    }
+
+    // an n-wesolowski proof with one segment per entry in segments, plus the
+    // final proof for the remaining iterations
+    fn prove_n(x: &Form, d: &BigInt, iters: u64, segments: &[u64]) -> (Form, Vec<u8>) {
+        let mut x = x.clone();
+        let mut remaining = iters;
+        let mut segment_blobs = Vec::new();
+        for segment_iters in segments {
+            let (y, proof, b) = prove(&x, d, *segment_iters);
+            let mut blob = segment_iters.to_be_bytes().to_vec();
+            let b_bytes = b.to_bytes_be();
+            blob.extend_from_slice(&[0; B_BYTES][b_bytes.len()..]);
+            blob.extend_from_slice(&b_bytes);
+            blob.extend_from_slice(&serialize_form(&proof, d.bits()).unwrap());
+            segment_blobs.push(blob);
+            remaining -= segment_iters;
+            x = y;
+        }
+        let (y, proof, _) = prove(&x, d, remaining);
+        let mut witness = serialize_form(&proof, d.bits()).unwrap().to_vec();
+        for blob in segment_blobs.iter().rev() {
+            witness.extend_from_slice(blob);
+        }
+        (y, witness)
+    }
+
+    #[test]
+    fn test_discriminant() {
+        let d = create_discriminant(&[0x11; 32], 1024).unwrap();
+        assert!(d.is_negative());
+        assert_eq!(d.bits(), 1024);
+        assert_eq!(d.mod_floor(&BigInt::from(8)), BigInt::one());
+        assert!(is_probable_prime(d.magnitude()));
+        assert_eq!(d, create_discriminant(&[0x11; 32], 1024).unwrap());
+        assert_ne!(d, create_discriminant(&[0x12; 32], 1024).unwrap());
+
+        let d = create_discriminant(&[0x11; 32], 512).unwrap();
+        assert_eq!(d.bits(), 512);
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(1)]
+    #[case(1020)]
+    fn test_discriminant_invalid_size(#[case] bits: u16) {
+        assert_eq!(create_discriminant(&[0x11; 32], bits), None);
+
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.discriminant_size_bits = bits;
+        let challenge = Bytes32::from([0x66; 32]);
+        let default = ClassgroupElement::default();
+        let (info, proof) = make_vdf(challenge, &default, 30, &[10], false);
+        assert!(!validate_vdf_proof(&constants, &proof, &default, &info));
+    }
+
+    #[rstest]
+    #[case(2, true)]
+    #[case(251, true)]
+    #[case(257, true)]
+    #[case(65_537, true)]
+    #[case(2_147_483_647, true)]
+    #[case(0, false)]
+    #[case(1, false)]
+    #[case(65_535, false)]
+    #[case(4_294_967_297, false)]
+    // Carmichael numbers
+    #[case(561, false)]
+    #[case(3_215_031_751, false)]
+    fn test_is_probable_prime(#[case] n: u64, #[case] expected: bool) {
+        assert_eq!(is_probable_prime(&BigUint::from(n)), expected);
+    }
+
+    #[test]
+    fn test_group_operations() {
+        let d = create_discriminant(&[0x22; 32], 512).unwrap();
+        let identity = Form::identity(&d).unwrap();
+        let gen = Form::generator(&d).unwrap();
+        assert!(identity.is_reduced());
+        assert!(gen.is_reduced());
+        assert_eq!(gen.compose(&identity, &d), gen);
+
+        // x^5 * x^7 == x^12 == (x^3)^4
+        let x = gen.pow(&BigUint::from(1_000_003_u32), &d).unwrap();
+        assert!(x.is_reduced());
+        let x5 = x.pow(&BigUint::from(5_u32), &d).unwrap();
+        let x7 = x.pow(&BigUint::from(7_u32), &d).unwrap();
+        let x12 = x.pow(&BigUint::from(12_u32), &d).unwrap();
+        assert_eq!(x5.compose(&x7, &d), x12);
+        assert_eq!(x7.compose(&x5, &d), x12);
+        assert_eq!(
+            x.pow(&BigUint::from(3_u32), &d)
+                .unwrap()
+                .pow(&BigUint::from(4_u32), &d)
+                .unwrap(),
+            x12
+        );
+
+        // the inverse of (a, b, c) is (a, -b, c)
+        let mut inverse = Form {
+            a: x.a.clone(),
+            b: -&x.b,
+            c: x.c.clone(),
+        };
+        inverse.reduce();
+        assert_eq!(x.compose(&inverse, &d), identity);
+    }
+
+    #[rstest]
+    #[case(1024)]
+    #[case(512)]
+    #[case(256)]
+    fn test_serialize_roundtrip(#[case] bits: u16) {
+        let d = create_discriminant(&[0x33; 32], bits).unwrap();
+        let identity = Form::identity(&d).unwrap();
+        let gen = Form::generator(&d).unwrap();
+
+        let mut expected = [0_u8; FORM_SIZE];
+        expected[0] = BQFC_IS_GEN;
+        assert_eq!(serialize_form(&gen, d.bits()), Some(expected));
+        assert_eq!(element(&gen, &d), ClassgroupElement::default());
+        expected[0] = BQFC_IS_1;
+        assert_eq!(serialize_form(&identity, d.bits()), Some(expected));
+
+        let mut x = gen.clone();
+        for i in 0..200_u32 {
+            x = x.compose(&x, &d);
+            if i % 3 == 0 {
+                x = x.compose(&gen, &d);
+            }
+            let buf = serialize_form(&x, d.bits()).unwrap();
+            assert_eq!(deserialize_form(&d, &buf), Some(x.clone()));
+            // a form with the other sign of b
+            let mut inverse = Form {
+                a: x.a.clone(),
+                b: -&x.b,
+                c: x.c.clone(),
+            };
+            inverse.reduce();
+            let buf = serialize_form(&inverse, d.bits()).unwrap();
+            assert_eq!(deserialize_form(&d, &buf), Some(inverse));
+        }
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        let d = create_discriminant(&[0x44; 32], 512).unwrap();
+        let x = Form::generator(&d)
+            .unwrap()
+            .pow(&BigUint::from(123_456_789_u32), &d)
+            .unwrap();
+        let buf = serialize_form(&x, d.bits()).unwrap();
+
+        // non-canonical padding
+        let mut bad = buf;
+        bad[FORM_SIZE - 1] = 1;
+        assert_eq!(deserialize_form(&d, &bad), None);
+
+        // identity with trailing garbage
+        let mut bad = [0_u8; FORM_SIZE];
+        bad[0] = BQFC_IS_1;
+        bad[10] = 1;
+        assert_eq!(deserialize_form(&d, &bad), None);
+
+        // g_size too large
+        let mut bad = buf;
+        bad[1] = 16;
+        assert_eq!(deserialize_form(&d, &bad), None);
+
+        // wrong size
+        assert_eq!(deserialize_form(&d, &buf[..99]), None);
+
+        // flipping bits either fails or yields a different form
+        for i in 2..20 {
+            let mut bad = buf;
+            bad[i] ^= 0x40;
+            assert_ne!(deserialize_form(&d, &bad), Some(x.clone()));
+        }
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&[40])]
+    #[case(&[20, 30])]
+    fn test_verify_n_wesolowski(#[case] segments: &[u64]) {
+        let d = create_discriminant(&[0x55; 32], 512).unwrap();
+        let x = Form::generator(&d).unwrap();
+        let input = element(&x, &d);
+        let iters = 100;
+        let depth = segments.len() as u8;
+        let (y, witness) = prove_n(&x, &d, iters, segments);
+        let output = element(&y, &d);
+
+        assert!(verify_n_wesolowski(
+            &d, &input, &output, &witness, iters, depth
+        ));
+
+        // wrong number of iterations
+        assert!(!verify_n_wesolowski(
+            &d,
+            &input,
+            &output,
+            &witness,
+            iters + 1,
+            depth
+        ));
+        // wrong recursion depth
+        assert!(!verify_n_wesolowski(
+            &d,
+            &input,
+            &output,
+            &witness,
+            iters,
+            depth + 1
+        ));
+        // wrong output
+        let wrong = element(&y.compose(&x, &d), &d);
+        assert!(!verify_n_wesolowski(
+            &d, &input, &wrong, &witness, iters, depth
+        ));
+        // wrong input
+        let wrong = element(&x.compose(&x, &d), &d);
+        assert!(!verify_n_wesolowski(
+            &d, &wrong, &output, &witness, iters, depth
+        ));
+        // wrong discriminant
+        let d2 = create_discriminant(&[0x56; 32], 512).unwrap();
+        assert!(!verify_n_wesolowski(
+            &d2, &input, &output, &witness, iters, depth
+        ));
+        // tampered proof
+        let mut bad = witness.clone();
+        bad[10] ^= 1;
+        assert!(!verify_n_wesolowski(
+            &d, &input, &output, &bad, iters, depth
+        ));
+        // tampered segment iterations and B
+        if depth > 0 {
+            let mut bad = witness.clone();
+            bad[FORM_SIZE + 7] ^= 1;
+            assert!(!verify_n_wesolowski(
+                &d, &input, &output, &bad, iters, depth
+            ));
+            let mut bad = witness.clone();
+            bad[FORM_SIZE + 20] ^= 1;
+            assert!(!verify_n_wesolowski(
+                &d, &input, &output, &bad, iters, depth
+            ));
+        }
+    }
+
+    #[rstest]
+    #[case(BigInt::from(-7))]
+    #[case(BigInt::from(-8))]
+    #[case(BigInt::from(-10))]
+    #[case(BigInt::from(5))]
+    #[case(BigInt::zero())]
+    fn test_verify_invalid_discriminant(#[case] d: BigInt) {
+        // the identity form doesn't exist for discriminants that aren't 1 mod
+        // 4, and positive ones aren't supported. These fail rather than panic
+        let input = ClassgroupElement::default();
+        let mut witness = vec![0_u8; FORM_SIZE];
+        witness[0] = BQFC_IS_1;
+        assert!(!verify_n_wesolowski(&d, &input, &input, &witness, 1, 0));
+    }
+
+    fn make_vdf(
+        challenge: Bytes32,
+        input: &ClassgroupElement,
+        iters: u64,
+        segments: &[u64],
+        normalized_to_identity: bool,
+    ) -> (VDFInfo, VDFProof) {
+        let d = create_discriminant(&challenge, TEST_CONSTANTS.discriminant_size_bits).unwrap();
+        let x = deserialize_form(&d, input.data.as_ref()).unwrap();
+        let (y, witness) = prove_n(&x, &d, iters, segments);
+        let info = VDFInfo::new(challenge, iters, element(&y, &d));
+        let proof = VDFProof::new(
+            segments.len() as u8,
+            Bytes::new(witness),
+            normalized_to_identity,
+        );
+        (info, proof)
+    }
+
+    // A compact proof of iters squarings of input, for tests in other
+    // modules. Creating the discriminant is slow, so they are cached
+    pub fn prove_vdf(
+        constants: &ConsensusConstants,
+        challenge: Bytes32,
+        input: &ClassgroupElement,
+        iters: u64,
+    ) -> (VDFInfo, VDFProof) {
+        static DISCRIMINANTS: OnceLock<Mutex<HashMap<(Bytes32, u16), BigInt>>> = OnceLock::new();
+        let d = DISCRIMINANTS
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap()
+            .entry((challenge, constants.discriminant_size_bits))
+            .or_insert_with(|| {
+                create_discriminant(&challenge, constants.discriminant_size_bits).unwrap()
+            })
+            .clone();
+        let x = deserialize_form(&d, input.data.as_ref()).unwrap();
+        let (y, witness) = prove_n(&x, &d, iters, &[]);
+        let info = VDFInfo::new(challenge, iters, element(&y, &d));
+        (info, VDFProof::new(0, Bytes::new(witness), false))
+    }
+
+    #[test]
+    fn test_validate_vdf_proof() {
+        let challenge = Bytes32::from([0x66; 32]);
+        let default = ClassgroupElement::default();
+        let (info, proof) = make_vdf(challenge, &default, 30, &[10], false);
+        assert!(validate_vdf_proof(&TEST_CONSTANTS, &proof, &default, &info));
+
+        // a compact proof, starting at some other element
+        let d = create_discriminant(&challenge, TEST_CONSTANTS.discriminant_size_bits).unwrap();
+        let input = element(&square(&Form::generator(&d).unwrap(), &d, 5), &d);
+        let (info, proof) = make_vdf(challenge, &input, 30, &[], false);
+        assert!(validate_vdf_proof(&TEST_CONSTANTS, &proof, &input, &info));
+        assert!(!validate_vdf_proof(
+            &TEST_CONSTANTS,
+            &proof,
+            &default,
+            &info
+        ));
+
+        // normalized to identity, the input is ignored
+        let (info, proof) = make_vdf(challenge, &default, 30, &[], true);
+        assert!(validate_vdf_proof(&TEST_CONSTANTS, &proof, &input, &info));
+
+        // the witness is too large
+        let mut constants = TEST_CONSTANTS.clone();
+        constants.max_vdf_witness_size = 1;
+        let (info, proof) = make_vdf(challenge, &default, 30, &[10], false);
+        assert!(!validate_vdf_proof(&constants, &proof, &default, &info));
+
+        // wrong challenge
+        let info = VDFInfo::new(
+            Bytes32::from([0x67; 32]),
+            info.number_of_iterations,
+            info.output,
+        );
+        assert!(!validate_vdf_proof(
+            &TEST_CONSTANTS,
+            &proof,
+            &default,
+            &info
+        ));
+    }
+}
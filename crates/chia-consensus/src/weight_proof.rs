@@ -0,0 +1,1580 @@
+use crate::consensus_constants::ConsensusConstants;
+use crate::gen::validation_error::ErrorCode;
+use crate::pot_iterations::{
+    calculate_ip_iters, calculate_required_iters, calculate_sp_iters, is_overflow_block,
+};
+use crate::proof_of_space::verify_and_get_quality_string;
+use crate::vdf::validate_vdf_proof;
+use chia_protocol::{
+    Bytes32, ChallengeChainSubSlot, ClassgroupElement, HeaderBlock, SubEpochChallengeSegment,
+    SubEpochData, SubEpochSummary, SubSlotData, VDFInfo, VDFProof, WeightProof,
+};
+use chia_traits::Streamable;
+use sha2::{Digest, Sha512};
+use std::collections::{BTreeSet, HashMap};
+
+// these parameters control how many sub-epochs are sampled. They must match
+// the full node's, since the peer creating the weight proof includes
+// segments for the sub-epochs it expects us to sample
+const LAMBDA_L: f64 = 100.0;
+const C: f64 = 0.5;
+const MAX_SAMPLES: usize = 20;
+
+// The result of validating a weight proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedWeightProof {
+    // the height from which our chain and the weight proof's chain may
+    // differ. Blocks below this height don't need to be synced again
+    pub fork_point: u32,
+    // the height and weight of the peak of the weight proof
+    pub peak_height: u32,
+    pub weight: u128,
+    // the sub-epoch summaries, reconstructed from the weight proof. These
+    // can be passed back in as local_summaries when validating the next one
+    pub summaries: Vec<SubEpochSummary>,
+}
+
+// The sub-epoch summaries and the weight at the start of each sub-epoch,
+// along with the total weight up to the last summary
+struct SubEpochSummaries {
+    summaries: Vec<SubEpochSummary>,
+    weights: Vec<u128>,
+    total_weight: u128,
+}
+
+// rebuilds the chain of sub-epoch summaries from the sub-epoch data. Each
+// summary commits to the hash of the previous one, the first one to the
+// genesis challenge. The difficulty only changes at the end of an epoch,
+// which is also when the sub slot iters change.
+fn map_sub_epoch_summaries(
+    constants: &ConsensusConstants,
+    sub_epochs: &[SubEpochData],
+) -> Result<SubEpochSummaries, ErrorCode> {
+    let mut prev_hash = constants.genesis_challenge;
+    let mut difficulty = constants.difficulty_starting as u128;
+    let mut total_weight: u128 = 0;
+    let mut summaries = Vec::<SubEpochSummary>::with_capacity(sub_epochs.len());
+    let mut weights = Vec::<u128>::with_capacity(sub_epochs.len());
+
+    for (idx, data) in sub_epochs.iter().enumerate() {
+        if data.new_difficulty.is_some() != data.new_sub_slot_iters.is_some() {
+            return Err(if data.new_difficulty.is_none() {
+                ErrorCode::InvalidNewDifficulty
+            } else {
+                ErrorCode::InvalidNewSubSlotIters
+            });
+        }
+        if data.new_difficulty == Some(0) {
+            return Err(ErrorCode::InvalidNewDifficulty);
+        }
+        if data.new_sub_slot_iters == Some(0) {
+            return Err(ErrorCode::InvalidNewSubSlotIters);
+        }
+
+        let summary = SubEpochSummary::new(
+            prev_hash,
+            data.reward_chain_hash,
+            data.num_blocks_overflow,
+            data.new_difficulty,
+            data.new_sub_slot_iters,
+        );
+
+        if let Some(next) = sub_epochs.get(idx + 1) {
+            let delta = if idx > 0 { data.num_blocks_overflow } else { 0 };
+            weights.push(total_weight + difficulty);
+            let num_blocks = (constants.sub_epoch_blocks + u32::from(next.num_blocks_overflow))
+                .checked_sub(u32::from(delta))
+                .ok_or(ErrorCode::InvalidSubEpochOverflow)?;
+            total_weight += difficulty * num_blocks as u128;
+        }
+        if let Some(new_difficulty) = data.new_difficulty {
+            difficulty = new_difficulty as u128;
+        }
+
+        prev_hash = summary.hash().into();
+        summaries.push(summary);
+    }
+    weights.push(total_weight + difficulty);
+
+    Ok(SubEpochSummaries {
+        summaries,
+        weights,
+        total_weight,
+    })
+}
+
+// the hash of the most recent sub-epoch summary included in the recent chain,
+// and the height of the block it was included in. Summaries are included in
+// the first block with finished sub slots after a sub-epoch boundary.
+fn last_ses_hash(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+) -> Option<(Bytes32, u32)> {
+    let start = recent_chain
+        .iter()
+        .rposition(|b| b.height() % constants.sub_epoch_blocks == 0)?;
+    recent_chain[start..].iter().find_map(|block| {
+        block.finished_sub_slots.iter().find_map(|slot| {
+            slot.challenge_chain
+                .subepoch_summary_hash
+                .map(|hash| (hash, block.height()))
+        })
+    })
+}
+
+fn validate_sub_epoch_summaries(
+    constants: &ConsensusConstants,
+    wp: &WeightProof,
+) -> Result<SubEpochSummaries, ErrorCode> {
+    let (last_hash, _height) =
+        last_ses_hash(constants, &wp.recent_chain_data).ok_or(ErrorCode::NoSubEpochSummaryHash)?;
+
+    let ses = map_sub_epoch_summaries(constants, &wp.sub_epochs)?;
+
+    // the weight at the end of the last summarized sub-epoch must match the
+    // block at that height in the recent chain
+    let last = ses
+        .summaries
+        .last()
+        .ok_or(ErrorCode::InvalidSubEpochSummary)?;
+    let end_height = ((ses.summaries.len() as u64 - 1) * constants.sub_epoch_blocks as u64
+        + last.num_blocks_overflow as u64)
+        .checked_sub(1)
+        .ok_or(ErrorCode::InvalidSubEpochOverflow)?;
+    let block = wp
+        .recent_chain_data
+        .iter()
+        .find(|b| b.height() as u64 == end_height)
+        .ok_or(ErrorCode::InvalidWeight)?;
+    if block.weight() != ses.total_weight {
+        return Err(ErrorCode::InvalidWeight);
+    }
+
+    // the hash chain must end in the summary committed to by the recent chain
+    if Bytes32::from(last.hash()) != last_hash {
+        return Err(ErrorCode::InvalidSubEpochSummaryHash);
+    }
+    Ok(ses)
+}
+
+// The sub-epochs to sample are picked by a random number generator seeded by
+// the second to last summary. It has to be compatible with python's
+// random.Random, since that's what the full node uses to pick the sub-epochs
+// it includes segments for. This is the Mersenne Twister (MT19937).
+struct PyRandom {
+    mt: [u32; 624],
+    index: usize,
+}
+
+impl PyRandom {
+    fn from_seed(seed: &[u8]) -> Self {
+        // python turns a bytes seed into an integer, big-endian, by
+        // appending its SHA-512 hash. The integer is then split into 32 bit
+        // words, least significant first
+        let mut seed = seed.to_vec();
+        seed.extend_from_slice(&Sha512::digest(&seed));
+        let mut key: Vec<u32> = seed
+            .rchunks(4)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0_u32, |acc, b| (acc << 8) | u32::from(*b))
+            })
+            .collect();
+        while key.len() > 1 && key.last() == Some(&0) {
+            key.pop();
+        }
+
+        let mut ret = Self {
+            mt: [0; 624],
+            index: 624,
+        };
+        ret.init_by_array(&key);
+        ret
+    }
+
+    fn init_genrand(&mut self, s: u32) {
+        self.mt[0] = s;
+        for i in 1..624 {
+            let prev = self.mt[i - 1];
+            self.mt[i] = 1_812_433_253_u32
+                .wrapping_mul(prev ^ (prev >> 30))
+                .wrapping_add(i as u32);
+        }
+        self.index = 624;
+    }
+
+    fn init_by_array(&mut self, key: &[u32]) {
+        self.init_genrand(19_650_218);
+        let mut i = 1;
+        let mut j = 0;
+        for _ in 0..624.max(key.len()) {
+            let prev = self.mt[i - 1];
+            self.mt[i] = (self.mt[i] ^ (prev ^ (prev >> 30)).wrapping_mul(1_664_525))
+                .wrapping_add(key[j])
+                .wrapping_add(j as u32);
+            i += 1;
+            j += 1;
+            if i >= 624 {
+                self.mt[0] = self.mt[623];
+                i = 1;
+            }
+            if j >= key.len() {
+                j = 0;
+            }
+        }
+        for _ in 0..623 {
+            let prev = self.mt[i - 1];
+            self.mt[i] = (self.mt[i] ^ (prev ^ (prev >> 30)).wrapping_mul(1_566_083_941))
+                .wrapping_sub(i as u32);
+            i += 1;
+            if i >= 624 {
+                self.mt[0] = self.mt[623];
+                i = 1;
+            }
+        }
+        self.mt[0] = 0x8000_0000;
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.index >= 624 {
+            for i in 0..624 {
+                let y = (self.mt[i] & 0x8000_0000) | (self.mt[(i + 1) % 624] & 0x7fff_ffff);
+                let mut v = self.mt[(i + 397) % 624] ^ (y >> 1);
+                if y & 1 != 0 {
+                    v ^= 0x9908_b0df;
+                }
+                self.mt[i] = v;
+            }
+            self.index = 0;
+        }
+        let mut y = self.mt[self.index];
+        self.index += 1;
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9d2c_5680;
+        y ^= (y << 15) & 0xefc6_0000;
+        y ^ (y >> 18)
+    }
+
+    // a float in the range [0, 1) with 53 bits of randomness, just like
+    // random.random()
+    fn random(&mut self) -> f64 {
+        let a = f64::from(self.next_u32() >> 5);
+        let b = f64::from(self.next_u32() >> 6);
+        (a * 67_108_864.0 + b) * (1.0 / 9_007_199_254_740_992.0)
+    }
+
+    // an integer in the range [0, n), just like random.choice(range(n)).
+    // Like python, this consumes randomness even if n is 1
+    fn randbelow(&mut self, n: u32) -> u32 {
+        let shift = n.leading_zeros();
+        loop {
+            let r = self.next_u32() >> shift;
+            if r < n {
+                return r;
+            }
+        }
+    }
+}
+
+// the weights to sample, sorted in ascending order. The number of samples
+// depends on how much of the total weight is covered by the recent chain.
+// None means the recent chain covers so much that every sub-epoch is sampled
+fn weights_for_sampling(
+    rng: &mut PyRandom,
+    total_weight: u128,
+    recent_chain: &[HeaderBlock],
+) -> Option<Vec<u128>> {
+    let first = recent_chain.first()?.weight();
+    let last = recent_chain.last()?.weight();
+    let delta = (last - first) as f64 / total_weight as f64;
+    if delta >= 1.0 {
+        return None;
+    }
+    let prob_of_adv_succeeding = 1.0 - C.ln() / delta.ln();
+    if prob_of_adv_succeeding <= 0.0 {
+        return None;
+    }
+    let queries = -LAMBDA_L * 2_f64.ln() / prob_of_adv_succeeding.ln();
+    let mut weights: Vec<u128> = (0..=(queries as u64))
+        .map(|_| {
+            let q = 1.0 - delta.powf(rng.random());
+            (q * total_weight as f64) as u128
+        })
+        .collect();
+    weights.sort_unstable();
+    Some(weights)
+}
+
+fn sample_sub_epoch(start_weight: u128, end_weight: u128, weights: Option<&[u128]>) -> bool {
+    let Some(weights) = weights else {
+        return true;
+    };
+    match (weights.first(), weights.last()) {
+        (Some(first), Some(last)) if *last >= start_weight && *first <= end_weight => {}
+        _ => return false,
+    }
+    for w in weights {
+        if *w > end_weight {
+            return false;
+        }
+        if *w > start_weight && *w < end_weight {
+            return true;
+        }
+    }
+    false
+}
+
+// every sub-epoch picked by the sampling must have segments in the weight
+// proof
+fn validate_sub_epoch_sampling(
+    rng: &mut PyRandom,
+    sub_epoch_weights: &[u128],
+    wp: &WeightProof,
+) -> Result<(), ErrorCode> {
+    let tip = wp
+        .recent_chain_data
+        .last()
+        .ok_or(ErrorCode::InvalidHeight)?;
+    let weights = weights_for_sampling(rng, tip.weight(), &wp.recent_chain_data);
+
+    let mut sampled = BTreeSet::<u32>::new();
+    for (idx, w) in sub_epoch_weights.windows(2).enumerate() {
+        if sample_sub_epoch(w[0], w[1], weights.as_deref()) {
+            sampled.insert(idx as u32);
+            if sampled.len() == MAX_SAMPLES {
+                break;
+            }
+        }
+    }
+    for segment in &wp.sub_epoch_segments {
+        sampled.remove(&segment.sub_epoch_n);
+    }
+    if sampled.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorCode::InvalidChallengeChainData)
+    }
+}
+
+// The segments are grouped by sub-epoch, in ascending order. Each segment
+// covers the sub slots leading up to, and following, one challenge block.
+// This only validates the structure of the segments, the VDFs and proofs of
+// space are verified by verify_segments()
+fn validate_segments(
+    segments: &[SubEpochChallengeSegment],
+    num_sub_epochs: usize,
+) -> Result<(), ErrorCode> {
+    let mut prev_sub_epoch = 0;
+    for segment in segments {
+        if segment.sub_epoch_n as usize >= num_sub_epochs || segment.sub_epoch_n < prev_sub_epoch {
+            return Err(ErrorCode::InvalidChallengeChainData);
+        }
+        prev_sub_epoch = segment.sub_epoch_n;
+
+        let mut challenge_blocks = segment.sub_slots.iter().filter(|s| s.is_challenge());
+        let Some(challenge) = challenge_blocks.next() else {
+            return Err(ErrorCode::InvalidChallengeChainData);
+        };
+        if challenge_blocks.next().is_some() {
+            return Err(ErrorCode::InvalidChallengeChainData);
+        }
+        if challenge.signage_point_index.is_none() {
+            return Err(ErrorCode::InvalidSpIndex);
+        }
+    }
+    Ok(())
+}
+
+fn check_vdf(
+    constants: &ConsensusConstants,
+    proof: &VDFProof,
+    input: &ClassgroupElement,
+    info: &VDFInfo,
+    error: ErrorCode,
+) -> Result<(), ErrorCode> {
+    if validate_vdf_proof(constants, proof, input, info) {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+fn total_iters(data: &SubSlotData) -> Result<u128, ErrorCode> {
+    data.total_iters.ok_or(ErrorCode::InvalidTotalIters)
+}
+
+// the difficulty and sub slot iters of a sub-epoch. These are set by the last
+// summary, before it, that changed them
+fn sub_epoch_params(
+    constants: &ConsensusConstants,
+    summaries: &[SubEpochSummary],
+    sub_epoch_n: usize,
+) -> (u64, u64) {
+    summaries[..sub_epoch_n]
+        .iter()
+        .rev()
+        .find_map(|ses| Some((ses.new_difficulty?, ses.new_sub_slot_iters?)))
+        .unwrap_or((
+            constants.difficulty_starting,
+            constants.sub_slot_iters_starting,
+        ))
+}
+
+// The challenge chain sub slot ending before the block at idx. The segment
+// only includes its VDFs, the summary is passed in for the first segment of
+// a sub-epoch, since that's the sub slot it was included in
+fn cc_sub_slot(
+    sub_slots: &[SubSlotData],
+    idx: usize,
+    ses: Option<&SubEpochSummary>,
+) -> Result<ChallengeChainSubSlot, ErrorCode> {
+    let (slot, cc_slot_end_info) = sub_slots[..idx]
+        .iter()
+        .rev()
+        .find_map(|s| s.cc_slot_end_info.as_ref().map(|info| (s, info)))
+        .ok_or(ErrorCode::InvalidChallengeChainData)?;
+    Ok(ChallengeChainSubSlot::new(
+        cc_slot_end_info.clone(),
+        slot.icc_slot_end_info
+            .as_ref()
+            .map(|info| info.hash().into()),
+        ses.map(|ses| ses.hash().into()),
+        ses.and_then(|ses| ses.new_sub_slot_iters),
+        ses.and_then(|ses| ses.new_difficulty),
+    ))
+}
+
+// Verifies the proof of space of the challenge block at idx, and that it's
+// good enough for the difficulty. The segment doesn't include the height of
+// the block, so the plot filter is applied at the height of the start of the
+// sub-epoch. The plot filter only changes at a few, far apart, heights
+#[allow(clippy::too_many_arguments)]
+fn verify_segment_pospace(
+    constants: &ConsensusConstants,
+    segment: &SubEpochChallengeSegment,
+    idx: usize,
+    ses: Option<&SubEpochSummary>,
+    first_in_sub_epoch: bool,
+    difficulty: u64,
+    sub_slot_iters: u64,
+    height: u32,
+) -> Result<(), ErrorCode> {
+    let data = &segment.sub_slots[idx];
+    let cc_sub_slot_hash = if first_in_sub_epoch && segment.sub_epoch_n == 0 && idx == 0 {
+        constants.genesis_challenge
+    } else {
+        cc_sub_slot(&segment.sub_slots, idx, ses)?.hash().into()
+    };
+    let signage_point_index = data.signage_point_index.ok_or(ErrorCode::InvalidSpIndex)?;
+
+    // overflow blocks use the challenge of the previous sub slot
+    let challenge = if is_overflow_block(constants, signage_point_index)? {
+        idx.checked_sub(1)
+            .and_then(|prev| segment.sub_slots[prev].cc_slot_end_info.as_ref())
+            .ok_or(ErrorCode::InvalidChallengeChainData)?
+            .challenge
+    } else {
+        cc_sub_slot_hash
+    };
+    let cc_sp_hash = data
+        .cc_sp_vdf_info
+        .as_ref()
+        .map_or(cc_sub_slot_hash, |info| info.output.hash().into());
+
+    let pos = data
+        .proof_of_space
+        .as_ref()
+        .ok_or(ErrorCode::InvalidPospace)?;
+    let quality = verify_and_get_quality_string(pos, constants, &challenge, &cc_sp_hash, height)
+        .ok_or(ErrorCode::InvalidPospace)?;
+    let required_iters = calculate_required_iters(
+        constants,
+        &quality,
+        pos.size,
+        difficulty,
+        &cc_sp_hash,
+        sub_slot_iters,
+    )?;
+    calculate_ip_iters(
+        constants,
+        sub_slot_iters,
+        signage_point_index,
+        required_iters,
+    )?;
+    Ok(())
+}
+
+// The input of the challenge chain signage point VDF of the block at idx. This
+// is the output of the last block infused before the signage point, if
+// there's one in the same sub slot, otherwise the default element. This
+// mirrors sub_slot_data_vdf_input() in chia
+fn cc_sp_vdf_input(
+    constants: &ConsensusConstants,
+    sub_slots: &[SubSlotData],
+    idx: usize,
+    new_sub_slot: bool,
+    sub_slot_iters: u64,
+) -> Result<ClassgroupElement, ErrorCode> {
+    let data = &sub_slots[idx];
+    let signage_point_index = data.signage_point_index.ok_or(ErrorCode::InvalidSpIndex)?;
+    let overflow = is_overflow_block(constants, signage_point_index)?;
+
+    let ip_iters = data
+        .cc_ip_vdf_info
+        .as_ref()
+        .ok_or(ErrorCode::InvalidCcIpVdf)?
+        .number_of_iterations;
+    let mut sp_total_iters = total_iters(data)?
+        .checked_sub(u128::from(ip_iters))
+        .ok_or(ErrorCode::InvalidTotalIters)?;
+    if overflow {
+        sp_total_iters = sp_total_iters
+            .checked_sub(u128::from(sub_slot_iters))
+            .ok_or(ErrorCode::InvalidTotalIters)?;
+    }
+    sp_total_iters += u128::from(calculate_sp_iters(
+        constants,
+        sub_slot_iters,
+        signage_point_index,
+    )?);
+
+    // walk backwards to the last block before the signage point
+    let mut ssd = None;
+    if overflow && new_sub_slot {
+        if idx < 2 || sub_slots[idx - 2].cc_slot_end_info.is_some() {
+            return Ok(ClassgroupElement::default());
+        }
+        for i in (0..idx - 1).rev() {
+            ssd = Some(&sub_slots[i]);
+            if sub_slots[i].is_end_of_slot() {
+                ssd = Some(&sub_slots[i + 1]);
+                break;
+            }
+            if total_iters(&sub_slots[i])? <= sp_total_iters {
+                break;
+            }
+        }
+    } else if !overflow && !new_sub_slot {
+        for i in (0..idx).rev() {
+            ssd = Some(&sub_slots[i]);
+            if sub_slots[i].is_end_of_slot() {
+                ssd = Some(&sub_slots[i + 1]);
+                break;
+            }
+            if total_iters(&sub_slots[i])? <= sp_total_iters {
+                break;
+            }
+        }
+    } else if overflow {
+        // the signage point is in the sub slot before the previous end of
+        // slot
+        let mut slots_seen = 0;
+        for i in (0..idx).rev() {
+            ssd = Some(&sub_slots[i]);
+            if sub_slots[i].is_end_of_slot() {
+                slots_seen += 1;
+                if slots_seen == 2 {
+                    return Ok(ClassgroupElement::default());
+                }
+            } else if total_iters(&sub_slots[i])? <= sp_total_iters {
+                break;
+            }
+        }
+    }
+
+    match ssd {
+        Some(SubSlotData {
+            cc_ip_vdf_info: Some(info),
+            total_iters: Some(iters),
+            ..
+        }) if *iters < sp_total_iters => Ok(info.output),
+        _ => Ok(ClassgroupElement::default()),
+    }
+}
+
+// Verifies the challenge chain signage point VDF of the block at idx, if it
+// has one
+fn verify_cc_sp_vdf(
+    constants: &ConsensusConstants,
+    sub_slots: &[SubSlotData],
+    idx: usize,
+    sub_slot_iters: u64,
+) -> Result<(), ErrorCode> {
+    let data = &sub_slots[idx];
+    let (proof, info) = match (&data.cc_signage_point, &data.cc_sp_vdf_info) {
+        (None, None) => return Ok(()),
+        (Some(proof), Some(info)) => (proof, info),
+        _ => return Err(ErrorCode::InvalidCcSpVdf),
+    };
+    let input = match idx.checked_sub(1) {
+        Some(prev) if !proof.normalized_to_identity => cc_sp_vdf_input(
+            constants,
+            sub_slots,
+            idx,
+            sub_slots[prev].is_end_of_slot(),
+            sub_slot_iters,
+        )?,
+        _ => ClassgroupElement::default(),
+    };
+    check_vdf(constants, proof, &input, info, ErrorCode::InvalidCcSpVdf)
+}
+
+// Verifies the challenge chain infusion point VDF of the block at idx. It
+// starts at the previous block's, unless it's the first block in the sub slot
+fn verify_cc_ip_vdf(
+    constants: &ConsensusConstants,
+    sub_slots: &[SubSlotData],
+    idx: usize,
+) -> Result<(), ErrorCode> {
+    let data = &sub_slots[idx];
+    let (Some(proof), Some(info)) = (&data.cc_infusion_point, &data.cc_ip_vdf_info) else {
+        return Err(ErrorCode::InvalidCcIpVdf);
+    };
+    match idx.checked_sub(1).map(|prev| &sub_slots[prev]) {
+        Some(prev) if !proof.normalized_to_identity && prev.cc_slot_end.is_none() => {
+            let prev_info = prev
+                .cc_ip_vdf_info
+                .as_ref()
+                .ok_or(ErrorCode::InvalidCcIpVdf)?;
+            let iters = total_iters(data)?
+                .checked_sub(total_iters(prev)?)
+                .and_then(|iters| u64::try_from(iters).ok())
+                .ok_or(ErrorCode::InvalidTotalIters)?;
+            let info = VDFInfo::new(info.challenge, iters, info.output);
+            check_vdf(
+                constants,
+                proof,
+                &prev_info.output,
+                &info,
+                ErrorCode::InvalidCcIpVdf,
+            )
+        }
+        _ => check_vdf(
+            constants,
+            proof,
+            &ClassgroupElement::default(),
+            info,
+            ErrorCode::InvalidCcIpVdf,
+        ),
+    }
+}
+
+// Verifies the VDFs of the end of slot, or block, at idx. This is used for
+// the sub slot data following the challenge block
+fn verify_sub_slot_vdfs(
+    constants: &ConsensusConstants,
+    sub_slots: &[SubSlotData],
+    idx: usize,
+    sub_slot_iters: u64,
+) -> Result<(), ErrorCode> {
+    let data = &sub_slots[idx];
+    let prev = &sub_slots[idx - 1];
+    let default = ClassgroupElement::default();
+
+    if data.is_end_of_slot() {
+        if let Some(proof) = &data.icc_slot_end {
+            let info = data
+                .icc_slot_end_info
+                .as_ref()
+                .ok_or(ErrorCode::InvalidIccEosVdf)?;
+            let input = match &prev.icc_ip_vdf_info {
+                Some(prev_info) if !proof.normalized_to_identity => &prev_info.output,
+                _ => &default,
+            };
+            check_vdf(constants, proof, input, info, ErrorCode::InvalidIccEosVdf)?;
+        }
+        let (Some(proof), Some(info)) = (&data.cc_slot_end, &data.cc_slot_end_info) else {
+            return Err(ErrorCode::InvalidCcEosVdf);
+        };
+        let input = if !prev.is_end_of_slot() && !proof.normalized_to_identity {
+            &prev
+                .cc_ip_vdf_info
+                .as_ref()
+                .ok_or(ErrorCode::InvalidCcEosVdf)?
+                .output
+        } else {
+            &default
+        };
+        return check_vdf(constants, proof, input, info, ErrorCode::InvalidCcEosVdf);
+    }
+
+    // the intermediate VDFs of a sub slot that's been normalized to identity
+    // are not verified, just like in chia
+    if let Some(end) = sub_slots[idx..sub_slots.len() - 1]
+        .iter()
+        .find(|s| s.is_end_of_slot())
+    {
+        let end_proof = end.cc_slot_end.as_ref().ok_or(ErrorCode::InvalidCcEosVdf)?;
+        if end_proof.normalized_to_identity {
+            return Ok(());
+        }
+    }
+
+    if let (Some(proof), Some(info)) = (&data.icc_infusion_point, &data.icc_ip_vdf_info) {
+        let input = match &prev.icc_ip_vdf_info {
+            Some(prev_info) if !prev.is_challenge() => &prev_info.output,
+            _ => &default,
+        };
+        check_vdf(constants, proof, input, info, ErrorCode::InvalidIccVdf)?;
+    }
+    if data.signage_point_index.is_none() {
+        return Err(ErrorCode::InvalidSpIndex);
+    }
+    verify_cc_sp_vdf(constants, sub_slots, idx, sub_slot_iters)?;
+    verify_cc_ip_vdf(constants, sub_slots, idx)
+}
+
+// Verifies one randomly picked segment of every sub-epoch that has segments.
+// The proof of space and VDFs of its challenge block are verified, as well as
+// the VDFs of the sub slots following it. The random number generator picks
+// up where the sub-epoch sampling left off, just like the full node's
+fn verify_segments(
+    constants: &ConsensusConstants,
+    rng: &mut PyRandom,
+    segments: &[SubEpochChallengeSegment],
+    summaries: &[SubEpochSummary],
+) -> Result<(), ErrorCode> {
+    let heights = ses_heights(constants, summaries);
+    for sub_epoch in segments.chunk_by(|a, b| a.sub_epoch_n == b.sub_epoch_n) {
+        let sub_epoch_n = sub_epoch[0].sub_epoch_n as usize;
+        let sampled = rng.randbelow(sub_epoch.len() as u32) as usize;
+        let segment = &sub_epoch[sampled];
+        let (difficulty, sub_slot_iters) = sub_epoch_params(constants, summaries, sub_epoch_n);
+        // only the first segment of a sub-epoch starts in the sub slot
+        // including the previous summary
+        let ses = match sampled {
+            0 => sub_epoch_n.checked_sub(1).map(|n| &summaries[n]),
+            _ => None,
+        };
+
+        let challenge_idx = segment
+            .sub_slots
+            .iter()
+            .position(SubSlotData::is_challenge)
+            .ok_or(ErrorCode::InvalidChallengeChainData)?;
+        verify_segment_pospace(
+            constants,
+            segment,
+            challenge_idx,
+            ses,
+            sampled == 0,
+            difficulty,
+            sub_slot_iters,
+            heights[sub_epoch_n],
+        )?;
+        verify_cc_sp_vdf(constants, &segment.sub_slots, challenge_idx, sub_slot_iters)?;
+        verify_cc_ip_vdf(constants, &segment.sub_slots, challenge_idx)?;
+        for idx in challenge_idx + 1..segment.sub_slots.len() {
+            verify_sub_slot_vdfs(constants, &segment.sub_slots, idx, sub_slot_iters)?;
+        }
+    }
+    Ok(())
+}
+
+// the sub-epoch summary hash included in the block, if any
+fn ses_hash(block: &HeaderBlock) -> Option<Bytes32> {
+    block
+        .finished_sub_slots
+        .iter()
+        .find_map(|slot| slot.challenge_chain.subepoch_summary_hash)
+}
+
+// the recent chain must be a contiguous chain of blocks, each one building on
+// the previous one. Every block adds the current difficulty to the weight.
+// The difficulty only changes in the first block of an epoch, which is the
+// block including the sub-epoch summary with the new difficulty
+fn validate_recent_chain(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+    summaries: &[SubEpochSummary],
+) -> Result<(), ErrorCode> {
+    // the difficulty before and after each summary
+    let mut difficulty = constants.difficulty_starting as u128;
+    let mut difficulties = HashMap::<Bytes32, (u128, u128)>::new();
+    for ses in summaries {
+        let before = difficulty;
+        if let Some(new_difficulty) = ses.new_difficulty {
+            difficulty = new_difficulty as u128;
+        }
+        difficulties.insert(ses.hash().into(), (before, difficulty));
+    }
+    let difficulty_of = |hash: Bytes32| {
+        difficulties
+            .get(&hash)
+            .copied()
+            .ok_or(ErrorCode::InvalidSubEpochSummaryHash)
+    };
+
+    // the difficulty of the blocks before the first summary in the recent
+    // chain
+    let mut difficulty = match recent_chain
+        .iter()
+        .enumerate()
+        .find_map(|(idx, block)| ses_hash(block).map(|hash| (idx, hash)))
+    {
+        Some((0, hash)) => difficulty_of(hash)?.1,
+        Some((_, hash)) => difficulty_of(hash)?.0,
+        None => return Err(ErrorCode::NoSubEpochSummaryHash),
+    };
+
+    for pair in recent_chain.windows(2) {
+        let (prev, block) = (&pair[0], &pair[1]);
+        if block.height() != prev.height() + 1 {
+            return Err(ErrorCode::InvalidHeight);
+        }
+        if block.prev_header_hash() != prev.header_hash() {
+            return Err(ErrorCode::InvalidPrevBlockHash);
+        }
+        if let Some(hash) = ses_hash(block) {
+            difficulty = difficulty_of(hash)?.1;
+        }
+        if block.weight().checked_sub(prev.weight()) != Some(difficulty) {
+            return Err(ErrorCode::InvalidWeight);
+        }
+    }
+    Ok(())
+}
+
+// Verifies the proofs of space, and the challenge chain and reward chain
+// infusion point VDFs, of the recent chain. The challenge of the blocks before
+// the first end of slot isn't known, so their proofs of space can't be
+// verified. Neither can the challenge chain VDF of the first block, unless it
+// starts a new sub slot
+fn verify_recent_chain(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+    summaries: &[SubEpochSummary],
+) -> Result<(), ErrorCode> {
+    let num_summaries = recent_chain
+        .iter()
+        .filter(|block| ses_hash(block).is_some())
+        .count();
+    let first_summary = summaries
+        .len()
+        .checked_sub(num_summaries)
+        .ok_or(ErrorCode::InvalidSubEpochSummary)?;
+    let (mut difficulty, mut sub_slot_iters) =
+        sub_epoch_params(constants, summaries, first_summary);
+
+    // the challenge of the current sub slot, and of the previous one
+    let mut challenges: Option<(Bytes32, Bytes32)> = None;
+    let mut prev: Option<&HeaderBlock> = None;
+    let default = ClassgroupElement::default();
+    for block in recent_chain {
+        for slot in &block.finished_sub_slots {
+            let cc = &slot.challenge_chain;
+            challenges = Some((
+                cc.hash().into(),
+                cc.challenge_chain_end_of_slot_vdf.challenge,
+            ));
+            if let Some(new_difficulty) = cc.new_difficulty {
+                difficulty = new_difficulty;
+            }
+            if let Some(new_sub_slot_iters) = cc.new_sub_slot_iters {
+                sub_slot_iters = new_sub_slot_iters;
+            }
+        }
+        let rcb = &block.reward_chain_block;
+
+        if let Some((challenge, prev_challenge)) = challenges {
+            let challenge = if is_overflow_block(constants, rcb.signage_point_index)? {
+                prev_challenge
+            } else {
+                challenge
+            };
+            let cc_sp_hash = rcb
+                .challenge_chain_sp_vdf
+                .as_ref()
+                .map_or(challenge, |vdf| vdf.output.hash().into());
+            let pos = &rcb.proof_of_space;
+            let quality = verify_and_get_quality_string(
+                pos,
+                constants,
+                &challenge,
+                &cc_sp_hash,
+                block.height(),
+            )
+            .ok_or(ErrorCode::InvalidPospace)?;
+            calculate_required_iters(
+                constants,
+                &quality,
+                pos.size,
+                difficulty,
+                &cc_sp_hash,
+                sub_slot_iters,
+            )?;
+        }
+
+        // the challenge chain infusion point VDF starts at the previous
+        // block's, unless this is the first block in the sub slot
+        let cc_ip = &rcb.challenge_chain_ip_vdf;
+        let cc_ip_proof = &block.challenge_chain_ip_proof;
+        if !block.finished_sub_slots.is_empty() || cc_ip_proof.normalized_to_identity {
+            check_vdf(
+                constants,
+                cc_ip_proof,
+                &default,
+                cc_ip,
+                ErrorCode::InvalidCcIpVdf,
+            )?;
+        } else if let Some(prev) = prev {
+            let iters = rcb
+                .total_iters
+                .checked_sub(prev.reward_chain_block.total_iters)
+                .and_then(|iters| u64::try_from(iters).ok())
+                .ok_or(ErrorCode::InvalidTotalIters)?;
+            check_vdf(
+                constants,
+                cc_ip_proof,
+                &prev.reward_chain_block.challenge_chain_ip_vdf.output,
+                &VDFInfo::new(cc_ip.challenge, iters, cc_ip.output),
+                ErrorCode::InvalidCcIpVdf,
+            )?;
+        }
+        check_vdf(
+            constants,
+            &block.reward_chain_ip_proof,
+            &default,
+            &rcb.reward_chain_ip_vdf,
+            ErrorCode::InvalidRcIpVdf,
+        )?;
+        prev = Some(block);
+    }
+    Ok(())
+}
+
+// the height of the block including each sub-epoch summary
+fn ses_heights(constants: &ConsensusConstants, summaries: &[SubEpochSummary]) -> Vec<u32> {
+    summaries
+        .iter()
+        .enumerate()
+        .map(|(idx, ses)| {
+            if idx == 0 {
+                0
+            } else {
+                idx as u32 * constants.sub_epoch_blocks + ses.num_blocks_overflow as u32
+            }
+        })
+        .collect()
+}
+
+// the fork point is where the summaries start to differ from the ones we
+// have. Two summaries may be identical even if the blocks differ, so we
+// back off by two sub-epochs
+fn fork_point(
+    constants: &ConsensusConstants,
+    local_summaries: &[SubEpochSummary],
+    summaries: &[SubEpochSummary],
+) -> u32 {
+    let mut fork_idx = 0;
+    for (idx, local) in local_summaries.iter().enumerate() {
+        if idx + 1 >= summaries.len() || local != &summaries[idx] {
+            break;
+        }
+        fork_idx = idx;
+    }
+    if fork_idx > 2 {
+        ses_heights(constants, summaries)[fork_idx - 2]
+    } else {
+        0
+    }
+}
+
+// Validates a weight proof received from a peer, without syncing the chain.
+// This checks:
+// * the sub-epoch summary hash chain, from the genesis challenge to the
+//   summary committed to by the recent chain
+// * the weight of the summarized sub-epochs against the recent chain
+// * that segments are included for every sampled sub-epoch, and that they are
+//   well formed
+// * the proof of space and VDFs of one randomly picked segment per sub-epoch
+// * that the recent chain is contiguous, that every block adds the difficulty
+//   of its epoch to the weight, and its proofs of space and infusion point
+//   VDFs
+// local_summaries are the sub-epoch summaries of the chain we already have
+// (if any), and is used to compute the fork point.
+pub fn validate_weight_proof(
+    constants: &ConsensusConstants,
+    wp: &WeightProof,
+    local_summaries: &[SubEpochSummary],
+) -> Result<ValidatedWeightProof, ErrorCode> {
+    if wp.sub_epochs.len() < 2 {
+        return Err(ErrorCode::InvalidSubEpochSummary);
+    }
+    let peak = wp
+        .recent_chain_data
+        .last()
+        .ok_or(ErrorCode::InvalidHeight)?;
+
+    let ses = validate_sub_epoch_summaries(constants, wp)?;
+    validate_recent_chain(constants, &wp.recent_chain_data, &ses.summaries)?;
+
+    let seed = ses.summaries[ses.summaries.len() - 2].hash();
+    let mut rng = PyRandom::from_seed(&seed);
+    validate_sub_epoch_sampling(&mut rng, &ses.weights, wp)?;
+    validate_segments(&wp.sub_epoch_segments, ses.summaries.len())?;
+
+    // the proofs are only verified once the structure has been validated,
+    // since that's much cheaper
+    verify_segments(constants, &mut rng, &wp.sub_epoch_segments, &ses.summaries)?;
+    verify_recent_chain(constants, &wp.recent_chain_data, &ses.summaries)?;
+
+    Ok(ValidatedWeightProof {
+        fork_point: fork_point(constants, local_summaries, &ses.summaries),
+        peak_height: peak.height(),
+        weight: peak.weight(),
+        summaries: ses.summaries,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::consensus_constants::TEST_CONSTANTS;
+    use crate::header_validation::tests::{farmer, K};
+    use crate::proof_of_space::calculate_pos_challenge;
+    use crate::vdf::tests::prove_vdf;
+    use chia_bls::{PublicKey, Signature};
+    use chia_protocol::{
+        Bytes, EndOfSubSlotBundle, Foliage, FoliageBlockData, PoolTarget, ProofOfSpace,
+        RewardChainBlock, RewardChainSubSlot, SubSlotProofs,
+    };
+    use rstest::rstest;
+    use std::sync::OnceLock;
+
+    const SUB_EPOCH_BLOCKS: u32 = 10;
+    const DIFFICULTY: u128 = 7;
+    const SUB_SLOT_ITERS: u64 = 1 << 27;
+    // creating discriminants is slow, so all VDFs use the same challenge
+    const VDF_CHALLENGE: Bytes32 = Bytes32::new([0x77; 32]);
+
+    // a tiny plot, with a low enough difficulty constant factor that every
+    // proof is good enough to make a block. The small discriminants make the
+    // VDFs faster to prove and verify
+    fn constants() -> ConsensusConstants {
+        TEST_CONSTANTS
+            .with_overrides([
+                ("SUB_EPOCH_BLOCKS", SUB_EPOCH_BLOCKS.to_string()),
+                ("MIN_PLOT_SIZE", K.to_string()),
+                ("NUMBER_ZERO_BITS_PLOT_FILTER", "0".to_string()),
+                ("DIFFICULTY_CONSTANT_FACTOR", "1073741824".to_string()),
+                ("DISCRIMINANT_SIZE_BITS", "256".to_string()),
+            ])
+            .expect("with_overrides")
+    }
+
+    pub fn vdf_info() -> VDFInfo {
+        VDFInfo::new(Bytes32::default(), 0, ClassgroupElement::default())
+    }
+
+    pub fn vdf_proof() -> VDFProof {
+        VDFProof::new(0, Bytes::default(), false)
+    }
+
+    fn pos() -> ProofOfSpace {
+        ProofOfSpace::new(
+            Bytes32::default(),
+            None,
+            Some(Bytes32::default()),
+            PublicKey::default(),
+            32,
+            Bytes::default(),
+        )
+    }
+
+    // the test farmer's proof of space for the challenge and signage point,
+    // if its plot has one
+    fn farmer_pos(challenge: &Bytes32, cc_sp_hash: &Bytes32) -> Option<ProofOfSpace> {
+        let farmer = farmer();
+        let pos_challenge = calculate_pos_challenge(&farmer.plot_id, challenge, cc_sp_hash);
+        if u64::from(u16::from_be_bytes([pos_challenge[0], pos_challenge[1]]) >> 4) != farmer.f7 {
+            return None;
+        }
+        Some(ProofOfSpace::new(
+            pos_challenge,
+            None,
+            Some(farmer.pool_ph),
+            farmer.sk.public_key(),
+            K,
+            farmer.proof.clone(),
+        ))
+    }
+
+    // A challenge chain sub slot, and a proof of space for the first signage
+    // point of the next sub slot. Its end of slot VDF is never verified, so
+    // the output is picked to make the farmer's plot have a proof
+    fn find_sub_slot(ses: Option<&SubEpochSummary>) -> (ChallengeChainSubSlot, ProofOfSpace) {
+        (0..u32::MAX)
+            .find_map(|i| {
+                let mut output = [0_u8; 100];
+                output[..4].copy_from_slice(&i.to_be_bytes());
+                let cc = ChallengeChainSubSlot::new(
+                    VDFInfo::new(
+                        VDF_CHALLENGE,
+                        SUB_SLOT_ITERS,
+                        ClassgroupElement::new(output.into()),
+                    ),
+                    None,
+                    ses.map(|ses| ses.hash().into()),
+                    ses.and_then(|ses| ses.new_sub_slot_iters),
+                    ses.and_then(|ses| ses.new_difficulty),
+                );
+                let challenge: Bytes32 = cc.hash().into();
+                farmer_pos(&challenge, &challenge).map(|pos| (cc, pos))
+            })
+            .unwrap()
+    }
+
+    fn make_header_block(
+        height: u32,
+        weight: u128,
+        prev_hash: Bytes32,
+        finished_sub_slots: Vec<EndOfSubSlotBundle>,
+    ) -> HeaderBlock {
+        let rcb = RewardChainBlock::new(
+            weight,
+            height,
+            0,
+            0,
+            Bytes32::default(),
+            pos(),
+            None,
+            Signature::default(),
+            vdf_info(),
+            None,
+            Signature::default(),
+            vdf_info(),
+            None,
+            false,
+        );
+        let foliage = Foliage::new(
+            prev_hash,
+            Bytes32::default(),
+            FoliageBlockData::new(
+                Bytes32::default(),
+                PoolTarget::new(Bytes32::default(), 0),
+                None,
+                Bytes32::default(),
+                Bytes32::default(),
+            ),
+            Signature::default(),
+            None,
+            None,
+        );
+        HeaderBlock::new(
+            finished_sub_slots,
+            rcb,
+            None,
+            vdf_proof(),
+            None,
+            vdf_proof(),
+            None,
+            foliage,
+            None,
+            Bytes::default(),
+            None,
+        )
+    }
+
+    // A segment of the first sub slot of the sub-epoch. The challenge block is
+    // followed by one more block and the end of the sub slot. The end of slot
+    // before the challenge block is only used for its hash
+    fn make_segment(summaries: &[SubEpochSummary], sub_epoch_n: u32) -> SubEpochChallengeSegment {
+        let constants = constants();
+        let default = ClassgroupElement::default();
+        let ses = sub_epoch_n.checked_sub(1).map(|n| &summaries[n as usize]);
+        let (cc, pos) = find_sub_slot(ses);
+        let empty = SubSlotData::new(
+            None, None, None, None, None, None, None, None, None, None, None, None, None,
+        );
+
+        let mut start = empty.clone();
+        start.cc_slot_end = Some(vdf_proof());
+        start.cc_slot_end_info = Some(cc.challenge_chain_end_of_slot_vdf);
+
+        let (cc_ip, cc_ip_proof) = prove_vdf(&constants, VDF_CHALLENGE, &default, 2);
+        let mut challenge = empty.clone();
+        challenge.proof_of_space = Some(pos);
+        challenge.signage_point_index = Some(0);
+        challenge.cc_infusion_point = Some(cc_ip_proof);
+        challenge.cc_ip_vdf_info = Some(cc_ip.clone());
+        challenge.total_iters = Some(10);
+
+        // the next block's signage point is after the challenge block's
+        // infusion point, so both its VDFs start there
+        let (cc_sp, cc_sp_proof) = prove_vdf(&constants, VDF_CHALLENGE, &cc_ip.output, 1);
+        let (next_ip, next_ip_proof) = prove_vdf(&constants, VDF_CHALLENGE, &cc_ip.output, 2);
+        let mut block = empty.clone();
+        block.signage_point_index = Some(1);
+        block.cc_signage_point = Some(cc_sp_proof);
+        block.cc_sp_vdf_info = Some(cc_sp);
+        block.cc_infusion_point = Some(next_ip_proof);
+        block.cc_ip_vdf_info = Some(next_ip.clone());
+        block.total_iters = Some(12);
+
+        let (cc_end, cc_end_proof) = prove_vdf(&constants, VDF_CHALLENGE, &next_ip.output, 3);
+        let mut end = empty;
+        end.cc_slot_end = Some(cc_end_proof);
+        end.cc_slot_end_info = Some(cc_end);
+
+        SubEpochChallengeSegment::new(sub_epoch_n, vec![start, challenge, block, end], None)
+    }
+
+    // a weight proof of a chain with 5 sub-epochs at a constant difficulty,
+    // with the recent chain starting at height 25 and ending at 45. The last
+    // summary is included in the block at height 40. Proving the VDFs takes a
+    // while, so it's only created once
+    fn make_weight_proof() -> WeightProof {
+        static WP: OnceLock<WeightProof> = OnceLock::new();
+        WP.get_or_init(|| {
+            let sub_epochs: Vec<SubEpochData> = (0..5)
+                .map(|i| SubEpochData::new(Bytes32::from([i; 32]), 0, None, None))
+                .collect();
+            make_weight_proof_with(sub_epochs)
+        })
+        .clone()
+    }
+
+    fn make_weight_proof_with(sub_epochs: Vec<SubEpochData>) -> WeightProof {
+        let constants = constants();
+        let ses = map_sub_epoch_summaries(&constants, &sub_epochs).expect("summaries");
+        let default = ClassgroupElement::default();
+        let (rc_ip, rc_ip_proof) = prove_vdf(&constants, VDF_CHALLENGE, &default, 1);
+        let mut recent_chain = Vec::<HeaderBlock>::new();
+        let mut prev_hash = Bytes32::default();
+        let mut weight = DIFFICULTY * 25;
+        let mut difficulty = DIFFICULTY;
+        let mut pos = pos();
+        let mut cc_ip_input = default;
+        for height in 25..=45 {
+            let mut slots = Vec::new();
+            if height % SUB_EPOCH_BLOCKS == 0 {
+                let summary = &ses.summaries[(height / SUB_EPOCH_BLOCKS) as usize];
+                if let Some(new_difficulty) = summary.new_difficulty {
+                    difficulty = new_difficulty as u128;
+                }
+                let (cc, slot_pos) = find_sub_slot(Some(summary));
+                slots.push(EndOfSubSlotBundle::new(
+                    cc,
+                    None,
+                    RewardChainSubSlot::new(vdf_info(), Bytes32::default(), None, 0),
+                    SubSlotProofs::new(vdf_proof(), None, vdf_proof()),
+                ));
+                pos = slot_pos;
+                cc_ip_input = default;
+            }
+            weight += difficulty;
+            let mut block = make_header_block(height, weight, prev_hash, slots);
+
+            // every block is infused one iteration after the previous one
+            let (cc_ip, cc_ip_proof) = prove_vdf(&constants, VDF_CHALLENGE, &cc_ip_input, 1);
+            cc_ip_input = cc_ip.output;
+            let rcb = &mut block.reward_chain_block;
+            rcb.total_iters = u128::from(height);
+            rcb.proof_of_space = pos.clone();
+            rcb.challenge_chain_ip_vdf = cc_ip;
+            rcb.reward_chain_ip_vdf = rc_ip.clone();
+            block.challenge_chain_ip_proof = cc_ip_proof;
+            block.reward_chain_ip_proof = rc_ip_proof.clone();
+
+            prev_hash = block.header_hash();
+            recent_chain.push(block);
+        }
+        let segments = (0..4).map(|n| make_segment(&ses.summaries, n)).collect();
+        WeightProof::new(sub_epochs, segments, recent_chain)
+    }
+
+    #[rstest]
+    #[case(&[1; 32], &[0.170_029_994_912_822, 0.926_615_242_510_999_1, 0.318_978_928_662_635])]
+    #[case(&[0; 32], &[0.279_945_442_455_909, 0.601_077_983_612_470_3])]
+    #[case(
+        &hex_literal::hex!("ccd5bb71183532bff220ba46c268991a3ff07eb358e8255a65c30a2dce0e5fbb"),
+        &[0.459_102_733_059_557_3, 0.020_392_550_206_928_206, 0.906_538_417_183_798_4]
+    )]
+    fn test_py_random(#[case] seed: &[u8], #[case] expected: &[f64]) {
+        // the expected values are from python's random.Random(seed).random(),
+        // and must match exactly
+        let mut rng = PyRandom::from_seed(seed);
+        for value in expected {
+            assert_eq!(rng.random().to_bits(), value.to_bits());
+        }
+    }
+
+    #[rstest]
+    #[case(&[1; 32], &[1, 1, 3, 7, 100, 2], &[0, 0, 0, 6, 49, 0])]
+    #[case(&[0; 32], &[5, 1, 2, 1000], &[2, 0, 1, 602])]
+    fn test_py_random_choice(#[case] seed: &[u8], #[case] n: &[u32], #[case] expected: &[u32]) {
+        // the expected values are from python's random.Random(seed) calling
+        // choice(range(n)) for each n
+        let mut rng = PyRandom::from_seed(seed);
+        for (n, value) in n.iter().zip(expected) {
+            assert_eq!(rng.randbelow(*n), *value);
+        }
+    }
+
+    #[test]
+    fn test_map_sub_epoch_summaries() {
+        let mut sub_epochs: Vec<SubEpochData> = (0..4)
+            .map(|i| SubEpochData::new(Bytes32::from([i; 32]), i, None, None))
+            .collect();
+        // the difficulty doubles after sub-epoch 1
+        sub_epochs[1].new_difficulty = Some(14);
+        sub_epochs[1].new_sub_slot_iters = Some(1000);
+        let ses = map_sub_epoch_summaries(&constants(), &sub_epochs).expect("summaries");
+
+        assert_eq!(ses.summaries.len(), 4);
+        assert_eq!(
+            ses.summaries[0].prev_subepoch_summary_hash,
+            constants().genesis_challenge
+        );
+        for pair in ses.summaries.windows(2) {
+            assert_eq!(pair[1].prev_subepoch_summary_hash, pair[0].hash().into());
+        }
+        assert_eq!(ses.summaries[1].new_difficulty, Some(14));
+        assert_eq!(ses.summaries[1].new_sub_slot_iters, Some(1000));
+
+        // sub-epoch 0 has 10 + 1 blocks, sub-epoch 1 has 10 + 2 - 1 and
+        // sub-epoch 2 has 10 + 3 - 2, the last two at the new difficulty
+        assert_eq!(
+            ses.weights,
+            vec![7, 7 * 11 + 7, 7 * 22 + 14, 7 * 22 + 14 * 11 + 14]
+        );
+        assert_eq!(ses.total_weight, 7 * 22 + 14 * 11);
+    }
+
+    #[test]
+    fn test_validate_weight_proof() {
+        let wp = make_weight_proof();
+        let ret = validate_weight_proof(&constants(), &wp, &[]).expect("validate");
+        assert_eq!(ret.peak_height, 45);
+        assert_eq!(ret.weight, DIFFICULTY * 46);
+        assert_eq!(ret.fork_point, 0);
+        assert_eq!(ret.summaries.len(), 5);
+
+        // with the same summaries locally, the fork point is two sub-epochs
+        // before the last one we agree on
+        let again = validate_weight_proof(&constants(), &wp, &ret.summaries).expect("validate");
+        assert_eq!(again.fork_point, SUB_EPOCH_BLOCKS);
+
+        // we only agree on the first summary
+        let mut local = ret.summaries.clone();
+        local[1].num_blocks_overflow = 1;
+        let ret = validate_weight_proof(&constants(), &wp, &local).expect("validate");
+        assert_eq!(ret.fork_point, 0);
+    }
+
+    #[test]
+    fn test_invalid_summary_hash() {
+        let mut wp = make_weight_proof();
+        wp.sub_epochs[2].reward_chain_hash = Bytes32::from([0xff; 32]);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidSubEpochSummaryHash
+        );
+    }
+
+    #[test]
+    fn test_no_summary_hash() {
+        let mut wp = make_weight_proof();
+        wp.recent_chain_data.truncate(15);
+        for block in &mut wp.recent_chain_data {
+            block.finished_sub_slots.clear();
+        }
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::NoSubEpochSummaryHash
+        );
+    }
+
+    #[test]
+    fn test_invalid_weight() {
+        let mut wp = make_weight_proof();
+        // the difficulty change means the weight of the sub-epochs no longer
+        // matches the recent chain
+        wp.sub_epochs[1].new_difficulty = Some(14);
+        wp.sub_epochs[1].new_sub_slot_iters = Some(1000);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidWeight
+        );
+    }
+
+    #[rstest]
+    #[case(Some(14), None, ErrorCode::InvalidNewSubSlotIters)]
+    #[case(None, Some(1000), ErrorCode::InvalidNewDifficulty)]
+    #[case(Some(0), Some(1000), ErrorCode::InvalidNewDifficulty)]
+    #[case(Some(14), Some(0), ErrorCode::InvalidNewSubSlotIters)]
+    fn test_invalid_epoch(
+        #[case] new_difficulty: Option<u64>,
+        #[case] new_sub_slot_iters: Option<u64>,
+        #[case] expected: ErrorCode,
+    ) {
+        let mut wp = make_weight_proof();
+        wp.sub_epochs[1].new_difficulty = new_difficulty;
+        wp.sub_epochs[1].new_sub_slot_iters = new_sub_slot_iters;
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_missing_samples() {
+        let mut wp = make_weight_proof();
+        wp.sub_epoch_segments.clear();
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidChallengeChainData
+        );
+    }
+
+    #[rstest]
+    #[case(5, ErrorCode::InvalidChallengeChainData)]
+    #[case(0, ErrorCode::InvalidChallengeChainData)]
+    fn test_invalid_segment_order(#[case] sub_epoch_n: u32, #[case] expected: ErrorCode) {
+        let mut wp = make_weight_proof();
+        let mut segment = wp.sub_epoch_segments[0].clone();
+        segment.sub_epoch_n = sub_epoch_n;
+        wp.sub_epoch_segments.push(segment);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_invalid_segment() {
+        let mut wp = make_weight_proof();
+        wp.sub_epoch_segments[0].sub_slots[1].signage_point_index = None;
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidSpIndex
+        );
+
+        let mut wp = make_weight_proof();
+        wp.sub_epoch_segments[0].sub_slots[1].proof_of_space = None;
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidChallengeChainData
+        );
+    }
+
+    #[test]
+    fn test_invalid_recent_chain() {
+        let mut wp = make_weight_proof();
+        wp.recent_chain_data.remove(3);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidHeight
+        );
+
+        let mut wp = make_weight_proof();
+        wp.recent_chain_data[3].foliage.prev_block_hash = Bytes32::default();
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidPrevBlockHash
+        );
+    }
+
+    #[test]
+    fn test_recent_chain_difficulty_change() {
+        // the last summary, included at height 40, doubles the difficulty
+        let mut sub_epochs: Vec<SubEpochData> = (0..5)
+            .map(|i| SubEpochData::new(Bytes32::from([i; 32]), 0, None, None))
+            .collect();
+        sub_epochs[4].new_difficulty = Some(14);
+        sub_epochs[4].new_sub_slot_iters = Some(SUB_SLOT_ITERS);
+        let wp = make_weight_proof_with(sub_epochs);
+        let ret = validate_weight_proof(&constants(), &wp, &[]).expect("check");
+        assert_eq!(ret.weight, DIFFICULTY * 40 + 14 * 6);
+    }
+
+    #[rstest]
+    #[case(39, 1)]
+    #[case(40, 7)]
+    #[case(44, 1)]
+    fn test_recent_chain_weight(#[case] height: u32, #[case] extra: u128) {
+        // every block must add exactly the difficulty to the weight, not just
+        // increase it
+        let mut wp = make_weight_proof();
+        for block in &mut wp.recent_chain_data {
+            if block.height() >= height {
+                block.reward_chain_block.weight += extra;
+            }
+        }
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            ErrorCode::InvalidWeight
+        );
+    }
+
+    #[rstest]
+    #[case::pospace(
+        |s: &mut Vec<SubSlotData>| s[1].proof_of_space.as_mut().unwrap().proof = Bytes::default(),
+        ErrorCode::InvalidPospace
+    )]
+    #[case::pos_challenge(
+        |s: &mut Vec<SubSlotData>| s[0].cc_slot_end_info.as_mut().unwrap().number_of_iterations += 1,
+        ErrorCode::InvalidPospace
+    )]
+    #[case::cc_ip_vdf(
+        |s: &mut Vec<SubSlotData>| s[1].cc_ip_vdf_info.as_mut().unwrap().number_of_iterations += 1,
+        ErrorCode::InvalidCcIpVdf
+    )]
+    #[case::cc_sp_vdf(
+        |s: &mut Vec<SubSlotData>| s[2].cc_sp_vdf_info.as_mut().unwrap().number_of_iterations += 1,
+        ErrorCode::InvalidCcSpVdf
+    )]
+    #[case::cc_sp_proof(|s: &mut Vec<SubSlotData>| s[2].cc_signage_point = None, ErrorCode::InvalidCcSpVdf)]
+    #[case::total_iters(|s: &mut Vec<SubSlotData>| s[2].total_iters = Some(13), ErrorCode::InvalidCcIpVdf)]
+    #[case::cc_eos_vdf(
+        |s: &mut Vec<SubSlotData>| s[3].cc_slot_end_info.as_mut().unwrap().number_of_iterations += 1,
+        ErrorCode::InvalidCcEosVdf
+    )]
+    fn test_invalid_segment_proofs(
+        #[case] modify: fn(&mut Vec<SubSlotData>),
+        #[case] expected: ErrorCode,
+    ) {
+        let mut wp = make_weight_proof();
+        modify(&mut wp.sub_epoch_segments[2].sub_slots);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::pospace(
+        35,
+        |b: &mut HeaderBlock| b.reward_chain_block.proof_of_space.proof = Bytes::default(),
+        ErrorCode::InvalidPospace
+    )]
+    #[case::cc_ip_vdf(
+        35,
+        |b: &mut HeaderBlock| b.reward_chain_block.total_iters += 1,
+        ErrorCode::InvalidCcIpVdf
+    )]
+    #[case::first_cc_ip_vdf(
+        40,
+        |b: &mut HeaderBlock| b.reward_chain_block.challenge_chain_ip_vdf.number_of_iterations += 1,
+        ErrorCode::InvalidCcIpVdf
+    )]
+    #[case::rc_ip_vdf(
+        35,
+        |b: &mut HeaderBlock| b.reward_chain_block.reward_chain_ip_vdf.number_of_iterations += 1,
+        ErrorCode::InvalidRcIpVdf
+    )]
+    fn test_invalid_recent_chain_proofs(
+        #[case] height: u32,
+        #[case] modify: fn(&mut HeaderBlock),
+        #[case] expected: ErrorCode,
+    ) {
+        let mut wp = make_weight_proof();
+        modify(&mut wp.recent_chain_data[(height - 25) as usize]);
+        assert_eq!(
+            validate_weight_proof(&constants(), &wp, &[]).unwrap_err(),
+            expected
+        );
+    }
+}
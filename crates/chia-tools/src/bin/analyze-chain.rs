@@ -177,6 +177,13 @@ fn main() {
             .expect("failed to get system time");
 
         assert!(clvm_cost + byte_cost + conds.cost == ti.cost);
+        // the most expensive spend, in terms of condition cost
+        let max_spend_cond_cost = conds
+            .spends
+            .iter()
+            .map(|s| s.condition_cost)
+            .max()
+            .unwrap_or(0);
         output
             .write_fmt(format_args!(
                 "{} val_stack: {} \
@@ -186,8 +193,10 @@ fn main() {
             pairs: {} \
             heap: {} \
             block_cost: {} \
+            byte_cost: {} \
             clvm_cost: {} \
             cond_cost: {} \
+            max_spend_cond_cost: {} \
             parse_time: {} \
             ref_lookup_time: {} \
             execute_time: {} \
@@ -203,8 +212,10 @@ fn main() {
                 counters.pair_count,
                 counters.heap_size,
                 ti.cost,
+                byte_cost,
                 clvm_cost,
                 conds.cost,
+                max_spend_cond_cost,
                 parse_timing.as_micros(),
                 ref_lookup_timing.as_micros(),
                 execute_timing.as_micros(),
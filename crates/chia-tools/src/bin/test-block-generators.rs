@@ -2,8 +2,9 @@ use clap::Parser;
 
 use chia_bls::PublicKey;
 use chia_consensus::consensus_constants::TEST_CONSTANTS;
+use chia_consensus::fork_heights::get_flags_for_height;
 use chia_consensus::gen::conditions::{EmptyVisitor, NewCoin, Spend, SpendBundleConditions};
-use chia_consensus::gen::flags::{ALLOW_BACKREFS, MEMPOOL_MODE};
+use chia_consensus::gen::flags::ALLOW_BACKREFS;
 use chia_consensus::gen::run_block_generator::{run_block_generator, run_block_generator2};
 use chia_tools::iterate_tx_blocks;
 use clvmr::allocator::NodePtr;
@@ -140,12 +141,13 @@ fn main() {
         .queue_len(num_cores + 5)
         .build();
 
-    let flags = if args.mempool { MEMPOOL_MODE } else { 0 }
-        | if args.test_backrefs {
-            ALLOW_BACKREFS
-        } else {
-            0
-        };
+    // generators are re-serialized with back-references, which means they
+    // need to be allowed regardless of height
+    let extra_flags = if args.test_backrefs {
+        ALLOW_BACKREFS
+    } else {
+        0
+    };
 
     let block_runner = if args.rust_generator {
         run_block_generator2::<_, EmptyVisitor>
@@ -162,6 +164,7 @@ fn main() {
         |height, block, block_refs| {
             pool.execute(move || {
                 let mut a = Allocator::new_limited(500_000_000);
+                let flags = get_flags_for_height(height, constants, args.mempool) | extra_flags;
 
                 let ti = block.transactions_info.as_ref().expect("transactions_info");
                 let prg = block
@@ -201,7 +204,7 @@ fn main() {
                         prg.as_ref(),
                         &block_refs,
                         ti.cost,
-                        0,
+                        get_flags_for_height(height, constants, false),
                         constants,
                     )
                     .expect("failed to run block generator");
@@ -8,6 +8,7 @@ from chia_rs import (
     compute_merkle_set_root,
     confirm_included_already_hashed as ru_confirm_included_already_hashed,
     confirm_not_included_already_hashed as ru_confirm_not_included_already_hashed,
+    confirm_multiproof_already_hashed,
 )
 from random import Random
 from merkle_set import (
@@ -91,6 +92,12 @@ def check_tree(leafs: List[bytes32]) -> None:
             expect_included=False,
         )
 
+    # a single proof for all the leafs, and some items that aren't included
+    items = leafs + [bytes32([i] + [2] * 31) for i in range(256)]
+    included, proof = ru_tree.generate_multiproof(items)
+    assert included == [True] * len(leafs) + [False] * 256
+    assert confirm_multiproof_already_hashed(root, items, proof) == included
+
 
 def h(b: str) -> bytes32:
     return bytes32.fromhex(b)
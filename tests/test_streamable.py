@@ -43,6 +43,8 @@ def test_hash_spend() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
     a2 = Spend(
         coin,
@@ -64,6 +66,8 @@ def test_hash_spend() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
     b = hash(a1)
     c = hash(a2)
@@ -75,10 +79,34 @@ def test_hash_spend() -> None:
 def test_hash_spend_bundle_conditions() -> None:
 
     a1 = SpendBundleConditions(
-        [], 1000, 1337, 42, None, None, [(pk, b"msg")], 12345678, 123, 456
+        [],
+        1000,
+        1337,
+        42,
+        None,
+        None,
+        [(pk, b"msg")],
+        12345678,
+        123,
+        456,
+        345678,
+        11000000,
+        1000000,
     )
     a2 = SpendBundleConditions(
-        [], 1001, 1337, 42, None, None, [(pk, b"msg")], 12345678, 123, 456
+        [],
+        1001,
+        1337,
+        42,
+        None,
+        None,
+        [(pk, b"msg")],
+        12345678,
+        123,
+        456,
+        345678,
+        11000000,
+        1000000,
     )
     b = hash(a1)
     c = hash(a2)
@@ -109,6 +137,8 @@ def test_json_spend() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
 
     assert a.to_json_dict() == {
@@ -131,6 +161,8 @@ def test_json_spend() -> None:
         "agg_sig_parent_amount": [],
         "agg_sig_parent_puzzle": [],
         "flags": 0,
+        "execution_cost": 0,
+        "condition_cost": 0,
     }
 
 
@@ -156,6 +188,8 @@ def test_from_json_spend() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
 
     b = Spend.from_json_dict(
@@ -179,6 +213,8 @@ def test_from_json_spend() -> None:
             "agg_sig_parent_amount": [],
             "agg_sig_parent_puzzle": [],
             "flags": 0,
+            "execution_cost": 0,
+            "condition_cost": 0,
         }
     )
     assert a == b
@@ -206,6 +242,8 @@ def test_from_json_spend_set_optional() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
 
     b = Spend.from_json_dict(
@@ -229,6 +267,8 @@ def test_from_json_spend_set_optional() -> None:
             "agg_sig_parent_amount": [],
             "agg_sig_parent_puzzle": [],
             "flags": 0,
+            "execution_cost": 0,
+            "condition_cost": 0,
         }
     )
     assert a == b
@@ -259,6 +299,8 @@ def test_invalid_hex_prefix() -> None:
                 "agg_sig_parent_amount": [],
                 "agg_sig_parent_puzzle": [],
                 "flags": 0,
+                "execution_cost": 0,
+                "condition_cost": 0,
             }
         )
 
@@ -288,6 +330,8 @@ def test_invalid_hex_prefix_bytes() -> None:
                 "agg_sig_parent_amount": [],
                 "agg_sig_parent_puzzle": [],
                 "flags": 0,
+                "execution_cost": 0,
+                "condition_cost": 0,
             }
         )
 
@@ -317,6 +361,8 @@ def test_invalid_hex_digit() -> None:
                 "agg_sig_parent_amount": [],
                 "agg_sig_parent_puzzle": [],
                 "flags": 0,
+                "execution_cost": 0,
+                "condition_cost": 0,
             }
         )
 
@@ -346,6 +392,8 @@ def test_invalid_hex_length() -> None:
                 "agg_sig_parent_amount": [],
                 "agg_sig_parent_puzzle": [],
                 "flags": 0,
+                "execution_cost": 0,
+                "condition_cost": 0,
             }
         )
 
@@ -374,6 +422,8 @@ def test_missing_field() -> None:
                 "agg_sig_parent_amount": [],
                 "agg_sig_parent_puzzle": [],
                 "flags": 0,
+                "execution_cost": 0,
+                "condition_cost": 0,
             }
         )
 
@@ -381,7 +431,19 @@ def test_missing_field() -> None:
 def test_json_spend_bundle_conditions() -> None:
 
     a = SpendBundleConditions(
-        [], 1000, 1337, 42, None, None, [(pk, b"msg")], 12345678, 123, 456
+        [],
+        1000,
+        1337,
+        42,
+        None,
+        None,
+        [(pk, b"msg")],
+        12345678,
+        123,
+        456,
+        345678,
+        11000000,
+        1000000,
     )
 
     assert a.to_json_dict() == {
@@ -395,13 +457,28 @@ def test_json_spend_bundle_conditions() -> None:
         "cost": 12345678,
         "removal_amount": 123,
         "addition_amount": 456,
+        "byte_cost": 345678,
+        "execution_cost": 11000000,
+        "condition_cost": 1000000,
     }
 
 
 def test_from_json_spend_bundle_conditions() -> None:
 
     a = SpendBundleConditions(
-        [], 1000, 1337, 42, None, None, [(pk, b"msg")], 12345678, 123, 456
+        [],
+        1000,
+        1337,
+        42,
+        None,
+        None,
+        [(pk, b"msg")],
+        12345678,
+        123,
+        456,
+        345678,
+        11000000,
+        1000000,
     )
     b = SpendBundleConditions.from_json_dict(
         {
@@ -415,6 +492,9 @@ def test_from_json_spend_bundle_conditions() -> None:
             "cost": 12345678,
             "removal_amount": 123,
             "addition_amount": 456,
+            "byte_cost": 345678,
+            "execution_cost": 11000000,
+            "condition_cost": 1000000,
         }
     )
     assert a == b
@@ -442,6 +522,8 @@ def test_copy_spend() -> None:
         [],
         [],
         False,
+        0,
+        0,
     )
     b = copy.copy(a)
 
@@ -456,7 +538,19 @@ def test_copy_spend() -> None:
 def test_copy_spend_bundle_conditions() -> None:
 
     a = SpendBundleConditions(
-        [], 1000, 1337, 42, None, None, [(pk, b"msg")], 12345678, 123, 456
+        [],
+        1000,
+        1337,
+        42,
+        None,
+        None,
+        [(pk, b"msg")],
+        12345678,
+        123,
+        456,
+        345678,
+        11000000,
+        1000000,
     )
     b = copy.copy(a)
 
@@ -306,6 +306,12 @@ def confirm_not_included_already_hashed(
     proof: bytes,
 ) -> bool: ...
 
+def confirm_multiproof_already_hashed(
+    root: bytes32,
+    items: List[bytes32],
+    proof: bytes,
+) -> List[bool]: ...
+
 COND_ARGS_NIL: int = ...
 NO_UNKNOWN_CONDS: int = ...
 STRICT_ARGS_COUNT: int = ...
@@ -368,6 +374,7 @@ class AugSchemeMPL:
 class MerkleSet:
     def get_root(self) -> bytes32: ...
     def is_included_already_hashed(self, to_check: bytes) -> Tuple[bool, bytes]: ...
+    def generate_multiproof(self, leafs: List[bytes32]) -> Tuple[List[bool], bytes]: ...
     def __init__(
         self,
         leafs: List[bytes32],
@@ -452,6 +459,8 @@ class MerkleSet:
             "agg_sig_parent_amount: List[Tuple[G1Element, bytes]]",
             "agg_sig_parent_puzzle: List[Tuple[G1Element, bytes]]",
             "flags: int",
+            "execution_cost: int",
+            "condition_cost: int",
         ],
     )
 
@@ -469,6 +478,9 @@ class MerkleSet:
             "cost: int",
             "removal_amount: int",
             "addition_amount: int",
+            "byte_cost: int",
+            "execution_cost: int",
+            "condition_cost: int",
         ],
     )
 
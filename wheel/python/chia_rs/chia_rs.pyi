@@ -49,6 +49,12 @@ def confirm_not_included_already_hashed(
     proof: bytes,
 ) -> bool: ...
 
+def confirm_multiproof_already_hashed(
+    root: bytes32,
+    items: List[bytes32],
+    proof: bytes,
+) -> List[bool]: ...
+
 COND_ARGS_NIL: int = ...
 NO_UNKNOWN_CONDS: int = ...
 STRICT_ARGS_COUNT: int = ...
@@ -111,6 +117,7 @@ class AugSchemeMPL:
 class MerkleSet:
     def get_root(self) -> bytes32: ...
     def is_included_already_hashed(self, to_check: bytes) -> Tuple[bool, bytes]: ...
+    def generate_multiproof(self, leafs: List[bytes32]) -> Tuple[List[bool], bytes]: ...
     def __init__(
         self,
         leafs: List[bytes32],
@@ -253,6 +260,8 @@ class Spend:
     agg_sig_parent_amount: List[Tuple[G1Element, bytes]]
     agg_sig_parent_puzzle: List[Tuple[G1Element, bytes]]
     flags: int
+    execution_cost: int
+    condition_cost: int
     def __init__(
         self,
         coin_id: bytes,
@@ -273,7 +282,9 @@ class Spend:
         agg_sig_puzzle_amount: Sequence[Tuple[G1Element, bytes]],
         agg_sig_parent_amount: Sequence[Tuple[G1Element, bytes]],
         agg_sig_parent_puzzle: Sequence[Tuple[G1Element, bytes]],
-        flags: int
+        flags: int,
+        execution_cost: int,
+        condition_cost: int
     ) -> None: ...
     def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
@@ -311,7 +322,9 @@ class Spend:
         agg_sig_puzzle_amount: Union[ List[Tuple[G1Element, bytes]], _Unspec] = _Unspec(),
         agg_sig_parent_amount: Union[ List[Tuple[G1Element, bytes]], _Unspec] = _Unspec(),
         agg_sig_parent_puzzle: Union[ List[Tuple[G1Element, bytes]], _Unspec] = _Unspec(),
-        flags: Union[ int, _Unspec] = _Unspec()) -> Spend: ...
+        flags: Union[ int, _Unspec] = _Unspec(),
+        execution_cost: Union[ int, _Unspec] = _Unspec(),
+        condition_cost: Union[ int, _Unspec] = _Unspec()) -> Spend: ...
 
 class SpendBundleConditions:
     spends: List[Spend]
@@ -324,6 +337,9 @@ class SpendBundleConditions:
     cost: int
     removal_amount: int
     addition_amount: int
+    byte_cost: int
+    execution_cost: int
+    condition_cost: int
     def __init__(
         self,
         spends: Sequence[Spend],
@@ -335,7 +351,10 @@ class SpendBundleConditions:
         agg_sig_unsafe: Sequence[Tuple[G1Element, bytes]],
         cost: int,
         removal_amount: int,
-        addition_amount: int
+        addition_amount: int,
+        byte_cost: int,
+        execution_cost: int,
+        condition_cost: int
     ) -> None: ...
     def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
@@ -364,7 +383,10 @@ class SpendBundleConditions:
         agg_sig_unsafe: Union[ List[Tuple[G1Element, bytes]], _Unspec] = _Unspec(),
         cost: Union[ int, _Unspec] = _Unspec(),
         removal_amount: Union[ int, _Unspec] = _Unspec(),
-        addition_amount: Union[ int, _Unspec] = _Unspec()) -> SpendBundleConditions: ...
+        addition_amount: Union[ int, _Unspec] = _Unspec(),
+        byte_cost: Union[ int, _Unspec] = _Unspec(),
+        execution_cost: Union[ int, _Unspec] = _Unspec(),
+        condition_cost: Union[ int, _Unspec] = _Unspec()) -> SpendBundleConditions: ...
 
 class BlockRecord:
     header_hash: bytes32
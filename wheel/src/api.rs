@@ -12,7 +12,7 @@ use chia_consensus::gen::run_puzzle::run_puzzle as native_run_puzzle;
 use chia_consensus::gen::solution_generator::solution_generator as native_solution_generator;
 use chia_consensus::gen::solution_generator::solution_generator_backrefs as native_solution_generator_backrefs;
 use chia_consensus::merkle_set::compute_merkle_set_root as compute_merkle_root_impl;
-use chia_consensus::merkle_tree::{validate_merkle_proof, MerkleSet};
+use chia_consensus::merkle_tree::{validate_merkle_multiproof, validate_merkle_proof, MerkleSet};
 use chia_protocol::{
     BlockRecord, Bytes32, ChallengeBlockInfo, ChallengeChainSubSlot, ClassgroupElement, Coin,
     CoinSpend, CoinState, CoinStateFilters, CoinStateUpdate, EndOfSubSlotBundle, Foliage,
@@ -107,6 +107,17 @@ pub fn confirm_not_included_already_hashed(
         .map(|r| !r)
 }
 
+#[pyfunction]
+pub fn confirm_multiproof_already_hashed(
+    root: Bytes32,
+    items: Vec<Bytes32>,
+    proof: &[u8],
+) -> PyResult<Vec<bool>> {
+    let items: Vec<[u8; 32]> = items.iter().map(Into::into).collect();
+    validate_merkle_multiproof(proof, &items, (&root).into())
+        .map_err(|_| PyValueError::new_err("Invalid proof"))
+}
+
 #[pyfunction]
 pub fn tree_hash(py: Python<'_>, blob: PyBuffer<u8>) -> PyResult<Bound<'_, PyBytes>> {
     assert!(
@@ -393,6 +404,7 @@ pub fn chia_rs(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<MerkleSet>()?;
     m.add_function(wrap_pyfunction!(confirm_included_already_hashed, m)?)?;
     m.add_function(wrap_pyfunction!(confirm_not_included_already_hashed, m)?)?;
+    m.add_function(wrap_pyfunction!(confirm_multiproof_already_hashed, m)?)?;
 
     // clvm functions
     m.add("COND_ARGS_NIL", COND_ARGS_NIL)?;